@@ -0,0 +1,35 @@
+//! External transfer hook the tests chain after the token manager hook, logging
+//! each transfer it receives so they can check the chained call happened.
+
+use anchor_lang::prelude::*;
+
+declare_id!("5E4NjAbqt7dw4EcWj3gz7p8N7eiVTPrW7vwARxCHaNTS");
+
+#[program]
+pub mod mock_hook {
+    use super::*;
+
+    #[interface(spl_transfer_hook_interface::execute)]
+    pub fn transfer_hook(ctx: Context<TransferHook>, amount: u64) -> Result<()> {
+        msg!(
+            "Chained hook: {} units of {}",
+            amount,
+            ctx.accounts.mint.key()
+        );
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct TransferHook<'info> {
+    /// CHECK: Passed read-only by the token manager hook
+    pub source_token: UncheckedAccount<'info>,
+    /// CHECK: Passed read-only by the token manager hook
+    pub mint: UncheckedAccount<'info>,
+    /// CHECK: Passed read-only by the token manager hook
+    pub destination_token: UncheckedAccount<'info>,
+    /// CHECK: Passed read-only by the token manager hook
+    pub owner: UncheckedAccount<'info>,
+    /// CHECK: Never created; the mock reads no extra accounts
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+}
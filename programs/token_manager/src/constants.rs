@@ -0,0 +1,164 @@
+//! Stable values shared with off-chain clients.
+//!
+//! Everything marked `#[constant]` is exported in the generated IDL so that
+//! clients written in any language can map program errors and events without
+//! hard-coding Anchor internals.
+
+use anchor_lang::prelude::*;
+
+/// Offset added by Anchor to every `TokenManagerError` discriminant.
+/// The on-chain error code of a variant is `ERROR_CODE_OFFSET + discriminant`.
+#[constant]
+pub const ERROR_CODE_OFFSET: u32 = 6000;
+
+/// Version of the event layouts emitted by the program.
+/// Bumped whenever a field is added, removed or reordered in any `#[event]`
+/// struct so indexers can select the matching decoder.
+#[constant]
+pub const EVENT_SCHEMA_VERSION: u8 = 1;
+
+/// Version of the `TokenState` layout written by `export_state`.
+/// Bumped whenever a field is added, removed or reordered so replicas can select
+/// the matching decoder before reading the rest of the export.
+#[constant]
+pub const STATE_EXPORT_VERSION: u16 = 6;
+
+/// Largest drift of the cluster timestamp a manager may configure for its
+/// time-based rules.
+#[constant]
+pub const MAX_CLOCK_TOLERANCE_SECONDS: u32 = 3_600;
+
+/// Delay after a lockdown before the creator may lift it with `end_lockdown`,
+/// leaving time to respond if the creator key is the one compromised.
+#[constant]
+pub const LOCKDOWN_UNLOCK_DELAY_SECONDS: u32 = 172_800;
+
+/// Largest number of legs `settle_batch` accepts in a single transaction.
+#[constant]
+pub const MAX_SETTLEMENT_LEGS: u8 = 16;
+
+/// Largest number of items a crank such as `settle_orders` processes in a single
+/// transaction.
+#[constant]
+pub const MAX_CRANK_BATCH: u8 = 8;
+
+/// Largest number of wallets `add_many_to_whitelist` and
+/// `remove_many_from_whitelist` process in a single transaction.
+#[constant]
+pub const MAX_WHITELIST_BATCH: u8 = 10;
+
+/// Largest compute budget a token may grant the external hook program chained
+/// after its own transfer rules.
+#[constant]
+pub const MAX_CHAINED_HOOK_COMPUTE_UNITS: u32 = 50_000;
+
+/// Prefix of the structured log lines written next to critical events, so simple
+/// log-subscribing monitors can alert without decoding Anchor events.
+///
+/// Lines read `REAL/v1 <kind> key=value ...` where keys are stable per kind.
+/// The version is bumped whenever a key of an existing kind changes.
+#[constant]
+pub const LOG_PREFIX: &str = "REAL/v1";
+
+/// Structured log kind of transfers rejected by the hook.
+/// Keys: `mint`, `from`, `to`, `amount`, `rule`.
+#[constant]
+pub const LOG_TRANSFER_REJECTED: &str = "transfer_rejected";
+
+/// Structured log kind of wallets removed from a whitelist.
+/// Keys: `mint`, `wallet`.
+#[constant]
+pub const LOG_WHITELIST_REMOVED: &str = "whitelist_removed";
+
+/// Structured log kind of transfers denied because a wallet is on the sanctions
+/// list. Keys: `mint`, `wallet`.
+#[constant]
+pub const LOG_SANCTIONS_HIT: &str = "sanctions_hit";
+
+/// Structured log kind of token managers locked down by their guardian, pausing
+/// every token and admin action. Keys: `manager`, `guardian`.
+#[constant]
+pub const LOG_MANAGER_LOCKDOWN: &str = "manager_lockdown";
+
+/// Structured log kind of circuit breakers tripped by anomalies, pausing the
+/// transfers of the token. Keys: `mint`, `anomaly` (`AnomalyKind` index), `count`.
+#[constant]
+pub const LOG_CIRCUIT_BREAKER_TRIPPED: &str = "circuit_breaker_tripped";
+
+/// Rule ids reported in `TransferRejected` events.
+///
+/// Ids are grouped by family so new rules can be added without renumbering:
+/// `1..=99` whitelist rules, `100..=199` transfer shape rules,
+/// `200..=299` holding limit rules, `300..=399` investor consent rules,
+/// `400..=499` corporate action rules, `500..=599` protective pause rules.
+#[constant]
+pub const RULE_DESTINATION_WHITELISTED: u16 = 1;
+
+#[constant]
+pub const RULE_SANCTIONS_SCREENING: u16 = 2;
+
+#[constant]
+pub const RULE_SOURCE_WHITELISTED: u16 = 3;
+
+#[constant]
+pub const RULE_AUTHORIZATION_CURRENT: u16 = 4;
+
+#[constant]
+pub const RULE_TRANSFER_APPROVED: u16 = 5;
+
+#[constant]
+pub const RULE_ZERO_AMOUNT: u16 = 100;
+
+#[constant]
+pub const RULE_ACCOUNT_EMPTYING: u16 = 101;
+
+#[constant]
+pub const RULE_SELF_TRANSFER: u16 = 102;
+
+#[constant]
+pub const RULE_TRUSTED_VAULT: u16 = 103;
+
+#[constant]
+pub const RULE_MARKET_MAKER_INVENTORY: u16 = 200;
+
+#[constant]
+pub const RULE_MAX_HOLDERS: u16 = 201;
+
+#[constant]
+pub const RULE_HOLDER_CAP: u16 = 202;
+
+#[constant]
+pub const RULE_LOCKUP: u16 = 203;
+
+#[constant]
+pub const RULE_AGREEMENT_ACCEPTED: u16 = 300;
+
+#[constant]
+pub const RULE_AGREEMENT_REACCEPTED: u16 = 301;
+
+#[constant]
+pub const RULE_INCOMING_CONSENT: u16 = 302;
+
+#[constant]
+pub const RULE_RECORD_DATE_BLACKOUT: u16 = 400;
+
+#[constant]
+pub const RULE_CREDIT_STATE: u16 = 401;
+
+#[constant]
+pub const RULE_CIRCUIT_BREAKER: u16 = 500;
+
+#[constant]
+pub const RULE_MANAGER_LOCKDOWN: u16 = 501;
+
+/// Rules `suspend_module` refuses to suspend, and which apply even if a
+/// suspension was recorded for them: the whitelist rules, sanctions screening
+/// and the manager lockdown.
+pub const NON_SUSPENDABLE_RULES: [u16; 6] = [
+    RULE_DESTINATION_WHITELISTED,
+    RULE_SANCTIONS_SCREENING,
+    RULE_SOURCE_WHITELISTED,
+    RULE_AUTHORIZATION_CURRENT,
+    RULE_TRANSFER_APPROVED,
+    RULE_MANAGER_LOCKDOWN,
+];
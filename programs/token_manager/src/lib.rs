@@ -2,25 +2,53 @@ use anchor_lang::{
     prelude::*,
     solana_program::{
         account_info::AccountInfo,
+        compute_units::sol_remaining_compute_units,
+        hash::hashv,
+        instruction::{AccountMeta, Instruction},
         program::{invoke, invoke_signed},
         pubkey::Pubkey,
         system_instruction,
-        sysvar::rent::Rent,
+        sysvar::{
+            self,
+            instructions::{load_current_index_checked, load_instruction_at_checked},
+            rent::Rent,
+        },
     },
 };
 
 use anchor_spl::{
-    token_2022::{mint_to, MintTo, Token2022},
+    associated_token::{self, AssociatedToken},
+    token_2022::{
+        burn, freeze_account, mint_to, thaw_account, Burn, FreezeAccount, MintTo, ThawAccount,
+        Token2022,
+    },
     token_2022_extensions::spl_token_metadata_interface,
-    token_interface::{Mint, TokenAccount},
+    token_interface::{
+        close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
+        TransferChecked,
+    },
 };
 
-use spl_token_2022::extension::ExtensionType;
+use spl_token_2022::extension::{
+    transfer_hook::TransferHookAccount, BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+};
 
-use spl_tlv_account_resolution::{account::ExtraAccountMeta, state::ExtraAccountMetaList};
+use spl_tlv_account_resolution::{
+    account::ExtraAccountMeta, seeds::Seed, state::ExtraAccountMetaList,
+};
 
 use spl_transfer_hook_interface::instruction::ExecuteInstruction;
 
+use spl_type_length_value::state::TlvStateBorrowed;
+
+pub mod constants;
+#[cfg(feature = "client")]
+pub mod sizes;
+#[cfg(feature = "client")]
+pub mod solana_pay;
+#[cfg(feature = "verifier")]
+pub mod verifier;
+
 declare_id!("DFUYFchyBFtTjwGUKwdd6KsozCkT1Qkpx18KJAk5Esv5");
 
 #[program]
@@ -36,38 +64,272 @@ pub mod token_manager {
         /// The main account that stores token information and whitelist
         /// Created as a PDA derived from "token-manager" + signer
         #[account(
-        init,
+        init_if_needed,
         payer = signer,
-        space = TokenManager::INIT_SPACE,
+        space = 8 + TokenManager::INIT_SPACE,
         seeds = [b"token-manager", signer.key().as_ref()],
         bump,
     )]
         pub token_manager: Account<'info, TokenManager>,
 
+        /// Public profile of the manager, created when a display name is configured
+        /// CHECK: Created in the InitializeTokenManager implementation
+        #[account(
+            mut,
+            seeds = [b"issuer-profile", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub issuer_profile: UncheckedAccount<'info>,
+
+        /// Fee schedule of the manager, created when fee terms are configured
+        /// CHECK: Created in the InitializeTokenManager implementation
+        #[account(
+            mut,
+            seeds = [b"fee-schedule", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub fee_schedule: UncheckedAccount<'info>,
+
+        /// Guardian of the treasury of the manager, created when a guardian is
+        /// configured
+        /// CHECK: Created in the InitializeTokenManager implementation
+        #[account(
+            mut,
+            seeds = [b"treasury-yield", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub treasury_yield: UncheckedAccount<'info>,
+
         /// Required for creating new accounts
         pub system_program: Program<'info, System>,
     }
 
     /// Initializes the TokenManager state account.
     /// This account will store all created token mints along with their ISIN codes.
-    pub fn initialize_token_manager(ctx: Context<InitializeTokenManager>) -> Result<()> {
-        ctx.accounts.token_manager.tokens = Vec::new();
-        ctx.accounts.token_manager.whitelist = Vec::new();
+    ///
+    /// `config` sets up the manager in the same transaction: the compliance
+    /// requirements of the shares it creates and, when set, its display name, fee
+    /// schedule and treasury guardian. Initializing an existing manager fails with
+    /// `TokenManagerAlreadyInitialized`.
+    pub fn initialize_token_manager(
+        ctx: Context<InitializeTokenManager>,
+        config: ManagerConfig,
+    ) -> Result<()> {
+        if ctx.accounts.token_manager.creator != Pubkey::default() {
+            return Err(error!(TokenManagerError::TokenManagerAlreadyInitialized));
+        }
+        if config
+            .display_name
+            .as_ref()
+            .is_some_and(|display_name| display_name.len() > 64)
+        {
+            return Err(error!(TokenManagerError::FieldTooLong));
+        }
+        if let Some(terms) = &config.fee_terms {
+            terms.validate()?;
+        }
+
+        ctx.accounts.token_manager.compliance_defaults = config.compliance_defaults;
+        ctx.accounts.token_manager.suspended_modules = Vec::new();
+        ctx.accounts.token_manager.clock_tolerance_seconds = 0;
+        ctx.accounts.token_manager.penalty_withholding_bps = 0;
         ctx.accounts.token_manager.current_token_index = 0;
         ctx.accounts.token_manager.creator = ctx.accounts.signer.key();
+        ctx.accounts.token_manager.original_creator = ctx.accounts.signer.key();
+        ctx.accounts.token_manager.pending_creator = None;
+        ctx.accounts.token_manager.lockdown_guardian = None;
+        ctx.accounts.token_manager.locked_down_at = None;
+        ctx.accounts.token_manager.platform = None;
+
+        let token_manager = ctx.accounts.token_manager.key();
+        let payer = ctx.accounts.signer.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        if let Some(display_name) = config.display_name {
+            let account = ctx.accounts.issuer_profile.to_account_info();
+            create_program_account(
+                &account,
+                &payer,
+                &system_program,
+                &[
+                    b"issuer-profile",
+                    token_manager.as_ref(),
+                    &[ctx.bumps.issuer_profile],
+                ],
+                8 + IssuerProfile::INIT_SPACE,
+            )?;
+            IssuerProfile {
+                token_manager,
+                display_name,
+                website: String::new(),
+                logo_uri: String::new(),
+            }
+            .try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+        }
+        if let Some(terms) = config.fee_terms {
+            let account = ctx.accounts.fee_schedule.to_account_info();
+            create_program_account(
+                &account,
+                &payer,
+                &system_program,
+                &[
+                    b"fee-schedule",
+                    token_manager.as_ref(),
+                    &[ctx.bumps.fee_schedule],
+                ],
+                8 + FeeSchedule::INIT_SPACE,
+            )?;
+            FeeSchedule {
+                token_manager,
+                version: 1,
+                terms: terms.clone(),
+            }
+            .try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+            emit!(FeeScheduleUpdated {
+                token_manager,
+                version: 1,
+                terms,
+            });
+        }
+        if let Some(guardian) = config.guardian {
+            let account = ctx.accounts.treasury_yield.to_account_info();
+            create_program_account(
+                &account,
+                &payer,
+                &system_program,
+                &[
+                    b"treasury-yield",
+                    token_manager.as_ref(),
+                    &[ctx.bumps.treasury_yield],
+                ],
+                8 + TreasuryYield::INIT_SPACE,
+            )?;
+            TreasuryYield {
+                token_manager,
+                guardian,
+                emergency_recall: false,
+                programs: Vec::new(),
+            }
+            .try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+        }
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct RegisterPlatform<'info> {
+        /// Upgrade authority of the platform program, paying for the registration
+        #[account(mut)]
+        pub authority: Signer<'info>,
+
+        /// The platform program allowed to operate token managers through its PDAs
+        /// CHECK: Only its key is used; the upgrade authority is checked on `program_data`
+        #[account(executable)]
+        pub platform_program: UncheckedAccount<'info>,
+
+        /// Program data account of the platform program
+        #[account(
+            address = Pubkey::find_program_address(
+                &[platform_program.key().as_ref()],
+                &anchor_lang::solana_program::bpf_loader_upgradeable::ID,
+            ).0,
+            constraint = program_data.upgrade_authority_address == Some(authority.key())
+                @ TokenManagerError::Unauthorized,
+        )]
+        pub program_data: Account<'info, ProgramData>,
+
+        /// Registration of the platform program
+        #[account(
+            init,
+            payer = authority,
+            space = 8 + Platform::INIT_SPACE,
+            seeds = [b"platform", platform_program.key().as_ref()],
+            bump,
+        )]
+        pub platform: Account<'info, Platform>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Registers a white-label tokenization platform program. Only its upgrade
+    /// authority can register it; its PDAs can then own token managers created
+    /// with `initialize_platform_token_manager`.
+    pub fn register_platform(ctx: Context<RegisterPlatform>) -> Result<()> {
+        let platform = &mut ctx.accounts.platform;
+        platform.program = ctx.accounts.platform_program.key();
+        platform.registered_by = ctx.accounts.authority.key();
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct InitializePlatformTokenManager<'info> {
+        /// The wallet paying for account creation
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// PDA of the platform program becoming the creator, signing through CPI
+        pub creator: Signer<'info>,
+
+        /// Registration of the platform program owning `creator`
+        #[account(
+            seeds = [b"platform", platform.program.as_ref()],
+            bump,
+        )]
+        pub platform: Account<'info, Platform>,
+
+        /// The main account that stores token information and whitelist
+        #[account(
+            init,
+            payer = payer,
+            space = 8 + TokenManager::INIT_SPACE,
+            seeds = [b"token-manager", creator.key().as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Initializes a TokenManager owned by a PDA of a registered platform program,
+    /// like `initialize_token_manager` but for managers operated programmatically
+    /// on behalf of issuers. `authority_seeds` are the seeds, bump included, of
+    /// `creator` under the platform program; since only that program can sign for
+    /// its PDAs, the platform itself must issue this call by CPI.
+    pub fn initialize_platform_token_manager(
+        ctx: Context<InitializePlatformTokenManager>,
+        authority_seeds: Vec<Vec<u8>>,
+    ) -> Result<()> {
+        let seeds: Vec<&[u8]> = authority_seeds.iter().map(|seed| seed.as_slice()).collect();
+        let authority = Pubkey::create_program_address(&seeds, &ctx.accounts.platform.program)
+            .map_err(|_| error!(TokenManagerError::NotPlatformAuthority))?;
+        if authority != ctx.accounts.creator.key() {
+            return Err(error!(TokenManagerError::NotPlatformAuthority));
+        }
+
+        ctx.accounts.token_manager.suspended_modules = Vec::new();
+        ctx.accounts.token_manager.clock_tolerance_seconds = 0;
+        ctx.accounts.token_manager.penalty_withholding_bps = 0;
+        ctx.accounts.token_manager.current_token_index = 0;
+        ctx.accounts.token_manager.creator = authority;
+        ctx.accounts.token_manager.original_creator = authority;
+        ctx.accounts.token_manager.pending_creator = None;
+        ctx.accounts.token_manager.lockdown_guardian = None;
+        ctx.accounts.token_manager.locked_down_at = None;
+        ctx.accounts.token_manager.platform = Some(ctx.accounts.platform.program);
         Ok(())
     }
 
     #[derive(Accounts)]
+    #[instruction(decimals: u8, isin: Isin)]
     pub struct CreateNewShare<'info> {
         /// The wallet signing and paying for the transaction
         #[account(mut)]
         pub signer: Signer<'info>,
 
-        /// Account storing token metadata and whitelist information
+        /// Account storing the token index counter
         #[account(
         mut,
-        seeds = [b"token-manager", signer.key().as_ref()],
+        seeds = [b"token-manager", token_manager.original_creator.as_ref()],
         bump,
         )]
         pub token_manager: Account<'info, TokenManager>,
@@ -92,6 +354,26 @@ pub mod token_manager {
         )]
         pub extra_account_meta_list: AccountInfo<'info>,
 
+        /// Configuration of the new share
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + TokenShare::INIT_SPACE,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Whitelist of the new share
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + TokenWhitelist::INIT_SPACE,
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
         /// Token program interface for SPL Token 2022
         pub token_program: Program<'info, Token2022>,
 
@@ -107,461 +389,18841 @@ pub mod token_manager {
     ///
     /// * `decimals` - The number of decimals for the token mint.
     /// * `isin` - The unique ISIN code identifier for the token.
+    /// * `max_supply` - Supply issuances may never exceed, `None` for no cap.
     pub fn create_new_share(
         ctx: Context<CreateNewShare>,
         decimals: u8,
-        isin: String,
-    ) -> Result<()> {
-        // Validate ISIN format (should be 12 characters)
-        if isin.len() != 12 {
-            return Err(error!(TokenManagerError::InvalidIsinLength));
-        }
-
-        // 1. Calculate required space for mint with all extensions and metadata
-        let name = format!("Security Token {}", isin);
-        let symbol = isin.clone();
-        let uri = String::new();
-
-        // Calculate space with embedded metadata
-        let token_space =
-            ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&[
-                ExtensionType::TransferHook,
-                ExtensionType::MetadataPointer,
-            ])
-            .expect("Failed to calculate space");
-        let metadata_space = calculate_metadata_space(&name, &symbol, &uri);
-        let total_space = token_space + metadata_space;
-
-        // 2. Calculate rent exemption
-        let rent = Rent::get()?;
-        let lamports = rent.minimum_balance(total_space);
-
-        // 3. Get PDA seeds from Anchor's context
-        let token_mint_bump = ctx.bumps.token_mint;
-        let token_manager = ctx.accounts.token_manager.key();
-        let token_mint_seeds = &[
-            b"token-mint",
-            token_manager.as_ref(),
-            &ctx.accounts.token_manager.current_token_index.to_le_bytes(),
-            &[token_mint_bump],
-        ];
-        let token_mint_signer = &[&token_mint_seeds[..]];
+        isin: Isin,
+        max_supply: Option<u64>,
+        additional_metadata: Option<Vec<MetadataPair>>,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
 
-        // 4. Create the mint account
-        let token_mint_key = &ctx.accounts.token_mint.key();
+        isin.validate()?;
 
-        invoke_signed(
-            &system_instruction::create_account(
-                &ctx.accounts.signer.key(),
-                token_mint_key,
-                lamports,
-                token_space as u64,
-                &ctx.accounts.token_program.key(),
-            ),
-            &[
-                ctx.accounts.signer.to_account_info(),
-                ctx.accounts.token_mint.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-            ],
-            token_mint_signer,
+        create_share_mint(
+            ShareMintAccounts {
+                signer: ctx.accounts.signer.to_account_info(),
+                token_mint: ctx.accounts.token_mint.to_account_info(),
+                token_mint_bump: ctx.bumps.token_mint,
+                extra_account_meta_list: ctx.accounts.extra_account_meta_list.to_account_info(),
+                extra_account_meta_list_bump: ctx.bumps.extra_account_meta_list,
+                token_program: ctx.accounts.token_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            },
+            &mut ctx.accounts.token_manager,
+            &mut ctx.accounts.token_share,
+            &mut ctx.accounts.token_whitelist,
+            decimals,
+            isin,
+            &additional_metadata.unwrap_or_default(),
         )?;
+        ctx.accounts.token_share.max_supply = max_supply;
+        Ok(())
+    }
 
-        // 5. Initialize extensions first
+    #[derive(Accounts)]
+    #[instruction(args: CreateShareArgs)]
+    pub struct CreateShare<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
 
-        // Initialize TransferHook extension
-        let transfer_hook_ix = spl_token_2022::extension::transfer_hook::instruction::initialize(
-            &ctx.accounts.token_program.key(),
-            token_mint_key,
-            Some(ctx.accounts.token_manager.key()),
-            Some(*ctx.program_id),
-        )?;
+        /// Account storing the token index counter
+        #[account(
+        mut,
+        seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+        bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
 
-        invoke(
-            &transfer_hook_ix,
-            &[
-                ctx.accounts.token_mint.to_account_info(),
-                ctx.accounts.extra_account_meta_list.to_account_info(),
-                ctx.accounts.token_program.to_account_info(),
-            ],
-        )?;
+        /// The SPL token mint being created for this share
+        /// Uses token-mint + token_manager + index as seeds
+        #[account(
+        mut,
+        seeds = [b"token-mint", token_manager.key().as_ref(), &token_manager.current_token_index.to_le_bytes()],
+        bump,
+        )]
+        /// CHECK: This is initialized within the instruction
+        pub token_mint: AccountInfo<'info>,
 
-        // Initialize MetadataPointer extension
-        let metadata_pointer_ix =
-            spl_token_2022::extension::metadata_pointer::instruction::initialize(
-                &ctx.accounts.token_program.key(),
-                token_mint_key,
-                Some(*token_mint_key),
-                Some(*token_mint_key),
-            )?;
+        /// Account storing metadata for SPL's transfer hook
+        /// Lists additional accounts to pass during transfers
+        /// CHECK: This account is verified in the CreateShare implementation
+        #[account(
+        mut,
+        seeds = [b"extra-account-metas", token_mint.key().as_ref()],
+        bump,
+        )]
+        pub extra_account_meta_list: AccountInfo<'info>,
 
-        invoke(
-            &metadata_pointer_ix,
-            &[
-                ctx.accounts.token_mint.to_account_info(),
-            ],
-        )?;
+        /// Configuration of the new share
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + TokenShare::INIT_SPACE,
+            seeds = [b"token-share", token_manager.key().as_ref(), args.isin().as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
 
-        // 6. Now initialize the basic mint
-        let init_mint_ix = spl_token_2022::instruction::initialize_mint2(
-            &ctx.accounts.token_program.key(),
-            token_mint_key,
-            token_mint_key,
-            Some(token_mint_key),
+        /// Whitelist of the new share
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + TokenWhitelist::INIT_SPACE,
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), args.isin().as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// Token program interface for SPL Token 2022
+        pub token_program: Program<'info, Token2022>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Creates a new token share like `create_new_share`, from arguments tagged with
+    /// their layout version. New optional parameters are added as a new version of
+    /// `CreateShareArgs`, so clients built against an older version keep working
+    /// unchanged.
+    pub fn create_share(ctx: Context<CreateShare>, args: CreateShareArgs) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let (decimals, isin, max_supply, additional_metadata) = args.into_latest();
+        isin.validate()?;
+
+        create_share_mint(
+            ShareMintAccounts {
+                signer: ctx.accounts.signer.to_account_info(),
+                token_mint: ctx.accounts.token_mint.to_account_info(),
+                token_mint_bump: ctx.bumps.token_mint,
+                extra_account_meta_list: ctx.accounts.extra_account_meta_list.to_account_info(),
+                extra_account_meta_list_bump: ctx.bumps.extra_account_meta_list,
+                token_program: ctx.accounts.token_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            },
+            &mut ctx.accounts.token_manager,
+            &mut ctx.accounts.token_share,
+            &mut ctx.accounts.token_whitelist,
             decimals,
+            isin,
+            &additional_metadata,
         )?;
+        ctx.accounts.token_share.max_supply = max_supply;
+        Ok(())
+    }
 
-        invoke(
-            &init_mint_ix,
-            &[
-                ctx.accounts.token_mint.to_account_info(),
-            ],
-        )?;
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct TransferHookAuthority<'info> {
+        /// The wallet signing the transaction
+        pub signer: Signer<'info>,
 
-        // Initialize TokenMetadata extension
-        let token_metadata_ix = spl_token_metadata_interface::instruction::initialize(
-            &ctx.accounts.token_program.key(),
-            token_mint_key,
-            token_mint_key,
-            token_mint_key,
-            token_mint_key,
-            name.clone(),
-            symbol.clone(),
-            uri.clone(),
-        );
+        /// The token manager, current authority of the transfer hook
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token whose hook is updated
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The mint of the token
+        #[account(mut, address = token_share.mint @ TokenManagerError::InvalidTokenAccount)]
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Token program interface for SPL Token 2022
+        pub token_program: Program<'info, Token2022>,
+    }
+
+    /// Hands the authority over the transfer hook of a token identified by its ISIN
+    /// to `new_authority`, or drops it for good when `None`. The token manager can
+    /// no longer update or remove the hook afterwards.
+    pub fn set_transfer_hook_authority(
+        ctx: Context<TransferHookAuthority>,
+        _isin: Isin,
+        new_authority: Option<Pubkey>,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
 
+        let set_authority_ix = spl_token_2022::instruction::set_authority(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.token_mint.key(),
+            new_authority.as_ref(),
+            spl_token_2022::instruction::AuthorityType::TransferHookProgramId,
+            &ctx.accounts.token_manager.key(),
+            &[],
+        )?;
         invoke_signed(
-            &token_metadata_ix,
+            &set_authority_ix,
             &[
                 ctx.accounts.token_mint.to_account_info(),
+                ctx.accounts.token_manager.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
             ],
-            token_mint_signer,
+            &[&[
+                b"token-manager",
+                ctx.accounts.token_manager.original_creator.as_ref(),
+                &[ctx.bumps.token_manager],
+            ]],
         )?;
 
-        // 7. Create and initialize the extra account meta list for transfer hooks
-        let account_metas = vec![ExtraAccountMeta::new_with_pubkey(
-            &ctx.accounts.token_manager.key(),
-            false, // is_signer
-            false, // is_writable
-        )?];
+        emit!(TransferHookAuthorityChanged {
+            mint: ctx.accounts.token_mint.key(),
+            new_authority,
+        });
+        Ok(())
+    }
 
-        // Calculate account size for meta list
-        let account_size = ExtraAccountMetaList::size_of(account_metas.len())?;
-        let meta_list_lamports = rent.minimum_balance(account_size);
+    /// Unsets the transfer hook program of a token identified by its ISIN, e.g. when
+    /// winding down a deregistered instrument to plain transfers. Transfers are no
+    /// longer checked against any rule of the token manager afterwards.
+    pub fn remove_transfer_hook(ctx: Context<TransferHookAuthority>, _isin: Isin) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
 
-        // Create the account for the meta list
-        let meta_list_seeds = &[
-            b"extra-account-metas",
-            token_mint_key.as_ref(),
-            &[ctx.bumps.extra_account_meta_list],
-        ];
-        let meta_list_signer = &[&meta_list_seeds[..]];
+        let update_ix = spl_token_2022::extension::transfer_hook::instruction::update(
+            &ctx.accounts.token_program.key(),
+            &ctx.accounts.token_mint.key(),
+            &ctx.accounts.token_manager.key(),
+            &[],
+            None,
+        )?;
         invoke_signed(
-            &system_instruction::create_account(
-                &ctx.accounts.signer.key(),
-                &ctx.accounts.extra_account_meta_list.key(),
-                meta_list_lamports,
-                account_size as u64,
-                ctx.program_id,
-            ),
+            &update_ix,
             &[
-                ctx.accounts.signer.to_account_info(),
-                ctx.accounts.extra_account_meta_list.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.token_mint.to_account_info(),
+                ctx.accounts.token_manager.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
             ],
-            meta_list_signer,
+            &[&[
+                b"token-manager",
+                ctx.accounts.token_manager.original_creator.as_ref(),
+                &[ctx.bumps.token_manager],
+            ]],
         )?;
 
-        // Initialize the meta list data
-        let mut data = ctx.accounts.extra_account_meta_list.try_borrow_mut_data()?;
-        ExtraAccountMetaList::init::<ExecuteInstruction>(&mut data, &account_metas)?;
-
-        // 9. Store the token in the token manager
-        let current_index = ctx.accounts.token_manager.current_token_index.clone();
-        ctx.accounts.token_manager.tokens.push(TokenShare {
-            mint: *token_mint_key,
-            isin: isin,
-            index: current_index,
+        emit!(TransferHookRemoved {
+            mint: ctx.accounts.token_mint.key(),
         });
-        ctx.accounts.token_manager.current_token_index = current_index
-        .checked_add(1)
-        .ok_or(error!(TokenManagerError::IndexOverflow))?;
-
         Ok(())
     }
 
     #[derive(Accounts)]
-    pub struct Whitelist<'info> {
-        /// The wallet signing the transaction
+    #[instruction(isin: Isin)]
+    pub struct UpdateTokenMetadata<'info> {
+        /// The creator of the token manager, paying for the metadata growth
         #[account(mut)]
         pub signer: Signer<'info>,
 
-        /// The account containing the whitelist to be modified
-        /// Only the creator should modify the whitelist
+        /// The token manager of the token
         #[account(
-        mut,
-        seeds = [b"token-manager", signer.key().as_ref()],
-        bump,
-    )]
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
         pub token_manager: Account<'info, TokenManager>,
-    }
 
-    /// Adds a wallet authorization to the whitelist for a token identified by its ISIN.
-    pub fn add_to_whitelist(ctx: Context<Whitelist>, wallet: Pubkey, isin: String) -> Result<()> {
-        // Verify the signer is the creator of the token manager
-        if ctx.accounts.signer.key() != ctx.accounts.token_manager.creator {
-            return Err(error!(TokenManagerError::Unauthorized));
-        }
+        /// Configuration of the token whose metadata is updated
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
 
-        // Check if the whitelist is full
-        if ctx.accounts.token_manager.whitelist.len() >= 10 {
-            return Err(error!(TokenManagerError::WhitelistFull));
-        }
+        /// The mint of the token, holding its metadata and update authority over it
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub token_mint: InterfaceAccount<'info, Mint>,
 
-        if let Some(token) = &ctx
-            .accounts
-            .token_manager
-            .tokens
-            .iter()
-            .find(|token| token.isin == isin)
-        {
-            let authorization = Authorization {
-                mint: token.mint,
-                wallet: wallet,
-            };
-            ctx.accounts.token_manager.whitelist.push(authorization);
-            return Ok(());
-        }
-        return Err(error!(TokenManagerError::TokenNotFound));
+        pub token_program: Program<'info, Token2022>,
+        pub system_program: Program<'info, System>,
     }
 
-    /// Removes a wallet authorization from the whitelist.
-    pub fn remove_from_whitelist(
-        ctx: Context<Whitelist>,
-        wallet: Pubkey,
-        isin: String,
+    /// Sets `field` of the metadata of a token identified by its ISIN to `value`,
+    /// e.g. to point the URI at a new prospectus. Custom fields are added when
+    /// missing. The signer pays for the rent of a larger metadata.
+    pub fn update_token_metadata(
+        ctx: Context<UpdateTokenMetadata>,
+        _isin: Isin,
+        field: MetadataField,
+        value: String,
     ) -> Result<()> {
-        // Verify the signer is the creator of the token manager
-        if ctx.accounts.signer.key() != ctx.accounts.token_manager.creator {
-            return Err(error!(TokenManagerError::Unauthorized));
-        }
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
 
-        if let Some(token) = &ctx
-            .accounts
-            .token_manager
-            .tokens
-            .iter()
-            .find(|token| token.isin == isin)
-        {
-            if let Some(index) = &ctx
-                .accounts
-                .token_manager
-                .whitelist
-                .iter()
-                .position(|auth| auth.mint == token.mint && auth.wallet == wallet)
-            {
-                ctx.accounts.token_manager.whitelist.remove(*index);
-                return Ok(());
-            }
-            return Err(error!(TokenManagerError::WalletNotFound));
-        }
-        Err(error!(TokenManagerError::TokenNotFound))
+        let token_index_bytes = ctx.accounts.token_share.index.to_le_bytes();
+        let token_manager_key = ctx.accounts.token_manager.key();
+        let token_mint_seeds = &[
+            b"token-mint".as_ref(),
+            token_manager_key.as_ref(),
+            &token_index_bytes,
+            &[ctx.bumps.token_mint],
+        ];
+        let token_mint = ctx.accounts.token_mint.to_account_info();
+        update_metadata_field(
+            &ctx.accounts.token_program.to_account_info(),
+            &token_mint,
+            &[&token_mint_seeds[..]],
+            field.clone(),
+            value.clone(),
+        )?;
+        top_up_rent(
+            &token_mint,
+            &ctx.accounts.signer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        emit!(TokenMetadataUpdated {
+            mint: token_mint.key(),
+            field,
+            value,
+        });
+        Ok(())
     }
 
     #[derive(Accounts)]
-    pub struct TransferHook<'info> {
-        /// The token account sending tokens
-        /// Must have the specified mint and be owned by owner
-        #[account(
-        token::mint = mint,
-        token::authority = owner,
-    )]
-        pub source_token: InterfaceAccount<'info, TokenAccount>,
-
-        /// The mint of the token being transferred
-        pub mint: InterfaceAccount<'info, Mint>,
+    #[instruction(isin: Isin)]
+    pub struct PushReferenceData<'info> {
+        /// The creator of the token manager or a data provider, paying for the
+        /// metadata growth
+        #[account(mut)]
+        pub signer: Signer<'info>,
 
-        /// The token account receiving tokens
-        /// Must have the specified mint
+        /// The token manager of the token
         #[account(
-        token::mint = mint,
-    )]
-        pub destination_token: InterfaceAccount<'info, TokenAccount>,
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
 
-        /// The authority (owner) of the source token account
-        /// The program verifies if this wallet is whitelisted
-        /// CHECK: This account is verified in the TransferHook implementation
-        pub owner: UncheckedAccount<'info>,
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the PushReferenceData implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
 
-        /// Account containing extra metadata for the transfer hook
-        /// Created by SPL Token 2022 program
-        /// CHECK: This account is verified in the TransferHook implementation
+        /// Configuration of the token whose metadata is updated
         #[account(
-        seeds = [b"extra-account-metas", mint.key().as_ref()],
-        bump)
-    ]
-        pub extra_account_meta_list: AccountInfo<'info>,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
 
-        /// Account storing the whitelist of authorized wallets
-        /// Used to validate if the owner can transfer tokens
+        /// The mint of the token, holding its metadata and update authority over it
         #[account(
-        seeds = [b"token-manager", token_manager.creator.as_ref()],
-        bump,
-    )]
-        pub token_manager: Account<'info, TokenManager>,
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        pub token_program: Program<'info, Token2022>,
+        pub system_program: Program<'info, System>,
     }
 
-    #[interface(spl_transfer_hook_interface::execute)]
-    pub fn transfer_hook(ctx: Context<TransferHook>) -> Result<()> {
-        let mint_key = ctx.accounts.mint.key();
-        let destination_owner = ctx.accounts.destination_token.owner;
+    /// Writes the standardized reference data of a token identified by its ISIN
+    /// into custom fields of its metadata, so that it stays aligned with the ISIN
+    /// database of the numbering agency. Meant for an authorized data feed holding
+    /// the `DataProvider` role; fields left `None` are not changed.
+    pub fn push_reference_data(
+        ctx: Context<PushReferenceData>,
+        _isin: Isin,
+        data: ReferenceData,
+    ) -> Result<()> {
+        require_role(
+            &ctx.accounts.token_manager,
+            &ctx.accounts.signer.key(),
+            &ctx.accounts.role_assignment,
+            Role::DataProvider,
+        )?;
+        let fields = data.fields()?;
 
-        if let Some(_) = &ctx
-            .accounts
-            .token_manager
-            .whitelist
-            .iter()
-            .find(|auth| auth.mint == mint_key && auth.wallet == destination_owner)
-        {
-            return Ok(());
+        let token_index_bytes = ctx.accounts.token_share.index.to_le_bytes();
+        let token_manager_key = ctx.accounts.token_manager.key();
+        let token_mint_seeds = &[
+            b"token-mint".as_ref(),
+            token_manager_key.as_ref(),
+            &token_index_bytes,
+            &[ctx.bumps.token_mint],
+        ];
+        let token_mint = ctx.accounts.token_mint.to_account_info();
+        for (key, value) in fields {
+            let field = MetadataField::Key(key.to_string());
+            update_metadata_field(
+                &ctx.accounts.token_program.to_account_info(),
+                &token_mint,
+                &[&token_mint_seeds[..]],
+                field.clone(),
+                value.clone(),
+            )?;
+            emit!(TokenMetadataUpdated {
+                mint: token_mint.key(),
+                field,
+                value,
+            });
         }
-
-        Err(error!(TokenManagerError::TransferNotAllowed))
+        top_up_rent(
+            &token_mint,
+            &ctx.accounts.signer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )
     }
 
-    /// Structure for the mint_tokens instruction
     #[derive(Accounts)]
-    #[instruction(token_index: u64)]
-    pub struct MintToken<'info> {
-        /// The wallet signing the transaction
+    #[instruction(wallet: Pubkey, isin: Isin)]
+    pub struct AddToWhitelist<'info> {
+        /// The creator of the token manager or a compliance officer
         #[account(mut)]
         pub signer: Signer<'info>,
 
-        /// Account storing token metadata and whitelist information
+        /// The token manager of the token
         #[account(
-            mut,
-            seeds = [b"token-manager", signer.key().as_ref()],
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
             bump,
         )]
         pub token_manager: Account<'info, TokenManager>,
 
-        /// The token mint - with seeds derived from token-manager + index
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the AddToWhitelist implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+
+        /// Configuration of the token, telling whether additions need a second officer
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The whitelist to be modified
         #[account(
             mut,
-            seeds = [b"token-mint", token_manager.key().as_ref(), &token_index.to_le_bytes()],
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
             bump,
         )]
-        pub token_mint: InterfaceAccount<'info, Mint>,
+        pub token_whitelist: Account<'info, TokenWhitelist>,
 
-        /// The account receiving the tokens
-        #[account(mut)]
-        pub destination: InterfaceAccount<'info, TokenAccount>,
+        /// Authorization of the wallet, created by this instruction
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + WhitelistEntry::INIT_SPACE,
+            seeds = [b"whitelist", token_whitelist.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_entry: Account<'info, WhitelistEntry>,
 
-        /// The Token 2022 program
-        pub token_program: Program<'info, Token2022>,
+        /// Membership periods of the wallet, kept after removal
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + WhitelistHistory::INIT_SPACE,
+            seeds = [b"whitelist-history", token_manager.key().as_ref(), isin.as_bytes(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_history: Account<'info, WhitelistHistory>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
     }
 
-    pub fn mint_tokens(ctx: Context<MintToken>, token_index: u64, amount: u64) -> Result<()> {
-        // Verify the signer is the creator of the token manager
-        if ctx.accounts.signer.key() != ctx.accounts.token_manager.creator {
-            return Err(error!(TokenManagerError::Unauthorized));
-        }
+    #[derive(Accounts)]
+    #[instruction(wallet: Pubkey, isin: Isin)]
+    pub struct RemoveFromWhitelist<'info> {
+        /// The creator of the token manager or a compliance officer, refunded the
+        /// rent of the entry
+        #[account(mut)]
+        pub signer: Signer<'info>,
 
-        let token_mint_bump = ctx.bumps.token_mint;
-        let token_manager_key = ctx.accounts.token_manager.key();
-        let token_mint_seeds = &[
-            b"token-mint",
-            token_manager_key.as_ref(),
-            &token_index.to_le_bytes(),
-            &[token_mint_bump],
-        ];
-        let token_mint_signer = &[&token_mint_seeds[..]];
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
 
-        let cpi_accounts = MintTo {
-            mint: ctx.accounts.token_mint.to_account_info(),
-            to: ctx.accounts.destination.to_account_info(),
-            authority: ctx.accounts.token_mint.to_account_info(),
-        };
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the RemoveFromWhitelist implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
 
-        mint_to(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                cpi_accounts,
-                token_mint_signer,
-            ),
-            amount,
+        /// The whitelist to be modified
+        #[account(
+            mut,
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// Authorization of the wallet, closed by this instruction
+        /// CHECK: May not exist; deserialized in the RemoveFromWhitelist implementation
+        #[account(
+            mut,
+            seeds = [b"whitelist", token_whitelist.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_entry: UncheckedAccount<'info>,
+
+        /// Membership periods of the wallet, kept after removal
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + WhitelistHistory::INIT_SPACE,
+            seeds = [b"whitelist-history", token_manager.key().as_ref(), isin.as_bytes(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_history: Account<'info, WhitelistHistory>,
+
+        /// Circuit breaker of the token, counting whitelist removals
+        /// CHECK: May not exist; deserialized in the RemoveFromWhitelist implementation
+        #[account(
+            mut,
+            seeds = [b"circuit-breaker", token_whitelist.mint.as_ref()],
+            bump,
+        )]
+        pub circuit_breaker: UncheckedAccount<'info>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Adds a wallet authorization to the whitelist for a token identified by its ISIN.
+    /// The transfer hook blocks the wallet from `expires_at` on, when its KYC
+    /// lapses, unless the authorization is renewed; 0 never expires. Tokens that
+    /// require four-eyes approval only accept additions through
+    /// `propose_whitelist_addition` and `confirm_whitelist_addition`.
+    pub fn add_to_whitelist(
+        ctx: Context<AddToWhitelist>,
+        wallet: Pubkey,
+        _isin: Isin,
+        expires_at: i64,
+    ) -> Result<()> {
+        require_role(
+            &ctx.accounts.token_manager,
+            &ctx.accounts.signer.key(),
+            &ctx.accounts.role_assignment,
+            Role::ComplianceOfficer,
         )?;
+        if ctx.accounts.token_share.whitelist_approval_window.is_some() {
+            return Err(error!(TokenManagerError::WhitelistApprovalRequired));
+        }
 
+        let token_whitelist = &mut ctx.accounts.token_whitelist;
+        let mint = token_whitelist.mint;
+        token_whitelist.count += 1;
+        ctx.accounts.whitelist_entry.set_inner(WhitelistEntry {
+            mint,
+            wallet,
+            corridor: None,
+            jurisdiction: [0; 2],
+            investor_class: 0,
+            expires_at,
+            _reserved: [0; 24],
+        });
+        emit!(WhitelistEntryAdded { mint, wallet });
+        let slot = now(ctx.remaining_accounts)?.slot;
+        ctx.accounts
+            .whitelist_history
+            .record_added(mint, wallet, slot);
         Ok(())
     }
-}
 
-// Calculate metadata space based on actual content
-fn calculate_metadata_space(name: &String, symbol: &String, uri: &String) -> usize {
-    // Base metadata header size (approximate)
-    let header_size = 32;
+    /// Removes a wallet authorization from the whitelist.
+    pub fn remove_from_whitelist(
+        ctx: Context<RemoveFromWhitelist>,
+        wallet: Pubkey,
+        _isin: Isin,
+    ) -> Result<()> {
+        require_role(
+            &ctx.accounts.token_manager,
+            &ctx.accounts.signer.key(),
+            &ctx.accounts.role_assignment,
+            Role::ComplianceOfficer,
+        )?;
 
-    // Space for each field includes:
-    // - Field type identifier (1 byte)
-    // - Length prefix (typically 4 bytes)
-    // - The string content itself
-    // - Potential padding for alignment (up to 8 bytes worst case)
+        let entry_info = ctx.accounts.whitelist_entry.to_account_info();
+        if load_optional_account::<WhitelistEntry>(&entry_info)?.is_some() {
+            let token_whitelist = &mut ctx.accounts.token_whitelist;
+            let mint = token_whitelist.mint;
+            token_whitelist.count -= 1;
+            close_program_account(&entry_info, &ctx.accounts.signer.to_account_info())?;
+            emit!(WhitelistEntryRemoved { mint, wallet });
+            log_whitelist_removed(&mint, &wallet);
+            let slot = now(ctx.remaining_accounts)?.slot;
+            ctx.accounts.whitelist_history.record_removed(slot);
+            count_anomaly(
+                &ctx.accounts.circuit_breaker,
+                AnomalyKind::WhitelistRemoval,
+                1,
+                slot,
+            )?;
+            return Ok(());
+        }
+        Err(error!(TokenManagerError::WalletNotFound))
+    }
 
-    let name_size = 1 + 4 + name.len() + 8;
-    let symbol_size = 1 + 4 + symbol.len() + 8;
-    let uri_size = 1 + 4 + uri.len() + 8;
+    #[derive(Accounts)]
+    #[instruction(wallet: Pubkey, isin: Isin)]
+    pub struct ProposeWhitelistAddition<'info> {
+        /// The compliance officer proposing the addition, paying for the proposal
+        #[account(mut)]
+        pub signer: Signer<'info>,
 
-    // Add some buffer for additional metadata fields that might be added
-    // (e.g., standard fields like "decimals" or custom fields)
-    let additional_fields_buffer = 256;
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
 
-    header_size + name_size + symbol_size + uri_size + additional_fields_buffer
-}
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the ProposeWhitelistAddition implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
 
-#[account]
-#[derive(InitSpace)]
-pub struct TokenShare {
-    pub index: u64,
-    #[max_len(12)]
-    pub isin: String,
-    pub mint: Pubkey,
-}
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
 
-#[account]
-#[derive(InitSpace)]
-pub struct Authorization {
-    pub mint: Pubkey,
-    pub wallet: Pubkey,
-}
+        /// The proposal, replaced when the wallet was already proposed
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + WhitelistProposal::INIT_SPACE,
+            seeds = [b"whitelist-proposal", token_share.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_proposal: Account<'info, WhitelistProposal>,
 
-#[account]
-#[derive(InitSpace)]
-pub struct TokenManager {
-    pub creator: Pubkey,
-    pub current_token_index: u64,
-    #[max_len(10)]
-    pub tokens: Vec<TokenShare>,
-    #[max_len(10)]
-    pub whitelist: Vec<Authorization>,
-}
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
 
-#[error_code]
-pub enum TokenManagerError {
-    #[msg("Token not found")]
-    TokenNotFound,
-    #[msg("Wallet not found")]
-    WalletNotFound,
-    #[msg("Transfer not allowed")]
-    TransferNotAllowed,
-    #[msg("Failed to initialize transfer hook")]
-    TransferHookInitFailed,
-    #[msg("Invalid token account")]
-    InvalidTokenAccount,
-    #[msg("Invalid ISIN length")]
-    InvalidIsinLength,
-    #[msg("Unauthorized operation")]
-    Unauthorized,
-    #[msg("Index overflow")]
-    IndexOverflow,
-    #[msg("Whitelist is full")]
-    WhitelistFull,
+    /// Proposes to whitelist a wallet for a token identified by its ISIN, as the
+    /// first of the two officers four-eyes approval requires. The addition takes
+    /// effect once another officer confirms it with `confirm_whitelist_addition`;
+    /// proposing the wallet again restarts the approval.
+    pub fn propose_whitelist_addition(
+        ctx: Context<ProposeWhitelistAddition>,
+        wallet: Pubkey,
+        _isin: Isin,
+        expires_at: i64,
+    ) -> Result<()> {
+        require_role(
+            &ctx.accounts.token_manager,
+            &ctx.accounts.signer.key(),
+            &ctx.accounts.role_assignment,
+            Role::ComplianceOfficer,
+        )?;
+
+        let mint = ctx.accounts.token_share.mint;
+        let proposer = ctx.accounts.signer.key();
+        ctx.accounts
+            .whitelist_proposal
+            .set_inner(WhitelistProposal {
+                mint,
+                wallet,
+                proposer,
+                expires_at,
+                proposed_at: now(ctx.remaining_accounts)?.unix_timestamp,
+            });
+        emit!(WhitelistAdditionProposed {
+            mint,
+            wallet,
+            proposer,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(wallet: Pubkey, isin: Isin)]
+    pub struct ConfirmWhitelistAddition<'info> {
+        /// The compliance officer confirming the addition, paying for the entry
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the ConfirmWhitelistAddition implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+
+        /// Configuration of the token, holding the approval window
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The whitelist to be modified
+        #[account(
+            mut,
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// The confirmed proposal, closed by this instruction
+        #[account(
+            mut,
+            close = proposer,
+            seeds = [b"whitelist-proposal", token_share.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_proposal: Account<'info, WhitelistProposal>,
+
+        /// The officer who proposed the addition, refunded the rent of the proposal
+        /// CHECK: Must be the proposer recorded in the proposal
+        #[account(mut, address = whitelist_proposal.proposer)]
+        pub proposer: UncheckedAccount<'info>,
+
+        /// Authorization of the wallet, created by this instruction
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + WhitelistEntry::INIT_SPACE,
+            seeds = [b"whitelist", token_whitelist.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_entry: Account<'info, WhitelistEntry>,
+
+        /// Membership periods of the wallet, kept after removal
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + WhitelistHistory::INIT_SPACE,
+            seeds = [b"whitelist-history", token_manager.key().as_ref(), isin.as_bytes(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_history: Account<'info, WhitelistHistory>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Confirms the whitelist addition proposed for a wallet, as an officer other
+    /// than its proposer, within the approval window of the token. The wallet is
+    /// then whitelisted like `add_to_whitelist` does.
+    pub fn confirm_whitelist_addition(
+        ctx: Context<ConfirmWhitelistAddition>,
+        wallet: Pubkey,
+        _isin: Isin,
+    ) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        require_role(
+            &ctx.accounts.token_manager,
+            &signer,
+            &ctx.accounts.role_assignment,
+            Role::ComplianceOfficer,
+        )?;
+        let proposal = &ctx.accounts.whitelist_proposal;
+        if signer == proposal.proposer {
+            return Err(error!(TokenManagerError::ApproverNotDistinct));
+        }
+        let now = now(ctx.remaining_accounts)?;
+        if let Some(window) = ctx.accounts.token_share.whitelist_approval_window {
+            if now.may_have_passed(
+                proposal.proposed_at.saturating_add(i64::from(window)),
+                ctx.accounts.token_manager.clock_tolerance_seconds,
+            ) {
+                return Err(error!(TokenManagerError::WhitelistProposalExpired));
+            }
+        }
+
+        let expires_at = proposal.expires_at;
+        let token_whitelist = &mut ctx.accounts.token_whitelist;
+        let mint = token_whitelist.mint;
+        token_whitelist.count += 1;
+        ctx.accounts.whitelist_entry.set_inner(WhitelistEntry {
+            mint,
+            wallet,
+            corridor: None,
+            jurisdiction: [0; 2],
+            investor_class: 0,
+            expires_at,
+            _reserved: [0; 24],
+        });
+        emit!(WhitelistEntryAdded { mint, wallet });
+        ctx.accounts
+            .whitelist_history
+            .record_added(mint, wallet, now.slot);
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(wallets: Vec<Pubkey>, isin: Isin)]
+    pub struct AddManyToWhitelist<'info> {
+        /// The creator of the token manager or a compliance officer
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the AddManyToWhitelist implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+
+        /// Configuration of the token, telling whether additions need a second officer
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The whitelist to be modified
+        #[account(
+            mut,
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    #[derive(Accounts)]
+    #[instruction(wallets: Vec<Pubkey>, isin: Isin)]
+    pub struct RemoveManyFromWhitelist<'info> {
+        /// The creator of the token manager or a compliance officer, refunded the
+        /// rent of the entries
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the RemoveManyFromWhitelist implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+
+        /// The whitelist to be modified
+        #[account(
+            mut,
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// Circuit breaker of the token, counting whitelist removals
+        /// CHECK: May not exist; deserialized in the RemoveManyFromWhitelist implementation
+        #[account(
+            mut,
+            seeds = [b"circuit-breaker", token_whitelist.mint.as_ref()],
+            bump,
+        )]
+        pub circuit_breaker: UncheckedAccount<'info>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Adds up to `constants::MAX_WHITELIST_BATCH` wallets to the whitelist of a
+    /// token identified by its ISIN, like `add_to_whitelist` does for one.
+    ///
+    /// Each wallet is passed as two remaining accounts, in the order of `wallets`:
+    /// its `WhitelistEntry` address and its `WhitelistHistory` address. Wallets
+    /// that are already whitelisted are skipped rather than failing the batch, so
+    /// a batch can be resubmitted after a partial failure; a `WhitelistEntryAdded`
+    /// event is emitted for every wallet added and the number added is returned.
+    /// All the authorizations expire at `expires_at`.
+    pub fn add_many_to_whitelist<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AddManyToWhitelist<'info>>,
+        wallets: Vec<Pubkey>,
+        isin: Isin,
+        expires_at: i64,
+    ) -> Result<u32> {
+        require_role(
+            &ctx.accounts.token_manager,
+            &ctx.accounts.signer.key(),
+            &ctx.accounts.role_assignment,
+            Role::ComplianceOfficer,
+        )?;
+        if ctx.accounts.token_share.whitelist_approval_window.is_some() {
+            return Err(error!(TokenManagerError::WhitelistApprovalRequired));
+        }
+        if wallets.is_empty()
+            || wallets.len() > constants::MAX_WHITELIST_BATCH as usize
+            || ctx.remaining_accounts.len() < 2 * wallets.len()
+        {
+            return Err(error!(TokenManagerError::InvalidWhitelistBatch));
+        }
+
+        let token_manager_key = ctx.accounts.token_manager.key();
+        let mint = ctx.accounts.token_whitelist.mint;
+        let slot = now(ctx.remaining_accounts)?.slot;
+        let payer = ctx.accounts.signer.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let mut added = 0;
+        for (wallet, accounts) in wallets.iter().zip(ctx.remaining_accounts.chunks_exact(2)) {
+            let [entry_info, history_info] = accounts else {
+                return Err(error!(TokenManagerError::InvalidWhitelistBatch));
+            };
+            let entry_seeds: &[&[u8]] = &[b"whitelist", mint.as_ref(), wallet.as_ref()];
+            let history_seeds: &[&[u8]] = &[
+                b"whitelist-history",
+                token_manager_key.as_ref(),
+                isin.as_bytes(),
+                wallet.as_ref(),
+            ];
+            let (entry_address, entry_bump) = Pubkey::find_program_address(entry_seeds, &crate::ID);
+            let (history_address, history_bump) =
+                Pubkey::find_program_address(history_seeds, &crate::ID);
+            if entry_info.key() != entry_address || history_info.key() != history_address {
+                return Err(error!(TokenManagerError::InvalidWhitelistBatch));
+            }
+            if load_optional_account::<WhitelistEntry>(entry_info)?.is_some() {
+                continue;
+            }
+
+            create_program_account(
+                entry_info,
+                &payer,
+                &system_program,
+                &[entry_seeds, &[&[entry_bump]]].concat(),
+                8 + WhitelistEntry::INIT_SPACE,
+            )?;
+            WhitelistEntry {
+                mint,
+                wallet: *wallet,
+                corridor: None,
+                jurisdiction: [0; 2],
+                investor_class: 0,
+                expires_at,
+                _reserved: [0; 24],
+            }
+            .try_serialize(&mut &mut entry_info.try_borrow_mut_data()?[..])?;
+
+            let mut history = match load_optional_account::<WhitelistHistory>(history_info)? {
+                Some(history) => history,
+                None => {
+                    create_program_account(
+                        history_info,
+                        &payer,
+                        &system_program,
+                        &[history_seeds, &[&[history_bump]]].concat(),
+                        8 + WhitelistHistory::INIT_SPACE,
+                    )?;
+                    WhitelistHistory {
+                        mint,
+                        wallet: *wallet,
+                        periods: Vec::new(),
+                    }
+                }
+            };
+            history.record_added(mint, *wallet, slot);
+            history.try_serialize(&mut &mut history_info.try_borrow_mut_data()?[..])?;
+
+            emit!(WhitelistEntryAdded {
+                mint,
+                wallet: *wallet
+            });
+            added += 1;
+        }
+
+        ctx.accounts.token_whitelist.count += added;
+        Ok(added)
+    }
+
+    /// Removes up to `constants::MAX_WHITELIST_BATCH` wallets from the whitelist,
+    /// like `remove_from_whitelist` does for one.
+    ///
+    /// Wallets are passed as remaining accounts like for `add_many_to_whitelist`;
+    /// the histories must already exist. Wallets that are not whitelisted are
+    /// skipped; a `WhitelistEntryRemoved` event is emitted for every wallet removed
+    /// and the number removed is returned.
+    pub fn remove_many_from_whitelist<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RemoveManyFromWhitelist<'info>>,
+        wallets: Vec<Pubkey>,
+        isin: Isin,
+    ) -> Result<u32> {
+        require_role(
+            &ctx.accounts.token_manager,
+            &ctx.accounts.signer.key(),
+            &ctx.accounts.role_assignment,
+            Role::ComplianceOfficer,
+        )?;
+        if wallets.is_empty()
+            || wallets.len() > constants::MAX_WHITELIST_BATCH as usize
+            || ctx.remaining_accounts.len() < 2 * wallets.len()
+        {
+            return Err(error!(TokenManagerError::InvalidWhitelistBatch));
+        }
+
+        let token_manager_key = ctx.accounts.token_manager.key();
+        let mint = ctx.accounts.token_whitelist.mint;
+        let slot = now(ctx.remaining_accounts)?.slot;
+        let mut removed = 0;
+        for (wallet, accounts) in wallets.iter().zip(ctx.remaining_accounts.chunks_exact(2)) {
+            let [entry_info, history_info] = accounts else {
+                return Err(error!(TokenManagerError::InvalidWhitelistBatch));
+            };
+            let entry_address = Pubkey::find_program_address(
+                &[b"whitelist", mint.as_ref(), wallet.as_ref()],
+                &crate::ID,
+            )
+            .0;
+            let history_address = Pubkey::find_program_address(
+                &[
+                    b"whitelist-history",
+                    token_manager_key.as_ref(),
+                    isin.as_bytes(),
+                    wallet.as_ref(),
+                ],
+                &crate::ID,
+            )
+            .0;
+            if entry_info.key() != entry_address || history_info.key() != history_address {
+                return Err(error!(TokenManagerError::InvalidWhitelistBatch));
+            }
+            if load_optional_account::<WhitelistEntry>(entry_info)?.is_none() {
+                continue;
+            }
+
+            close_program_account(entry_info, &ctx.accounts.signer.to_account_info())?;
+            if let Some(mut history) = load_optional_account::<WhitelistHistory>(history_info)? {
+                history.record_removed(slot);
+                history.try_serialize(&mut &mut history_info.try_borrow_mut_data()?[..])?;
+            }
+            emit!(WhitelistEntryRemoved {
+                mint,
+                wallet: *wallet
+            });
+            log_whitelist_removed(&mint, wallet);
+            removed += 1;
+        }
+
+        ctx.accounts.token_whitelist.count -= removed;
+        if removed > 0 {
+            count_anomaly(
+                &ctx.accounts.circuit_breaker,
+                AnomalyKind::WhitelistRemoval,
+                removed,
+                slot,
+            )?;
+        }
+        Ok(removed)
+    }
+
+    #[derive(Accounts)]
+    #[instruction(wallet: Pubkey, isin: Isin)]
+    pub struct ConfigureWhitelistBucket<'info> {
+        /// The creator of the token manager or a compliance officer
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the ConfigureWhitelistBucket implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+
+        /// Configuration of the token, telling whether additions need a second officer
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Bucket of the wallets sharing the first byte of `wallet`, created on
+        /// first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + WhitelistBucket::INIT_SPACE,
+            seeds = [b"whitelist-bucket", token_share.mint.as_ref(), &wallet.as_ref()[..1]],
+            bump,
+        )]
+        pub whitelist_bucket: Account<'info, WhitelistBucket>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Whitelists `wallet` for a token identified by its ISIN through its bucket,
+    /// a cheaper alternative to `add_to_whitelist` for investors without an
+    /// authorization expiry, jurisdiction or investor class.
+    ///
+    /// Wallets are spread over 256 buckets by the first byte of their key, each
+    /// holding up to 32 sorted wallets, so the hook reads a single small account
+    /// per wallet and the rent of a whitelisted investor is that of its key. The
+    /// hook treats a wallet of a bucket as holding an entry without restrictions.
+    pub fn add_to_whitelist_bucket(
+        ctx: Context<ConfigureWhitelistBucket>,
+        wallet: Pubkey,
+        _isin: Isin,
+    ) -> Result<()> {
+        require_role(
+            &ctx.accounts.token_manager,
+            &ctx.accounts.signer.key(),
+            &ctx.accounts.role_assignment,
+            Role::ComplianceOfficer,
+        )?;
+        if ctx.accounts.token_share.whitelist_approval_window.is_some() {
+            return Err(error!(TokenManagerError::WhitelistApprovalRequired));
+        }
+
+        let mint = ctx.accounts.token_share.mint;
+        let bucket = &mut ctx.accounts.whitelist_bucket;
+        if bucket.mint == Pubkey::default() {
+            bucket.mint = mint;
+            bucket.prefix = wallet.to_bytes()[0];
+        }
+        if bucket.insert(wallet)? {
+            emit!(WhitelistBucketEntryAdded {
+                mint,
+                wallet,
+                prefix: bucket.prefix,
+            });
+        }
+        Ok(())
+    }
+
+    /// Removes `wallet` from the bucket whitelist of a token identified by its
+    /// ISIN. Wallets not in their bucket are left untouched.
+    pub fn remove_from_whitelist_bucket(
+        ctx: Context<ConfigureWhitelistBucket>,
+        wallet: Pubkey,
+        _isin: Isin,
+    ) -> Result<()> {
+        require_role(
+            &ctx.accounts.token_manager,
+            &ctx.accounts.signer.key(),
+            &ctx.accounts.role_assignment,
+            Role::ComplianceOfficer,
+        )?;
+
+        let mint = ctx.accounts.token_share.mint;
+        let bucket = &mut ctx.accounts.whitelist_bucket;
+        if bucket.remove(&wallet) {
+            emit!(WhitelistBucketEntryRemoved {
+                mint,
+                wallet,
+                prefix: bucket.prefix,
+            });
+            log_whitelist_removed(&mint, &wallet);
+        }
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(wallet: Pubkey, isin: Isin)]
+    pub struct RenewAuthorization<'info> {
+        /// The creator of the token manager or a compliance officer
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the RenewAuthorization implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+
+        /// The whitelist containing the authorization
+        #[account(
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// The authorization to renew
+        #[account(
+            mut,
+            seeds = [b"whitelist", token_whitelist.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_entry: Account<'info, WhitelistEntry>,
+    }
+
+    /// Moves the expiry of the authorization of `wallet` to `expires_at` once its
+    /// KYC has been refreshed, without removing it from the whitelist; 0 never
+    /// expires.
+    pub fn renew_authorization(
+        ctx: Context<RenewAuthorization>,
+        wallet: Pubkey,
+        _isin: Isin,
+        expires_at: i64,
+    ) -> Result<()> {
+        require_role(
+            &ctx.accounts.token_manager,
+            &ctx.accounts.signer.key(),
+            &ctx.accounts.role_assignment,
+            Role::ComplianceOfficer,
+        )?;
+
+        ctx.accounts.whitelist_entry.expires_at = expires_at;
+        emit!(AuthorizationRenewed {
+            mint: ctx.accounts.whitelist_entry.mint,
+            wallet,
+            expires_at,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(wallet: Pubkey, isin: Isin)]
+    pub struct SetWhitelistNote<'info> {
+        /// The creator of the token manager or one of its compliance officers,
+        /// paying for the personal data record when it does not exist yet
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The whitelist containing the entry to annotate
+        #[account(
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// The entry to annotate
+        #[account(
+            seeds = [b"whitelist", token_whitelist.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_entry: Account<'info, WhitelistEntry>,
+
+        /// Personal data of the wallet, holding the note
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + PersonalData::INIT_SPACE,
+            seeds = [b"personal-data", token_whitelist.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub personal_data: Account<'info, PersonalData>,
+
+        /// Compliance officers of the token manager; only needed when an officer signs
+        #[account(
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: Option<Account<'info, ComplianceQueue>>,
+
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the SetWhitelistNote implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Sets or clears the note of a whitelisted wallet, kept in its personal data
+    /// record. The note is encrypted off-chain and typically holds a
+    /// case-management id.
+    pub fn set_whitelist_note(
+        ctx: Context<SetWhitelistNote>,
+        wallet: Pubkey,
+        _isin: Isin,
+        note: Option<[u8; 64]>,
+    ) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let creator = ctx.accounts.token_manager.creator;
+        ctx.accounts.token_manager.require_unlocked()?;
+        let is_officer = ctx
+            .accounts
+            .compliance_queue
+            .as_ref()
+            .is_some_and(|queue| queue.is_officer(&signer, &creator));
+        if !is_officer
+            && !has_role(
+                &ctx.accounts.token_manager,
+                &signer,
+                &ctx.accounts.role_assignment,
+                Role::ComplianceOfficer,
+            )?
+        {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+
+        let personal_data = &mut ctx.accounts.personal_data;
+        personal_data.mint = ctx.accounts.whitelist_entry.mint;
+        personal_data.wallet = wallet;
+        personal_data.jurisdiction = ctx.accounts.whitelist_entry.jurisdiction;
+        personal_data.note = note;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(wallet: Pubkey, isin: Isin)]
+    pub struct PurgePersonalData<'info> {
+        /// The wallet the data is about, a compliance officer or the creator,
+        /// refunded the rent of the record
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The whitelist of the token
+        #[account(
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// Personal data to purge
+        #[account(
+            mut,
+            close = signer,
+            seeds = [b"personal-data", token_whitelist.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub personal_data: Account<'info, PersonalData>,
+
+        /// Compliance officers of the token manager; only needed when an officer signs
+        #[account(
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: Option<Account<'info, ComplianceQueue>>,
+
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the PurgePersonalData implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+    }
+
+    /// Erases the personal data of a wallet for a token identified by its ISIN, as
+    /// requested by the investor. Its whitelist entry, history and statements are
+    /// kept, so the register stays complete.
+    pub fn purge_personal_data(
+        ctx: Context<PurgePersonalData>,
+        wallet: Pubkey,
+        _isin: Isin,
+    ) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let creator = ctx.accounts.token_manager.creator;
+        ctx.accounts.token_manager.require_unlocked()?;
+        let is_officer = ctx
+            .accounts
+            .compliance_queue
+            .as_ref()
+            .is_some_and(|queue| queue.is_officer(&signer, &creator));
+        if signer != wallet
+            && !is_officer
+            && !has_role(
+                &ctx.accounts.token_manager,
+                &signer,
+                &ctx.accounts.role_assignment,
+                Role::ComplianceOfficer,
+            )?
+        {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+
+        emit!(PersonalDataPurged {
+            mint: ctx.accounts.personal_data.mint,
+            wallet,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(wallet: Pubkey, isin: Isin)]
+    pub struct SetInvestorAttributes<'info> {
+        /// The creator of the token manager or one of its compliance officers
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The whitelist containing the entry to classify
+        #[account(
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// The entry to classify
+        #[account(
+            mut,
+            seeds = [b"whitelist", token_whitelist.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_entry: Account<'info, WhitelistEntry>,
+
+        /// Compliance officers of the token manager; only needed when an officer signs
+        #[account(
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: Option<Account<'info, ComplianceQueue>>,
+
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the SetInvestorAttributes implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+    }
+
+    /// Sets the jurisdiction and investor class of a whitelist entry, the
+    /// attributes `freeze_by_filter` matches holders against.
+    ///
+    /// # Arguments
+    ///
+    /// * `jurisdiction` - ISO 3166-1 alpha-2 country code of the investor.
+    /// * `investor_class` - Issuer-defined investor class, e.g. retail or professional.
+    pub fn set_investor_attributes(
+        ctx: Context<SetInvestorAttributes>,
+        _wallet: Pubkey,
+        _isin: Isin,
+        jurisdiction: [u8; 2],
+        investor_class: u8,
+    ) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let creator = ctx.accounts.token_manager.creator;
+        ctx.accounts.token_manager.require_unlocked()?;
+        let is_officer = ctx
+            .accounts
+            .compliance_queue
+            .as_ref()
+            .is_some_and(|queue| queue.is_officer(&signer, &creator));
+        if !is_officer
+            && !has_role(
+                &ctx.accounts.token_manager,
+                &signer,
+                &ctx.accounts.role_assignment,
+                Role::ComplianceOfficer,
+            )?
+        {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+
+        let entry = &mut ctx.accounts.whitelist_entry;
+        entry.jurisdiction = jurisdiction;
+        entry.investor_class = investor_class;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct FreezeByFilter<'info> {
+        /// The creator of the token manager or one of its compliance officers
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Compliance officers of the token manager; only needed when an officer signs
+        #[account(
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: Option<Account<'info, ComplianceQueue>>,
+
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the FreezeByFilter implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+
+        /// The mint of the token, which is the freeze authority of its accounts
+        #[account(
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        pub token_program: Program<'info, Token2022>,
+    }
+
+    /// Freezes the token accounts passed as remaining accounts, for a token
+    /// identified by its ISIN. The remaining accounts are pairs of a token account
+    /// and the whitelist entry of its owner. The accounts are selected off-chain,
+    /// but every one must belong to a whitelisted holder whose attributes match
+    /// `filter`, or the whole batch fails. Accounts already frozen are skipped.
+    pub fn freeze_by_filter<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FreezeByFilter<'info>>,
+        _isin: Isin,
+        filter: HolderFilter,
+    ) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let creator = ctx.accounts.token_manager.creator;
+        ctx.accounts.token_manager.require_unlocked()?;
+        let is_officer = ctx
+            .accounts
+            .compliance_queue
+            .as_ref()
+            .is_some_and(|queue| queue.is_officer(&signer, &creator));
+        if !is_officer
+            && !has_role(
+                &ctx.accounts.token_manager,
+                &signer,
+                &ctx.accounts.role_assignment,
+                Role::Pauser,
+            )?
+        {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+
+        let mint = ctx.accounts.token_mint.key();
+        let token_index_bytes = ctx.accounts.token_share.index.to_le_bytes();
+        let token_mint_seeds = &[
+            b"token-mint".as_ref(),
+            ctx.accounts.token_manager.to_account_info().key.as_ref(),
+            &token_index_bytes,
+            &[ctx.bumps.token_mint],
+        ];
+        let token_mint_signer = &[&token_mint_seeds[..]];
+
+        let mut frozen = 0u16;
+        for accounts in ctx.remaining_accounts.chunks(2) {
+            let [account_info, entry_info] = accounts else {
+                return Err(error!(TokenManagerError::InvalidTokenAccount));
+            };
+            let token_account = InterfaceAccount::<TokenAccount>::try_from(account_info)?;
+            if token_account.mint != mint {
+                return Err(error!(TokenManagerError::InvalidTokenAccount));
+            }
+            let matches =
+                load_optional_account::<WhitelistEntry>(entry_info)?.is_some_and(|entry| {
+                    entry.mint == mint
+                        && entry.wallet == token_account.owner
+                        && filter.matches(&entry)
+                });
+            if !matches {
+                return Err(error!(TokenManagerError::HolderDoesNotMatchFilter));
+            }
+            if token_account.is_frozen() {
+                continue;
+            }
+
+            freeze_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                FreezeAccount {
+                    account: account_info.clone(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    authority: ctx.accounts.token_mint.to_account_info(),
+                },
+                token_mint_signer,
+            ))?;
+            frozen += 1;
+        }
+
+        emit!(HoldersFrozen {
+            mint,
+            jurisdiction: filter.jurisdiction,
+            investor_class: filter.investor_class,
+            accounts: frozen,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct HolderAccountFreeze<'info> {
+        /// The creator of the token manager or a pauser
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the HolderAccountFreeze implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The mint of the token, which is the freeze authority of its accounts
+        #[account(
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// The account of the holder to freeze or thaw
+        #[account(mut, token::mint = token_mint)]
+        pub holder_account: InterfaceAccount<'info, TokenAccount>,
+
+        pub token_program: Program<'info, Token2022>,
+    }
+
+    /// Freezes the account of a single holder of a token identified by its ISIN,
+    /// e.g. under a court order or after the holder lost its keys.
+    pub fn freeze_holder_account(ctx: Context<HolderAccountFreeze>, _isin: Isin) -> Result<()> {
+        require_role(
+            &ctx.accounts.token_manager,
+            &ctx.accounts.signer.key(),
+            &ctx.accounts.role_assignment,
+            Role::Pauser,
+        )?;
+
+        let token_index_bytes = ctx.accounts.token_share.index.to_le_bytes();
+        let token_mint_seeds = &[
+            b"token-mint".as_ref(),
+            ctx.accounts.token_manager.to_account_info().key.as_ref(),
+            &token_index_bytes,
+            &[ctx.bumps.token_mint],
+        ];
+        freeze_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            FreezeAccount {
+                account: ctx.accounts.holder_account.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                authority: ctx.accounts.token_mint.to_account_info(),
+            },
+            &[&token_mint_seeds[..]],
+        ))?;
+
+        emit!(HolderAccountFrozen {
+            mint: ctx.accounts.token_mint.key(),
+            account: ctx.accounts.holder_account.key(),
+            holder: ctx.accounts.holder_account.owner,
+        });
+        Ok(())
+    }
+
+    /// Thaws the account of a single holder of a token identified by its ISIN,
+    /// whether it was frozen by `freeze_holder_account` or `freeze_by_filter`.
+    pub fn thaw_holder_account(ctx: Context<HolderAccountFreeze>, _isin: Isin) -> Result<()> {
+        require_role(
+            &ctx.accounts.token_manager,
+            &ctx.accounts.signer.key(),
+            &ctx.accounts.role_assignment,
+            Role::Pauser,
+        )?;
+
+        let token_index_bytes = ctx.accounts.token_share.index.to_le_bytes();
+        let token_mint_seeds = &[
+            b"token-mint".as_ref(),
+            ctx.accounts.token_manager.to_account_info().key.as_ref(),
+            &token_index_bytes,
+            &[ctx.bumps.token_mint],
+        ];
+        thaw_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            ThawAccount {
+                account: ctx.accounts.holder_account.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                authority: ctx.accounts.token_mint.to_account_info(),
+            },
+            &[&token_mint_seeds[..]],
+        ))?;
+
+        emit!(HolderAccountThawed {
+            mint: ctx.accounts.token_mint.key(),
+            account: ctx.accounts.holder_account.key(),
+            holder: ctx.accounts.holder_account.owner,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct OpenCorridor<'info> {
+        /// Creator of the first token manager, paying for the corridor
+        #[account(mut)]
+        pub first_creator: Signer<'info>,
+
+        /// Creator of the second token manager
+        pub second_creator: Signer<'info>,
+
+        /// Token manager with the lower key
+        #[account(
+            seeds = [b"token-manager", first_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub first_manager: Account<'info, TokenManager>,
+
+        /// Token manager with the higher key
+        #[account(
+            seeds = [b"token-manager", second_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub second_manager: Account<'info, TokenManager>,
+
+        /// Reciprocal agreement between both managers
+        #[account(
+            init,
+            payer = first_creator,
+            space = 8 + Corridor::INIT_SPACE,
+            seeds = [b"corridor", first_manager.key().as_ref(), second_manager.key().as_ref()],
+            bump,
+        )]
+        pub corridor: Account<'info, Corridor>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Opens a reciprocal transfer corridor between two token managers of this
+    /// program, co-signed by both creators. Managers must be passed in ascending
+    /// key order so each pair has a single corridor. Once open, wallets whitelisted
+    /// by one manager can be imported into the other's whitelist with
+    /// `apply_corridor_authorization`.
+    pub fn open_corridor(ctx: Context<OpenCorridor>) -> Result<()> {
+        let first_manager = ctx.accounts.first_manager.key();
+        let second_manager = ctx.accounts.second_manager.key();
+        if first_manager >= second_manager {
+            return Err(error!(TokenManagerError::CorridorManagersUnordered));
+        }
+        if ctx.accounts.first_creator.key() != ctx.accounts.first_manager.creator
+            || ctx.accounts.second_creator.key() != ctx.accounts.second_manager.creator
+        {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+        ctx.accounts.first_manager.require_unlocked()?;
+        ctx.accounts.second_manager.require_unlocked()?;
+
+        let corridor = &mut ctx.accounts.corridor;
+        corridor.first_manager = first_manager;
+        corridor.second_manager = second_manager;
+        corridor.opened_at = now(ctx.remaining_accounts)?.unix_timestamp;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct CloseCorridor<'info> {
+        /// Creator of either side of the corridor, receiving the reclaimed rent
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// Token manager of the signer
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Corridor being closed
+        #[account(
+            mut,
+            close = signer,
+            constraint = corridor.first_manager == token_manager.key()
+                || corridor.second_manager == token_manager.key()
+                @ TokenManagerError::CorridorMismatch,
+        )]
+        pub corridor: Account<'info, Corridor>,
+    }
+
+    /// Closes a corridor. Either side can leave unilaterally; authorizations that
+    /// were imported through it can then be revoked with
+    /// `revoke_corridor_authorization`.
+    pub fn close_corridor(ctx: Context<CloseCorridor>) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(wallet: Pubkey, source_isin: Isin, target_isin: Isin)]
+    pub struct CorridorAuthorization<'info> {
+        /// Anyone paying for the membership history of the wallet
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// Token manager whose whitelist grants the authorization
+        pub source_manager: Account<'info, TokenManager>,
+
+        /// Whitelist of `source_isin` in the source manager
+        #[account(
+            seeds = [b"token-whitelist", source_manager.key().as_ref(), source_isin.as_bytes()],
+            bump,
+        )]
+        pub source_whitelist: Account<'info, TokenWhitelist>,
+
+        /// Authorization of the wallet in the source whitelist, if any
+        /// CHECK: May not exist; deserialized in the CorridorAuthorization implementation
+        #[account(
+            seeds = [b"whitelist", source_whitelist.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub source_entry: UncheckedAccount<'info>,
+
+        /// Token manager whose whitelist receives the imported authorization
+        pub target_manager: Account<'info, TokenManager>,
+
+        /// Share of `target_isin` in the target manager
+        #[account(
+            seeds = [b"token-share", target_manager.key().as_ref(), target_isin.as_bytes()],
+            bump,
+        )]
+        pub target_share: Account<'info, TokenShare>,
+
+        /// Whitelist of `target_isin` in the target manager
+        #[account(
+            mut,
+            seeds = [b"token-whitelist", target_manager.key().as_ref(), target_isin.as_bytes()],
+            bump,
+        )]
+        pub target_whitelist: Account<'info, TokenWhitelist>,
+
+        /// Authorization of the wallet in the target whitelist, created on import
+        #[account(
+            init_if_needed,
+            payer = payer,
+            space = 8 + WhitelistEntry::INIT_SPACE,
+            seeds = [b"whitelist", target_whitelist.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub target_entry: Account<'info, WhitelistEntry>,
+
+        /// Corridor between both managers; may have been closed
+        /// CHECK: The address is derived from both managers in the implementation
+        pub corridor: UncheckedAccount<'info>,
+
+        /// Membership periods of the wallet in the target whitelist
+        #[account(
+            init_if_needed,
+            payer = payer,
+            space = 8 + WhitelistHistory::INIT_SPACE,
+            seeds = [
+                b"whitelist-history",
+                target_manager.key().as_ref(),
+                target_isin.as_bytes(),
+                wallet.as_ref(),
+            ],
+            bump,
+        )]
+        pub whitelist_history: Account<'info, WhitelistHistory>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Imports the authorization of `wallet` for `source_isin` of the source manager
+    /// as an authorization for `target_isin` of the target manager.
+    /// Permissionless: the open corridor is the target creator's consent. Tokens
+    /// requiring four-eyes additions do not accept imported authorizations.
+    pub fn apply_corridor_authorization(
+        ctx: Context<CorridorAuthorization>,
+        wallet: Pubkey,
+        _source_isin: Isin,
+        _target_isin: Isin,
+    ) -> Result<()> {
+        ctx.accounts.target_manager.require_unlocked()?;
+        if ctx
+            .accounts
+            .target_share
+            .whitelist_approval_window
+            .is_some()
+        {
+            return Err(error!(TokenManagerError::WhitelistApprovalRequired));
+        }
+        let source_manager_key = ctx.accounts.source_manager.key();
+        let target_manager_key = ctx.accounts.target_manager.key();
+        if ctx.accounts.corridor.key() != corridor_address(&source_manager_key, &target_manager_key)
+            || load_optional_account::<Corridor>(&ctx.accounts.corridor)?.is_none()
+        {
+            return Err(error!(TokenManagerError::CorridorMismatch));
+        }
+
+        let Some(source_entry) =
+            load_optional_account::<WhitelistEntry>(&ctx.accounts.source_entry)?
+        else {
+            return Err(error!(TokenManagerError::WalletNotFound));
+        };
+
+        // The entry exists already if the wallet was whitelisted before
+        if ctx.accounts.target_entry.wallet == wallet {
+            return Ok(());
+        }
+        let target_whitelist = &mut ctx.accounts.target_whitelist;
+        let mint = target_whitelist.mint;
+        target_whitelist.count += 1;
+        ctx.accounts.target_entry.set_inner(WhitelistEntry {
+            mint,
+            wallet,
+            corridor: Some(source_manager_key),
+            jurisdiction: [0; 2],
+            investor_class: 0,
+            expires_at: source_entry.expires_at,
+            _reserved: [0; 24],
+        });
+        emit!(WhitelistEntryAdded { mint, wallet });
+        let slot = now(ctx.remaining_accounts)?.slot;
+        ctx.accounts
+            .whitelist_history
+            .record_added(mint, wallet, slot);
+        Ok(())
+    }
+
+    /// Removes an authorization imported through a corridor once the wallet is no
+    /// longer whitelisted by the source manager or the corridor has been closed.
+    /// Permissionless so either side can clean up stale imports.
+    pub fn revoke_corridor_authorization(
+        ctx: Context<CorridorAuthorization>,
+        wallet: Pubkey,
+        _source_isin: Isin,
+        _target_isin: Isin,
+    ) -> Result<()> {
+        let source_manager_key = ctx.accounts.source_manager.key();
+        let target_manager_key = ctx.accounts.target_manager.key();
+        if ctx.accounts.corridor.key() != corridor_address(&source_manager_key, &target_manager_key)
+        {
+            return Err(error!(TokenManagerError::CorridorMismatch));
+        }
+
+        let corridor_open = load_optional_account::<Corridor>(&ctx.accounts.corridor)?.is_some();
+        let source_whitelisted =
+            load_optional_account::<WhitelistEntry>(&ctx.accounts.source_entry)?.is_some();
+        if corridor_open && source_whitelisted {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+
+        let target_entry = &ctx.accounts.target_entry;
+        if target_entry.wallet != wallet || target_entry.corridor != Some(source_manager_key) {
+            return Err(error!(TokenManagerError::WalletNotFound));
+        }
+        target_entry.close(ctx.accounts.payer.to_account_info())?;
+        let target_whitelist = &mut ctx.accounts.target_whitelist;
+        let mint = target_whitelist.mint;
+        target_whitelist.count -= 1;
+        emit!(WhitelistEntryRemoved { mint, wallet });
+        log_whitelist_removed(&mint, &wallet);
+        let slot = now(ctx.remaining_accounts)?.slot;
+        ctx.accounts.whitelist_history.record_removed(slot);
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(wallet: Pubkey, isin: Isin)]
+    pub struct ProveWhitelistMembership<'info> {
+        /// Account storing token and whitelist information
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token, proving it exists
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Membership periods of the wallet, if it was ever whitelisted
+        /// CHECK: May not exist; deserialized in the implementation
+        #[account(
+            seeds = [b"whitelist-history", token_manager.key().as_ref(), isin.as_bytes(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_history: UncheckedAccount<'info>,
+    }
+
+    /// Returns whether `wallet` was whitelisted for the token registered under `isin`
+    /// at `slot`, from the membership periods recorded on every addition and removal.
+    pub fn prove_whitelist_membership(
+        ctx: Context<ProveWhitelistMembership>,
+        _wallet: Pubkey,
+        _isin: Isin,
+        slot: u64,
+    ) -> Result<bool> {
+        Ok(
+            load_optional_account::<WhitelistHistory>(&ctx.accounts.whitelist_history)?
+                .is_some_and(|history| history.was_member_at(slot)),
+        )
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct CommitWhitelistHash<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The whitelist being committed
+        #[account(
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// Latest whitelist commitment for the token, created on first commit
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + WhitelistCheckpoint::INIT_SPACE,
+            seeds = [b"whitelist-checkpoint", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub whitelist_checkpoint: Account<'info, WhitelistCheckpoint>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Commits a hash of the full whitelist of a token.
+    /// `merkle_root` is computed off-chain over the wallets of the whitelist sorted
+    /// by their bytes; `count` must match the number of entries the whitelist
+    /// currently counts on-chain. Together with the add/remove events, auditors can replay the
+    /// whitelist between two checkpoints and detect unexpected entries.
+    pub fn commit_whitelist_hash(
+        ctx: Context<CommitWhitelistHash>,
+        _isin: Isin,
+        merkle_root: [u8; 32],
+        count: u32,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let mint = ctx.accounts.token_whitelist.mint;
+        if ctx.accounts.token_whitelist.count != count {
+            return Err(error!(TokenManagerError::WhitelistCountMismatch));
+        }
+
+        let current = now(ctx.remaining_accounts)?;
+        let checkpoint = &mut ctx.accounts.whitelist_checkpoint;
+        checkpoint.mint = mint;
+        checkpoint.sequence = checkpoint
+            .sequence
+            .checked_add(1)
+            .ok_or(error!(TokenManagerError::IndexOverflow))?;
+        checkpoint.merkle_root = merkle_root;
+        checkpoint.count = count;
+        checkpoint.slot = current.slot;
+        checkpoint.unix_timestamp = current.unix_timestamp;
+
+        emit!(WhitelistCheckpointCommitted {
+            mint,
+            sequence: checkpoint.sequence,
+            merkle_root,
+            count,
+            slot: current.slot,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct SetWhitelistAttestor<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token whose whitelist is mirrored
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Mirror of the whitelist held on the source cluster, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + WhitelistMirror::INIT_SPACE,
+            seeds = [b"whitelist-mirror", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub whitelist_mirror: Account<'info, WhitelistMirror>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Designates the bridge or attestor allowed to post commitments of the
+    /// whitelist a token identified by its ISIN has on another cluster, such as the
+    /// devnet deployment promoted to mainnet or a private permissioned chain.
+    /// Changing the attestor or the source cluster discards the current commitment.
+    ///
+    /// # Arguments
+    ///
+    /// * `attestor` - Key signing the commitments.
+    /// * `source_cluster` - Genesis hash of the cluster holding the source whitelist.
+    pub fn set_whitelist_attestor(
+        ctx: Context<SetWhitelistAttestor>,
+        _isin: Isin,
+        attestor: Pubkey,
+        source_cluster: [u8; 32],
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let mirror = &mut ctx.accounts.whitelist_mirror;
+        mirror.mint = ctx.accounts.token_share.mint;
+        if mirror.attestor != attestor || mirror.source_cluster != source_cluster {
+            mirror.attestor = attestor;
+            mirror.source_cluster = source_cluster;
+            mirror.merkle_root = [0; 32];
+            mirror.count = 0;
+        }
+
+        emit!(WhitelistAttestorSet {
+            mint: mirror.mint,
+            attestor,
+            source_cluster,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct AcceptWhitelistCommitment<'info> {
+        /// The attestor designated for the mirror
+        pub attestor: Signer<'info>,
+
+        /// Mirror receiving the commitment
+        #[account(
+            mut,
+            seeds = [b"whitelist-mirror", whitelist_mirror.mint.as_ref()],
+            bump,
+            has_one = attestor @ TokenManagerError::Unauthorized,
+        )]
+        pub whitelist_mirror: Account<'info, WhitelistMirror>,
+    }
+
+    /// Accepts a commitment of the whitelist on the source cluster, signed by the
+    /// attestor of the mirror. `merkle_root` is computed like the one of
+    /// `commit_whitelist_hash`, each node hashing its two children smallest
+    /// first, except that leaves commit to the authorization of each wallet as
+    /// hashed by `MirroredAuthorization::leaf`. `sequence` must exceed the one of the
+    /// commitment already accepted, so replays and reordered commitments fail.
+    pub fn accept_whitelist_commitment(
+        ctx: Context<AcceptWhitelistCommitment>,
+        sequence: u64,
+        merkle_root: [u8; 32],
+        count: u32,
+    ) -> Result<()> {
+        let mirror = &mut ctx.accounts.whitelist_mirror;
+        if sequence <= mirror.sequence {
+            return Err(error!(TokenManagerError::InvalidWhitelistCommitment));
+        }
+
+        let slot = now(ctx.remaining_accounts)?.slot;
+        mirror.sequence = sequence;
+        mirror.merkle_root = merkle_root;
+        mirror.count = count;
+        mirror.slot = slot;
+
+        emit!(WhitelistCommitmentAccepted {
+            mint: mirror.mint,
+            source_cluster: mirror.source_cluster,
+            sequence,
+            merkle_root,
+            count,
+            slot,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, wallet: Pubkey)]
+    pub struct MirrorWhitelistEntry<'info> {
+        /// Anyone paying for the entry
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token, which may require four-eyes additions
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The whitelist receiving the wallet
+        #[account(
+            mut,
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// Mirror holding the accepted commitment
+        #[account(
+            seeds = [b"whitelist-mirror", token_whitelist.mint.as_ref()],
+            bump,
+        )]
+        pub whitelist_mirror: Account<'info, WhitelistMirror>,
+
+        /// Authorization of the wallet, created by this instruction
+        #[account(
+            init,
+            payer = payer,
+            space = 8 + WhitelistEntry::INIT_SPACE,
+            seeds = [b"whitelist", token_whitelist.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_entry: Account<'info, WhitelistEntry>,
+
+        /// Membership periods of the wallet, kept after removal
+        #[account(
+            init_if_needed,
+            payer = payer,
+            space = 8 + WhitelistHistory::INIT_SPACE,
+            seeds = [b"whitelist-history", token_manager.key().as_ref(), isin.as_bytes(), wallet.as_ref()],
+            bump,
+        )]
+        pub whitelist_history: Account<'info, WhitelistHistory>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Adds a wallet to the whitelist of a token identified by its ISIN, given a
+    /// proof that its `authorization` belongs to the whitelist committed by the
+    /// attestor of the mirror. Anyone may submit proofs, so the registries
+    /// converge without the creator key.
+    ///
+    /// Removals are not mirrored and stay with the creator: a wallet removed from
+    /// the whitelist, whose history keeps the removal, can only be added back by
+    /// an officer. Tokens requiring four-eyes additions do not accept mirrored
+    /// ones.
+    pub fn mirror_whitelist_entry(
+        ctx: Context<MirrorWhitelistEntry>,
+        _isin: Isin,
+        wallet: Pubkey,
+        authorization: MirroredAuthorization,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        ctx.accounts.token_manager.require_unlocked()?;
+        if ctx.accounts.token_share.whitelist_approval_window.is_some() {
+            return Err(error!(TokenManagerError::WhitelistApprovalRequired));
+        }
+        if !ctx.accounts.whitelist_history.periods.is_empty() {
+            return Err(error!(TokenManagerError::MirroredWalletRemoved));
+        }
+        let leaf = authorization.leaf(&wallet);
+        if !ctx.accounts.whitelist_mirror.contains(leaf, &proof) {
+            return Err(error!(TokenManagerError::InvalidWhitelistProof));
+        }
+
+        let token_whitelist = &mut ctx.accounts.token_whitelist;
+        let mint = token_whitelist.mint;
+        token_whitelist.count += 1;
+        ctx.accounts.whitelist_entry.set_inner(WhitelistEntry {
+            mint,
+            wallet,
+            corridor: None,
+            jurisdiction: authorization.jurisdiction,
+            investor_class: authorization.investor_class,
+            expires_at: authorization.expires_at,
+            _reserved: [0; 24],
+        });
+        emit!(WhitelistEntryAdded { mint, wallet });
+        let slot = now(ctx.remaining_accounts)?.slot;
+        ctx.accounts
+            .whitelist_history
+            .record_added(mint, wallet, slot);
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct ExportState<'info> {
+        /// Anyone paying for the export account
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Whitelist of the token
+        #[account(
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// The mint of the token
+        #[account(
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Latest whitelist commitment of the token, if any
+        /// CHECK: May not exist; deserialized in the ExportState implementation
+        #[account(
+            seeds = [b"whitelist-checkpoint", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub whitelist_checkpoint: UncheckedAccount<'info>,
+
+        /// Sanctions list of the token manager, if a screening provider was set
+        /// CHECK: May not exist; deserialized in the ExportState implementation
+        #[account(
+            seeds = [b"sanctions-list", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub sanctions_list: UncheckedAccount<'info>,
+
+        /// Fee schedule of the token manager, if any
+        /// CHECK: May not exist; deserialized in the ExportState implementation
+        #[account(
+            seeds = [b"fee-schedule", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub fee_schedule: UncheckedAccount<'info>,
+
+        /// Circuit breaker of the token, if the issuer configured one
+        /// CHECK: May not exist; deserialized in the ExportState implementation
+        #[account(
+            seeds = [b"circuit-breaker", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub circuit_breaker: UncheckedAccount<'info>,
+
+        /// Debt terms of the token, if it is a debt instrument
+        /// CHECK: May not exist; deserialized in the ExportState implementation
+        #[account(
+            seeds = [b"debt-instrument", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub debt_instrument: UncheckedAccount<'info>,
+
+        /// Compliance officers and tasks of the token manager, if any
+        /// CHECK: May not exist; deserialized in the ExportState implementation
+        #[account(
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: UncheckedAccount<'info>,
+
+        /// Export of the token, overwritten by every call
+        #[account(
+            init_if_needed,
+            payer = payer,
+            space = 8 + StateExport::INIT_SPACE,
+            seeds = [b"state-export", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub state_export: Account<'info, StateExport>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Writes the full compliance configuration and summary stats of a token
+    /// identified by its ISIN to its `StateExport` account, so auditors and
+    /// disaster recovery systems can snapshot it with a single account read.
+    /// Permissionless: the export only copies state anyone can already read.
+    pub fn export_state(ctx: Context<ExportState>, _isin: Isin) -> Result<()> {
+        let state = TokenStateAccounts {
+            token_manager: &ctx.accounts.token_manager,
+            token_share: &ctx.accounts.token_share,
+            token_whitelist: &ctx.accounts.token_whitelist,
+            token_mint: &ctx.accounts.token_mint,
+            whitelist_checkpoint: &ctx.accounts.whitelist_checkpoint,
+            sanctions_list: &ctx.accounts.sanctions_list,
+            fee_schedule: &ctx.accounts.fee_schedule,
+            circuit_breaker: &ctx.accounts.circuit_breaker,
+            debt_instrument: &ctx.accounts.debt_instrument,
+            compliance_queue: &ctx.accounts.compliance_queue,
+        }
+        .read()?;
+
+        let slot = now(ctx.remaining_accounts)?.slot;
+        let export = &mut ctx.accounts.state_export;
+        export.mint = ctx.accounts.token_share.mint;
+        export.format_version = constants::STATE_EXPORT_VERSION;
+        export.sequence = export
+            .sequence
+            .checked_add(1)
+            .ok_or(error!(TokenManagerError::IndexOverflow))?;
+        export.slot = slot;
+        export.state = state;
+
+        emit!(StateExported {
+            mint: export.mint,
+            format_version: export.format_version,
+            sequence: export.sequence,
+            slot,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct ComputeStateHash<'info> {
+        /// Anyone paying for the hash account
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Whitelist of the token
+        #[account(
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// The mint of the token
+        #[account(
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Latest whitelist commitment of the token, if any
+        /// CHECK: May not exist; deserialized in the ComputeStateHash implementation
+        #[account(
+            seeds = [b"whitelist-checkpoint", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub whitelist_checkpoint: UncheckedAccount<'info>,
+
+        /// Sanctions list of the token manager, if a screening provider was set
+        /// CHECK: May not exist; deserialized in the ComputeStateHash implementation
+        #[account(
+            seeds = [b"sanctions-list", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub sanctions_list: UncheckedAccount<'info>,
+
+        /// Fee schedule of the token manager, if any
+        /// CHECK: May not exist; deserialized in the ComputeStateHash implementation
+        #[account(
+            seeds = [b"fee-schedule", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub fee_schedule: UncheckedAccount<'info>,
+
+        /// Circuit breaker of the token, if the issuer configured one
+        /// CHECK: May not exist; deserialized in the ComputeStateHash implementation
+        #[account(
+            seeds = [b"circuit-breaker", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub circuit_breaker: UncheckedAccount<'info>,
+
+        /// Debt terms of the token, if it is a debt instrument
+        /// CHECK: May not exist; deserialized in the ComputeStateHash implementation
+        #[account(
+            seeds = [b"debt-instrument", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub debt_instrument: UncheckedAccount<'info>,
+
+        /// Compliance officers and tasks of the token manager, if any
+        /// CHECK: May not exist; deserialized in the ComputeStateHash implementation
+        #[account(
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: UncheckedAccount<'info>,
+
+        /// Latest hash of the state of the token, overwritten by every call
+        #[account(
+            init_if_needed,
+            payer = payer,
+            space = 8 + StateHash::INIT_SPACE,
+            seeds = [b"state-hash", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub state_hash: Account<'info, StateHash>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Hashes the canonical serialization of the state of a token identified by
+    /// its ISIN, the `TokenState` `export_state` would write, including the
+    /// latest whitelist root. The hash is stored in the `StateHash` account and
+    /// returned, so replicas and the registrar's books can check they agree with
+    /// the chain byte for byte.
+    pub fn compute_state_hash(ctx: Context<ComputeStateHash>, _isin: Isin) -> Result<[u8; 32]> {
+        let state = TokenStateAccounts {
+            token_manager: &ctx.accounts.token_manager,
+            token_share: &ctx.accounts.token_share,
+            token_whitelist: &ctx.accounts.token_whitelist,
+            token_mint: &ctx.accounts.token_mint,
+            whitelist_checkpoint: &ctx.accounts.whitelist_checkpoint,
+            sanctions_list: &ctx.accounts.sanctions_list,
+            fee_schedule: &ctx.accounts.fee_schedule,
+            circuit_breaker: &ctx.accounts.circuit_breaker,
+            debt_instrument: &ctx.accounts.debt_instrument,
+            compliance_queue: &ctx.accounts.compliance_queue,
+        }
+        .read()?;
+        let hash = state.hash()?;
+
+        let slot = now(ctx.remaining_accounts)?.slot;
+        ctx.accounts.state_hash.set_inner(StateHash {
+            mint: ctx.accounts.token_share.mint,
+            format_version: constants::STATE_EXPORT_VERSION,
+            hash,
+            slot,
+        });
+
+        emit!(StateHashComputed {
+            mint: ctx.accounts.token_share.mint,
+            format_version: constants::STATE_EXPORT_VERSION,
+            hash,
+            slot,
+        });
+        Ok(hash)
+    }
+
+    #[derive(Accounts)]
+    pub struct TransferHook<'info> {
+        /// The token account sending tokens
+        /// Must have the specified mint and be owned by owner
+        #[account(
+        token::mint = mint,
+        token::authority = owner,
+    )]
+        pub source_token: InterfaceAccount<'info, TokenAccount>,
+
+        /// The mint of the token being transferred
+        pub mint: InterfaceAccount<'info, Mint>,
+
+        /// The token account receiving tokens
+        /// Must have the specified mint
+        #[account(
+        token::mint = mint,
+    )]
+        pub destination_token: InterfaceAccount<'info, TokenAccount>,
+
+        /// The authority (owner) of the source token account
+        /// The program verifies if this wallet is whitelisted
+        /// CHECK: This account is verified in the TransferHook implementation
+        pub owner: UncheckedAccount<'info>,
+
+        /// Account containing extra metadata for the transfer hook
+        /// Created by SPL Token 2022 program
+        /// CHECK: This account is verified in the TransferHook implementation
+        #[account(
+        seeds = [b"extra-account-metas", mint.key().as_ref()],
+        bump)
+    ]
+        pub extra_account_meta_list: AccountInfo<'info>,
+
+        /// Token manager of the token, holding manager-wide settings
+        #[account(
+        seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+        bump,
+    )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token being transferred
+        #[account(
+            constraint = token_share.mint == mint.key() @ TokenManagerError::TokenNotFound,
+            constraint = token_share.token_manager == token_manager.key()
+                @ TokenManagerError::TokenNotFound,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Authorization of the destination wallet, if it is whitelisted
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            seeds = [b"whitelist", mint.key().as_ref(), destination_token.owner.as_ref()],
+            bump,
+        )]
+        pub destination_entry: UncheckedAccount<'info>,
+
+        /// Market maker registration of the destination wallet, if any
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            seeds = [b"market-maker", mint.key().as_ref(), destination_token.owner.as_ref()],
+            bump,
+        )]
+        pub destination_market_maker: UncheckedAccount<'info>,
+
+        /// Agreement acceptance of the destination wallet, if any
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            seeds = [b"agreement-acceptance", mint.key().as_ref(), destination_token.owner.as_ref()],
+            bump,
+        )]
+        pub destination_agreement: UncheckedAccount<'info>,
+
+        /// Agreement acceptance of the source wallet, if any
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            seeds = [b"agreement-acceptance", mint.key().as_ref(), source_token.owner.as_ref()],
+            bump,
+        )]
+        pub source_agreement: UncheckedAccount<'info>,
+
+        /// Trusted vault registration of the source wallet, if any
+        /// CHECK: May not exist; checked in the TransferHook implementation
+        #[account(
+            seeds = [b"trusted-vault", mint.key().as_ref(), source_token.owner.as_ref()],
+            bump,
+        )]
+        pub source_trusted_vault: UncheckedAccount<'info>,
+
+        /// Trusted vault registration of the destination wallet, if any
+        /// CHECK: May not exist; checked in the TransferHook implementation
+        #[account(
+            seeds = [b"trusted-vault", mint.key().as_ref(), destination_token.owner.as_ref()],
+            bump,
+        )]
+        pub destination_trusted_vault: UncheckedAccount<'info>,
+
+        /// Compliance receipts of the mint, written only if the issuer enabled them
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            mut,
+            seeds = [b"transfer-receipts", mint.key().as_ref()],
+            bump,
+        )]
+        pub transfer_receipts: UncheckedAccount<'info>,
+
+        /// Due bill ledger of the mint, naming the corporate action tracking due bills
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            seeds = [b"due-bills", mint.key().as_ref()],
+            bump,
+        )]
+        pub due_bill_ledger: UncheckedAccount<'info>,
+
+        /// Statement of the source wallet, written only if the holder opened it
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            mut,
+            seeds = [b"holder-statement", mint.key().as_ref(), source_token.owner.as_ref()],
+            bump,
+        )]
+        pub source_statement: UncheckedAccount<'info>,
+
+        /// Statement of the destination wallet, written only if the holder opened it
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            mut,
+            seeds = [b"holder-statement", mint.key().as_ref(), destination_token.owner.as_ref()],
+            bump,
+        )]
+        pub destination_statement: UncheckedAccount<'info>,
+
+        /// Sanctions list of the token manager, if a screening provider was set
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            seeds = [b"sanctions-list", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub sanctions_list: UncheckedAccount<'info>,
+
+        /// Incoming consent of the destination wallet, if any
+        /// CHECK: May not exist; checked in the TransferHook implementation
+        #[account(
+            seeds = [b"incoming-consent", mint.key().as_ref(), destination_token.owner.as_ref()],
+            bump,
+        )]
+        pub destination_consent: UncheckedAccount<'info>,
+
+        /// Debt terms of the token, if it is a debt instrument
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            seeds = [b"debt-instrument", mint.key().as_ref()],
+            bump,
+        )]
+        pub debt_instrument: UncheckedAccount<'info>,
+
+        /// Circuit breaker of the token, if the issuer configured one
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            seeds = [b"circuit-breaker", mint.key().as_ref()],
+            bump,
+        )]
+        pub circuit_breaker: UncheckedAccount<'info>,
+
+        /// Authorization of the source wallet, if it is whitelisted
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            seeds = [b"whitelist", mint.key().as_ref(), source_token.owner.as_ref()],
+            bump,
+        )]
+        pub source_entry: UncheckedAccount<'info>,
+
+        /// Lockup of the source wallet, if the issuer set one
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            seeds = [b"lockup", mint.key().as_ref(), source_token.owner.as_ref()],
+            bump,
+        )]
+        pub source_lockup: UncheckedAccount<'info>,
+
+        /// Whitelist bucket of the source wallet, if it was created
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            seeds = [b"whitelist-bucket", mint.key().as_ref(), &source_token.owner.as_ref()[..1]],
+            bump,
+        )]
+        pub source_bucket: UncheckedAccount<'info>,
+
+        /// Whitelist bucket of the destination wallet, if it was created
+        /// CHECK: May not exist; deserialized in the TransferHook implementation
+        #[account(
+            seeds = [b"whitelist-bucket", mint.key().as_ref(), &destination_token.owner.as_ref()[..1]],
+            bump,
+        )]
+        pub destination_bucket: UncheckedAccount<'info>,
+    }
+
+    #[interface(spl_transfer_hook_interface::execute)]
+    pub fn transfer_hook<'info>(
+        ctx: Context<'_, '_, '_, 'info, TransferHook<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        // Token-2022 flags both accounts for the duration of the transfer, so a
+        // direct call cannot record transfers that never happened
+        for token_account in [&ctx.accounts.source_token, &ctx.accounts.destination_token] {
+            if !is_transferring(&token_account.to_account_info())? {
+                return Err(error!(TokenManagerError::NotTransferring));
+            }
+        }
+
+        let mint = ctx.accounts.mint.key();
+        let now = now(ctx.remaining_accounts)?;
+        let sanctions_list = load_optional_account::<SanctionsList>(&ctx.accounts.sanctions_list)?;
+        let circuit_breaker =
+            load_optional_account::<CircuitBreaker>(&ctx.accounts.circuit_breaker)?;
+        let transfer_request = if ctx.accounts.token_share.require_transfer_approval {
+            find_transfer_request(
+                ctx.remaining_accounts,
+                &mint,
+                &ctx.accounts.source_token.owner,
+                &ctx.accounts.destination_token.owner,
+            )?
+        } else {
+            None
+        };
+        let check = TransferCheck {
+            token_manager: &ctx.accounts.token_manager,
+            token: &ctx.accounts.token_share,
+            source_entry: load_whitelist_entry(
+                &ctx.accounts.source_entry,
+                Some(&ctx.accounts.source_bucket),
+                &ctx.accounts.source_token.owner,
+            )?,
+            destination_entry: load_whitelist_entry(
+                &ctx.accounts.destination_entry,
+                Some(&ctx.accounts.destination_bucket),
+                &ctx.accounts.destination_token.owner,
+            )?,
+            mint,
+            source_owner: ctx.accounts.source_token.owner,
+            destination_owner: ctx.accounts.destination_token.owner,
+            amount,
+            // The hook runs after balances are updated
+            empties_source: ctx.accounts.source_token.amount == 0,
+            source_balance: Some(ctx.accounts.source_token.amount),
+            source_lockup: load_optional_account(&ctx.accounts.source_lockup)?,
+            destination_balance: Some(ctx.accounts.destination_token.amount),
+            destination_market_maker: load_optional_account(
+                &ctx.accounts.destination_market_maker,
+            )?,
+            destination_agreement: load_optional_account(&ctx.accounts.destination_agreement)?,
+            source_agreement: load_optional_account(&ctx.accounts.source_agreement)?,
+            source_trusted_vault: load_optional_account::<TrustedVault>(
+                &ctx.accounts.source_trusted_vault,
+            )?
+            .is_some(),
+            destination_trusted_vault: load_optional_account::<TrustedVault>(
+                &ctx.accounts.destination_trusted_vault,
+            )?
+            .is_some(),
+            destination_consent: load_optional_account::<IncomingConsent>(
+                &ctx.accounts.destination_consent,
+            )?
+            .is_some(),
+            transfer_approved: transfer_request
+                .as_ref()
+                .is_some_and(|(_, request)| request.approves(amount)),
+            sanctions_list: sanctions_list.as_ref(),
+            credit_state: load_optional_account::<DebtInstrument>(&ctx.accounts.debt_instrument)?
+                .map(|debt| debt.state),
+            circuit_breaker: circuit_breaker.as_ref(),
+            now,
+        };
+
+        let evaluations = evaluate_transfer(&check);
+        if let Some(failed) = evaluations.iter().find(|evaluation| !evaluation.passed) {
+            if failed.rule_id == constants::RULE_SANCTIONS_SCREENING {
+                alert_sanctions_hit(&check);
+            }
+            return Err(reject_transfer(&check, failed.rule_id));
+        }
+
+        // An approval holds for the single transfer it was given for
+        if let Some((request_info, mut request)) = transfer_request {
+            if check.transfer_approved {
+                request.consumed = true;
+                request.try_serialize(&mut &mut request_info.try_borrow_mut_data()?[..])?;
+            }
+        }
+
+        // Meta lists written before holders were counted pass the token share
+        // read-only until migrated with `update_extra_account_meta_list`
+        let token_share_info = ctx.accounts.token_share.to_account_info();
+        if token_share_info.is_writable
+            && ctx.accounts.source_token.key() != ctx.accounts.destination_token.key()
+        {
+            let source_balance = ctx.accounts.source_token.amount;
+            let destination_balance = ctx.accounts.destination_token.amount;
+            let mut token_share = (*ctx.accounts.token_share).clone();
+            token_share.record_holding(source_balance.saturating_add(amount), source_balance)?;
+            token_share.record_holding(
+                destination_balance.saturating_sub(amount),
+                destination_balance,
+            )?;
+            token_share.try_serialize(&mut &mut token_share_info.try_borrow_mut_data()?[..])?;
+        }
+
+        invoke_chained_hook(
+            [
+                &ctx.accounts.source_token.to_account_info(),
+                &ctx.accounts.mint.to_account_info(),
+                &ctx.accounts.destination_token.to_account_info(),
+                &ctx.accounts.owner.to_account_info(),
+            ],
+            &ctx.accounts.extra_account_meta_list.to_account_info(),
+            ctx.remaining_accounts,
+            amount,
+        )?;
+
+        record_transfer_receipt(&ctx.accounts.transfer_receipts, &check, &evaluations)?;
+
+        // Due bills are kept per holder, so that any number of holders may trade
+        // while an action tracks them; both must have been opened
+        if let Some(ledger) = load_optional_account::<DueBillLedger>(&ctx.accounts.due_bill_ledger)?
+        {
+            record_due_bills(
+                &ledger,
+                ctx.remaining_accounts,
+                check.source_owner,
+                check.destination_owner,
+                amount,
+                now.slot,
+            )?;
+        }
+
+        // Moves between two accounts of the same holder leave its statement as is
+        if check.source_owner != check.destination_owner {
+            let memo_hash = transfer_memo_hash(ctx.remaining_accounts)?;
+            for (statement_info, received, sent) in [
+                (&ctx.accounts.source_statement, 0, amount),
+                (&ctx.accounts.destination_statement, amount, 0),
+            ] {
+                record_statement_activity(statement_info, received, sent, &now, memo_hash)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(from: Pubkey, to: Pubkey)]
+    pub struct ExplainTransfer<'info> {
+        /// The mint of the token that would be transferred
+        pub mint: InterfaceAccount<'info, Mint>,
+
+        /// Optional source token account of `from`, used to detect transfers
+        /// emptying it
+        #[account(
+            token::mint = mint,
+            constraint = source_token.owner == from @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub source_token: Option<InterfaceAccount<'info, TokenAccount>>,
+
+        /// Optional destination token account of `to`, used by balance-based rules
+        #[account(
+            token::mint = mint,
+            constraint = destination_token.owner == to @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub destination_token: Option<InterfaceAccount<'info, TokenAccount>>,
+
+        /// Market maker registration of the destination wallet, if any
+        /// CHECK: May not exist; deserialized in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"market-maker", mint.key().as_ref(), to.as_ref()],
+            bump,
+        )]
+        pub destination_market_maker: UncheckedAccount<'info>,
+
+        /// Agreement acceptance of the destination wallet, if any
+        /// CHECK: May not exist; deserialized in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"agreement-acceptance", mint.key().as_ref(), to.as_ref()],
+            bump,
+        )]
+        pub destination_agreement: UncheckedAccount<'info>,
+
+        /// Agreement acceptance of the source wallet, if any
+        /// CHECK: May not exist; deserialized in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"agreement-acceptance", mint.key().as_ref(), from.as_ref()],
+            bump,
+        )]
+        pub source_agreement: UncheckedAccount<'info>,
+
+        /// Trusted vault registration of the source wallet, if any
+        /// CHECK: May not exist; checked in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"trusted-vault", mint.key().as_ref(), from.as_ref()],
+            bump,
+        )]
+        pub source_trusted_vault: UncheckedAccount<'info>,
+
+        /// Trusted vault registration of the destination wallet, if any
+        /// CHECK: May not exist; checked in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"trusted-vault", mint.key().as_ref(), to.as_ref()],
+            bump,
+        )]
+        pub destination_trusted_vault: UncheckedAccount<'info>,
+
+        /// Token manager of the token, holding manager-wide settings
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Sanctions list of the token manager, if a screening provider was set
+        /// CHECK: May not exist; deserialized in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"sanctions-list", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub sanctions_list: UncheckedAccount<'info>,
+
+        /// Incoming consent of the destination wallet, if any
+        /// CHECK: May not exist; checked in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"incoming-consent", mint.key().as_ref(), to.as_ref()],
+            bump,
+        )]
+        pub destination_consent: UncheckedAccount<'info>,
+
+        /// Debt terms of the token, if it is a debt instrument
+        /// CHECK: May not exist; deserialized in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"debt-instrument", mint.key().as_ref()],
+            bump,
+        )]
+        pub debt_instrument: UncheckedAccount<'info>,
+
+        /// Circuit breaker of the token, if the issuer configured one
+        /// CHECK: May not exist; deserialized in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"circuit-breaker", mint.key().as_ref()],
+            bump,
+        )]
+        pub circuit_breaker: UncheckedAccount<'info>,
+
+        /// Configuration of the token that would be transferred
+        #[account(
+            constraint = token_share.mint == mint.key() @ TokenManagerError::TokenNotFound,
+            constraint = token_share.token_manager == token_manager.key()
+                @ TokenManagerError::TokenNotFound,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Authorization of the source wallet, if it is whitelisted
+        /// CHECK: May not exist; deserialized in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"whitelist", mint.key().as_ref(), from.as_ref()],
+            bump,
+        )]
+        pub source_entry: UncheckedAccount<'info>,
+
+        /// Authorization of the destination wallet, if it is whitelisted
+        /// CHECK: May not exist; deserialized in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"whitelist", mint.key().as_ref(), to.as_ref()],
+            bump,
+        )]
+        pub destination_entry: UncheckedAccount<'info>,
+
+        /// Lockup of the source wallet, if the issuer set one
+        /// CHECK: May not exist; deserialized in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"lockup", mint.key().as_ref(), from.as_ref()],
+            bump,
+        )]
+        pub source_lockup: UncheckedAccount<'info>,
+
+        /// Whitelist bucket of the source wallet, when the token uses buckets
+        /// CHECK: May not exist; deserialized in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"whitelist-bucket", mint.key().as_ref(), &from.as_ref()[..1]],
+            bump,
+        )]
+        pub source_bucket: Option<UncheckedAccount<'info>>,
+
+        /// Whitelist bucket of the destination wallet, when the token uses buckets
+        /// CHECK: May not exist; deserialized in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"whitelist-bucket", mint.key().as_ref(), &to.as_ref()[..1]],
+            bump,
+        )]
+        pub destination_bucket: Option<UncheckedAccount<'info>>,
+
+        /// Transfer request of the source wallet to the destination wallet, when
+        /// the token requires approved transfers
+        /// CHECK: May not exist; deserialized in the ExplainTransfer implementation
+        #[account(
+            seeds = [b"transfer-request", mint.key().as_ref(), from.as_ref(), to.as_ref()],
+            bump,
+        )]
+        pub transfer_request: Option<UncheckedAccount<'info>>,
+    }
+
+    /// Evaluates every transfer rule for a prospective transfer without failing.
+    /// Returns one entry per rule with its pass/fail result through return data,
+    /// so wallets can simulate it and explain a rejection before the user signs.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Wallet owning the source token account.
+    /// * `to` - Wallet owning the destination token account.
+    /// * `amount` - Amount of tokens, in base units, that would be transferred.
+    pub fn explain_transfer(
+        ctx: Context<ExplainTransfer>,
+        from: Pubkey,
+        to: Pubkey,
+        amount: u64,
+    ) -> Result<Vec<RuleEvaluation>> {
+        let sanctions_list = load_optional_account::<SanctionsList>(&ctx.accounts.sanctions_list)?;
+        let circuit_breaker =
+            load_optional_account::<CircuitBreaker>(&ctx.accounts.circuit_breaker)?;
+        Ok(evaluate_transfer(&TransferCheck {
+            token_manager: &ctx.accounts.token_manager,
+            token: &ctx.accounts.token_share,
+            source_entry: load_whitelist_entry(
+                &ctx.accounts.source_entry,
+                ctx.accounts.source_bucket.as_deref(),
+                &from,
+            )?,
+            destination_entry: load_whitelist_entry(
+                &ctx.accounts.destination_entry,
+                ctx.accounts.destination_bucket.as_deref(),
+                &to,
+            )?,
+            mint: ctx.accounts.mint.key(),
+            source_owner: from,
+            destination_owner: to,
+            amount,
+            empties_source: ctx
+                .accounts
+                .source_token
+                .as_ref()
+                .is_some_and(|source| source.amount == amount),
+            source_balance: ctx
+                .accounts
+                .source_token
+                .as_ref()
+                .map(|source| source.amount.saturating_sub(amount)),
+            source_lockup: load_optional_account(&ctx.accounts.source_lockup)?,
+            destination_balance: ctx
+                .accounts
+                .destination_token
+                .as_ref()
+                .map(|destination| destination.amount.saturating_add(amount)),
+            destination_market_maker: load_optional_account(
+                &ctx.accounts.destination_market_maker,
+            )?,
+            destination_agreement: load_optional_account(&ctx.accounts.destination_agreement)?,
+            source_agreement: load_optional_account(&ctx.accounts.source_agreement)?,
+            source_trusted_vault: load_optional_account::<TrustedVault>(
+                &ctx.accounts.source_trusted_vault,
+            )?
+            .is_some(),
+            destination_trusted_vault: load_optional_account::<TrustedVault>(
+                &ctx.accounts.destination_trusted_vault,
+            )?
+            .is_some(),
+            destination_consent: load_optional_account::<IncomingConsent>(
+                &ctx.accounts.destination_consent,
+            )?
+            .is_some(),
+            transfer_approved: match ctx.accounts.transfer_request.as_deref() {
+                Some(request) => load_optional_account::<TransferRequest>(request)?
+                    .is_some_and(|request| request.approves(amount)),
+                None => false,
+            },
+            sanctions_list: sanctions_list.as_ref(),
+            credit_state: load_optional_account::<DebtInstrument>(&ctx.accounts.debt_instrument)?
+                .map(|debt| debt.state),
+            circuit_breaker: circuit_breaker.as_ref(),
+            now: now(ctx.remaining_accounts)?,
+        }))
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct SettleBatch<'info> {
+        /// Venue settling the batch, owner or delegate of every source account
+        pub venue: Signer<'info>,
+
+        /// Token manager of the security, holding manager-wide settings
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the security being settled
+        #[account(
+            mut,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Sanctions list of the token manager, if a screening provider was set
+        /// CHECK: May not exist; deserialized when settling security transfers
+        #[account(
+            seeds = [b"sanctions-list", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub sanctions_list: UncheckedAccount<'info>,
+
+        /// Debt terms of the security, if it is a debt instrument
+        /// CHECK: May not exist; deserialized when settling security transfers
+        #[account(
+            seeds = [b"debt-instrument", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub debt_instrument: UncheckedAccount<'info>,
+
+        /// Circuit breaker of the security, if the issuer configured one
+        /// CHECK: May not exist; deserialized when settling security transfers
+        #[account(
+            seeds = [b"circuit-breaker", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub circuit_breaker: UncheckedAccount<'info>,
+
+        /// Registration of the external hook chained to the security, if any
+        /// CHECK: May not exist; deserialized when settling security transfers
+        #[account(
+            seeds = [b"chained-hook", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub chained_hook: UncheckedAccount<'info>,
+
+        /// The mint of the security, whose supply the security legs burn and re-mint
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub security_mint: InterfaceAccount<'info, Mint>,
+
+        /// The mint the cash legs are paid in
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Payment mints approved by the issuer
+        #[account(
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+            constraint = payment_mints.is_approved(&payment_mint)
+                @ TokenManagerError::PaymentMintNotApproved,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Token program interface for SPL Token 2022
+        pub token_program: Program<'info, Token2022>,
+
+        /// Token program of the payment mint
+        pub payment_token_program: Interface<'info, TokenInterface>,
+
+        /// Fee schedule of the token manager, if the issuer set one
+        /// CHECK: May not exist; deserialized in the instruction
+        #[account(
+            seeds = [b"fee-schedule", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub fee_schedule: UncheckedAccount<'info>,
+
+        /// Payment account of the issuer receiving transfer fees, required when the
+        /// cash legs are charged a fee
+        #[account(
+            mut,
+            token::mint = payment_mint,
+            token::authority = token_manager.creator,
+        )]
+        pub fee_collector: Option<InterfaceAccount<'info, TokenAccount>>,
+    }
+
+    /// Settles the security and cash legs of a netting cycle of a token identified
+    /// by its ISIN atomically, at most `constants::MAX_SETTLEMENT_LEGS` per batch.
+    /// Token accounts of every leg are passed as remaining accounts and referenced
+    /// by index; the venue must own or be delegated every source account. The
+    /// whitelist entries or buckets and the lockups of the parties to security legs
+    /// are passed alongside them, as are their market maker registrations,
+    /// agreement acceptances, trusted vault registrations and incoming consents
+    /// when the transfer rules depend on them. While a corporate action tracks due
+    /// bills, the due bill ledger of the token and the due bills of the parties are
+    /// passed too, and updated as the hook does. So are the receipt log of the
+    /// token and the holder statements of the parties, when enabled or opened,
+    /// with the instructions sysvar to record the memo preceding the settlement.
+    ///
+    /// Token-2022 cannot invoke the hook of this program from within one of its
+    /// instructions, so security legs are checked against the transfer rules here
+    /// and settled by burning from the seller and minting to the buyer. Securities
+    /// the issuer chained an external hook to must settle through regular transfers.
+    ///
+    /// Cash legs are charged the transfer fee of the current fee schedule, taken
+    /// from the amount received and paid to `fee_collector`. The batch reverts if
+    /// the schedule is no longer at `expected_fee_version`, the version the venue
+    /// quoted, 0 when no schedule was set.
+    pub fn settle_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SettleBatch<'info>>,
+        _isin: Isin,
+        legs: Vec<SettlementLeg>,
+        expected_fee_version: u32,
+    ) -> Result<()> {
+        if legs.len() > constants::MAX_SETTLEMENT_LEGS as usize {
+            return Err(error!(TokenManagerError::TooManySettlementLegs));
+        }
+        let fee_schedule = load_optional_account::<FeeSchedule>(&ctx.accounts.fee_schedule)?;
+        check_fee_version(
+            fee_schedule.as_ref().map_or(0, |schedule| schedule.version),
+            expected_fee_version,
+        )?;
+
+        let mut settlement = Settlement {
+            venue: ctx.accounts.venue.to_account_info(),
+            token_manager: &ctx.accounts.token_manager,
+            token_share: &mut ctx.accounts.token_share,
+            security_mint: &ctx.accounts.security_mint,
+            security_mint_bump: ctx.bumps.security_mint,
+            payment_mint: Some(&ctx.accounts.payment_mint),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            payment_token_program: Some(ctx.accounts.payment_token_program.to_account_info()),
+            sanctions_list: load_optional_account(&ctx.accounts.sanctions_list)?,
+            credit_state: load_optional_account::<DebtInstrument>(&ctx.accounts.debt_instrument)?
+                .map(|debt| debt.state),
+            circuit_breaker: load_optional_account(&ctx.accounts.circuit_breaker)?,
+            chained_hook: load_optional_account::<ChainedHook>(&ctx.accounts.chained_hook)?
+                .is_some(),
+            transfer_fee_bps: fee_schedule.map_or(0, |schedule| schedule.terms.transfer_fee_bps),
+            fee_collector: ctx
+                .accounts
+                .fee_collector
+                .as_ref()
+                .map(|collector| collector.to_account_info()),
+            transfer_approved: false,
+            now: now(ctx.remaining_accounts)?,
+        };
+        for leg in &legs {
+            settlement.settle(leg, ctx.remaining_accounts)?;
+        }
+
+        emit!(SettlementBatchExecuted {
+            mint: ctx.accounts.security_mint.key(),
+            payment_mint: ctx.accounts.payment_mint.key(),
+            legs: legs.len() as u8,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct RegisterTrade<'info> {
+        /// Venue the trade was executed on, paying for the netting cycle
+        #[account(mut)]
+        pub venue: Signer<'info>,
+
+        /// Token manager of the security
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the traded security
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The mint the trade is paid in
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Payment mints approved by the issuer
+        #[account(
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+            constraint = payment_mints.is_approved(&payment_mint)
+                @ TokenManagerError::PaymentMintNotApproved,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Open obligations of the venue for the security, created on first use
+        #[account(
+            init_if_needed,
+            payer = venue,
+            space = 8 + NettingCycle::INIT_SPACE,
+            seeds = [b"netting-cycle", token_share.mint.as_ref(), venue.key().as_ref()],
+            bump,
+        )]
+        pub netting_cycle: Account<'info, NettingCycle>,
+
+        /// Fee schedule of the token manager, if the issuer set one
+        /// CHECK: May not exist; deserialized in the instruction
+        #[account(
+            seeds = [b"fee-schedule", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub fee_schedule: UncheckedAccount<'info>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Records a gross trade obligation of the venue for a token identified by its
+    /// ISIN: `seller` owes `quantity` tokens to `buyer`, who owes `cash_amount` of the
+    /// payment mint in return. Obligations settle net at the end of the cycle through
+    /// `net_and_settle`.
+    ///
+    /// The cycle settles with the fee schedule in force when it opened, which must
+    /// be at `expected_fee_version`, 0 when no schedule was set.
+    pub fn register_trade(
+        ctx: Context<RegisterTrade>,
+        _isin: Isin,
+        seller: Pubkey,
+        buyer: Pubkey,
+        quantity: u64,
+        cash_amount: u64,
+        expected_fee_version: u32,
+    ) -> Result<()> {
+        let netting_cycle = &mut ctx.accounts.netting_cycle;
+        if netting_cycle.obligations.is_empty() {
+            let fee_schedule = load_optional_account::<FeeSchedule>(&ctx.accounts.fee_schedule)?;
+            netting_cycle.mint = ctx.accounts.token_share.mint;
+            netting_cycle.venue = ctx.accounts.venue.key();
+            netting_cycle.payment_mint = ctx.accounts.payment_mint.key();
+            netting_cycle.fee_version =
+                fee_schedule.as_ref().map_or(0, |schedule| schedule.version);
+            netting_cycle.transfer_fee_bps =
+                fee_schedule.map_or(0, |schedule| schedule.terms.transfer_fee_bps);
+        } else if netting_cycle.payment_mint != ctx.accounts.payment_mint.key() {
+            return Err(error!(TokenManagerError::PaymentMintMismatch));
+        }
+        check_fee_version(netting_cycle.fee_version, expected_fee_version)?;
+        if netting_cycle.obligations.len() >= 16 {
+            return Err(error!(TokenManagerError::NettingCycleFull));
+        }
+
+        netting_cycle.obligations.push(TradeObligation {
+            seller,
+            buyer,
+            quantity,
+            cash_amount,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct NetAndSettle<'info> {
+        /// Venue whose netting cycle is settled, owner or delegate of every paying account
+        pub venue: Signer<'info>,
+
+        /// Token manager of the security, holding manager-wide settings
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the security being settled
+        #[account(
+            mut,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Sanctions list of the token manager, if a screening provider was set
+        /// CHECK: May not exist; deserialized when settling security transfers
+        #[account(
+            seeds = [b"sanctions-list", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub sanctions_list: UncheckedAccount<'info>,
+
+        /// Debt terms of the security, if it is a debt instrument
+        /// CHECK: May not exist; deserialized when settling security transfers
+        #[account(
+            seeds = [b"debt-instrument", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub debt_instrument: UncheckedAccount<'info>,
+
+        /// Circuit breaker of the security, if the issuer configured one
+        /// CHECK: May not exist; deserialized when settling security transfers
+        #[account(
+            seeds = [b"circuit-breaker", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub circuit_breaker: UncheckedAccount<'info>,
+
+        /// Registration of the external hook chained to the security, if any
+        /// CHECK: May not exist; deserialized when settling security transfers
+        #[account(
+            seeds = [b"chained-hook", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub chained_hook: UncheckedAccount<'info>,
+
+        /// The mint of the security, whose supply the security legs burn and re-mint
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub security_mint: InterfaceAccount<'info, Mint>,
+
+        /// The mint the obligations are paid in
+        #[account(address = netting_cycle.payment_mint @ TokenManagerError::PaymentMintMismatch)]
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Payment mints approved by the issuer
+        #[account(
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+            constraint = payment_mints.is_approved(&payment_mint)
+                @ TokenManagerError::PaymentMintNotApproved,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Open obligations of the venue, cleared once settled
+        #[account(
+            mut,
+            seeds = [b"netting-cycle", security_mint.key().as_ref(), venue.key().as_ref()],
+            bump,
+        )]
+        pub netting_cycle: Account<'info, NettingCycle>,
+
+        /// Token program interface for SPL Token 2022
+        pub token_program: Program<'info, Token2022>,
+
+        /// Token program of the payment mint
+        pub payment_token_program: Interface<'info, TokenInterface>,
+
+        /// Payment account of the issuer receiving transfer fees, required when the
+        /// cash legs are charged a fee
+        #[account(
+            mut,
+            token::mint = payment_mint,
+            token::authority = token_manager.creator,
+        )]
+        pub fee_collector: Option<InterfaceAccount<'info, TokenAccount>>,
+    }
+
+    /// Nets the open obligations of the venue per counterparty and settles the net
+    /// positions with as few transfers as possible, following the same rules as
+    /// `settle_batch`, with the transfer fee in force when the cycle opened. The security and payment token accounts of every
+    /// counterparty are passed as remaining accounts, in any order, along with the
+    /// whitelist entries or buckets, the lockups and the other per-wallet records of
+    /// the counterparties exchanging securities.
+    pub fn net_and_settle<'info>(
+        ctx: Context<'_, '_, 'info, 'info, NetAndSettle<'info>>,
+        _isin: Isin,
+    ) -> Result<()> {
+        let obligations = std::mem::take(&mut ctx.accounts.netting_cycle.obligations);
+        let mut positions = NetPositions::default();
+        for obligation in &obligations {
+            positions.add(
+                obligation.seller,
+                -(obligation.quantity as i128),
+                obligation.cash_amount as i128,
+            );
+            positions.add(
+                obligation.buyer,
+                obligation.quantity as i128,
+                -(obligation.cash_amount as i128),
+            );
+        }
+
+        let security_mint = ctx.accounts.security_mint.key();
+        let payment_mint = ctx.accounts.payment_mint.key();
+        let mut legs = Vec::new();
+        for (asset, mint, transfers) in [
+            (
+                LegAsset::Security,
+                security_mint,
+                positions.transfers(|position| position.1),
+            ),
+            (
+                LegAsset::Cash,
+                payment_mint,
+                positions.transfers(|position| position.2),
+            ),
+        ] {
+            for (from, to, amount) in transfers {
+                legs.push(SettlementLeg {
+                    asset,
+                    from: find_token_account(ctx.remaining_accounts, &from, &mint)?,
+                    to: find_token_account(ctx.remaining_accounts, &to, &mint)?,
+                    amount,
+                });
+            }
+        }
+
+        let mut settlement = Settlement {
+            venue: ctx.accounts.venue.to_account_info(),
+            token_manager: &ctx.accounts.token_manager,
+            token_share: &mut ctx.accounts.token_share,
+            security_mint: &ctx.accounts.security_mint,
+            security_mint_bump: ctx.bumps.security_mint,
+            payment_mint: Some(&ctx.accounts.payment_mint),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            payment_token_program: Some(ctx.accounts.payment_token_program.to_account_info()),
+            sanctions_list: load_optional_account(&ctx.accounts.sanctions_list)?,
+            credit_state: load_optional_account::<DebtInstrument>(&ctx.accounts.debt_instrument)?
+                .map(|debt| debt.state),
+            circuit_breaker: load_optional_account(&ctx.accounts.circuit_breaker)?,
+            chained_hook: load_optional_account::<ChainedHook>(&ctx.accounts.chained_hook)?
+                .is_some(),
+            transfer_fee_bps: ctx.accounts.netting_cycle.transfer_fee_bps,
+            fee_collector: ctx
+                .accounts
+                .fee_collector
+                .as_ref()
+                .map(|collector| collector.to_account_info()),
+            transfer_approved: false,
+            now: now(ctx.remaining_accounts)?,
+        };
+        for leg in &legs {
+            settlement.settle(leg, ctx.remaining_accounts)?;
+        }
+
+        emit!(NettingCycleSettled {
+            mint: security_mint,
+            venue: ctx.accounts.venue.key(),
+            trades: obligations.len() as u8,
+            transfers: legs.len() as u8,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, fail_id: u64)]
+    pub struct OpenSettlementFail<'info> {
+        /// Venue the failed trade was executed on, paying for the record
+        #[account(mut)]
+        pub venue: Signer<'info>,
+
+        /// Token manager of the security
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the traded security
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The mint penalties are charged in
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Payment mints approved by the issuer
+        #[account(
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+            constraint = payment_mints.is_approved(&payment_mint)
+                @ TokenManagerError::PaymentMintNotApproved,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Tracking of the failed settlement
+        #[account(
+            init,
+            payer = venue,
+            space = 8 + SettlementFail::INIT_SPACE,
+            seeds = [
+                b"settlement-fail",
+                token_share.mint.as_ref(),
+                venue.key().as_ref(),
+                &fail_id.to_le_bytes(),
+            ],
+            bump,
+        )]
+        pub settlement_fail: Account<'info, SettlementFail>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Starts tracking a trade of a token identified by its ISIN that the failing
+    /// party did not settle by its intended settlement date. Penalties accrue from
+    /// that date until the trade settles or a buy-in is declared.
+    pub fn open_settlement_fail(
+        ctx: Context<OpenSettlementFail>,
+        _isin: Isin,
+        fail_id: u64,
+        trade: FailedTrade,
+    ) -> Result<()> {
+        let settlement_fail = &mut ctx.accounts.settlement_fail;
+        settlement_fail.set_inner(SettlementFail {
+            mint: ctx.accounts.token_share.mint,
+            venue: ctx.accounts.venue.key(),
+            id: fail_id,
+            failing_party: trade.failing_party,
+            counterparty: trade.counterparty,
+            payment_mint: ctx.accounts.payment_mint.key(),
+            quantity: trade.quantity,
+            cash_amount: trade.cash_amount,
+            intended_settlement: trade.intended_settlement,
+            buy_in_declared_at: None,
+        });
+
+        emit!(SettlementFailOpened {
+            mint: settlement_fail.mint,
+            id: fail_id,
+            failing_party: trade.failing_party,
+            counterparty: trade.counterparty,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct DeclareBuyIn<'info> {
+        /// The counterparty of the failing party
+        pub counterparty: Signer<'info>,
+
+        /// Token manager of the security, holding manager-wide settings
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the traded security
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Tracking of the failed settlement
+        #[account(
+            mut,
+            constraint = settlement_fail.mint == token_share.mint @ TokenManagerError::InvalidTokenAccount,
+            constraint = settlement_fail.counterparty == counterparty.key() @ TokenManagerError::Unauthorized,
+        )]
+        pub settlement_fail: Account<'info, SettlementFail>,
+    }
+
+    /// Escalates a failed settlement of a token identified by its ISIN to a buy-in,
+    /// once it is late by the configured number of days. Penalties stop accruing
+    /// from that point on.
+    pub fn declare_buy_in(ctx: Context<DeclareBuyIn>, _isin: Isin) -> Result<()> {
+        let now = now(ctx.remaining_accounts)?;
+        let settlement_fail = &mut ctx.accounts.settlement_fail;
+        if settlement_fail.buy_in_declared_at.is_some() {
+            return Err(error!(TokenManagerError::BuyInAlreadyDeclared));
+        }
+        let buy_in_from = settlement_fail.intended_settlement.saturating_add(
+            i64::from(ctx.accounts.token_share.buy_in_after_days) * SECONDS_PER_DAY,
+        );
+        if !now.has_passed(
+            buy_in_from,
+            ctx.accounts.token_manager.clock_tolerance_seconds,
+        ) {
+            return Err(error!(TokenManagerError::BuyInNotYetAllowed));
+        }
+
+        settlement_fail.buy_in_declared_at = Some(now.unix_timestamp);
+        emit!(BuyInDeclared {
+            mint: settlement_fail.mint,
+            id: settlement_fail.id,
+            penalty: settlement_fail.accrued_penalty(
+                now.unix_timestamp,
+                ctx.accounts.token_share.fail_penalty_bps_per_day,
+            ),
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct CloseSettlementFail<'info> {
+        /// Venue that opened the record, owner or delegate of the failing party's
+        /// payment account, receiving the reclaimed rent
+        #[account(mut)]
+        pub venue: Signer<'info>,
+
+        /// Token manager of the security
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the traded security
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The mint penalties are charged in
+        #[account(address = settlement_fail.payment_mint @ TokenManagerError::PaymentMintMismatch)]
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Payment mints approved by the issuer
+        #[account(
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+            constraint = payment_mints.is_approved(&payment_mint)
+                @ TokenManagerError::PaymentMintNotApproved,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Tracking of the failed settlement, closed once the penalty is charged
+        #[account(
+            mut,
+            close = venue,
+            has_one = venue @ TokenManagerError::Unauthorized,
+            constraint = settlement_fail.mint == token_share.mint @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub settlement_fail: Account<'info, SettlementFail>,
+
+        /// Payment account of the failing party, charged the penalty
+        #[account(
+            mut,
+            token::mint = payment_mint,
+            token::authority = settlement_fail.failing_party,
+        )]
+        pub failing_party_payment: InterfaceAccount<'info, TokenAccount>,
+
+        /// Payment account of the counterparty, credited the penalty
+        #[account(
+            mut,
+            token::mint = payment_mint,
+            token::authority = settlement_fail.counterparty,
+        )]
+        pub counterparty_payment: InterfaceAccount<'info, TokenAccount>,
+
+        /// Token program of the payment mint
+        pub payment_token_program: Interface<'info, TokenInterface>,
+    }
+
+    /// Closes a failed settlement of a token identified by its ISIN once the trade
+    /// settled or was bought in, charging the accrued penalty to the failing party
+    /// in favour of its counterparty.
+    pub fn close_settlement_fail(ctx: Context<CloseSettlementFail>, _isin: Isin) -> Result<()> {
+        let settlement_fail = &ctx.accounts.settlement_fail;
+        let now = now(ctx.remaining_accounts)?;
+        let penalty = settlement_fail.accrued_penalty(
+            settlement_fail
+                .buy_in_declared_at
+                .unwrap_or(now.unix_timestamp),
+            ctx.accounts.token_share.fail_penalty_bps_per_day,
+        );
+
+        if penalty > 0 {
+            transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.payment_token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.failing_party_payment.to_account_info(),
+                        mint: ctx.accounts.payment_mint.to_account_info(),
+                        to: ctx.accounts.counterparty_payment.to_account_info(),
+                        authority: ctx.accounts.venue.to_account_info(),
+                    },
+                ),
+                penalty,
+                ctx.accounts.payment_mint.decimals,
+            )?;
+        }
+
+        emit!(SettlementFailClosed {
+            mint: settlement_fail.mint,
+            id: settlement_fail.id,
+            penalty,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, offer_id: u64)]
+    pub struct CreateOffer<'info> {
+        /// The creator of the token manager, paying for the offer accounts
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// Token manager of the security
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the security offered or bought back
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The mint the offer is paid in
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Payment mints approved by the issuer
+        #[account(
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+            constraint = payment_mints.is_approved(&payment_mint)
+                @ TokenManagerError::PaymentMintNotApproved,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// The offer being created
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + Offer::INIT_SPACE,
+            seeds = [b"offer", token_share.mint.as_ref(), &offer_id.to_le_bytes()],
+            bump,
+        )]
+        pub offer: Account<'info, Offer>,
+
+        /// Fee schedule of the token manager, if the issuer set one
+        /// CHECK: May not exist; deserialized in the CreateOffer implementation
+        #[account(
+            seeds = [b"fee-schedule", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub fee_schedule: UncheckedAccount<'info>,
+
+        /// Cash escrow of the offer: subscription deposits, or the buyback budget
+        /// funded by the issuer
+        #[account(
+            init,
+            payer = signer,
+            seeds = [b"offer-vault", offer.key().as_ref()],
+            bump,
+            token::mint = payment_mint,
+            token::authority = offer,
+            token::token_program = payment_token_program,
+        )]
+        pub offer_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// Token program of the payment mint
+        pub payment_token_program: Interface<'info, TokenInterface>,
+
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Opens a subscription or buyback offer on a token identified by its ISIN.
+    /// `price` is in payment mint units per token unit and `cap` is the number of
+    /// token units issued or bought back at most. Orders are accepted until
+    /// `closes_at`, then filled pro-rata if the offer is oversubscribed.
+    ///
+    /// The fees of the current fee schedule are pinned on the offer, so that later
+    /// changes to the schedule never apply to its orders.
+    pub fn create_offer(
+        ctx: Context<CreateOffer>,
+        _isin: Isin,
+        offer_id: u64,
+        kind: OfferKind,
+        price: u64,
+        cap: u64,
+        closes_at: i64,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let fee_schedule = load_optional_account::<FeeSchedule>(&ctx.accounts.fee_schedule)?;
+        let fees = fee_schedule.map_or(
+            OfferFees {
+                schedule_version: 0,
+                fee_bps: 0,
+                admin_fee: 0,
+            },
+            |schedule| OfferFees {
+                schedule_version: schedule.version,
+                fee_bps: match kind {
+                    OfferKind::Subscription => schedule.terms.subscription_fee_bps,
+                    OfferKind::Buyback => schedule.terms.redemption_fee_bps,
+                },
+                admin_fee: schedule.terms.admin_fee,
+            },
+        );
+        let offer = &mut ctx.accounts.offer;
+        offer.set_inner(Offer {
+            mint: ctx.accounts.token_share.mint,
+            id: offer_id,
+            kind,
+            payment_mint: ctx.accounts.payment_mint.key(),
+            price,
+            cap,
+            total_requested: 0,
+            order_count: 0,
+            settled_orders: 0,
+            closes_at,
+            fx_snapshot: None,
+            fees,
+            bump: ctx.bumps.offer,
+        });
+
+        emit!(OfferCreated {
+            mint: offer.mint,
+            id: offer_id,
+            kind,
+            price,
+            cap,
+            closes_at,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct OfferCash<'info> {
+        /// The creator of the token manager
+        pub signer: Signer<'info>,
+
+        /// Token manager of the security
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the security offered or bought back
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The offer whose vault is funded or emptied
+        #[account(constraint = offer.mint == token_share.mint @ TokenManagerError::InvalidTokenAccount)]
+        pub offer: Account<'info, Offer>,
+
+        /// The mint the offer is paid in
+        #[account(address = offer.payment_mint @ TokenManagerError::PaymentMintMismatch)]
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Cash escrow of the offer
+        #[account(
+            mut,
+            seeds = [b"offer-vault", offer.key().as_ref()],
+            bump,
+        )]
+        pub offer_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// Payment account of the issuer
+        #[account(mut, token::mint = payment_mint, token::authority = signer)]
+        pub issuer_payment: InterfaceAccount<'info, TokenAccount>,
+
+        /// Token program of the payment mint
+        pub payment_token_program: Interface<'info, TokenInterface>,
+    }
+
+    /// Deposits `amount` payment mint units from `issuer_payment` into the vault
+    /// of an offer of a token identified by its ISIN, the budget buyback tenders
+    /// are paid from.
+    pub fn fund_offer(ctx: Context<OfferCash>, _isin: Isin, amount: u64) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        transfer_checked(
+            CpiContext::new(
+                ctx.accounts.payment_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.issuer_payment.to_account_info(),
+                    mint: ctx.accounts.payment_mint.to_account_info(),
+                    to: ctx.accounts.offer_vault.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.payment_mint.decimals,
+        )?;
+
+        emit!(OfferFunded {
+            mint: ctx.accounts.offer.mint,
+            id: ctx.accounts.offer.id,
+            amount,
+        });
+        Ok(())
+    }
+
+    /// Returns what is left in the vault of an offer of a token identified by its
+    /// ISIN to `issuer_payment` once the offer closed and every order was settled,
+    /// such as an unused buyback budget.
+    ///
+    /// The offer and its vault stay open, since cash swept to a yield program is
+    /// only recalled through them; closing again returns what was recalled.
+    pub fn close_offer(ctx: Context<OfferCash>, _isin: Isin) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let offer = &ctx.accounts.offer;
+        if !now(ctx.remaining_accounts)?.has_passed(
+            offer.closes_at,
+            ctx.accounts.token_manager.clock_tolerance_seconds,
+        ) {
+            return Err(error!(TokenManagerError::OfferStillOpen));
+        }
+        if offer.settled_orders < offer.order_count {
+            return Err(error!(TokenManagerError::OfferOrdersPending));
+        }
+
+        let returned = ctx.accounts.offer_vault.amount;
+        let offer_seeds = &[
+            b"offer".as_ref(),
+            offer.mint.as_ref(),
+            &offer.id.to_le_bytes(),
+            &[offer.bump],
+        ];
+        transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.payment_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.offer_vault.to_account_info(),
+                    mint: ctx.accounts.payment_mint.to_account_info(),
+                    to: ctx.accounts.issuer_payment.to_account_info(),
+                    authority: offer.to_account_info(),
+                },
+                &[&offer_seeds[..]],
+            ),
+            returned,
+            ctx.accounts.payment_mint.decimals,
+        )?;
+
+        emit!(OfferCashReturned {
+            mint: offer.mint,
+            id: offer.id,
+            returned,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct EnableOfferReceipts<'info> {
+        /// The creator of the token manager, paying for the receipt mint
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// Token manager of the security
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the security
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The offer whose orders get receipts
+        #[account(constraint = offer.mint == token_share.mint @ TokenManagerError::InvalidTokenAccount)]
+        pub offer: Account<'info, Offer>,
+
+        /// The mint of the security, whose decimals the receipts use
+        #[account(address = token_share.mint @ TokenManagerError::InvalidTokenAccount)]
+        pub security_mint: InterfaceAccount<'info, Mint>,
+
+        /// Receipt mint of the offer
+        /// CHECK: Created in the EnableOfferReceipts implementation
+        #[account(
+            mut,
+            seeds = [b"offer-receipt", offer.key().as_ref()],
+            bump,
+        )]
+        pub receipt_mint: UncheckedAccount<'info>,
+
+        pub token_program: Program<'info, Token2022>,
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Creates the receipt mint of an offer on a token identified by its ISIN.
+    /// From then on every order mints a non-transferable receipt of the ordered
+    /// units to the investor, burned when the order is settled, so custodial
+    /// reporting tools still see the escrowed position in the wallet.
+    pub fn enable_offer_receipts(ctx: Context<EnableOfferReceipts>, _isin: Isin) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        let receipt_mint = ctx.accounts.receipt_mint.to_account_info();
+        if !receipt_mint.data_is_empty() {
+            return Err(error!(TokenManagerError::OfferReceiptsAlreadyEnabled));
+        }
+
+        let offer = ctx.accounts.offer.key();
+        let space = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(
+            &RECEIPT_MINT_EXTENSIONS,
+        )?;
+        let token_program = ctx.accounts.token_program.to_account_info();
+        invoke_signed(
+            &system_instruction::create_account(
+                ctx.accounts.signer.key,
+                receipt_mint.key,
+                Rent::get()?.minimum_balance(space),
+                space as u64,
+                token_program.key,
+            ),
+            &[
+                ctx.accounts.signer.to_account_info(),
+                receipt_mint.clone(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            &[&[b"offer-receipt", offer.as_ref(), &[ctx.bumps.receipt_mint]]],
+        )?;
+        invoke(
+            &spl_token_2022::instruction::initialize_non_transferable_mint(
+                token_program.key,
+                receipt_mint.key,
+            )?,
+            std::slice::from_ref(&receipt_mint),
+        )?;
+        // The mint is the permanent delegate of the receipts, so that it burns them
+        // at settlement without the investor signing
+        invoke(
+            &spl_token_2022::instruction::initialize_permanent_delegate(
+                token_program.key,
+                receipt_mint.key,
+                receipt_mint.key,
+            )?,
+            std::slice::from_ref(&receipt_mint),
+        )?;
+        invoke(
+            &spl_token_2022::instruction::initialize_mint2(
+                token_program.key,
+                receipt_mint.key,
+                receipt_mint.key,
+                None,
+                ctx.accounts.security_mint.decimals,
+            )?,
+            std::slice::from_ref(&receipt_mint),
+        )?;
+
+        emit!(OfferReceiptsEnabled {
+            mint: ctx.accounts.offer.mint,
+            offer,
+            receipt_mint: receipt_mint.key(),
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct PlaceOrder<'info> {
+        /// Pays for the order record: the investor or an allowlisted relayer
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// The investor subscribing or tendering
+        pub investor: Signer<'info>,
+
+        /// Token manager of the security
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the security
+        #[account(
+            mut,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Whitelist entry of the investor, which subscribers must have
+        /// CHECK: May not exist; deserialized in the PlaceOrder implementation
+        #[account(
+            seeds = [b"whitelist", token_share.mint.as_ref(), investor.key().as_ref()],
+            bump,
+        )]
+        pub whitelist_entry: UncheckedAccount<'info>,
+
+        /// Relayers allowed to pay for investor actions, if any were configured
+        #[account(
+            seeds = [b"relayers", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub relayer_registry: Option<Account<'info, RelayerRegistry>>,
+
+        /// The offer the order is placed on
+        #[account(
+            mut,
+            constraint = offer.mint == token_share.mint @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub offer: Account<'info, Offer>,
+
+        /// The mint of the security, from which tendered tokens are burnt
+        #[account(mut, address = token_share.mint @ TokenManagerError::InvalidTokenAccount)]
+        pub security_mint: InterfaceAccount<'info, Mint>,
+
+        /// The mint the offer is paid in
+        #[account(address = offer.payment_mint @ TokenManagerError::PaymentMintMismatch)]
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Cash escrow of the offer
+        #[account(
+            mut,
+            seeds = [b"offer-vault", offer.key().as_ref()],
+            bump,
+        )]
+        pub offer_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// Security account of the investor, debited by tenders
+        #[account(
+            mut,
+            token::mint = security_mint,
+            token::authority = investor,
+        )]
+        pub investor_security: InterfaceAccount<'info, TokenAccount>,
+
+        /// Payment account of the investor, debited by subscriptions
+        #[account(
+            mut,
+            token::mint = payment_mint,
+            token::authority = investor,
+        )]
+        pub investor_payment: InterfaceAccount<'info, TokenAccount>,
+
+        /// Order of the investor, topped up by every call before the offer closes
+        #[account(
+            init_if_needed,
+            payer = payer,
+            space = 8 + OfferOrder::INIT_SPACE,
+            seeds = [b"offer-order", offer.key().as_ref(), investor.key().as_ref()],
+            bump,
+        )]
+        pub order: Account<'info, OfferOrder>,
+
+        /// Receipt mint of the offer, if the issuer enabled receipts
+        /// CHECK: May not exist; checked in the PlaceOrder implementation
+        #[account(
+            mut,
+            seeds = [b"offer-receipt", offer.key().as_ref()],
+            bump,
+        )]
+        pub receipt_mint: UncheckedAccount<'info>,
+
+        /// Receipt account of the investor, created with the first receipt
+        /// CHECK: May not exist; created in the PlaceOrder implementation
+        #[account(
+            mut,
+            seeds = [b"offer-receipt", offer.key().as_ref(), investor.key().as_ref()],
+            bump,
+        )]
+        pub investor_receipt: UncheckedAccount<'info>,
+
+        pub token_program: Program<'info, Token2022>,
+
+        /// Token program of the payment mint
+        pub payment_token_program: Interface<'info, TokenInterface>,
+
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Places an order of `amount` token units on an offer of a token identified
+    /// by its ISIN. Subscriptions deposit the full price and the subscription fee,
+    /// plus the admin fee with the first order, in the offer vault and tenders burn
+    /// the tendered tokens; the unfilled portion is returned when the order is
+    /// settled. Tenders are only accepted while the vault holds the price of
+    /// every unit tendered so far up to the cap, funded with `fund_offer`.
+    ///
+    /// The order reverts unless the offer is still at `expected_price` with the
+    /// fees of `expected_fee_version`, the terms the investor was shown, and for
+    /// an empty `amount`.
+    pub fn place_order(
+        ctx: Context<PlaceOrder>,
+        _isin: Isin,
+        amount: u64,
+        expected_price: u64,
+        expected_fee_version: u32,
+    ) -> Result<()> {
+        check_sponsor(
+            &ctx.accounts.payer.key(),
+            &ctx.accounts.investor.key(),
+            ctx.accounts.relayer_registry.as_deref(),
+        )?;
+        if amount == 0 {
+            return Err(error!(TokenManagerError::InvalidOrderAmount));
+        }
+        let offer = &ctx.accounts.offer;
+        if offer.price != expected_price {
+            return Err(error!(TokenManagerError::PriceChanged));
+        }
+        check_fee_version(offer.fees.schedule_version, expected_fee_version)?;
+        let now = now(ctx.remaining_accounts)?;
+        if now.may_have_passed(
+            offer.closes_at,
+            ctx.accounts.token_manager.clock_tolerance_seconds,
+        ) {
+            return Err(error!(TokenManagerError::OfferClosed));
+        }
+
+        let (mut fee, mut admin_fee) = (0, 0);
+        match offer.kind {
+            OfferKind::Subscription => {
+                if load_optional_account::<WhitelistEntry>(&ctx.accounts.whitelist_entry)?.is_none()
+                {
+                    return Err(error!(TokenManagerError::WalletNotFound));
+                }
+                let cash = offer.cash_for(amount)?;
+                fee = fee_on(cash, offer.fees.fee_bps);
+                if ctx.accounts.order.requested == 0 {
+                    admin_fee = offer.fees.admin_fee;
+                }
+                let deposit = cash
+                    .checked_add(fee)
+                    .and_then(|deposit| deposit.checked_add(admin_fee))
+                    .ok_or(error!(TokenManagerError::AmountOverflow))?;
+                transfer_checked(
+                    CpiContext::new(
+                        ctx.accounts.payment_token_program.to_account_info(),
+                        TransferChecked {
+                            from: ctx.accounts.investor_payment.to_account_info(),
+                            mint: ctx.accounts.payment_mint.to_account_info(),
+                            to: ctx.accounts.offer_vault.to_account_info(),
+                            authority: ctx.accounts.investor.to_account_info(),
+                        },
+                    ),
+                    deposit,
+                    ctx.accounts.payment_mint.decimals,
+                )?;
+            }
+            OfferKind::Buyback => {
+                let covered = offer
+                    .total_requested
+                    .checked_add(amount)
+                    .ok_or(error!(TokenManagerError::AmountOverflow))?
+                    .min(offer.cap);
+                if ctx.accounts.offer_vault.amount < offer.cash_for(covered)? {
+                    return Err(error!(TokenManagerError::BuybackUnderfunded));
+                }
+                burn(
+                    CpiContext::new(
+                        ctx.accounts.token_program.to_account_info(),
+                        Burn {
+                            mint: ctx.accounts.security_mint.to_account_info(),
+                            from: ctx.accounts.investor_security.to_account_info(),
+                            authority: ctx.accounts.investor.to_account_info(),
+                        },
+                    ),
+                    amount,
+                )?;
+                let balance = ctx.accounts.investor_security.amount;
+                ctx.accounts
+                    .token_share
+                    .record_holding(balance, balance - amount)?;
+            }
+        }
+
+        let first_order = ctx.accounts.order.offer == Pubkey::default();
+        let order = &mut ctx.accounts.order;
+        order.offer = offer.key();
+        order.investor = ctx.accounts.investor.key();
+        order.requested = order
+            .requested
+            .checked_add(amount)
+            .ok_or(error!(TokenManagerError::AmountOverflow))?;
+        order.fees_paid = order.fees_paid.saturating_add(fee);
+        order.admin_fee = order.admin_fee.saturating_add(admin_fee);
+        let offer = &mut ctx.accounts.offer;
+        if first_order {
+            order.index = offer.order_count;
+            offer.order_count += 1;
+        }
+        offer.total_requested = offer
+            .total_requested
+            .checked_add(amount)
+            .ok_or(error!(TokenManagerError::AmountOverflow))?;
+
+        OrderReceipt {
+            offer: offer.key(),
+            mint: ctx.accounts.receipt_mint.to_account_info(),
+            mint_bump: ctx.bumps.receipt_mint,
+            token_program: ctx.accounts.token_program.to_account_info(),
+        }
+        .issue(
+            &ctx.accounts.investor_receipt,
+            ctx.bumps.investor_receipt,
+            &ctx.accounts.investor,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            amount,
+        )?;
+
+        emit!(OrderPlaced {
+            mint: offer.mint,
+            id: offer.id,
+            investor: order.investor,
+            amount,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct SettleOrder<'info> {
+        /// Token manager of the security
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the security
+        #[account(
+            mut,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The offer the order was placed on
+        #[account(
+            mut,
+            constraint = offer.mint == token_share.mint @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub offer: Account<'info, Offer>,
+
+        /// Payment mints approved by the issuer, with their oracles
+        #[account(
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Rate feed of the payment mint; required at the first settlement of an
+        /// offer paid in a mint with an oracle
+        pub fx_rate: Option<Account<'info, FxRateFeed>>,
+
+        /// The mint of the security, minting filled subscriptions and unfilled tenders
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub security_mint: InterfaceAccount<'info, Mint>,
+
+        /// The mint the offer is paid in
+        #[account(address = offer.payment_mint @ TokenManagerError::PaymentMintMismatch)]
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Cash escrow of the offer
+        #[account(
+            mut,
+            seeds = [b"offer-vault", offer.key().as_ref()],
+            bump,
+        )]
+        pub offer_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// Order being settled, closed once settled
+        #[account(
+            mut,
+            close = investor,
+            seeds = [b"offer-order", offer.key().as_ref(), investor.key().as_ref()],
+            bump,
+        )]
+        pub order: Account<'info, OfferOrder>,
+
+        /// Receipt mint of the offer, if the issuer enabled receipts
+        /// CHECK: May not exist; checked in the SettleOrder implementation
+        #[account(
+            mut,
+            seeds = [b"offer-receipt", offer.key().as_ref()],
+            bump,
+        )]
+        pub receipt_mint: UncheckedAccount<'info>,
+
+        /// Receipt account of the investor, emptied once settled
+        /// CHECK: May not exist; checked in the SettleOrder implementation
+        #[account(
+            mut,
+            seeds = [b"offer-receipt", offer.key().as_ref(), investor.key().as_ref()],
+            bump,
+        )]
+        pub investor_receipt: UncheckedAccount<'info>,
+
+        /// The investor who placed the order, receiving the reclaimed rent
+        /// CHECK: Only receives lamports; bound to the order by its seeds
+        #[account(mut)]
+        pub investor: UncheckedAccount<'info>,
+
+        /// Security account of the investor
+        #[account(
+            mut,
+            token::mint = security_mint,
+            token::authority = investor,
+        )]
+        pub investor_security: InterfaceAccount<'info, TokenAccount>,
+
+        /// Payment account of the investor
+        #[account(
+            mut,
+            token::mint = payment_mint,
+            token::authority = investor,
+        )]
+        pub investor_payment: InterfaceAccount<'info, TokenAccount>,
+
+        /// Payment account of the issuer, credited with the subscription proceeds
+        #[account(
+            mut,
+            token::mint = payment_mint,
+            token::authority = token_manager.creator,
+        )]
+        pub issuer_payment: InterfaceAccount<'info, TokenAccount>,
+
+        /// Statement of the investor, if opened
+        #[account(
+            mut,
+            seeds = [b"holder-statement", security_mint.key().as_ref(), investor.key().as_ref()],
+            bump,
+        )]
+        pub investor_statement: Option<Account<'info, HolderStatement>>,
+
+        /// Collateral attestation of the security, if it is asset-backed
+        /// CHECK: May not exist; deserialized in the SettleOrder implementation
+        #[account(
+            seeds = [b"collateral", security_mint.key().as_ref()],
+            bump,
+        )]
+        pub collateral: UncheckedAccount<'info>,
+
+        pub token_program: Program<'info, Token2022>,
+
+        /// Token program of the payment mint
+        pub payment_token_program: Interface<'info, TokenInterface>,
+    }
+
+    /// Settles an order on an offer of a token identified by its ISIN once the
+    /// offer closed. Anyone may crank it. Subscribers receive their filled tokens
+    /// and a refund of the unfilled portion; tendering holders receive the price
+    /// of their filled tokens and get the unfilled tokens back.
+    pub fn settle_order<'info>(
+        ctx: Context<'_, '_, '_, 'info, SettleOrder<'info>>,
+        _isin: Isin,
+    ) -> Result<()> {
+        let now = now(ctx.remaining_accounts)?;
+        if ctx.accounts.offer.fx_snapshot.is_none() {
+            take_fx_snapshot(
+                &mut ctx.accounts.offer,
+                &ctx.accounts.payment_mints,
+                ctx.accounts.fx_rate.as_ref(),
+                &now,
+                ctx.accounts.token_manager.clock_tolerance_seconds,
+            )?;
+        }
+
+        if !now.has_passed(
+            ctx.accounts.offer.closes_at,
+            ctx.accounts.token_manager.clock_tolerance_seconds,
+        ) {
+            return Err(error!(TokenManagerError::OfferStillOpen));
+        }
+
+        let balance = ctx.accounts.investor_security.amount;
+        OrderSettlement {
+            token_manager: &ctx.accounts.token_manager,
+            token_share: &ctx.accounts.token_share,
+            offer: &ctx.accounts.offer,
+            security_mint: ctx.accounts.security_mint.to_account_info(),
+            security_mint_bump: ctx.bumps.security_mint,
+            payment_mint: &ctx.accounts.payment_mint,
+            offer_vault: ctx.accounts.offer_vault.to_account_info(),
+            issuer_payment: ctx.accounts.issuer_payment.to_account_info(),
+            collateral: &ctx.accounts.collateral,
+            token_program: ctx.accounts.token_program.to_account_info(),
+            payment_token_program: ctx.accounts.payment_token_program.to_account_info(),
+            now,
+        }
+        .settle(
+            &ctx.accounts.order,
+            ctx.accounts.investor_security.to_account_info(),
+            ctx.accounts.investor_payment.to_account_info(),
+            ctx.accounts.investor_statement.as_deref_mut(),
+        )?;
+        ctx.accounts.investor_security.reload()?;
+        ctx.accounts
+            .token_share
+            .record_holding(balance, ctx.accounts.investor_security.amount)?;
+        OrderReceipt {
+            offer: ctx.accounts.offer.key(),
+            mint: ctx.accounts.receipt_mint.to_account_info(),
+            mint_bump: ctx.bumps.receipt_mint,
+            token_program: ctx.accounts.token_program.to_account_info(),
+        }
+        .release(&ctx.accounts.investor_receipt)?;
+        ctx.accounts.offer.settled_orders += 1;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct SettleOrders<'info> {
+        /// Keeper cranking the settlement, paying for the crank state on first use
+        #[account(mut)]
+        pub keeper: Signer<'info>,
+
+        /// Token manager of the security
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the security
+        #[account(
+            mut,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The offer whose orders are settled
+        #[account(
+            mut,
+            constraint = offer.mint == token_share.mint @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub offer: Account<'info, Offer>,
+
+        /// Payment mints approved by the issuer, with their oracles
+        #[account(
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Rate feed of the payment mint; required at the first settlement of an
+        /// offer paid in a mint with an oracle
+        pub fx_rate: Option<Account<'info, FxRateFeed>>,
+
+        /// The mint of the security, minting filled subscriptions and unfilled tenders
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub security_mint: InterfaceAccount<'info, Mint>,
+
+        /// The mint the offer is paid in
+        #[account(address = offer.payment_mint @ TokenManagerError::PaymentMintMismatch)]
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Cash escrow of the offer
+        #[account(
+            mut,
+            seeds = [b"offer-vault", offer.key().as_ref()],
+            bump,
+        )]
+        pub offer_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// Payment account of the issuer, credited with the subscription proceeds
+        #[account(
+            mut,
+            token::mint = payment_mint,
+            token::authority = token_manager.creator,
+        )]
+        pub issuer_payment: InterfaceAccount<'info, TokenAccount>,
+
+        /// Collateral attestation of the security, if it is asset-backed
+        /// CHECK: May not exist; deserialized in the SettleOrders implementation
+        #[account(
+            seeds = [b"collateral", security_mint.key().as_ref()],
+            bump,
+        )]
+        pub collateral: UncheckedAccount<'info>,
+
+        /// Receipt mint of the offer, if the issuer enabled receipts
+        /// CHECK: May not exist; checked in the SettleOrders implementation
+        #[account(
+            mut,
+            seeds = [b"offer-receipt", offer.key().as_ref()],
+            bump,
+        )]
+        pub receipt_mint: UncheckedAccount<'info>,
+
+        /// Progress of the settlement of the offer, created on first use
+        #[account(
+            init_if_needed,
+            payer = keeper,
+            space = 8 + CrankState::INIT_SPACE,
+            seeds = [b"crank", offer.key().as_ref()],
+            bump,
+        )]
+        pub crank_state: Account<'info, CrankState>,
+
+        /// Bounty paid to keepers, if the issuer set one
+        #[account(
+            mut,
+            seeds = [b"keeper-rewards", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub keeper_rewards: Option<Account<'info, KeeperRewards>>,
+
+        /// Vault of token bounties
+        #[account(
+            mut,
+            seeds = [b"keeper-vault", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub reward_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+        /// Account of the keeper receiving token bounties
+        #[account(mut, token::authority = keeper)]
+        pub keeper_reward_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+        /// Mint token bounties are paid in
+        pub reward_mint: Option<InterfaceAccount<'info, Mint>>,
+
+        /// Token program of the reward mint
+        pub reward_token_program: Option<Interface<'info, TokenInterface>>,
+
+        pub token_program: Program<'info, Token2022>,
+
+        /// Token program of the payment mint
+        pub payment_token_program: Interface<'info, TokenInterface>,
+
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Settles up to `limit` orders on a closed offer of a token identified by its
+    /// ISIN, at most `constants::MAX_CRANK_BATCH`, like `settle_order` does for a
+    /// single one. Anyone may crank it: progress is kept in the crank state of the
+    /// offer so that offers with many orders settle across many transactions.
+    ///
+    /// Each order is passed as five remaining accounts: the order, its investor,
+    /// the investor's security and payment accounts and the investor's statement
+    /// address, which is updated if opened. Offers with receipts take the receipt
+    /// account of the investor as a sixth account. Orders must come by increasing index
+    /// from `cursor`, the position the crank state is at, so that concurrent
+    /// keepers working on the same page fail instead of racing; orders settled on
+    /// their own in between are simply left out.
+    ///
+    /// The keeper earns the keeper bounty of the manager for every order settled
+    /// with a fill, so that padding an offer with empty orders earns nothing.
+    pub fn settle_orders<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SettleOrders<'info>>,
+        _isin: Isin,
+        cursor: u64,
+        limit: u8,
+    ) -> Result<()> {
+        if ctx.accounts.crank_state.cursor != cursor {
+            return Err(error!(TokenManagerError::CrankCursorMismatch));
+        }
+        if limit == 0 || limit > constants::MAX_CRANK_BATCH {
+            return Err(error!(TokenManagerError::InvalidCrankBatch));
+        }
+        let now = now(ctx.remaining_accounts)?;
+        if ctx.accounts.offer.fx_snapshot.is_none() {
+            take_fx_snapshot(
+                &mut ctx.accounts.offer,
+                &ctx.accounts.payment_mints,
+                ctx.accounts.fx_rate.as_ref(),
+                &now,
+                ctx.accounts.token_manager.clock_tolerance_seconds,
+            )?;
+        }
+        if !now.has_passed(
+            ctx.accounts.offer.closes_at,
+            ctx.accounts.token_manager.clock_tolerance_seconds,
+        ) {
+            return Err(error!(TokenManagerError::OfferStillOpen));
+        }
+
+        let settlement = OrderSettlement {
+            token_manager: &ctx.accounts.token_manager,
+            token_share: &ctx.accounts.token_share,
+            offer: &ctx.accounts.offer,
+            security_mint: ctx.accounts.security_mint.to_account_info(),
+            security_mint_bump: ctx.bumps.security_mint,
+            payment_mint: &ctx.accounts.payment_mint,
+            offer_vault: ctx.accounts.offer_vault.to_account_info(),
+            issuer_payment: ctx.accounts.issuer_payment.to_account_info(),
+            collateral: &ctx.accounts.collateral,
+            token_program: ctx.accounts.token_program.to_account_info(),
+            payment_token_program: ctx.accounts.payment_token_program.to_account_info(),
+            now,
+        };
+        let receipt = OrderReceipt {
+            offer: ctx.accounts.offer.key(),
+            mint: ctx.accounts.receipt_mint.to_account_info(),
+            mint_bump: ctx.bumps.receipt_mint,
+            token_program: ctx.accounts.token_program.to_account_info(),
+        };
+        let accounts_per_order = if receipt.is_enabled() { 6 } else { 5 };
+        let (mut next, mut settled, mut filled) = (cursor, 0, 0);
+        // Balances of the investors before and after their orders, counted once the
+        // settlement no longer borrows the token share
+        let mut holdings = Vec::new();
+        for accounts in ctx
+            .remaining_accounts
+            .chunks_exact(accounts_per_order)
+            .take(limit as usize)
+        {
+            let [order_info, investor, investor_security, investor_payment, statement_info, receipt_info @ ..] =
+                accounts
+            else {
+                return Err(error!(TokenManagerError::InvalidCrankBatch));
+            };
+            let order = Account::<OfferOrder>::try_from(order_info)?;
+            if order.offer != ctx.accounts.offer.key()
+                || order.index < next
+                || investor.key() != order.investor
+            {
+                return Err(error!(TokenManagerError::InvalidCrankBatch));
+            }
+            for (token_account, mint) in [
+                (investor_security, ctx.accounts.security_mint.key()),
+                (investor_payment, ctx.accounts.payment_mint.key()),
+            ] {
+                let token_account = InterfaceAccount::<TokenAccount>::try_from(token_account)?;
+                if token_account.mint != mint || token_account.owner != order.investor {
+                    return Err(error!(TokenManagerError::InvalidTokenAccount));
+                }
+            }
+            let statement_address = Pubkey::find_program_address(
+                &[
+                    b"holder-statement",
+                    ctx.accounts.security_mint.key().as_ref(),
+                    order.investor.as_ref(),
+                ],
+                &crate::ID,
+            )
+            .0;
+            if statement_info.key() != statement_address {
+                return Err(error!(TokenManagerError::InvalidCrankBatch));
+            }
+
+            let mut statement = load_optional_account::<HolderStatement>(statement_info)?;
+            let balance = token_balance(investor_security)?;
+            if settlement.offer.filled(order.requested) > 0 {
+                filled += 1;
+            }
+            settlement.settle(
+                &order,
+                investor_security.clone(),
+                investor_payment.clone(),
+                statement.as_mut(),
+            )?;
+            holdings.push((balance, token_balance(investor_security)?));
+            if let Some(statement) = statement {
+                statement.try_serialize(&mut &mut statement_info.try_borrow_mut_data()?[..])?;
+            }
+            if let [receipt_info] = receipt_info {
+                if receipt_info.key() != receipt.account_address(&order.investor) {
+                    return Err(error!(TokenManagerError::InvalidCrankBatch));
+                }
+                receipt.release(receipt_info)?;
+            }
+            next = order.index + 1;
+            settled += 1;
+            order.close(investor.clone())?;
+        }
+        if settled == 0 {
+            return Err(error!(TokenManagerError::InvalidCrankBatch));
+        }
+        for (before, after) in holdings {
+            ctx.accounts.token_share.record_holding(before, after)?;
+        }
+
+        let offer = &mut ctx.accounts.offer;
+        offer.settled_orders += settled;
+        let crank_state = &mut ctx.accounts.crank_state;
+        crank_state.subject = offer.key();
+        crank_state.kind = CrankKind::OfferSettlement;
+        crank_state.cursor = next;
+        crank_state.processed += settled;
+        crank_state.completed = offer.settled_orders == offer.order_count;
+
+        emit!(CrankAdvanced {
+            subject: crank_state.subject,
+            kind: crank_state.kind,
+            cursor: next,
+            processed: settled,
+            completed: crank_state.completed,
+        });
+
+        if let Some(keeper_rewards) = ctx.accounts.keeper_rewards.as_mut() {
+            pay_keeper_bounty(
+                keeper_rewards,
+                &ctx.accounts.keeper.to_account_info(),
+                ctx.accounts.reward_vault.as_ref(),
+                ctx.accounts.keeper_reward_account.as_ref(),
+                ctx.accounts.reward_mint.as_ref(),
+                ctx.accounts.reward_token_program.as_ref(),
+                filled,
+            )?;
+        }
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct ConfigureTreasuryYield<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager whose escrowed cash may earn yield
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Yield programs and guardian of the token manager, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + TreasuryYield::INIT_SPACE,
+            seeds = [b"treasury-yield", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub treasury_yield: Account<'info, TreasuryYield>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Sets the guardian allowed to trigger an emergency recall of the cash swept
+    /// to yield programs. The guardian cannot be replaced during an emergency
+    /// recall, which only it may lift.
+    pub fn set_treasury_guardian(
+        ctx: Context<ConfigureTreasuryYield>,
+        guardian: Pubkey,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let treasury_yield = &mut ctx.accounts.treasury_yield;
+        if treasury_yield.emergency_recall {
+            return Err(error!(TokenManagerError::EmergencyRecallActive));
+        }
+        treasury_yield.token_manager = ctx.accounts.token_manager.key();
+        treasury_yield.guardian = guardian;
+        Ok(())
+    }
+
+    /// Lifts the emergency recall in force, allowing sweeps to yield programs
+    /// again. Only the guardian may lift it.
+    pub fn lift_emergency_recall(ctx: Context<ConfigureTreasuryYield>) -> Result<()> {
+        let treasury_yield = &mut ctx.accounts.treasury_yield;
+        if ctx.accounts.signer.key() != treasury_yield.guardian {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+        treasury_yield.emergency_recall = false;
+        emit!(EmergencyRecallLifted {
+            token_manager: ctx.accounts.token_manager.key(),
+            guardian: ctx.accounts.signer.key(),
+        });
+        Ok(())
+    }
+
+    /// Allowlists or removes a yield program offer vault cash may be swept to.
+    /// `cap` bounds the payment mint units deposited in the program at any time,
+    /// across all vaults. The token, system and associated token programs and this
+    /// program cannot be allowlisted, as the offer signing for its vault could then
+    /// hand the vault over.
+    pub fn set_yield_program(
+        ctx: Context<ConfigureTreasuryYield>,
+        program_id: Pubkey,
+        cap: u64,
+        allowed: bool,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        if allowed && RESERVED_YIELD_PROGRAM_IDS.contains(&program_id) {
+            return Err(error!(TokenManagerError::YieldProgramNotAllowed));
+        }
+
+        let treasury_yield = &mut ctx.accounts.treasury_yield;
+        treasury_yield.token_manager = ctx.accounts.token_manager.key();
+        match treasury_yield
+            .programs
+            .iter_mut()
+            .find(|program| program.program_id == program_id)
+        {
+            Some(program) if allowed => program.cap = cap,
+            Some(_) => treasury_yield
+                .programs
+                .retain(|program| program.program_id != program_id),
+            None if allowed => {
+                if treasury_yield.programs.len() >= 4 {
+                    return Err(error!(TokenManagerError::YieldProgramRegistryFull));
+                }
+                treasury_yield.programs.push(YieldProgram {
+                    program_id,
+                    cap,
+                    deposited: 0,
+                });
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct YieldSweep<'info> {
+        /// The creator of the token manager, or its guardian when recalling
+        pub signer: Signer<'info>,
+
+        /// Token manager of the security
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the security the offer belongs to
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Yield programs and guardian of the token manager
+        #[account(
+            mut,
+            seeds = [b"treasury-yield", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub treasury_yield: Account<'info, TreasuryYield>,
+
+        /// The offer whose vault cash is swept, signing the yield program calls
+        #[account(constraint = offer.mint == token_share.mint @ TokenManagerError::InvalidTokenAccount)]
+        pub offer: Account<'info, Offer>,
+
+        /// Cash escrow of the offer
+        #[account(
+            mut,
+            seeds = [b"offer-vault", offer.key().as_ref()],
+            bump,
+        )]
+        pub offer_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// The yield program called, which must be allowlisted
+        /// CHECK: Checked against the allowlist of `treasury_yield`
+        #[account(executable)]
+        pub yield_program: UncheckedAccount<'info>,
+    }
+
+    /// Deposits `amount` of an offer vault of a token identified by its ISIN in an
+    /// allowlisted yield program. `data` is the deposit instruction of the yield
+    /// program, called with the remaining accounts and signed by the offer. The
+    /// call may not debit the vault by more than `amount` nor exceed the cap of
+    /// the program.
+    pub fn sweep_to_yield<'info>(
+        ctx: Context<'_, '_, 'info, 'info, YieldSweep<'info>>,
+        _isin: Isin,
+        amount: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        if ctx.accounts.treasury_yield.emergency_recall {
+            return Err(error!(TokenManagerError::EmergencyRecallActive));
+        }
+        let program_id = ctx.accounts.yield_program.key();
+        let program = ctx
+            .accounts
+            .treasury_yield
+            .program(&program_id)
+            .ok_or(error!(TokenManagerError::YieldProgramNotAllowed))?;
+        if program.deposited.saturating_add(amount) > program.cap {
+            return Err(error!(TokenManagerError::YieldCapExceeded));
+        }
+
+        let before = ctx.accounts.offer_vault.amount;
+        invoke_yield_program(
+            &ctx.accounts.offer,
+            &mut ctx.accounts.offer_vault,
+            &ctx.accounts.yield_program,
+            ctx.remaining_accounts,
+            data,
+        )?;
+        let swept = before.saturating_sub(ctx.accounts.offer_vault.amount);
+        if swept > amount {
+            return Err(error!(TokenManagerError::YieldSweepExceeded));
+        }
+
+        let program = ctx
+            .accounts
+            .treasury_yield
+            .program_mut(&program_id)
+            .ok_or(error!(TokenManagerError::YieldProgramNotAllowed))?;
+        program.deposited += swept;
+        emit!(TreasurySwept {
+            offer: ctx.accounts.offer.key(),
+            yield_program: program_id,
+            amount: swept,
+        });
+        Ok(())
+    }
+
+    /// Withdraws cash of an offer vault of a token identified by its ISIN from a
+    /// yield program. `data` is the withdrawal instruction of the yield program,
+    /// called with the remaining accounts and signed by the offer. The creator or
+    /// the guardian may recall.
+    pub fn recall_from_yield<'info>(
+        ctx: Context<'_, '_, 'info, 'info, YieldSweep<'info>>,
+        _isin: Isin,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        if signer != ctx.accounts.treasury_yield.guardian {
+            require_creator(&ctx.accounts.token_manager, &signer)?;
+        }
+        recall(ctx, data)
+    }
+
+    /// Recalls cash of an offer vault of a token identified by its ISIN from a
+    /// yield program like `recall_from_yield`, and blocks further sweeps until the
+    /// guardian lifts it with `lift_emergency_recall`. Only the guardian may
+    /// trigger it.
+    pub fn emergency_recall<'info>(
+        ctx: Context<'_, '_, 'info, 'info, YieldSweep<'info>>,
+        _isin: Isin,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        if ctx.accounts.signer.key() != ctx.accounts.treasury_yield.guardian {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+        ctx.accounts.treasury_yield.emergency_recall = true;
+        emit!(EmergencyRecallTriggered {
+            token_manager: ctx.accounts.token_manager.key(),
+            guardian: ctx.accounts.signer.key(),
+        });
+        recall(ctx, data)
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct Fractionalize<'info> {
+        /// The creator of the token manager, owner of the underlying NFT
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the share
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the share issued against the NFT
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Mint of the underlying asset NFT
+        #[account(
+            constraint = nft_mint.decimals == 0 && nft_mint.supply == 1
+                @ TokenManagerError::InvalidUnderlying,
+        )]
+        pub nft_mint: InterfaceAccount<'info, Mint>,
+
+        /// Token account of the signer holding the NFT
+        #[account(
+            mut,
+            token::mint = nft_mint,
+            token::authority = signer,
+        )]
+        pub nft_source: InterfaceAccount<'info, TokenAccount>,
+
+        /// Link between the share and the NFT
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + UnderlyingAsset::INIT_SPACE,
+            seeds = [b"underlying", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub underlying: Account<'info, UnderlyingAsset>,
+
+        /// Vault escrowing the NFT until redemption
+        #[account(
+            init,
+            payer = signer,
+            seeds = [b"underlying-vault", underlying.key().as_ref()],
+            bump,
+            token::mint = nft_mint,
+            token::authority = underlying,
+            token::token_program = nft_token_program,
+        )]
+        pub underlying_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// Token program of the NFT
+        pub nft_token_program: Interface<'info, TokenInterface>,
+
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Escrows an underlying asset NFT, such as a real-estate deed, in a program
+    /// vault and links it to the share identified by its ISIN. The NFT can only
+    /// leave the vault through `redeem_underlying`.
+    pub fn fractionalize(ctx: Context<Fractionalize>, _isin: Isin) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        transfer_checked(
+            CpiContext::new(
+                ctx.accounts.nft_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.nft_source.to_account_info(),
+                    mint: ctx.accounts.nft_mint.to_account_info(),
+                    to: ctx.accounts.underlying_vault.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            ),
+            1,
+            0,
+        )?;
+
+        ctx.accounts.underlying.set_inner(UnderlyingAsset {
+            mint: ctx.accounts.token_share.mint,
+            nft_mint: ctx.accounts.nft_mint.key(),
+            redeemed_by: None,
+            bump: ctx.bumps.underlying,
+        });
+        emit!(UnderlyingLinked {
+            mint: ctx.accounts.token_share.mint,
+            nft_mint: ctx.accounts.nft_mint.key(),
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct RedeemUnderlying<'info> {
+        /// Holder of the whole supply of the share
+        pub holder: Signer<'info>,
+
+        /// The token manager of the share
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the share
+        #[account(
+            mut,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Mint of the share, whose whole supply is burnt
+        #[account(mut, address = token_share.mint @ TokenManagerError::InvalidTokenAccount)]
+        pub share_mint: InterfaceAccount<'info, Mint>,
+
+        /// Share account of the holder
+        #[account(
+            mut,
+            token::mint = share_mint,
+            token::authority = holder,
+        )]
+        pub holder_shares: InterfaceAccount<'info, TokenAccount>,
+
+        /// Link between the share and the NFT
+        #[account(
+            mut,
+            seeds = [b"underlying", share_mint.key().as_ref()],
+            bump = underlying.bump,
+        )]
+        pub underlying: Account<'info, UnderlyingAsset>,
+
+        /// Mint of the underlying asset NFT
+        #[account(address = underlying.nft_mint @ TokenManagerError::InvalidUnderlying)]
+        pub nft_mint: InterfaceAccount<'info, Mint>,
+
+        /// Vault escrowing the NFT
+        #[account(
+            mut,
+            seeds = [b"underlying-vault", underlying.key().as_ref()],
+            bump,
+        )]
+        pub underlying_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// Token account of the holder receiving the NFT
+        #[account(
+            mut,
+            token::mint = nft_mint,
+            token::authority = holder,
+        )]
+        pub holder_nft: InterfaceAccount<'info, TokenAccount>,
+
+        pub token_program: Program<'info, Token2022>,
+
+        /// Token program of the NFT
+        pub nft_token_program: Interface<'info, TokenInterface>,
+    }
+
+    /// Burns the whole supply of a share identified by its ISIN and releases the
+    /// underlying NFT to its holder. Only a holder of 100% of the supply may redeem.
+    pub fn redeem_underlying(ctx: Context<RedeemUnderlying>, _isin: Isin) -> Result<()> {
+        if ctx.accounts.underlying.redeemed_by.is_some() {
+            return Err(error!(TokenManagerError::UnderlyingRedeemed));
+        }
+        let supply = ctx.accounts.share_mint.supply;
+        if supply == 0 || ctx.accounts.holder_shares.amount != supply {
+            return Err(error!(TokenManagerError::FullSupplyRequired));
+        }
+
+        burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.share_mint.to_account_info(),
+                    from: ctx.accounts.holder_shares.to_account_info(),
+                    authority: ctx.accounts.holder.to_account_info(),
+                },
+            ),
+            supply,
+        )?;
+        ctx.accounts.token_share.record_holding(supply, 0)?;
+
+        let share_mint = ctx.accounts.share_mint.key();
+        let underlying_seeds = &[
+            b"underlying".as_ref(),
+            share_mint.as_ref(),
+            &[ctx.accounts.underlying.bump],
+        ];
+        transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.nft_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.underlying_vault.to_account_info(),
+                    mint: ctx.accounts.nft_mint.to_account_info(),
+                    to: ctx.accounts.holder_nft.to_account_info(),
+                    authority: ctx.accounts.underlying.to_account_info(),
+                },
+                &[&underlying_seeds[..]],
+            ),
+            1,
+            0,
+        )?;
+
+        ctx.accounts.underlying.redeemed_by = Some(ctx.accounts.holder.key());
+        emit!(UnderlyingRedeemed {
+            mint: share_mint,
+            nft_mint: ctx.accounts.nft_mint.key(),
+            holder: ctx.accounts.holder.key(),
+            burnt: supply,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct LinkCollateral<'info> {
+        /// The creator of the token manager, or the custodian refreshing its attestation
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the asset-backed token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the asset-backed token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Collateral attestation of the token, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + CollateralAttestation::INIT_SPACE,
+            seeds = [b"collateral", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub collateral: Account<'info, CollateralAttestation>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Records the attestation of the collateral backing a token identified by its
+    /// ISIN. The creator links a custodian; later attestations may be posted by
+    /// the creator or by that custodian. An attestation may neither refer to a
+    /// moment still to come nor be older than the current one.
+    ///
+    /// # Arguments
+    ///
+    /// * `custodian_key` - Custodian holding the collateral.
+    /// * `attestation_uri` - Location of the full attestation report.
+    /// * `hash` - Hash of the attestation report.
+    /// * `value` - Attested value of the collateral, in the reporting unit of the issuer.
+    /// * `as_of` - Moment the attestation refers to.
+    pub fn link_collateral(
+        ctx: Context<LinkCollateral>,
+        _isin: Isin,
+        custodian_key: Pubkey,
+        attestation_uri: String,
+        hash: [u8; 32],
+        value: u64,
+        as_of: i64,
+    ) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let collateral = &mut ctx.accounts.collateral;
+        let is_custodian = collateral.custodian == custodian_key && signer == custodian_key;
+        if !is_custodian {
+            require_creator(&ctx.accounts.token_manager, &signer)?;
+        }
+        if attestation_uri.len() > 200 {
+            return Err(error!(TokenManagerError::InvalidCollateralAttestation));
+        }
+        if collateral.custodian == custodian_key && as_of < collateral.as_of {
+            return Err(error!(TokenManagerError::InvalidCollateralAttestation));
+        }
+        if !now(ctx.remaining_accounts)?
+            .may_have_passed(as_of, ctx.accounts.token_manager.clock_tolerance_seconds)
+        {
+            return Err(error!(TokenManagerError::InvalidCollateralAttestation));
+        }
+
+        collateral.mint = ctx.accounts.token_share.mint;
+        collateral.custodian = custodian_key;
+        collateral.attestation_uri = attestation_uri;
+        collateral.hash = hash;
+        collateral.value = value;
+        collateral.as_of = as_of;
+        emit!(CollateralAttested {
+            mint: collateral.mint,
+            custodian: custodian_key,
+            hash,
+            value,
+            as_of,
+        });
+        Ok(())
+    }
+
+    /// Sets how long a collateral attestation of a token identified by its ISIN
+    /// stays fresh, and whether issuance is paused while it is stale. A zero
+    /// `staleness_seconds` disables the check.
+    pub fn set_collateral_policy(
+        ctx: Context<LinkCollateral>,
+        _isin: Isin,
+        staleness_seconds: u32,
+        pause_issuance_when_stale: bool,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let collateral = &mut ctx.accounts.collateral;
+        collateral.mint = ctx.accounts.token_share.mint;
+        collateral.staleness_seconds = staleness_seconds;
+        collateral.pause_issuance_when_stale = pause_issuance_when_stale;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct GetPermissions<'info> {
+        /// The token manager the permissions are queried for
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Compliance officers of the token manager, if any were configured
+        #[account(
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: Option<Account<'info, ComplianceQueue>>,
+
+        /// Relayers of the token manager, if any were configured
+        #[account(
+            seeds = [b"relayers", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub relayer_registry: Option<Account<'info, RelayerRegistry>>,
+    }
+
+    /// Returns the roles `wallet` currently holds across the token manager through
+    /// return data, so admin UIs don't have to reconstruct them from several accounts.
+    /// Per-token roles are reported for the tokens whose `TokenWhitelist` accounts
+    /// are passed as remaining accounts, together with the `WhitelistEntry` and
+    /// `MarketMaker` accounts of the wallet for those tokens.
+    pub fn get_permissions(ctx: Context<GetPermissions>, wallet: Pubkey) -> Result<Permissions> {
+        let token_manager = &ctx.accounts.token_manager;
+        let mut whitelists = Vec::new();
+        let mut entries = Vec::new();
+        let mut market_makers = Vec::new();
+        for account in ctx.remaining_accounts {
+            if has_discriminator::<TokenWhitelist>(account) {
+                if let Some(whitelist) = load_optional_account::<TokenWhitelist>(account)? {
+                    if whitelist.token_manager == token_manager.key() {
+                        whitelists.push(whitelist);
+                    }
+                }
+            } else if has_discriminator::<WhitelistEntry>(account) {
+                if let Some(entry) = load_optional_account::<WhitelistEntry>(account)? {
+                    entries.push(entry);
+                }
+            } else if let Some(market_maker) = load_optional_account::<MarketMaker>(account)? {
+                market_makers.push(market_maker);
+            }
+        }
+
+        Ok(Permissions {
+            creator: wallet == token_manager.creator,
+            compliance_officer: ctx
+                .accounts
+                .compliance_queue
+                .as_ref()
+                .is_some_and(|queue| queue.officers.contains(&wallet)),
+            relayer: ctx
+                .accounts
+                .relayer_registry
+                .as_ref()
+                .is_some_and(|registry| registry.relayers.contains(&wallet)),
+            tokens: whitelists
+                .iter()
+                .map(|whitelist| TokenPermissions {
+                    mint: whitelist.mint,
+                    whitelisted: entries
+                        .iter()
+                        .any(|entry| entry.mint == whitelist.mint && entry.wallet == wallet),
+                    market_maker: market_makers
+                        .iter()
+                        .any(|maker| maker.mint == whitelist.mint && maker.wallet == wallet),
+                })
+                .collect(),
+        })
+    }
+
+    #[derive(Accounts)]
+    #[instruction(wallet: Pubkey, isin: Isin)]
+    pub struct RegisterMarketMaker<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// Account storing token information
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The mint of the token the market maker quotes
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, which must use `token_mint`
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Registration of the market maker, created or updated
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + MarketMaker::INIT_SPACE,
+            seeds = [b"market-maker", token_mint.key().as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub market_maker: Account<'info, MarketMaker>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Registers a wallet as designated market maker for a token identified by its
+    /// ISIN, or updates its inventory limit. The transfer hook rejects any transfer
+    /// that would leave the token account of a market maker above `max_inventory`;
+    /// market makers remain subject to the other transfer rules, lockups included.
+    pub fn register_market_maker(
+        ctx: Context<RegisterMarketMaker>,
+        wallet: Pubkey,
+        _isin: Isin,
+        max_inventory: u64,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let market_maker = &mut ctx.accounts.market_maker;
+        market_maker.mint = ctx.accounts.token_mint.key();
+        market_maker.wallet = wallet;
+        market_maker.max_inventory = max_inventory;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(wallet: Pubkey, isin: Isin)]
+    pub struct RemoveMarketMaker<'info> {
+        /// The wallet signing the transaction, receiving the reclaimed rent
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// Account storing token information
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The mint of the token the market maker quotes
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, which must use `token_mint`
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Registration being closed
+        #[account(
+            mut,
+            close = signer,
+            seeds = [b"market-maker", token_mint.key().as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub market_maker: Account<'info, MarketMaker>,
+    }
+
+    /// Removes the market maker designation of a wallet for a token identified by its ISIN.
+    pub fn remove_market_maker(
+        ctx: Context<RemoveMarketMaker>,
+        _wallet: Pubkey,
+        _isin: Isin,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(vault: Pubkey, isin: Isin)]
+    pub struct RegisterTrustedVault<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// Account storing token information
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The mint of the token held by the vault
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, which must use `token_mint`
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Registration of the vault
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + TrustedVault::INIT_SPACE,
+            seeds = [b"trusted-vault", token_mint.key().as_ref(), vault.as_ref()],
+            bump,
+        )]
+        pub trusted_vault: Account<'info, TrustedVault>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Marks a vault PDA of this program (escrow, distribution, treasury...) as
+    /// trusted for a token identified by its ISIN. `vault_seeds` are the seeds,
+    /// bump included, `vault` is derived from, so PDAs of other programs, which
+    /// could move tokens at will, cannot be registered. Transfers strictly between
+    /// two trusted vaults skip the regular transfer rules, as they only move
+    /// tokens internally; protective stops such as lockdowns still apply.
+    pub fn register_trusted_vault(
+        ctx: Context<RegisterTrustedVault>,
+        vault: Pubkey,
+        _isin: Isin,
+        vault_seeds: Vec<Vec<u8>>,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let seeds: Vec<&[u8]> = vault_seeds.iter().map(|seed| seed.as_slice()).collect();
+        if Pubkey::create_program_address(&seeds, &crate::ID) != Ok(vault) {
+            return Err(error!(TokenManagerError::VaultNotPda));
+        }
+
+        let trusted_vault = &mut ctx.accounts.trusted_vault;
+        trusted_vault.mint = ctx.accounts.token_mint.key();
+        trusted_vault.vault = vault;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(vault: Pubkey, isin: Isin)]
+    pub struct RemoveTrustedVault<'info> {
+        /// The wallet signing the transaction, receiving the reclaimed rent
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// Account storing token information
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The mint of the token held by the vault
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, which must use `token_mint`
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Registration being closed
+        #[account(
+            mut,
+            close = signer,
+            seeds = [b"trusted-vault", token_mint.key().as_ref(), vault.as_ref()],
+            bump,
+        )]
+        pub trusted_vault: Account<'info, TrustedVault>,
+    }
+
+    /// Removes the trusted flag of a vault for a token identified by its ISIN.
+    pub fn remove_trusted_vault(
+        ctx: Context<RemoveTrustedVault>,
+        _vault: Pubkey,
+        _isin: Isin,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct ConfigureChainedHook<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token calling the external hook
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Extra account meta list of the token, rewritten to carry the accounts of
+        /// the external hook
+        /// CHECK: Rewritten in the ConfigureChainedHook implementation
+        #[account(
+            mut,
+            seeds = [b"extra-account-metas", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub extra_account_meta_list: UncheckedAccount<'info>,
+
+        /// Chained hook of the token, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + ChainedHook::INIT_SPACE,
+            seeds = [b"chained-hook", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub chained_hook: Account<'info, ChainedHook>,
+
+        /// The external transfer hook program
+        /// CHECK: Must be an executable program other than this one and Token-2022
+        #[account(executable)]
+        pub hook_program: UncheckedAccount<'info>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Registers an external transfer hook program that the hook of a token
+    /// identified by its ISIN calls once its own rules pass, so issuers can layer
+    /// bespoke checks without forking this program. The external program only
+    /// receives the source, mint, destination, owner and its own extra account meta
+    /// list, all read-only, and fails the transfer if it consumes more than
+    /// `max_compute_units`. Transfers settled by `settle_batch` do not call it.
+    pub fn set_chained_hook(
+        ctx: Context<ConfigureChainedHook>,
+        _isin: Isin,
+        max_compute_units: u32,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        let program = ctx.accounts.hook_program.key();
+        if program == crate::ID
+            || program == Token2022::id()
+            || max_compute_units == 0
+            || max_compute_units > constants::MAX_CHAINED_HOOK_COMPUTE_UNITS
+        {
+            return Err(error!(TokenManagerError::InvalidChainedHook));
+        }
+
+        let mint = ctx.accounts.token_share.mint;
+        ctx.accounts.chained_hook.set_inner(ChainedHook {
+            mint,
+            program,
+            max_compute_units,
+        });
+
+        let mut account_metas = transfer_hook_extra_account_metas(
+            &ctx.accounts.token_manager.key(),
+            &ctx.accounts.token_share.key(),
+        )?;
+        account_metas.extend(chained_hook_extra_account_metas(
+            &program,
+            account_metas.len(),
+        )?);
+        write_extra_account_meta_list(
+            &ctx.accounts.extra_account_meta_list,
+            &ctx.accounts.signer,
+            &ctx.accounts.system_program,
+            &account_metas,
+        )?;
+
+        emit!(ChainedHookSet {
+            mint,
+            program,
+            max_compute_units,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct RemoveChainedHook<'info> {
+        /// The wallet signing the transaction, refunded the rent of the chained hook
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Extra account meta list of the token, rewritten without the external hook
+        /// CHECK: Rewritten in the RemoveChainedHook implementation
+        #[account(
+            mut,
+            seeds = [b"extra-account-metas", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub extra_account_meta_list: UncheckedAccount<'info>,
+
+        /// Chained hook of the token
+        #[account(
+            mut,
+            close = signer,
+            seeds = [b"chained-hook", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub chained_hook: Account<'info, ChainedHook>,
+
+        /// Required to top up the rent of the meta list
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Stops calling the external hook of a token identified by its ISIN.
+    pub fn remove_chained_hook(ctx: Context<RemoveChainedHook>, _isin: Isin) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let account_metas = transfer_hook_extra_account_metas(
+            &ctx.accounts.token_manager.key(),
+            &ctx.accounts.token_share.key(),
+        )?;
+        write_extra_account_meta_list(
+            &ctx.accounts.extra_account_meta_list,
+            &ctx.accounts.signer,
+            &ctx.accounts.system_program,
+            &account_metas,
+        )?;
+
+        emit!(ChainedHookRemoved {
+            mint: ctx.accounts.token_share.mint,
+            program: ctx.accounts.chained_hook.program,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct UpdateExtraAccountMetaList<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Extra account meta list of the token, rewritten with the current layout
+        /// CHECK: Rewritten in the UpdateExtraAccountMetaList implementation
+        #[account(
+            mut,
+            seeds = [b"extra-account-metas", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub extra_account_meta_list: UncheckedAccount<'info>,
+
+        /// Chained hook of the token, whose accounts are kept in the list
+        /// CHECK: May not exist; deserialized in the UpdateExtraAccountMetaList implementation
+        #[account(
+            seeds = [b"chained-hook", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub chained_hook: UncheckedAccount<'info>,
+
+        /// Operation lock of the token, if a long-running operation holds it
+        /// CHECK: May not exist; deserialized in the UpdateExtraAccountMetaList implementation
+        #[account(
+            seeds = [b"operation-lock", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub operation_lock: UncheckedAccount<'info>,
+
+        /// Required to top up the rent of the meta list
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Rewrites the extra account meta list of a token identified by its ISIN with
+    /// the accounts the hook of this program version reads, growing the list if
+    /// needed. Lets the hook depend on new or relocated compliance state without
+    /// recreating the mint.
+    pub fn update_extra_account_meta_list(
+        ctx: Context<UpdateExtraAccountMetaList>,
+        _isin: Isin,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        check_operation_lock(&ctx.accounts.operation_lock, TokenOperation::Migration)?;
+
+        let mut account_metas = transfer_hook_extra_account_metas(
+            &ctx.accounts.token_manager.key(),
+            &ctx.accounts.token_share.key(),
+        )?;
+        if let Some(chained_hook) =
+            load_optional_account::<ChainedHook>(&ctx.accounts.chained_hook)?
+        {
+            account_metas.extend(chained_hook_extra_account_metas(
+                &chained_hook.program,
+                account_metas.len(),
+            )?);
+        }
+        write_extra_account_meta_list(
+            &ctx.accounts.extra_account_meta_list,
+            &ctx.accounts.signer,
+            &ctx.accounts.system_program,
+            &account_metas,
+        )?;
+
+        emit!(ExtraAccountMetaListUpdated {
+            mint: ctx.accounts.token_share.mint,
+            accounts: account_metas.len() as u8,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct ConfigureCircuitBreaker<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token protected by the breaker
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Circuit breaker of the token, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + CircuitBreaker::INIT_SPACE,
+            seeds = [b"circuit-breaker", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub circuit_breaker: Account<'info, CircuitBreaker>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Configures the circuit breaker of a token identified by its ISIN. Once
+    /// `thresholds[kind]` anomalies of a kind, indexed by `AnomalyKind` with 0 for
+    /// no limit, are counted within `window_slots`, transfers of the token pause
+    /// until `guardian` reviews the alert and resumes them. A tripped breaker
+    /// stays tripped when reconfigured, and keeps its guardian until it resumes
+    /// transfers.
+    pub fn configure_circuit_breaker(
+        ctx: Context<ConfigureCircuitBreaker>,
+        _isin: Isin,
+        guardian: Pubkey,
+        window_slots: u64,
+        thresholds: [u32; 3],
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        if window_slots == 0 {
+            return Err(error!(TokenManagerError::InvalidCircuitBreaker));
+        }
+
+        let circuit_breaker = &mut ctx.accounts.circuit_breaker;
+        if circuit_breaker.tripped_at.is_some() && circuit_breaker.guardian != guardian {
+            return Err(error!(TokenManagerError::CircuitBreakerGuardianLocked));
+        }
+        circuit_breaker.mint = ctx.accounts.token_share.mint;
+        circuit_breaker.guardian = guardian;
+        circuit_breaker.window_slots = window_slots;
+        circuit_breaker.thresholds = thresholds;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct GuardCircuitBreaker<'info> {
+        /// The guardian of the breaker, or a compliance officer reporting anomalies
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token protected by the breaker
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Circuit breaker of the token
+        #[account(
+            mut,
+            seeds = [b"circuit-breaker", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub circuit_breaker: Account<'info, CircuitBreaker>,
+
+        /// Compliance officers of the token manager; only needed when an officer signs
+        #[account(
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: Option<Account<'info, ComplianceQueue>>,
+    }
+
+    /// Reports `count` anomalies of `kind` to the circuit breaker of a token
+    /// identified by its ISIN; callable by the guardian and compliance officers.
+    ///
+    /// Rejected transfers revert with their transaction, so the hook cannot count
+    /// them itself: monitors report them from the `TransferRejected` events of the
+    /// failed transactions. Whitelist removals are counted as they happen.
+    pub fn record_anomaly(
+        ctx: Context<GuardCircuitBreaker>,
+        _isin: Isin,
+        kind: AnomalyKind,
+        count: u32,
+    ) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let creator = ctx.accounts.token_manager.creator;
+        let is_officer = ctx
+            .accounts
+            .compliance_queue
+            .as_ref()
+            .is_some_and(|queue| queue.is_officer(&signer, &creator));
+        if signer != ctx.accounts.circuit_breaker.guardian && signer != creator && !is_officer {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+
+        let slot = now(ctx.remaining_accounts)?.slot;
+        ctx.accounts.circuit_breaker.record(kind, count, slot);
+        Ok(())
+    }
+
+    /// Resumes the transfers of a token identified by its ISIN after the breaker
+    /// tripped, starting a new counting window. Only the guardian may resume them.
+    pub fn resume_transfers(ctx: Context<GuardCircuitBreaker>, _isin: Isin) -> Result<()> {
+        let circuit_breaker = &mut ctx.accounts.circuit_breaker;
+        if ctx.accounts.signer.key() != circuit_breaker.guardian {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+
+        circuit_breaker.tripped_at = None;
+        circuit_breaker.window_start = now(ctx.remaining_accounts)?.slot;
+        circuit_breaker.counts = [0; 3];
+        emit!(CircuitBreakerReset {
+            mint: circuit_breaker.mint,
+            guardian: circuit_breaker.guardian,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct ConfigureToken<'info> {
+        /// The wallet signing the transaction
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        /// Only the creator should modify its tokens
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token being modified
+        #[account(
+            mut,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+    }
+
+    /// Sets how the transfer hook treats zero-amount transfers and transfers that
+    /// leave the source account empty for a token identified by its ISIN.
+    /// `Evaluate` runs the regular rules and `Deny` always rejects the transfer.
+    /// `Allow` accepts a zero-amount transfer without the regular rules, while a
+    /// transfer emptying its source still goes through them. Protective stops such
+    /// as lockdowns, circuit breakers and lockups apply whatever the policy.
+    pub fn set_transfer_edge_policies(
+        ctx: Context<ConfigureToken>,
+        _isin: Isin,
+        zero_amount: EdgeCasePolicy,
+        account_emptying: EdgeCasePolicy,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let token = &mut ctx.accounts.token_share;
+        token.zero_amount_policy = zero_amount;
+        token.account_emptying_policy = account_emptying;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct CloseHolderAccounts<'info> {
+        /// The holder closing its accounts, receiving their rent
+        #[account(mut)]
+        pub holder: Signer<'info>,
+
+        /// Token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Token account of the holder, emptied by its last transfer
+        #[account(
+            token::mint = token_share.mint,
+            token::authority = holder,
+            constraint = holder_token.amount == 0 @ TokenManagerError::HolderStillHolding,
+        )]
+        pub holder_token: InterfaceAccount<'info, TokenAccount>,
+
+        /// Statement of the holder, if it opened one
+        /// CHECK: May not exist; closed in the CloseHolderAccounts implementation
+        #[account(
+            mut,
+            seeds = [b"holder-statement", token_share.mint.as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub holder_statement: UncheckedAccount<'info>,
+
+        /// Due bill of the holder, if it opened one
+        /// CHECK: May not exist; closed in the CloseHolderAccounts implementation
+        #[account(
+            mut,
+            seeds = [b"due-bill", token_share.mint.as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub due_bill: UncheckedAccount<'info>,
+
+        /// Due bill ledger of the mint, naming the corporate action tracking due bills
+        /// CHECK: May not exist; deserialized in the CloseHolderAccounts implementation
+        #[account(
+            seeds = [b"due-bills", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub due_bill_ledger: UncheckedAccount<'info>,
+    }
+
+    /// Closes the statement and the due bill a holder opened for a token
+    /// identified by its ISIN once a transfer emptied its token account, returning
+    /// their rent to the holder. A due bill still carrying a balance for the
+    /// corporate action tracking due bills stays open until claimed or settled.
+    pub fn close_holder_accounts(ctx: Context<CloseHolderAccounts>, _isin: Isin) -> Result<()> {
+        let holder = ctx.accounts.holder.to_account_info();
+        let statement = ctx.accounts.holder_statement.to_account_info();
+        if load_optional_account::<HolderStatement>(&statement)?.is_some() {
+            close_program_account(&statement, &holder)?;
+        }
+
+        let due_bill_info = ctx.accounts.due_bill.to_account_info();
+        if let Some(due_bill) = load_optional_account::<DueBill>(&due_bill_info)? {
+            let ledger = load_optional_account::<DueBillLedger>(&ctx.accounts.due_bill_ledger)?;
+            if ledger.is_some_and(|ledger| due_bill.balance(ledger.action_id) != 0) {
+                return Err(error!(TokenManagerError::DueBillOutstanding));
+            }
+            close_program_account(&due_bill_info, &holder)?;
+        }
+        Ok(())
+    }
+
+    /// Lets holders move tokens between their own token accounts (e.g. when
+    /// migrating to a new associated token account) without evaluating the
+    /// regular transfer rules, for a token identified by its ISIN. Protective stops
+    /// such as lockdowns still apply.
+    pub fn set_self_transfer_exemption(
+        ctx: Context<ConfigureToken>,
+        _isin: Isin,
+        enabled: bool,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let token = &mut ctx.accounts.token_share;
+        token.allow_self_transfers = enabled;
+        Ok(())
+    }
+
+    /// Sets whether the transfer hook requires destination wallets to have accepted
+    /// the current subscription agreement of a token identified by its ISIN before
+    /// their first receipt of tokens, and whether holders must re-accept every newly
+    /// published version before their next outbound transfer.
+    pub fn set_agreement_requirement(
+        ctx: Context<ConfigureToken>,
+        _isin: Isin,
+        required: bool,
+        reaccept_new_versions: bool,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let token = &mut ctx.accounts.token_share;
+        token.require_agreement = required;
+        token.require_reacceptance = reaccept_new_versions;
+        Ok(())
+    }
+
+    /// Sets whether wallets must post an incoming consent with `accept_incoming`
+    /// before they can receive a token identified by its ISIN, protecting
+    /// investors from unsolicited securities.
+    pub fn set_incoming_consent_requirement(
+        ctx: Context<ConfigureToken>,
+        _isin: Isin,
+        required: bool,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        ctx.accounts.token_share.require_incoming_consent = required;
+        Ok(())
+    }
+
+    /// Sets whether the sender of a transfer of a token identified by its ISIN
+    /// must also be whitelisted, so holders whose authorization was removed can
+    /// no longer move their tokens.
+    pub fn set_sender_whitelist_requirement(
+        ctx: Context<ConfigureToken>,
+        _isin: Isin,
+        required: bool,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        ctx.accounts.token_share.require_sender_whitelisted = required;
+        Ok(())
+    }
+
+    /// Sets whether every transfer of a token identified by its ISIN needs a
+    /// request approved by a compliance officer with `approve_transfer`, for
+    /// instruments whose transfers the issuer must consent to one by one.
+    pub fn set_transfer_approval_requirement(
+        ctx: Context<ConfigureToken>,
+        _isin: Isin,
+        required: bool,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        ctx.accounts.token_share.require_transfer_approval = required;
+        Ok(())
+    }
+
+    /// Sets whether holders of a token identified by its ISIN may burn their own
+    /// tokens with `burn_tokens`, for instruments redeemable on demand.
+    pub fn set_self_redemption(
+        ctx: Context<ConfigureToken>,
+        _isin: Isin,
+        allowed: bool,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        ctx.accounts.token_share.allow_self_redemption = allowed;
+        Ok(())
+    }
+
+    /// Caps the number of token accounts holding a token identified by its ISIN,
+    /// for offerings limited to a number of investors, or lifts the cap with
+    /// `None`. A cap below the current holder count only blocks new holders.
+    pub fn set_max_holders(
+        ctx: Context<ConfigureToken>,
+        _isin: Isin,
+        max_holders: Option<u32>,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        ctx.accounts.token_share.max_holders = max_holders;
+        Ok(())
+    }
+
+    /// Caps the balance any token account may reach through transfers of a token
+    /// identified by its ISIN, or lifts the cap with `None`. Holders already above
+    /// a new cap keep their tokens but cannot receive more.
+    pub fn set_max_balance_per_holder(
+        ctx: Context<ConfigureToken>,
+        _isin: Isin,
+        max_balance: Option<u64>,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        ctx.accounts.token_share.max_balance_per_holder = max_balance;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(wallet: Pubkey, isin: Isin)]
+    pub struct SetLockup<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Lockup of the wallet, replaced when it already exists
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + Lockup::INIT_SPACE,
+            seeds = [b"lockup", token_share.mint.as_ref(), wallet.as_ref()],
+            bump,
+        )]
+        pub lockup: Account<'info, Lockup>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Locks `locked_amount` tokens of a wallet for a token identified by its ISIN,
+    /// such as founder or employee shares, released by the tranches of
+    /// `release_schedule`. The transfer hook rejects transfers that would leave
+    /// the wallet with fewer tokens than are still locked. Setting a lockup again
+    /// replaces it; an empty schedule with no locked amount lifts it.
+    ///
+    /// # Arguments
+    ///
+    /// * `locked_amount` - Tokens locked, the sum of the tranches.
+    /// * `release_schedule` - At most 16 tranches by increasing release time.
+    pub fn set_lockup(
+        ctx: Context<SetLockup>,
+        wallet: Pubkey,
+        _isin: Isin,
+        locked_amount: u64,
+        release_schedule: Vec<ReleaseTranche>,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let scheduled = release_schedule
+            .iter()
+            .try_fold(0u64, |total, tranche| total.checked_add(tranche.amount));
+        if release_schedule.len() > 16
+            || scheduled != Some(locked_amount)
+            || release_schedule
+                .windows(2)
+                .any(|tranches| tranches[0].release_at >= tranches[1].release_at)
+        {
+            return Err(error!(TokenManagerError::InvalidLockupSchedule));
+        }
+
+        let mint = ctx.accounts.token_share.mint;
+        ctx.accounts.lockup.set_inner(Lockup {
+            mint,
+            wallet,
+            locked_amount,
+            release_schedule,
+        });
+        emit!(LockupSet {
+            mint,
+            wallet,
+            locked_amount,
+        });
+        Ok(())
+    }
+
+    /// Requires the whitelist additions of a token identified by its ISIN to be
+    /// proposed by one officer and confirmed by another within `window_seconds`,
+    /// or lets single officers add wallets again with `None`. Corridor imports
+    /// and mirrored additions are refused while the window is set.
+    pub fn set_whitelist_approval_window(
+        ctx: Context<ConfigureToken>,
+        _isin: Isin,
+        window_seconds: Option<u32>,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        ctx.accounts.token_share.whitelist_approval_window = window_seconds;
+        Ok(())
+    }
+
+    /// Publishes a new version of the subscription agreement of a token identified
+    /// by its ISIN. Earlier acceptances no longer match the current agreement.
+    pub fn publish_agreement_version(
+        ctx: Context<ConfigureToken>,
+        _isin: Isin,
+        agreement_hash: [u8; 32],
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let token = &mut ctx.accounts.token_share;
+        token.agreement_version = token
+            .agreement_version
+            .checked_add(1)
+            .ok_or(error!(TokenManagerError::IndexOverflow))?;
+        token.agreement_hash = agreement_hash;
+
+        emit!(AgreementVersionPublished {
+            mint: token.mint,
+            version: token.agreement_version,
+            agreement_hash,
+        });
+        Ok(())
+    }
+
+    /// Sets the penalty regime of failed settlements of a token identified by its
+    /// ISIN: `penalty_bps_per_day` of the cash amount accrues for every full day a
+    /// settlement is late, and the counterparty may declare a buy-in once it is
+    /// `buy_in_after_days` late.
+    pub fn set_fail_penalty(
+        ctx: Context<ConfigureToken>,
+        _isin: Isin,
+        penalty_bps_per_day: u16,
+        buy_in_after_days: u16,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let token = &mut ctx.accounts.token_share;
+        token.fail_penalty_bps_per_day = penalty_bps_per_day;
+        token.buy_in_after_days = buy_in_after_days;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct AcquireOperationLock<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Operation lock of the token, created by this instruction
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + OperationLock::INIT_SPACE,
+            seeds = [b"operation-lock", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub operation_lock: Account<'info, OperationLock>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Marks a long-running operation, such as a corporate action, as in progress
+    /// on a token identified by its ISIN. Until the lock is released, admin
+    /// instructions belonging to another operation fail with `OperationInProgress`
+    /// instead of corrupting the intermediate state of this one.
+    pub fn acquire_operation_lock(
+        ctx: Context<AcquireOperationLock>,
+        _isin: Isin,
+        operation: TokenOperation,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let mint = ctx.accounts.token_share.mint;
+        let lock = &mut ctx.accounts.operation_lock;
+        if lock.mint != Pubkey::default() {
+            return Err(error!(TokenManagerError::OperationInProgress));
+        }
+        let now = now(ctx.remaining_accounts)?;
+        lock.set_inner(OperationLock {
+            mint,
+            operation,
+            acquired_at: now.unix_timestamp,
+            acquired_slot: now.slot,
+        });
+        emit!(OperationLockAcquired { mint, operation });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct ReleaseOperationLock<'info> {
+        /// The wallet signing the transaction, refunded the rent of the lock
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Operation lock of the token
+        #[account(
+            mut,
+            close = signer,
+            seeds = [b"operation-lock", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub operation_lock: Account<'info, OperationLock>,
+    }
+
+    /// Releases the operation lock of a token identified by its ISIN once its
+    /// operation completed or was abandoned. A split still being applied must be
+    /// completed or cancelled with `cancel_split` first.
+    pub fn release_operation_lock(ctx: Context<ReleaseOperationLock>, _isin: Isin) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        if ctx.accounts.token_share.split_in_progress() {
+            return Err(error!(TokenManagerError::SplitInProgress));
+        }
+
+        emit!(OperationLockReleased {
+            mint: ctx.accounts.token_share.mint,
+            operation: ctx.accounts.operation_lock.operation,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, action_id: u64)]
+    pub struct DeclareCorporateAction<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token, holding the blackouts enforced by the hook
+        #[account(
+            mut,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The corporate action being declared
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + CorporateAction::INIT_SPACE,
+            seeds = [b"corporate-action", token_share.mint.as_ref(), &action_id.to_le_bytes()],
+            bump,
+        )]
+        pub corporate_action: Account<'info, CorporateAction>,
+
+        /// Due bills of the token, written by the hook, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + DueBillLedger::INIT_SPACE,
+            seeds = [b"due-bills", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub due_bill_ledger: Account<'info, DueBillLedger>,
+
+        /// Operation lock of the token, if a long-running operation holds it
+        /// CHECK: May not exist; deserialized in the DeclareCorporateAction implementation
+        #[account(
+            seeds = [b"operation-lock", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub operation_lock: UncheckedAccount<'info>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Declares a corporate action on a token identified by its ISIN.
+    ///
+    /// When `terms.blackout_slots` is not zero, the hook rejects every transfer
+    /// from `blackout_slots` slots before the record slot until the payment slot
+    /// included, so that holdings cannot be shuffled around the record date.
+    ///
+    /// When `terms.due_bills` is set, the hook tracks a due bill for every transfer
+    /// between the ex slot and the payment slot whose buyer or seller is entitled
+    /// to the distribution against the holder of record, in the `DueBill` of each
+    /// party, which must have been opened with `open_due_bill`. Only one action
+    /// may track due bills at a time.
+    pub fn declare_corporate_action(
+        ctx: Context<DeclareCorporateAction>,
+        _isin: Isin,
+        action_id: u64,
+        terms: CorporateActionTerms,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        check_operation_lock(
+            &ctx.accounts.operation_lock,
+            TokenOperation::CorporateAction,
+        )?;
+        if terms.payment_slot < terms.record_slot || terms.payment_slot < terms.ex_slot {
+            return Err(error!(TokenManagerError::InvalidCorporateAction));
+        }
+        let now = now(ctx.remaining_accounts)?;
+        let token = &mut ctx.accounts.token_share;
+
+        if terms.blackout_slots > 0 {
+            token.add_blackout(
+                TransferBlackout {
+                    action_id,
+                    start_slot: terms.record_slot.saturating_sub(terms.blackout_slots),
+                    end_slot: terms.payment_slot,
+                },
+                now.slot,
+            )?;
+        }
+
+        if let Some(convention) = terms.due_bills {
+            let ledger = &mut ctx.accounts.due_bill_ledger;
+            if ledger.convention.is_some() && ledger.payment_slot >= now.slot {
+                return Err(error!(TokenManagerError::DueBillLedgerBusy));
+            }
+            ledger.set_inner(DueBillLedger {
+                mint: token.mint,
+                action_id,
+                convention: Some(convention),
+                ex_slot: terms.ex_slot,
+                record_slot: terms.record_slot,
+                payment_slot: terms.payment_slot,
+            });
+        } else if ctx.accounts.due_bill_ledger.mint == Pubkey::default() {
+            ctx.accounts.due_bill_ledger.mint = token.mint;
+        }
+
+        ctx.accounts.corporate_action.set_inner(CorporateAction {
+            mint: token.mint,
+            id: action_id,
+            ex_slot: terms.ex_slot,
+            record_slot: terms.record_slot,
+            payment_slot: terms.payment_slot,
+            blackout_slots: terms.blackout_slots,
+            due_bills: terms.due_bills,
+        });
+        emit!(CorporateActionDeclared {
+            mint: token.mint,
+            id: action_id,
+            record_slot: terms.record_slot,
+            payment_slot: terms.payment_slot,
+            blackout_slots: terms.blackout_slots,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(holder: Pubkey)]
+    pub struct OpenDueBill<'info> {
+        /// Pays for the due bill: the holder, or a venue trading on its behalf
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// Due bill ledger of the token
+        #[account(
+            seeds = [b"due-bills", due_bill_ledger.mint.as_ref()],
+            bump,
+        )]
+        pub due_bill_ledger: Account<'info, DueBillLedger>,
+
+        /// The due bill being opened
+        #[account(
+            init,
+            payer = payer,
+            space = 8 + DueBill::INIT_SPACE,
+            seeds = [b"due-bill", due_bill_ledger.mint.as_ref(), holder.as_ref()],
+            bump,
+        )]
+        pub due_bill: Account<'info, DueBill>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Opens the due bill of `holder` for the token of the ledger, which the hook
+    /// requires of both parties of a transfer creating a due bill. The due bill
+    /// is reused by every following corporate action.
+    pub fn open_due_bill(ctx: Context<OpenDueBill>, holder: Pubkey) -> Result<()> {
+        ctx.accounts.due_bill.set_inner(DueBill {
+            mint: ctx.accounts.due_bill_ledger.mint,
+            holder,
+            action_id: ctx.accounts.due_bill_ledger.action_id,
+            balance: 0,
+            bump: ctx.bumps.due_bill,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(holder: Pubkey)]
+    pub struct GetDueBill<'info> {
+        /// Due bill ledger of the token
+        #[account(
+            seeds = [b"due-bills", due_bill_ledger.mint.as_ref()],
+            bump,
+        )]
+        pub due_bill_ledger: Account<'info, DueBillLedger>,
+
+        /// Due bill of the holder, if it was opened
+        /// CHECK: May not exist; deserialized in the GetDueBill implementation
+        #[account(
+            seeds = [b"due-bill", due_bill_ledger.mint.as_ref(), holder.as_ref()],
+            bump,
+        )]
+        pub due_bill: UncheckedAccount<'info>,
+    }
+
+    /// Returns the due bill balance of `holder` for the action tracked by the
+    /// ledger, in token units: positive when the holder is owed the distribution
+    /// on that many units, negative when it owes it.
+    pub fn get_due_bill(ctx: Context<GetDueBill>, _holder: Pubkey) -> Result<i64> {
+        let action_id = ctx.accounts.due_bill_ledger.action_id;
+        Ok(load_optional_account::<DueBill>(&ctx.accounts.due_bill)?
+            .map_or(0, |due_bill| due_bill.balance(action_id)))
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, action_id: u64)]
+    pub struct CreateDistribution<'info> {
+        /// The creator of the token manager, depositing the payout
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Mint of the token, whose supply the deposit covers
+        #[account(address = token_share.mint @ TokenManagerError::InvalidTokenAccount)]
+        pub security_mint: InterfaceAccount<'info, Mint>,
+
+        /// The corporate action paying the distribution
+        #[account(
+            seeds = [b"corporate-action", token_share.mint.as_ref(), &action_id.to_le_bytes()],
+            bump,
+        )]
+        pub corporate_action: Account<'info, CorporateAction>,
+
+        /// Snapshot of the balances at the record slot, which the distribution
+        /// is paid on
+        #[account(constraint = snapshot.mint == token_share.mint @ TokenManagerError::TokenNotFound)]
+        pub snapshot: Account<'info, Snapshot>,
+
+        /// The distribution being created
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + Distribution::INIT_SPACE,
+            seeds = [b"distribution", corporate_action.key().as_ref()],
+            bump,
+        )]
+        pub distribution: Account<'info, Distribution>,
+
+        /// The mint the distribution is paid in
+        pub payout_mint: InterfaceAccount<'info, Mint>,
+
+        /// Payment mints approved by the issuer
+        #[account(
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+            constraint = payment_mints.is_approved(&payout_mint)
+                @ TokenManagerError::PaymentMintNotApproved,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Rate feed of the payout mint; required when the mint has an oracle
+        pub fx_rate: Option<Account<'info, FxRateFeed>>,
+
+        /// Escrow holding the payout until holders claim it
+        #[account(
+            init,
+            payer = signer,
+            seeds = [b"distribution-vault", distribution.key().as_ref()],
+            bump,
+            token::mint = payout_mint,
+            token::authority = distribution,
+            token::token_program = payout_token_program,
+        )]
+        pub distribution_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// Payout account of the issuer, funding the escrow
+        #[account(mut, token::mint = payout_mint, token::authority = signer)]
+        pub issuer_payout: InterfaceAccount<'info, TokenAccount>,
+
+        /// Token program of the payout mint
+        pub payout_token_program: Interface<'info, TokenInterface>,
+
+        /// Operation lock of the token, if a long-running operation holds it
+        /// CHECK: May not exist; deserialized in the CreateDistribution implementation
+        #[account(
+            seeds = [b"operation-lock", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub operation_lock: UncheckedAccount<'info>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Creates the distribution of the corporate action `action_id` of a token
+    /// identified by its ISIN, paying `rate` payout mint units per whole token held
+    /// at the record slot of the action.
+    ///
+    /// Holders are paid on their balances in `snapshot`, which must be complete
+    /// and capture the record slot, adjusted by their due bills. The payout on
+    /// the supply of the snapshot is deposited into an escrow from
+    /// `issuer_payout`; tokens minted afterwards are not entitled. When
+    /// `required_notice` is set, holders must acknowledge that notice, such as a
+    /// tax form, before claiming. The rate of a payout mint with an oracle in the
+    /// reporting currency is recorded with the distribution.
+    pub fn create_distribution(
+        ctx: Context<CreateDistribution>,
+        _isin: Isin,
+        action_id: u64,
+        rate: u64,
+        required_notice: Option<Pubkey>,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        check_operation_lock(&ctx.accounts.operation_lock, TokenOperation::Distribution)?;
+
+        let action = &ctx.accounts.corporate_action;
+        let snapshot = &ctx.accounts.snapshot;
+        snapshot.require_record_balances(action.record_slot)?;
+        let fx_snapshot = fx_snapshot(
+            &ctx.accounts.payout_mint.key(),
+            &ctx.accounts.payment_mints,
+            ctx.accounts.fx_rate.as_ref(),
+            &now(ctx.remaining_accounts)?,
+            ctx.accounts.token_manager.clock_tolerance_seconds,
+        )?;
+
+        let mut distribution = Distribution {
+            mint: action.mint,
+            action_id,
+            snapshot: snapshot.key(),
+            payout_mint: ctx.accounts.payout_mint.key(),
+            payout_token_index: None,
+            rate,
+            token_decimals: ctx.accounts.security_mint.decimals,
+            record_slot: action.record_slot,
+            payment_slot: action.payment_slot,
+            funded: 0,
+            claimed: 0,
+            withheld: 0,
+            required_notice,
+            fx_snapshot,
+            bump: ctx.bumps.distribution,
+        };
+        distribution.funded = distribution.payout(snapshot.supply)?;
+        transfer_checked(
+            CpiContext::new(
+                ctx.accounts.payout_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.issuer_payout.to_account_info(),
+                    mint: ctx.accounts.payout_mint.to_account_info(),
+                    to: ctx.accounts.distribution_vault.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            ),
+            distribution.funded,
+            ctx.accounts.payout_mint.decimals,
+        )?;
+
+        emit!(DistributionCreated {
+            mint: distribution.mint,
+            action_id,
+            payout_mint: distribution.payout_mint,
+            rate,
+            funded: distribution.funded,
+            fx_snapshot: distribution.fx_snapshot.clone(),
+        });
+        ctx.accounts.distribution.set_inner(distribution);
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, action_id: u64)]
+    pub struct ClaimDistribution<'info> {
+        /// The holder claiming its payout, paying for the claim record
+        #[account(mut)]
+        pub holder: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The corporate action paying the distribution
+        #[account(
+            seeds = [b"corporate-action", token_share.mint.as_ref(), &action_id.to_le_bytes()],
+            bump,
+        )]
+        pub corporate_action: Account<'info, CorporateAction>,
+
+        /// The distribution being claimed
+        #[account(
+            mut,
+            seeds = [b"distribution", corporate_action.key().as_ref()],
+            bump = distribution.bump,
+        )]
+        pub distribution: Account<'info, Distribution>,
+
+        /// Due bill of the holder, if it was opened, consumed by the claim
+        /// CHECK: May not exist; deserialized in the ClaimDistribution implementation
+        #[account(
+            mut,
+            seeds = [b"due-bill", token_share.mint.as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub due_bill: UncheckedAccount<'info>,
+
+        /// Acknowledgment by the holder of the notice the distribution requires, if any
+        pub notice_acknowledgment: Option<Account<'info, NoticeAcknowledgment>>,
+
+        /// Tax certification of the holder, if it submitted one
+        /// CHECK: May not exist; deserialized in the ClaimDistribution implementation
+        #[account(
+            seeds = [b"tax-certification", token_manager.key().as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub tax_certification: UncheckedAccount<'info>,
+
+        /// Record of the claim, preventing a second one
+        #[account(
+            init,
+            payer = holder,
+            space = 8 + DistributionClaim::INIT_SPACE,
+            seeds = [b"distribution-claim", distribution.key().as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub claim: Account<'info, DistributionClaim>,
+
+        /// The mint the distribution is paid in
+        #[account(address = distribution.payout_mint @ TokenManagerError::PaymentMintMismatch)]
+        pub payout_mint: InterfaceAccount<'info, Mint>,
+
+        /// Escrow of the distribution
+        #[account(
+            mut,
+            seeds = [b"distribution-vault", distribution.key().as_ref()],
+            bump,
+        )]
+        pub distribution_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// Payout account of the holder
+        #[account(mut, token::mint = payout_mint)]
+        pub holder_payout: InterfaceAccount<'info, TokenAccount>,
+
+        /// Token program of the payout mint
+        pub payout_token_program: Interface<'info, TokenInterface>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Pays the holder its share of the distribution of the corporate action
+    /// `action_id` of a token identified by its ISIN, once per holder.
+    ///
+    /// Claims are accepted from the record slot to the payment slot of the action.
+    /// Remaining accounts are the entries of the snapshot of the distribution
+    /// recording the token accounts of the holder; it is paid on their balances
+    /// adjusted by its due bill, which the claim consumes. The withholding of
+    /// `get_withholding_rate` stays in the escrow, returned to the issuer for
+    /// remittance when the distribution is closed, and a notice the distribution
+    /// requires must have been acknowledged.
+    pub fn claim_distribution(
+        ctx: Context<ClaimDistribution>,
+        _isin: Isin,
+        action_id: u64,
+    ) -> Result<()> {
+        let now = now(ctx.remaining_accounts)?;
+        let holder = ctx.accounts.holder.key();
+        let distribution = &ctx.accounts.distribution;
+        let mut due_bill = load_optional_account::<DueBill>(&ctx.accounts.due_bill)?;
+        let units = distribution.claimable_units(
+            &now,
+            snapshot_balance(ctx.remaining_accounts, &distribution.snapshot, &holder)?,
+            due_bill.as_mut(),
+        )?;
+        if let Some(due_bill) = due_bill {
+            due_bill.try_serialize(&mut &mut ctx.accounts.due_bill.try_borrow_mut_data()?[..])?;
+        }
+        check_notice_acknowledged(
+            distribution.required_notice,
+            ctx.accounts.notice_acknowledgment.as_deref(),
+            &holder,
+        )?;
+        let certification =
+            load_optional_account::<TaxCertification>(&ctx.accounts.tax_certification)?;
+        let (amount, withheld) = distribution.net_payout(
+            units,
+            withholding_bps(&ctx.accounts.token_manager, certification.as_ref(), now),
+        )?;
+
+        let action_key = ctx.accounts.corporate_action.key();
+        let distribution_seeds = &[
+            b"distribution".as_ref(),
+            action_key.as_ref(),
+            &[distribution.bump],
+        ];
+        transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.payout_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.distribution_vault.to_account_info(),
+                    mint: ctx.accounts.payout_mint.to_account_info(),
+                    to: ctx.accounts.holder_payout.to_account_info(),
+                    authority: ctx.accounts.distribution.to_account_info(),
+                },
+                &[&distribution_seeds[..]],
+            ),
+            amount,
+            ctx.accounts.payout_mint.decimals,
+        )?;
+
+        let distribution = &mut ctx.accounts.distribution;
+        distribution.claimed = distribution
+            .claimed
+            .checked_add(amount)
+            .ok_or(error!(TokenManagerError::AmountOverflow))?;
+        distribution.withheld = distribution
+            .withheld
+            .checked_add(withheld)
+            .ok_or(error!(TokenManagerError::AmountOverflow))?;
+        ctx.accounts.claim.set_inner(DistributionClaim {
+            distribution: distribution.key(),
+            holder,
+            units,
+            amount,
+            withheld,
+        });
+        emit!(DistributionClaimed {
+            mint: distribution.mint,
+            action_id,
+            holder,
+            units,
+            amount,
+            withheld,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, action_id: u64, payout_isin: Isin)]
+    pub struct CreatePikDistribution<'info> {
+        /// The creator of the token manager
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of both tokens
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Mint of the token, whose decimals define a whole token
+        #[account(address = token_share.mint @ TokenManagerError::InvalidTokenAccount)]
+        pub security_mint: InterfaceAccount<'info, Mint>,
+
+        /// The corporate action paying the distribution
+        #[account(
+            seeds = [b"corporate-action", token_share.mint.as_ref(), &action_id.to_le_bytes()],
+            bump,
+        )]
+        pub corporate_action: Account<'info, CorporateAction>,
+
+        /// Snapshot of the balances at the record slot, which the distribution
+        /// is paid on
+        #[account(constraint = snapshot.mint == token_share.mint @ TokenManagerError::TokenNotFound)]
+        pub snapshot: Account<'info, Snapshot>,
+
+        /// The distribution being created
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + Distribution::INIT_SPACE,
+            seeds = [b"distribution", corporate_action.key().as_ref()],
+            bump,
+        )]
+        pub distribution: Account<'info, Distribution>,
+
+        /// Configuration of the token the distribution is paid in
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), payout_isin.as_bytes()],
+            bump,
+        )]
+        pub payout_share: Account<'info, TokenShare>,
+
+        /// Mint of the token the distribution is paid in
+        #[account(address = payout_share.mint @ TokenManagerError::PaymentMintMismatch)]
+        pub payout_mint: InterfaceAccount<'info, Mint>,
+
+        /// Payment mints approved by the issuer
+        #[account(
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+            constraint = payment_mints.is_approved(&payout_mint)
+                @ TokenManagerError::PaymentMintNotApproved,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Operation lock of the token, if a long-running operation holds it
+        /// CHECK: May not exist; deserialized in the CreatePikDistribution implementation
+        #[account(
+            seeds = [b"operation-lock", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub operation_lock: UncheckedAccount<'info>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Creates the distribution of the corporate action `action_id` of a token
+    /// identified by its ISIN, paid in kind: `rate` units of the token registered
+    /// under `payout_isin` per whole token held at the record slot of the action.
+    ///
+    /// Nothing is escrowed; the payout is minted to each holder when it claims
+    /// with `claim_pik_distribution`, on its balances in `snapshot` as for a cash
+    /// distribution, and may require the same notice acknowledgment.
+    pub fn create_pik_distribution(
+        ctx: Context<CreatePikDistribution>,
+        _isin: Isin,
+        action_id: u64,
+        _payout_isin: Isin,
+        rate: u64,
+        required_notice: Option<Pubkey>,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        check_operation_lock(&ctx.accounts.operation_lock, TokenOperation::Distribution)?;
+
+        let action = &ctx.accounts.corporate_action;
+        let snapshot = &ctx.accounts.snapshot;
+        snapshot.require_record_balances(action.record_slot)?;
+
+        let payout_share = &ctx.accounts.payout_share;
+        let distribution = Distribution {
+            mint: action.mint,
+            action_id,
+            snapshot: snapshot.key(),
+            payout_mint: payout_share.mint,
+            payout_token_index: Some(payout_share.index),
+            rate,
+            token_decimals: ctx.accounts.security_mint.decimals,
+            record_slot: action.record_slot,
+            payment_slot: action.payment_slot,
+            funded: 0,
+            claimed: 0,
+            withheld: 0,
+            required_notice,
+            fx_snapshot: None,
+            bump: ctx.bumps.distribution,
+        };
+        emit!(DistributionCreated {
+            mint: distribution.mint,
+            action_id,
+            payout_mint: distribution.payout_mint,
+            rate,
+            funded: 0,
+            fx_snapshot: None,
+        });
+        ctx.accounts.distribution.set_inner(distribution);
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, action_id: u64)]
+    pub struct ClaimPikDistribution<'info> {
+        /// The holder claiming its payout, paying for the claim record
+        #[account(mut)]
+        pub holder: Signer<'info>,
+
+        /// The token manager of both tokens
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The corporate action paying the distribution
+        #[account(
+            seeds = [b"corporate-action", token_share.mint.as_ref(), &action_id.to_le_bytes()],
+            bump,
+        )]
+        pub corporate_action: Account<'info, CorporateAction>,
+
+        /// The distribution being claimed
+        #[account(
+            mut,
+            seeds = [b"distribution", corporate_action.key().as_ref()],
+            bump = distribution.bump,
+        )]
+        pub distribution: Account<'info, Distribution>,
+
+        /// Due bill of the holder, if it was opened, consumed by the claim
+        /// CHECK: May not exist; deserialized in the ClaimPikDistribution implementation
+        #[account(
+            mut,
+            seeds = [b"due-bill", token_share.mint.as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub due_bill: UncheckedAccount<'info>,
+
+        /// Acknowledgment by the holder of the notice the distribution requires, if any
+        pub notice_acknowledgment: Option<Account<'info, NoticeAcknowledgment>>,
+
+        /// Tax certification of the holder, if it submitted one
+        /// CHECK: May not exist; deserialized in the ClaimPikDistribution implementation
+        #[account(
+            seeds = [b"tax-certification", token_manager.key().as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub tax_certification: UncheckedAccount<'info>,
+
+        /// Record of the claim, preventing a second one
+        #[account(
+            init,
+            payer = holder,
+            space = 8 + DistributionClaim::INIT_SPACE,
+            seeds = [b"distribution-claim", distribution.key().as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub claim: Account<'info, DistributionClaim>,
+
+        /// Configuration of the token the distribution is paid in, counting its holders
+        #[account(
+            mut,
+            constraint = payout_share.token_manager == token_manager.key()
+                @ TokenManagerError::PaymentMintMismatch,
+            constraint = payout_share.mint == distribution.payout_mint
+                @ TokenManagerError::PaymentMintMismatch,
+            constraint = distribution.payout_token_index == Some(payout_share.index)
+                @ TokenManagerError::PaymentMintMismatch,
+        )]
+        pub payout_share: Account<'info, TokenShare>,
+
+        /// Mint of the token the distribution is paid in
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &payout_share.index.to_le_bytes()],
+            bump,
+            constraint = payout_mint.key() == distribution.payout_mint
+                @ TokenManagerError::PaymentMintMismatch,
+        )]
+        pub payout_mint: InterfaceAccount<'info, Mint>,
+
+        /// Account of the holder receiving the payout
+        #[account(mut, token::mint = payout_mint, token::authority = holder)]
+        pub holder_payout: InterfaceAccount<'info, TokenAccount>,
+
+        /// Whitelist entry of the holder for the token the distribution is paid in
+        /// CHECK: May not exist; deserialized in the ClaimPikDistribution implementation
+        #[account(
+            seeds = [b"whitelist", payout_mint.key().as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub payout_entry: UncheckedAccount<'info>,
+
+        /// The Token 2022 program
+        pub token_program: Program<'info, Token2022>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Mints the holder its share of the in-kind distribution of the corporate
+    /// action `action_id` of a token identified by its ISIN, once per holder.
+    ///
+    /// The holder is paid on its snapshot entries, passed as remaining accounts,
+    /// as for a cash distribution, less the withholding, which is not minted. As
+    /// an issuance of the token paid in, the claim requires the
+    /// holder to be whitelisted for it with a current authorization, and stays
+    /// within its supply cap, holder count and per-holder balance cap.
+    pub fn claim_pik_distribution(
+        ctx: Context<ClaimPikDistribution>,
+        _isin: Isin,
+        action_id: u64,
+    ) -> Result<()> {
+        ctx.accounts.token_manager.require_unlocked()?;
+        let now = now(ctx.remaining_accounts)?;
+        let holder = ctx.accounts.holder.key();
+        let distribution = &ctx.accounts.distribution;
+        let mut due_bill = load_optional_account::<DueBill>(&ctx.accounts.due_bill)?;
+        let units = distribution.claimable_units(
+            &now,
+            snapshot_balance(ctx.remaining_accounts, &distribution.snapshot, &holder)?,
+            due_bill.as_mut(),
+        )?;
+        if let Some(due_bill) = due_bill {
+            due_bill.try_serialize(&mut &mut ctx.accounts.due_bill.try_borrow_mut_data()?[..])?;
+        }
+        check_notice_acknowledged(
+            distribution.required_notice,
+            ctx.accounts.notice_acknowledgment.as_deref(),
+            &holder,
+        )?;
+        let certification =
+            load_optional_account::<TaxCertification>(&ctx.accounts.tax_certification)?;
+        let (amount, withheld) = distribution.net_payout(
+            units,
+            withholding_bps(&ctx.accounts.token_manager, certification.as_ref(), now),
+        )?;
+
+        let tolerance = ctx.accounts.token_manager.clock_tolerance_seconds;
+        if !is_whitelisted(&ctx.accounts.payout_entry, &now, tolerance)? {
+            return Err(error!(TokenManagerError::PayoutRecipientNotEligible));
+        }
+        let payout_share = &ctx.accounts.payout_share;
+        let balance = ctx.accounts.holder_payout.amount;
+        let balance_after =
+            payout_share.check_issuance(ctx.accounts.payout_mint.supply, balance, amount)?;
+
+        mint_share_tokens(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.payout_mint.to_account_info(),
+            ctx.accounts.holder_payout.to_account_info(),
+            &ctx.accounts.token_manager.key(),
+            payout_share.index,
+            ctx.bumps.payout_mint,
+            amount,
+        )?;
+        ctx.accounts
+            .payout_share
+            .record_holding(balance, balance_after)?;
+
+        let distribution = &mut ctx.accounts.distribution;
+        distribution.claimed = distribution
+            .claimed
+            .checked_add(amount)
+            .ok_or(error!(TokenManagerError::AmountOverflow))?;
+        distribution.withheld = distribution
+            .withheld
+            .checked_add(withheld)
+            .ok_or(error!(TokenManagerError::AmountOverflow))?;
+        ctx.accounts.claim.set_inner(DistributionClaim {
+            distribution: distribution.key(),
+            holder,
+            units,
+            amount,
+            withheld,
+        });
+        emit!(DistributionClaimed {
+            mint: distribution.mint,
+            action_id,
+            holder,
+            units,
+            amount,
+            withheld,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, action_id: u64)]
+    pub struct CloseDistribution<'info> {
+        /// The creator of the token manager
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The corporate action paying the distribution
+        #[account(
+            seeds = [b"corporate-action", token_share.mint.as_ref(), &action_id.to_le_bytes()],
+            bump,
+        )]
+        pub corporate_action: Account<'info, CorporateAction>,
+
+        /// The distribution being closed
+        #[account(
+            seeds = [b"distribution", corporate_action.key().as_ref()],
+            bump = distribution.bump,
+        )]
+        pub distribution: Account<'info, Distribution>,
+
+        /// The mint the distribution is paid in
+        #[account(address = distribution.payout_mint @ TokenManagerError::PaymentMintMismatch)]
+        pub payout_mint: InterfaceAccount<'info, Mint>,
+
+        /// Escrow of the distribution
+        #[account(
+            mut,
+            seeds = [b"distribution-vault", distribution.key().as_ref()],
+            bump,
+        )]
+        pub distribution_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// Payout account of the issuer, receiving what was left unclaimed
+        #[account(mut, token::mint = payout_mint)]
+        pub issuer_payout: InterfaceAccount<'info, TokenAccount>,
+
+        /// Token program of the payout mint
+        pub payout_token_program: Interface<'info, TokenInterface>,
+    }
+
+    /// Returns what was left unclaimed in the escrow of the distribution of the
+    /// corporate action `action_id` to `issuer_payout`, once its payment slot has
+    /// passed.
+    pub fn close_distribution(
+        ctx: Context<CloseDistribution>,
+        _isin: Isin,
+        action_id: u64,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let now = now(ctx.remaining_accounts)?;
+        let distribution = &ctx.accounts.distribution;
+        if now.slot <= distribution.payment_slot {
+            return Err(error!(TokenManagerError::DistributionStillOpen));
+        }
+
+        let returned = ctx.accounts.distribution_vault.amount;
+        let action_key = ctx.accounts.corporate_action.key();
+        let distribution_seeds = &[
+            b"distribution".as_ref(),
+            action_key.as_ref(),
+            &[distribution.bump],
+        ];
+        transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.payout_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.distribution_vault.to_account_info(),
+                    mint: ctx.accounts.payout_mint.to_account_info(),
+                    to: ctx.accounts.issuer_payout.to_account_info(),
+                    authority: ctx.accounts.distribution.to_account_info(),
+                },
+                &[&distribution_seeds[..]],
+            ),
+            returned,
+            ctx.accounts.payout_mint.decimals,
+        )?;
+
+        emit!(DistributionClosed {
+            mint: distribution.mint,
+            action_id,
+            returned,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(token_index: u64)]
+    pub struct CreateSnapshot<'info> {
+        /// The creator of the token manager
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The token mint, whose supply the snapshot must account for
+        #[account(
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_index.to_le_bytes()],
+            bump,
+        )]
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, counting its snapshots and holding the
+        /// blackout freezing balances during the capture
+        #[account(
+            mut,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::TokenNotFound,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The snapshot being taken
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + Snapshot::INIT_SPACE,
+            seeds = [
+                b"snapshot",
+                token_mint.key().as_ref(),
+                &(token_share.snapshot_count + 1).to_le_bytes(),
+            ],
+            bump,
+        )]
+        pub snapshot: Account<'info, Snapshot>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Takes a snapshot of the balances of a token, numbered after the previous
+    /// one, for corporate actions needing a record date balance set.
+    ///
+    /// Transfers are blocked for the `capture_slots` slots that follow, during
+    /// which anyone records the balance of each token account with
+    /// `record_holder_balance`. The snapshot is complete once the recorded
+    /// balances add up to the supply at the time it was taken.
+    pub fn create_snapshot(
+        ctx: Context<CreateSnapshot>,
+        _token_index: u64,
+        capture_slots: u64,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        if capture_slots == 0 {
+            return Err(error!(TokenManagerError::InvalidSnapshotWindow));
+        }
+        let now = now(ctx.remaining_accounts)?;
+        let token = &mut ctx.accounts.token_share;
+        let id = token.snapshot_count + 1;
+        let capture_end_slot = now.slot.saturating_add(capture_slots);
+        token.add_blackout(
+            TransferBlackout {
+                action_id: id,
+                start_slot: now.slot,
+                end_slot: capture_end_slot,
+            },
+            now.slot,
+        )?;
+        token.snapshot_count = id;
+
+        let snapshot = Snapshot {
+            mint: token.mint,
+            id,
+            taken_at: now.unix_timestamp,
+            slot: now.slot,
+            capture_end_slot,
+            supply: ctx.accounts.token_mint.supply,
+            recorded_supply: 0,
+            entries: 0,
+        };
+        emit!(SnapshotCreated {
+            mint: snapshot.mint,
+            id,
+            taken_at: snapshot.taken_at,
+            capture_end_slot,
+            supply: snapshot.supply,
+        });
+        ctx.accounts.snapshot.set_inner(snapshot);
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct RecordHolderBalance<'info> {
+        /// The wallet paying for the entry, typically a crank
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// The snapshot being captured
+        #[account(
+            mut,
+            seeds = [b"snapshot", snapshot.mint.as_ref(), &snapshot.id.to_le_bytes()],
+            bump,
+        )]
+        pub snapshot: Account<'info, Snapshot>,
+
+        /// The token account whose balance is recorded
+        #[account(token::mint = snapshot.mint)]
+        pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+        /// Balance of the token account in the snapshot
+        #[account(
+            init,
+            payer = payer,
+            space = 8 + SnapshotEntry::INIT_SPACE,
+            seeds = [b"snapshot-entry", snapshot.key().as_ref(), token_account.key().as_ref()],
+            bump,
+        )]
+        pub entry: Account<'info, SnapshotEntry>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Records the balance of a token account into a snapshot; callable by
+    /// anyone while the capture window of the snapshot is open, once per account.
+    pub fn record_holder_balance(ctx: Context<RecordHolderBalance>) -> Result<()> {
+        let now = now(ctx.remaining_accounts)?;
+        let snapshot = &mut ctx.accounts.snapshot;
+        if now.slot > snapshot.capture_end_slot {
+            return Err(error!(TokenManagerError::SnapshotCaptureClosed));
+        }
+
+        let token_account = &ctx.accounts.token_account;
+        snapshot.recorded_supply = snapshot
+            .recorded_supply
+            .checked_add(token_account.amount)
+            .ok_or(error!(TokenManagerError::AmountOverflow))?;
+        ctx.accounts.entry.set_inner(SnapshotEntry {
+            snapshot: snapshot.key(),
+            index: snapshot.entries,
+            token_account: token_account.key(),
+            owner: token_account.owner,
+            balance: token_account.amount,
+        });
+        snapshot.entries += 1;
+        emit!(HolderBalanceRecorded {
+            mint: snapshot.mint,
+            snapshot_id: snapshot.id,
+            owner: token_account.owner,
+            balance: token_account.amount,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(token_index: u64, snapshot_id: u64)]
+    pub struct SplitShares<'info> {
+        /// The creator of the token manager
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The token mint
+        #[account(
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_index.to_le_bytes()],
+            bump,
+        )]
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, whose supply cap is split too
+        #[account(
+            mut,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::TokenNotFound,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The snapshot of the balances being split
+        #[account(
+            seeds = [b"snapshot", token_mint.key().as_ref(), &snapshot_id.to_le_bytes()],
+            bump,
+        )]
+        pub snapshot: Account<'info, Snapshot>,
+
+        /// The split being declared
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + Split::INIT_SPACE,
+            seeds = [b"split", snapshot.key().as_ref()],
+            bump,
+        )]
+        pub split: Account<'info, Split>,
+
+        /// Operation lock of the token, which the split must hold
+        /// CHECK: May not exist; deserialized in the SplitShares implementation
+        #[account(
+            seeds = [b"operation-lock", token_mint.key().as_ref()],
+            bump,
+        )]
+        pub operation_lock: UncheckedAccount<'info>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Splits the shares of a token, `numerator` new shares for every
+    /// `denominator` held, a reverse split when `numerator` is the smaller.
+    ///
+    /// The split applies to the balances of the complete snapshot `snapshot_id`,
+    /// whose capture window must still be open so that no transfer moved them
+    /// since, and which must account for the current supply. The `Split`
+    /// operation lock must be held since before the snapshot was taken.
+    /// Transfers stay paused past the capture window while `apply_split` mints or
+    /// burns the difference holder by holder, rounding new balances down, until
+    /// every entry is applied, or the creator cancels the split with
+    /// `cancel_split`. The supply cap of the token is split alike.
+    pub fn split_shares(
+        ctx: Context<SplitShares>,
+        _token_index: u64,
+        snapshot_id: u64,
+        numerator: u64,
+        denominator: u64,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        if numerator == 0 || denominator == 0 || numerator == denominator {
+            return Err(error!(TokenManagerError::InvalidSplitRatio));
+        }
+        let snapshot = &ctx.accounts.snapshot;
+        require_operation_lock(
+            &ctx.accounts.operation_lock,
+            TokenOperation::Split,
+            snapshot.slot,
+        )?;
+        if !snapshot.is_complete() {
+            return Err(error!(TokenManagerError::SnapshotIncomplete));
+        }
+        if snapshot.supply != ctx.accounts.token_mint.supply {
+            return Err(error!(TokenManagerError::SplitSupplyMismatch));
+        }
+        if now(ctx.remaining_accounts)?.slot > snapshot.capture_end_slot {
+            return Err(error!(TokenManagerError::SnapshotCaptureClosed));
+        }
+
+        let split = Split {
+            mint: snapshot.mint,
+            snapshot_id,
+            numerator,
+            denominator,
+            next_entry: 0,
+            supply: snapshot.supply,
+        };
+        let token = &mut ctx.accounts.token_share;
+        if let Some(max_supply) = token.max_supply {
+            token.max_supply = Some(split.apply(max_supply)?);
+        }
+        // Transfers stay paused from the snapshot until the split completes
+        if snapshot.entries > 0 {
+            token
+                .snapshot_blackout(snapshot)
+                .ok_or(error!(TokenManagerError::SnapshotCaptureClosed))?
+                .end_slot = u64::MAX;
+        }
+        emit!(SharesSplit {
+            mint: split.mint,
+            snapshot_id,
+            numerator,
+            denominator,
+        });
+        ctx.accounts.split.set_inner(split);
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(token_index: u64)]
+    pub struct ApplySplit<'info> {
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The token mint
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_index.to_le_bytes()],
+            bump,
+        )]
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, counting its holders
+        #[account(
+            mut,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::TokenNotFound,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The snapshot of the balances being split
+        #[account(
+            seeds = [b"snapshot", token_mint.key().as_ref(), &split.snapshot_id.to_le_bytes()],
+            bump,
+        )]
+        pub snapshot: Account<'info, Snapshot>,
+
+        /// The split being applied, tracking the next entry to apply
+        #[account(
+            mut,
+            seeds = [b"split", snapshot.key().as_ref()],
+            bump,
+        )]
+        pub split: Account<'info, Split>,
+
+        /// Operation lock of the token, which the split must hold
+        /// CHECK: May not exist; deserialized in the ApplySplit implementation
+        #[account(
+            seeds = [b"operation-lock", token_mint.key().as_ref()],
+            bump,
+        )]
+        pub operation_lock: UncheckedAccount<'info>,
+
+        /// The Token 2022 program
+        pub token_program: Program<'info, Token2022>,
+    }
+
+    /// Applies a split to the next holders of its snapshot; callable by anyone
+    /// while the split holds the operation lock and nothing else changed the
+    /// supply. Transfers resume once the last entry is applied.
+    ///
+    /// Remaining accounts are pairs of a snapshot entry and its token account,
+    /// starting at the next entry to apply in recording order, at most
+    /// `constants::MAX_CRANK_BATCH` pairs per transaction.
+    pub fn apply_split<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ApplySplit<'info>>,
+        token_index: u64,
+    ) -> Result<()> {
+        ctx.accounts.token_manager.require_unlocked()?;
+        let now = now(ctx.remaining_accounts)?;
+        require_operation_lock(
+            &ctx.accounts.operation_lock,
+            TokenOperation::Split,
+            ctx.accounts.snapshot.slot,
+        )?;
+        if ctx.accounts.token_mint.supply != ctx.accounts.split.supply {
+            return Err(error!(TokenManagerError::SplitSupplyMismatch));
+        }
+
+        let snapshot = ctx.accounts.snapshot.key();
+        let mut applied = 0;
+        for accounts in ctx
+            .remaining_accounts
+            .chunks_exact(2)
+            .take(constants::MAX_CRANK_BATCH as usize)
+        {
+            let [entry_info, token_account_info] = accounts else {
+                return Err(error!(TokenManagerError::InvalidCrankBatch));
+            };
+            let entry = Account::<SnapshotEntry>::try_from(entry_info)?;
+            if entry.snapshot != snapshot
+                || entry.index != ctx.accounts.split.next_entry
+                || token_account_info.key() != entry.token_account
+            {
+                return Err(error!(TokenManagerError::InvalidCrankBatch));
+            }
+
+            let balance = token_balance(token_account_info)?;
+            let target = ctx.accounts.split.apply(entry.balance)?;
+            let after = if target > entry.balance {
+                let minted = target - entry.balance;
+                mint_share_tokens(
+                    ctx.accounts.token_program.to_account_info(),
+                    ctx.accounts.token_mint.to_account_info(),
+                    token_account_info.clone(),
+                    &ctx.accounts.token_manager.key(),
+                    token_index,
+                    ctx.bumps.token_mint,
+                    minted,
+                )?;
+                ctx.accounts.split.supply += minted;
+                balance.saturating_add(minted)
+            } else {
+                let burnt = entry.balance - target;
+                burn_share_tokens(
+                    ctx.accounts.token_program.to_account_info(),
+                    ctx.accounts.token_mint.to_account_info(),
+                    token_account_info.clone(),
+                    &ctx.accounts.token_manager.key(),
+                    token_index,
+                    ctx.bumps.token_mint,
+                    burnt,
+                )?;
+                ctx.accounts.split.supply -= burnt;
+                balance.saturating_sub(burnt)
+            };
+            ctx.accounts.token_share.record_holding(balance, after)?;
+            ctx.accounts.split.next_entry += 1;
+            applied += 1;
+        }
+
+        let split = &ctx.accounts.split;
+        let completed = split.next_entry == ctx.accounts.snapshot.entries;
+        if completed && applied > 0 {
+            if let Some(blackout) = ctx
+                .accounts
+                .token_share
+                .snapshot_blackout(&ctx.accounts.snapshot)
+            {
+                blackout.end_slot = now.slot;
+            }
+        }
+        emit!(SplitApplied {
+            mint: split.mint,
+            snapshot_id: split.snapshot_id,
+            applied,
+            next_entry: split.next_entry,
+            completed,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(token_index: u64)]
+    pub struct CancelSplit<'info> {
+        /// The creator of the token manager, refunded the rent of the split
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The token mint
+        #[account(
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_index.to_le_bytes()],
+            bump,
+        )]
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, whose transfers resume
+        #[account(
+            mut,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::TokenNotFound,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The snapshot of the balances being split
+        #[account(
+            seeds = [b"snapshot", token_mint.key().as_ref(), &split.snapshot_id.to_le_bytes()],
+            bump,
+        )]
+        pub snapshot: Account<'info, Snapshot>,
+
+        /// The split being cancelled, closed by this instruction
+        #[account(
+            mut,
+            close = signer,
+            seeds = [b"split", snapshot.key().as_ref()],
+            bump,
+        )]
+        pub split: Account<'info, Split>,
+    }
+
+    /// Cancels a split of a token that cannot be completed, for instance because
+    /// its supply changed, and resumes its transfers. Entries already applied keep
+    /// their split balance and the supply cap keeps its split value.
+    pub fn cancel_split(ctx: Context<CancelSplit>, _token_index: u64) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let slot = now(ctx.remaining_accounts)?.slot;
+        if let Some(blackout) = ctx
+            .accounts
+            .token_share
+            .snapshot_blackout(&ctx.accounts.snapshot)
+            .filter(|blackout| blackout.end_slot == u64::MAX)
+        {
+            blackout.end_slot = slot;
+        }
+        let split = &ctx.accounts.split;
+        emit!(SplitCancelled {
+            mint: split.mint,
+            snapshot_id: split.snapshot_id,
+            next_entry: split.next_entry,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, trade_id: u64)]
+    pub struct OpenDvp<'info> {
+        /// The buyer, escrowing the payment and paying for the trade accounts
+        #[account(mut)]
+        pub buyer: Signer<'info>,
+
+        /// Token manager of the security
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the security traded
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The trade being opened
+        #[account(
+            init,
+            payer = buyer,
+            space = 8 + DvpTrade::INIT_SPACE,
+            seeds = [b"dvp", token_share.mint.as_ref(), buyer.key().as_ref(), &trade_id.to_le_bytes()],
+            bump,
+        )]
+        pub trade: Account<'info, DvpTrade>,
+
+        /// The mint the trade is paid in
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Payment mints approved by the issuer
+        #[account(
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+            constraint = payment_mints.is_approved(&payment_mint)
+                @ TokenManagerError::PaymentMintNotApproved,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Escrow of the payment, owned by the trade
+        #[account(
+            init,
+            payer = buyer,
+            seeds = [b"dvp-vault", trade.key().as_ref()],
+            bump,
+            token::mint = payment_mint,
+            token::authority = trade,
+            token::token_program = payment_token_program,
+        )]
+        pub dvp_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// Payment account of the buyer
+        #[account(
+            mut,
+            token::mint = payment_mint,
+            token::authority = buyer,
+        )]
+        pub buyer_payment: InterfaceAccount<'info, TokenAccount>,
+
+        /// Token program of the payment mint
+        pub payment_token_program: Interface<'info, TokenInterface>,
+
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Opens a delivery-versus-payment trade of `amount` token units of a token
+    /// identified by its ISIN, sold by `seller` for `payment` units of an approved
+    /// payment mint. The payment is escrowed until the seller delivers the tokens
+    /// through `settle_dvp`, or the trade is cancelled.
+    pub fn open_dvp(
+        ctx: Context<OpenDvp>,
+        _isin: Isin,
+        trade_id: u64,
+        seller: Pubkey,
+        amount: u64,
+        payment: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        open_trade(
+            &mut ctx.accounts.trade,
+            DvpTrade {
+                mint: ctx.accounts.token_share.mint,
+                buyer: ctx.accounts.buyer.key(),
+                seller,
+                id: trade_id,
+                amount,
+                payment_mint: Some(ctx.accounts.payment_mint.key()),
+                payment,
+                expires_at,
+                bump: ctx.bumps.trade,
+            },
+        )?;
+
+        transfer_checked(
+            CpiContext::new(
+                ctx.accounts.payment_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.buyer_payment.to_account_info(),
+                    mint: ctx.accounts.payment_mint.to_account_info(),
+                    to: ctx.accounts.dvp_vault.to_account_info(),
+                    authority: ctx.accounts.buyer.to_account_info(),
+                },
+            ),
+            payment,
+            ctx.accounts.payment_mint.decimals,
+        )?;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, trade_id: u64)]
+    pub struct OpenDvpSol<'info> {
+        /// The buyer, escrowing the payment and paying for the trade account
+        #[account(mut)]
+        pub buyer: Signer<'info>,
+
+        /// Token manager of the security
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the security traded
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The trade being opened, holding the escrowed lamports
+        #[account(
+            init,
+            payer = buyer,
+            space = 8 + DvpTrade::INIT_SPACE,
+            seeds = [b"dvp", token_share.mint.as_ref(), buyer.key().as_ref(), &trade_id.to_le_bytes()],
+            bump,
+        )]
+        pub trade: Account<'info, DvpTrade>,
+
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Opens a delivery-versus-payment trade paid `payment` lamports, escrowed on
+    /// the trade account itself. See `open_dvp`.
+    pub fn open_dvp_sol(
+        ctx: Context<OpenDvpSol>,
+        _isin: Isin,
+        trade_id: u64,
+        seller: Pubkey,
+        amount: u64,
+        payment: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        open_trade(
+            &mut ctx.accounts.trade,
+            DvpTrade {
+                mint: ctx.accounts.token_share.mint,
+                buyer: ctx.accounts.buyer.key(),
+                seller,
+                id: trade_id,
+                amount,
+                payment_mint: None,
+                payment,
+                expires_at,
+                bump: ctx.bumps.trade,
+            },
+        )?;
+
+        invoke(
+            &system_instruction::transfer(
+                &ctx.accounts.buyer.key(),
+                &ctx.accounts.trade.key(),
+                payment,
+            ),
+            &[
+                ctx.accounts.buyer.to_account_info(),
+                ctx.accounts.trade.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct SettleDvp<'info> {
+        /// The seller of the trade, owner or delegate of the account delivered from
+        #[account(mut)]
+        pub seller: Signer<'info>,
+
+        /// The buyer of the trade, refunded the rent of the trade accounts
+        /// CHECK: Checked against the trade
+        #[account(mut, address = trade.buyer @ TokenManagerError::Unauthorized)]
+        pub buyer: UncheckedAccount<'info>,
+
+        /// Token manager of the security, holding manager-wide settings
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the security being delivered
+        #[account(
+            mut,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Sanctions list of the token manager, if a screening provider was set
+        /// CHECK: May not exist; deserialized when checking the delivery
+        #[account(
+            seeds = [b"sanctions-list", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub sanctions_list: UncheckedAccount<'info>,
+
+        /// Debt terms of the security, if it is a debt instrument
+        /// CHECK: May not exist; deserialized when checking the delivery
+        #[account(
+            seeds = [b"debt-instrument", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub debt_instrument: UncheckedAccount<'info>,
+
+        /// Circuit breaker of the security, if the issuer configured one
+        /// CHECK: May not exist; deserialized when checking the delivery
+        #[account(
+            seeds = [b"circuit-breaker", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub circuit_breaker: UncheckedAccount<'info>,
+
+        /// Registration of the external hook chained to the security, if any
+        /// CHECK: May not exist; deserialized when checking the delivery
+        #[account(
+            seeds = [b"chained-hook", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub chained_hook: UncheckedAccount<'info>,
+
+        /// The mint of the security, whose supply the delivery burns and re-mints
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub security_mint: InterfaceAccount<'info, Mint>,
+
+        /// The trade being settled
+        #[account(
+            mut,
+            close = buyer,
+            seeds = [b"dvp", trade.mint.as_ref(), trade.buyer.as_ref(), &trade.id.to_le_bytes()],
+            bump = trade.bump,
+            constraint = trade.mint == token_share.mint @ TokenManagerError::InvalidTokenAccount,
+            constraint = trade.seller == seller.key() @ TokenManagerError::Unauthorized,
+        )]
+        pub trade: Account<'info, DvpTrade>,
+
+        /// The mint the trade is paid in, omitted for trades paid in SOL
+        pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+
+        /// Escrow of the payment, omitted for trades paid in SOL
+        #[account(
+            mut,
+            seeds = [b"dvp-vault", trade.key().as_ref()],
+            bump,
+        )]
+        pub dvp_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+        /// Payment account of the seller, omitted for trades paid in SOL
+        #[account(mut)]
+        pub seller_payment: Option<InterfaceAccount<'info, TokenAccount>>,
+
+        /// Token program interface for SPL Token 2022
+        pub token_program: Program<'info, Token2022>,
+
+        /// Token program of the payment mint, omitted for trades paid in SOL
+        pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    }
+
+    /// Settles a delivery-versus-payment trade: the tokens of the seller are
+    /// delivered to the buyer and the escrowed payment is released to the seller
+    /// in the same instruction, so either both legs execute or neither does.
+    ///
+    /// The security accounts of the seller and the buyer are the first two
+    /// remaining accounts, followed by the whitelist entries or buckets of both
+    /// parties, the lockup of the seller and the other per-wallet records the
+    /// transfer rules depend on, and the due bill accounts while an action tracks
+    /// due bills. As in `settle_batch`, the delivery is
+    /// checked against the transfer rules and settled by burning from the seller
+    /// and minting to the buyer, Token-2022 being unable to invoke the hook of this
+    /// program from within one of its instructions.
+    pub fn settle_dvp<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SettleDvp<'info>>,
+        _isin: Isin,
+    ) -> Result<()> {
+        let trade = &ctx.accounts.trade;
+        let now = now(ctx.remaining_accounts)?;
+        if now.may_have_passed(
+            trade.expires_at,
+            ctx.accounts.token_manager.clock_tolerance_seconds,
+        ) {
+            return Err(error!(TokenManagerError::DvpExpired));
+        }
+        let buyer_security = ctx
+            .remaining_accounts
+            .get(1)
+            .ok_or(error!(TokenManagerError::InvalidSettlementLeg))?;
+        if InterfaceAccount::<TokenAccount>::try_from(buyer_security)?.owner != trade.buyer {
+            return Err(error!(TokenManagerError::InvalidSettlementLeg));
+        }
+
+        let mut settlement = Settlement {
+            venue: ctx.accounts.seller.to_account_info(),
+            token_manager: &ctx.accounts.token_manager,
+            token_share: &mut ctx.accounts.token_share,
+            security_mint: &ctx.accounts.security_mint,
+            security_mint_bump: ctx.bumps.security_mint,
+            payment_mint: None,
+            token_program: ctx.accounts.token_program.to_account_info(),
+            payment_token_program: None,
+            sanctions_list: load_optional_account(&ctx.accounts.sanctions_list)?,
+            credit_state: load_optional_account::<DebtInstrument>(&ctx.accounts.debt_instrument)?
+                .map(|debt| debt.state),
+            circuit_breaker: load_optional_account(&ctx.accounts.circuit_breaker)?,
+            chained_hook: load_optional_account::<ChainedHook>(&ctx.accounts.chained_hook)?
+                .is_some(),
+            transfer_fee_bps: 0,
+            fee_collector: None,
+            transfer_approved: false,
+            now,
+        };
+        settlement.settle(
+            &SettlementLeg {
+                asset: LegAsset::Security,
+                from: 0,
+                to: 1,
+                amount: trade.amount,
+            },
+            ctx.remaining_accounts,
+        )?;
+
+        let recipient = match &ctx.accounts.seller_payment {
+            Some(seller_payment) => seller_payment.to_account_info(),
+            None => ctx.accounts.seller.to_account_info(),
+        };
+        release_dvp_payment(
+            trade,
+            &recipient,
+            &ctx.accounts.buyer,
+            ctx.accounts.payment_mint.as_ref(),
+            ctx.accounts.dvp_vault.as_ref(),
+            ctx.accounts.payment_token_program.as_ref(),
+        )?;
+
+        emit!(DvpSettled {
+            mint: trade.mint,
+            buyer: trade.buyer,
+            seller: trade.seller,
+            trade_id: trade.id,
+            amount: trade.amount,
+            payment: trade.payment,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct CancelDvp<'info> {
+        /// The seller declining the trade, or the buyer once it expired
+        pub signer: Signer<'info>,
+
+        /// The buyer of the trade, refunded its payment and the rent of the trade
+        /// accounts
+        /// CHECK: Checked against the trade
+        #[account(mut, address = trade.buyer @ TokenManagerError::Unauthorized)]
+        pub buyer: UncheckedAccount<'info>,
+
+        /// Token manager of the security, holding the clock tolerance
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The trade being cancelled
+        #[account(
+            mut,
+            close = buyer,
+            seeds = [b"dvp", trade.mint.as_ref(), trade.buyer.as_ref(), &trade.id.to_le_bytes()],
+            bump = trade.bump,
+        )]
+        pub trade: Account<'info, DvpTrade>,
+
+        /// The mint the trade is paid in, omitted for trades paid in SOL
+        pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+
+        /// Escrow of the payment, omitted for trades paid in SOL
+        #[account(
+            mut,
+            seeds = [b"dvp-vault", trade.key().as_ref()],
+            bump,
+        )]
+        pub dvp_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+        /// Payment account of the buyer, omitted for trades paid in SOL
+        #[account(
+            mut,
+            constraint = buyer_payment.owner == trade.buyer @ TokenManagerError::DvpPaymentMismatch,
+        )]
+        pub buyer_payment: Option<InterfaceAccount<'info, TokenAccount>>,
+
+        /// Token program of the payment mint, omitted for trades paid in SOL
+        pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    }
+
+    /// Cancels a delivery-versus-payment trade and refunds its payment to the
+    /// buyer. The seller may decline the trade at any time; the buyer may reclaim
+    /// its payment once the trade has expired.
+    pub fn cancel_dvp(ctx: Context<CancelDvp>) -> Result<()> {
+        let trade = &ctx.accounts.trade;
+        let declined = ctx.accounts.signer.key() == trade.seller;
+        if !declined {
+            if ctx.accounts.signer.key() != trade.buyer {
+                return Err(error!(TokenManagerError::Unauthorized));
+            }
+            if !now(ctx.remaining_accounts)?.has_passed(
+                trade.expires_at,
+                ctx.accounts.token_manager.clock_tolerance_seconds,
+            ) {
+                return Err(error!(TokenManagerError::DvpNotExpired));
+            }
+        }
+
+        let recipient = match &ctx.accounts.buyer_payment {
+            Some(buyer_payment) => buyer_payment.to_account_info(),
+            None => ctx.accounts.buyer.to_account_info(),
+        };
+        release_dvp_payment(
+            trade,
+            &recipient,
+            &ctx.accounts.buyer,
+            ctx.accounts.payment_mint.as_ref(),
+            ctx.accounts.dvp_vault.as_ref(),
+            ctx.accounts.payment_token_program.as_ref(),
+        )?;
+
+        emit!(DvpCancelled {
+            mint: trade.mint,
+            buyer: trade.buyer,
+            trade_id: trade.id,
+            declined,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, to: Pubkey)]
+    pub struct RequestTransfer<'info> {
+        /// The holder asking to transfer its tokens, paying for the request
+        #[account(mut)]
+        pub holder: Signer<'info>,
+
+        /// Token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token to transfer
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Request of the holder to `to`, created on first use and reused by the
+        /// following requests
+        #[account(
+            init_if_needed,
+            payer = holder,
+            space = 8 + TransferRequest::INIT_SPACE,
+            seeds = [b"transfer-request", token_share.mint.as_ref(), holder.key().as_ref(), to.as_ref()],
+            bump,
+        )]
+        pub transfer_request: Account<'info, TransferRequest>,
+
+        /// Queue of the token manager, if officers were appointed, where the
+        /// request waits for them
+        /// CHECK: May not exist; deserialized in the RequestTransfer implementation
+        #[account(
+            mut,
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: UncheckedAccount<'info>,
+
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Asks to transfer `amount` token units of a token identified by its ISIN
+    /// to `to`, for tokens requiring approved transfers. A new request replaces
+    /// the previous one between the same wallets, along with its approval.
+    ///
+    /// The request is queued as a transfer approval task when the token manager
+    /// has a compliance queue, replacing the task of the previous request, so
+    /// `escalate_task` can settle it once its SLA lapses. Once a compliance
+    /// officer approved it with `approve_transfer`, the holder either transfers
+    /// the tokens itself, the hook using up the approval, or calls
+    /// `execute_approved_transfer`.
+    pub fn request_transfer(
+        ctx: Context<RequestTransfer>,
+        _isin: Isin,
+        to: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.token_manager.require_unlocked()?;
+        let requested_at = now(ctx.remaining_accounts)?.unix_timestamp;
+
+        let queue_info = ctx.accounts.compliance_queue.to_account_info();
+        let mut task_id = None;
+        if let Some(mut queue) = load_optional_account::<ComplianceQueue>(&queue_info)? {
+            if let Some(previous) = ctx.accounts.transfer_request.task_id {
+                queue.tasks.retain(|task| task.id != previous);
+            }
+            task_id = Some(queue.push_task(
+                TaskKind::TransferApproval,
+                ctx.accounts.transfer_request.key(),
+                TaskPriority::Normal,
+                requested_at,
+            )?);
+            queue.try_serialize(&mut &mut queue_info.try_borrow_mut_data()?[..])?;
+        }
+
+        let request = TransferRequest {
+            mint: ctx.accounts.token_share.mint,
+            from: ctx.accounts.holder.key(),
+            to,
+            amount,
+            requested_at,
+            approved_by: None,
+            consumed: false,
+            task_id,
+            bump: ctx.bumps.transfer_request,
+        };
+        emit!(TransferRequested {
+            mint: request.mint,
+            from: request.from,
+            to,
+            amount,
+        });
+        ctx.accounts.transfer_request.set_inner(request);
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, from: Pubkey, to: Pubkey)]
+    pub struct ApproveTransfer<'info> {
+        /// The creator of the token manager or a compliance officer
+        pub signer: Signer<'info>,
+
+        /// Token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the ApproveTransfer implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+
+        /// Configuration of the token to transfer
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The request being approved
+        #[account(
+            mut,
+            seeds = [b"transfer-request", token_share.mint.as_ref(), from.as_ref(), to.as_ref()],
+            bump = transfer_request.bump,
+        )]
+        pub transfer_request: Account<'info, TransferRequest>,
+
+        /// Queue of the token manager, if officers were appointed, holding the task
+        /// of the request
+        /// CHECK: May not exist; deserialized in the ApproveTransfer implementation
+        #[account(
+            mut,
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: UncheckedAccount<'info>,
+    }
+
+    /// Approves the pending request of `from` to transfer `amount` token units of
+    /// a token identified by its ISIN to `to`. The amount must be the one shown
+    /// to the officer, so a request replaced in the meantime is not approved.
+    /// Officers of the compliance queue may approve it as well, the approval
+    /// resolving the task queued for the request.
+    pub fn approve_transfer(
+        ctx: Context<ApproveTransfer>,
+        _isin: Isin,
+        from: Pubkey,
+        to: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let creator = ctx.accounts.token_manager.creator;
+        ctx.accounts.token_manager.require_unlocked()?;
+        let queue_info = ctx.accounts.compliance_queue.to_account_info();
+        let queue = load_optional_account::<ComplianceQueue>(&queue_info)?;
+        let is_officer = queue
+            .as_ref()
+            .is_some_and(|queue| queue.is_officer(&signer, &creator));
+        if !is_officer
+            && !has_role(
+                &ctx.accounts.token_manager,
+                &signer,
+                &ctx.accounts.role_assignment,
+                Role::ComplianceOfficer,
+            )?
+        {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+
+        let request = &mut ctx.accounts.transfer_request;
+        if request.consumed || request.amount != amount {
+            return Err(error!(TokenManagerError::TransferRequestMismatch));
+        }
+        request.approved_by = Some(signer);
+
+        if let (Some(mut queue), Some(task_id)) = (queue, request.task_id) {
+            let queued = queue.tasks.len();
+            queue.tasks.retain(|task| task.id != task_id);
+            if queue.tasks.len() != queued {
+                queue.try_serialize(&mut &mut queue_info.try_borrow_mut_data()?[..])?;
+                emit!(ComplianceTaskResolved {
+                    token_manager: queue.token_manager,
+                    id: task_id,
+                    officer: signer,
+                });
+            }
+        }
+
+        emit!(TransferApproved {
+            mint: request.mint,
+            from,
+            to,
+            amount,
+            approved_by: ctx.accounts.signer.key(),
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct ExecuteApprovedTransfer<'info> {
+        /// The holder of the request, owner of the account transferred from
+        #[account(mut)]
+        pub holder: Signer<'info>,
+
+        /// Token manager of the token, holding manager-wide settings
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token being transferred
+        #[account(
+            mut,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Sanctions list of the token manager, if a screening provider was set
+        /// CHECK: May not exist; deserialized when checking the transfer
+        #[account(
+            seeds = [b"sanctions-list", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub sanctions_list: UncheckedAccount<'info>,
+
+        /// Debt terms of the token, if it is a debt instrument
+        /// CHECK: May not exist; deserialized when checking the transfer
+        #[account(
+            seeds = [b"debt-instrument", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub debt_instrument: UncheckedAccount<'info>,
+
+        /// Circuit breaker of the token, if the issuer configured one
+        /// CHECK: May not exist; deserialized when checking the transfer
+        #[account(
+            seeds = [b"circuit-breaker", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub circuit_breaker: UncheckedAccount<'info>,
+
+        /// Registration of the external hook chained to the security, if any
+        /// CHECK: May not exist; deserialized when checking the transfer
+        #[account(
+            seeds = [b"chained-hook", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub chained_hook: UncheckedAccount<'info>,
+
+        /// The mint of the token, whose supply the transfer burns and re-mints
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub security_mint: InterfaceAccount<'info, Mint>,
+
+        /// The approved request, closed once executed
+        #[account(
+            mut,
+            close = holder,
+            seeds = [
+                b"transfer-request",
+                transfer_request.mint.as_ref(),
+                transfer_request.from.as_ref(),
+                transfer_request.to.as_ref(),
+            ],
+            bump = transfer_request.bump,
+            constraint = transfer_request.mint == token_share.mint @ TokenManagerError::InvalidTokenAccount,
+            constraint = transfer_request.from == holder.key() @ TokenManagerError::Unauthorized,
+        )]
+        pub transfer_request: Account<'info, TransferRequest>,
+
+        /// Token program interface for SPL Token 2022
+        pub token_program: Program<'info, Token2022>,
+    }
+
+    /// Executes an approved transfer request of the signing holder.
+    ///
+    /// The token accounts of the holder and of the recipient of the request are
+    /// the first two remaining accounts, followed by the whitelist entries or
+    /// buckets of both wallets, the lockup of the holder and the other per-wallet
+    /// records the transfer rules depend on. As in `settle_dvp`,
+    /// the transfer is checked against the transfer rules and settled by burning
+    /// from the holder and minting to the recipient.
+    pub fn execute_approved_transfer<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteApprovedTransfer<'info>>,
+        _isin: Isin,
+    ) -> Result<()> {
+        let request = &ctx.accounts.transfer_request;
+        if !request.approves(request.amount) {
+            return Err(error!(TokenManagerError::TransferNotApproved));
+        }
+        for (index, owner) in [(0, request.from), (1, request.to)] {
+            let account = ctx
+                .remaining_accounts
+                .get(index)
+                .ok_or(error!(TokenManagerError::InvalidSettlementLeg))?;
+            if InterfaceAccount::<TokenAccount>::try_from(account)?.owner != owner {
+                return Err(error!(TokenManagerError::InvalidSettlementLeg));
+            }
+        }
+
+        let mut settlement = Settlement {
+            venue: ctx.accounts.holder.to_account_info(),
+            token_manager: &ctx.accounts.token_manager,
+            token_share: &mut ctx.accounts.token_share,
+            security_mint: &ctx.accounts.security_mint,
+            security_mint_bump: ctx.bumps.security_mint,
+            payment_mint: None,
+            token_program: ctx.accounts.token_program.to_account_info(),
+            payment_token_program: None,
+            sanctions_list: load_optional_account(&ctx.accounts.sanctions_list)?,
+            credit_state: load_optional_account::<DebtInstrument>(&ctx.accounts.debt_instrument)?
+                .map(|debt| debt.state),
+            circuit_breaker: load_optional_account(&ctx.accounts.circuit_breaker)?,
+            chained_hook: load_optional_account::<ChainedHook>(&ctx.accounts.chained_hook)?
+                .is_some(),
+            transfer_fee_bps: 0,
+            fee_collector: None,
+            transfer_approved: true,
+            now: now(ctx.remaining_accounts)?,
+        };
+        settlement.settle(
+            &SettlementLeg {
+                asset: LegAsset::Security,
+                from: 0,
+                to: 1,
+                amount: request.amount,
+            },
+            ctx.remaining_accounts,
+        )?;
+
+        emit!(ApprovedTransferExecuted {
+            mint: request.mint,
+            from: request.from,
+            to: request.to,
+            amount: request.amount,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct CreateDebtInstrument<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token carrying the debt terms
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Debt terms of the token, fixed at creation
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + DebtInstrument::INIT_SPACE,
+            seeds = [b"debt-instrument", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub debt_instrument: Account<'info, DebtInstrument>,
+
+        /// The mint coupons and redemptions are paid in
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Payment mints approved by the issuer
+        #[account(
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+            constraint = payment_mints.is_approved(&payment_mint)
+                @ TokenManagerError::PaymentMintNotApproved,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Vault the issuer funds coupons and redemptions into
+        #[account(
+            init,
+            payer = signer,
+            seeds = [b"debt-vault", debt_instrument.key().as_ref()],
+            bump,
+            token::mint = payment_mint,
+            token::authority = debt_instrument,
+            token::token_program = payment_token_program,
+        )]
+        pub debt_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// Token program of the payment mint
+        pub payment_token_program: Interface<'info, TokenInterface>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Makes a token identified by its ISIN an interest-bearing instrument.
+    ///
+    /// Coupons fall every `terms.coupon_interval_months` months from the issue
+    /// date until maturity, and interest accrues at `terms.coupon_rate_bps` a year
+    /// under `terms.day_count`. The terms cannot be changed once created, so that
+    /// coupon amounts stay the ones holders bought the instrument for.
+    ///
+    /// Coupons and the redemption at maturity are paid in `payment_mint`, one
+    /// payment mint unit per token unit of principal, from a vault the issuer
+    /// funds with `fund_debt_service`.
+    pub fn create_debt_instrument(
+        ctx: Context<CreateDebtInstrument>,
+        _isin: Isin,
+        terms: DebtTerms,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        if terms.maturity <= terms.issued_at || !(1..=12).contains(&terms.coupon_interval_months) {
+            return Err(error!(TokenManagerError::InvalidDebtTerms));
+        }
+
+        let mint = ctx.accounts.token_share.mint;
+        ctx.accounts.debt_instrument.set_inner(DebtInstrument {
+            mint,
+            coupon_rate_bps: terms.coupon_rate_bps,
+            day_count: terms.day_count,
+            issued_at: terms.issued_at,
+            maturity: terms.maturity,
+            coupon_interval_months: terms.coupon_interval_months,
+            grace_period_days: terms.grace_period_days,
+            payment_mint: ctx.accounts.payment_mint.key(),
+            state: CreditState::Performing,
+            next_coupon: 1,
+            funded: 0,
+            serviced: 0,
+            bump: ctx.bumps.debt_instrument,
+        });
+        emit!(DebtInstrumentCreated {
+            mint,
+            coupon_rate_bps: terms.coupon_rate_bps,
+            day_count: terms.day_count,
+            issued_at: terms.issued_at,
+            maturity: terms.maturity,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct FundDebtService<'info> {
+        /// The wallet paying in the funds
+        pub funder: Signer<'info>,
+
+        /// Token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Debt terms of the token, counting the funds paid in
+        #[account(
+            mut,
+            seeds = [b"debt-instrument", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub debt_instrument: Account<'info, DebtInstrument>,
+
+        /// The mint coupons and redemptions are paid in
+        #[account(address = debt_instrument.payment_mint @ TokenManagerError::PaymentMintMismatch)]
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Payment mints approved by the issuer
+        #[account(
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+            constraint = payment_mints.is_approved(&payment_mint)
+                @ TokenManagerError::PaymentMintNotApproved,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Vault of the instrument
+        #[account(
+            mut,
+            seeds = [b"debt-vault", debt_instrument.key().as_ref()],
+            bump,
+        )]
+        pub debt_vault: InterfaceAccount<'info, TokenAccount>,
+
+        /// Payment account of the funder
+        #[account(mut, token::mint = payment_mint, token::authority = funder)]
+        pub funder_payment: InterfaceAccount<'info, TokenAccount>,
+
+        /// Token program of the payment mint
+        pub payment_token_program: Interface<'info, TokenInterface>,
+    }
+
+    /// Pays `amount` payment mint units into the vault of a debt instrument
+    /// identified by its ISIN, towards its next coupons and redemption.
+    pub fn fund_debt_service(
+        ctx: Context<FundDebtService>,
+        _isin: Isin,
+        amount: u64,
+    ) -> Result<()> {
+        transfer_checked(
+            CpiContext::new(
+                ctx.accounts.payment_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.funder_payment.to_account_info(),
+                    mint: ctx.accounts.payment_mint.to_account_info(),
+                    to: ctx.accounts.debt_vault.to_account_info(),
+                    authority: ctx.accounts.funder.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.payment_mint.decimals,
+        )?;
+
+        let debt = &mut ctx.accounts.debt_instrument;
+        debt.funded = debt
+            .funded
+            .checked_add(amount)
+            .ok_or(error!(TokenManagerError::AmountOverflow))?;
+        emit!(DebtServiceFunded {
+            mint: debt.mint,
+            amount,
+            funded: debt.funded,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct AssessDebtService<'info> {
+        /// Debt terms of the token, holding its credit state
+        #[account(
+            mut,
+            seeds = [b"debt-instrument", debt_instrument.mint.as_ref()],
+            bump,
+        )]
+        pub debt_instrument: Account<'info, DebtInstrument>,
+
+        /// Token manager of the token, holding the clock tolerance
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The mint of the token, whose supply is the principal outstanding
+        #[account(address = debt_instrument.mint @ TokenManagerError::InvalidTokenAccount)]
+        pub mint: InterfaceAccount<'info, Mint>,
+    }
+
+    /// Moves a debt instrument through its credit states; callable by anyone.
+    ///
+    /// Every coupon due, and the redemption at maturity, is serviced from the funds
+    /// paid in when they cover it. A payment left unfunded once due moves a
+    /// performing instrument into its grace period, which turns into a default
+    /// `grace_period_days` after the due date. Funding during the grace period
+    /// cures it, while a default stands until the instrument is restructured.
+    pub fn assess_debt_service(ctx: Context<AssessDebtService>) -> Result<()> {
+        let now = now(ctx.remaining_accounts)?;
+        let tolerance = ctx.accounts.token_manager.clock_tolerance_seconds;
+        let principal = ctx.accounts.mint.supply;
+        let debt = &mut ctx.accounts.debt_instrument;
+
+        while matches!(
+            debt.state,
+            CreditState::Performing | CreditState::GracePeriod
+        ) && !debt.is_repaid()
+        {
+            let due_at = debt.coupon_date(debt.next_coupon);
+            if !now.has_passed(due_at, tolerance) {
+                break;
+            }
+
+            let amount_due = debt.amount_due(principal, debt.next_coupon)?;
+            if debt.funded.saturating_sub(debt.serviced) >= amount_due {
+                debt.serviced = debt.serviced.saturating_add(amount_due);
+                emit!(CouponServiced {
+                    mint: debt.mint,
+                    coupon: debt.next_coupon,
+                    amount: amount_due,
+                });
+                debt.next_coupon += 1;
+                debt.transition(CreditState::Performing);
+                continue;
+            }
+
+            let grace_ends_at =
+                due_at.saturating_add(i64::from(debt.grace_period_days) * SECONDS_PER_DAY);
+            if now.has_passed(grace_ends_at, tolerance) {
+                debt.transition(CreditState::Default);
+            } else {
+                debt.transition(CreditState::GracePeriod);
+            }
+            break;
+        }
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct GetAccruedInterest<'info> {
+        /// Debt terms of the token
+        #[account(
+            seeds = [b"debt-instrument", debt_instrument.mint.as_ref()],
+            bump,
+        )]
+        pub debt_instrument: Account<'info, DebtInstrument>,
+    }
+
+    /// Returns the interest accrued on `principal` token units since the last
+    /// coupon date, in token units, under the day-count convention of the
+    /// instrument.
+    pub fn get_accrued_interest(ctx: Context<GetAccruedInterest>, principal: u64) -> Result<u64> {
+        let now = now(ctx.remaining_accounts)?;
+        ctx.accounts
+            .debt_instrument
+            .accrued_interest(principal, now.unix_timestamp)
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, new_isin: Isin)]
+    pub struct ProposeRestructuring<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of both instruments
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the defaulted instrument
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Configuration of the instrument offered in exchange
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), new_isin.as_bytes()],
+            bump,
+        )]
+        pub new_token_share: Account<'info, TokenShare>,
+
+        /// Debt terms of the defaulted instrument
+        #[account(
+            seeds = [b"debt-instrument", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub debt_instrument: Account<'info, DebtInstrument>,
+
+        /// Complete snapshot of the balances of the defaulted instrument, the
+        /// weights of the votes
+        #[account(
+            constraint = snapshot.mint == token_share.mint @ TokenManagerError::TokenNotFound,
+        )]
+        pub snapshot: Account<'info, Snapshot>,
+
+        /// Restructuring of the instrument, reused by every new proposal
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + Restructuring::INIT_SPACE,
+            seeds = [b"restructuring", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub restructuring: Account<'info, Restructuring>,
+
+        /// Operation lock of the token, if a long-running operation holds it
+        /// CHECK: May not exist; deserialized in the ProposeRestructuring implementation
+        #[account(
+            seeds = [b"operation-lock", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub operation_lock: UncheckedAccount<'info>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Proposes to exchange a defaulted debt instrument identified by its ISIN for
+    /// the instrument `new_isin`, which the issuer created beforehand.
+    ///
+    /// Holders vote with their balances in `snapshot`, which must be complete,
+    /// until `terms.voting_ends_at`, grouped by the investor class of their
+    /// whitelist entry, so tokens moved after the proposal do not vote twice. A
+    /// rejected proposal may be replaced by a new one, on a new snapshot.
+    pub fn propose_restructuring(
+        ctx: Context<ProposeRestructuring>,
+        _isin: Isin,
+        new_isin: Isin,
+        terms: RestructuringTerms,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        check_operation_lock(&ctx.accounts.operation_lock, TokenOperation::Restructuring)?;
+        if ctx.accounts.debt_instrument.state != CreditState::Default {
+            return Err(error!(TokenManagerError::InstrumentNotInDefault));
+        }
+        if !ctx.accounts.snapshot.is_complete() {
+            return Err(error!(TokenManagerError::SnapshotIncomplete));
+        }
+        if terms.haircut_bps > 10_000
+            || terms.threshold_bps == 0
+            || terms.threshold_bps > 10_000
+            || ctx.accounts.new_token_share.mint == ctx.accounts.token_share.mint
+        {
+            return Err(error!(TokenManagerError::InvalidRestructuring));
+        }
+        let restructuring = &mut ctx.accounts.restructuring;
+        if restructuring.mint != Pubkey::default()
+            && restructuring.status != RestructuringStatus::Rejected
+        {
+            return Err(error!(TokenManagerError::InvalidRestructuring));
+        }
+
+        let mint = ctx.accounts.token_share.mint;
+        let new_mint = ctx.accounts.new_token_share.mint;
+        let round = if restructuring.mint == Pubkey::default() {
+            0
+        } else {
+            restructuring.round.saturating_add(1)
+        };
+        restructuring.set_inner(Restructuring {
+            mint,
+            round,
+            new_isin,
+            new_mint,
+            haircut_bps: terms.haircut_bps,
+            new_maturity: terms.new_maturity,
+            threshold_bps: terms.threshold_bps,
+            voting_ends_at: terms.voting_ends_at,
+            status: RestructuringStatus::Voting,
+            snapshot: ctx.accounts.snapshot.key(),
+            tallies: Vec::new(),
+            required_notice: terms.required_notice,
+        });
+        emit!(RestructuringProposed {
+            mint,
+            new_mint,
+            round,
+            haircut_bps: terms.haircut_bps,
+            new_maturity: terms.new_maturity,
+            voting_ends_at: terms.voting_ends_at,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct VoteRestructuring<'info> {
+        /// Pays for the vote record: the holder or an allowlisted relayer
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// The holder voting
+        pub holder: Signer<'info>,
+
+        /// Token manager of the instrument
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Relayers allowed to pay for investor actions, if any were configured
+        #[account(
+            seeds = [b"relayers", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub relayer_registry: Option<Account<'info, RelayerRegistry>>,
+
+        /// Configuration of the instrument
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Whitelist entry of the holder, holding its investor class
+        /// CHECK: May not exist; deserialized in the VoteRestructuring implementation
+        #[account(
+            seeds = [b"whitelist", token_share.mint.as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub whitelist_entry: UncheckedAccount<'info>,
+
+        /// The restructuring voted on
+        #[account(
+            mut,
+            seeds = [b"restructuring", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub restructuring: Account<'info, Restructuring>,
+
+        /// Vote of the holder, one per proposal
+        #[account(
+            init,
+            payer = payer,
+            space = 8 + RestructuringVote::INIT_SPACE,
+            seeds = [
+                b"restructuring-vote",
+                restructuring.key().as_ref(),
+                &restructuring.round.to_le_bytes(),
+                holder.key().as_ref(),
+            ],
+            bump,
+        )]
+        pub vote: Account<'info, RestructuringVote>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Votes for or against the proposed restructuring of an instrument identified
+    /// by its ISIN, in the investor class of the holder, or class 0 when it is not
+    /// on the whitelist. Remaining accounts are the entries of the snapshot of the
+    /// proposal recording the token accounts of the holder, whose balances are
+    /// the weight of the vote.
+    pub fn vote_restructuring(
+        ctx: Context<VoteRestructuring>,
+        _isin: Isin,
+        approve: bool,
+    ) -> Result<()> {
+        check_sponsor(
+            &ctx.accounts.payer.key(),
+            &ctx.accounts.holder.key(),
+            ctx.accounts.relayer_registry.as_deref(),
+        )?;
+        let now = now(ctx.remaining_accounts)?;
+        let restructuring = &mut ctx.accounts.restructuring;
+        if restructuring.status != RestructuringStatus::Voting
+            || now.may_have_passed(
+                restructuring.voting_ends_at,
+                ctx.accounts.token_manager.clock_tolerance_seconds,
+            )
+        {
+            return Err(error!(TokenManagerError::RestructuringVotingClosed));
+        }
+
+        let holder = ctx.accounts.holder.key();
+        let investor_class =
+            load_optional_account::<WhitelistEntry>(&ctx.accounts.whitelist_entry)?
+                .map(|entry| entry.investor_class)
+                .unwrap_or_default();
+        let weight = snapshot_balance(ctx.remaining_accounts, &restructuring.snapshot, &holder)?;
+        restructuring.record_vote(investor_class, approve, weight)?;
+
+        ctx.accounts.vote.set_inner(RestructuringVote {
+            restructuring: restructuring.key(),
+            round: restructuring.round,
+            holder,
+            investor_class,
+            approve,
+            weight,
+        });
+        emit!(RestructuringVoteCast {
+            mint: restructuring.mint,
+            holder,
+            investor_class,
+            approve,
+            weight,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct FinalizeRestructuring<'info> {
+        /// The restructuring being decided
+        #[account(
+            mut,
+            seeds = [b"restructuring", restructuring.mint.as_ref()],
+            bump,
+        )]
+        pub restructuring: Account<'info, Restructuring>,
+
+        /// Debt terms of the instrument, marked restructured on approval
+        #[account(
+            mut,
+            seeds = [b"debt-instrument", restructuring.mint.as_ref()],
+            bump,
+        )]
+        pub debt_instrument: Account<'info, DebtInstrument>,
+
+        /// Token manager of the instrument, holding the clock tolerance
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+    }
+
+    /// Decides a restructuring once voting ended; callable by anyone. It is
+    /// approved when every class that voted reached the threshold of the votes it
+    /// cast, and then binds every holder, who may only exchange their tokens.
+    pub fn finalize_restructuring(ctx: Context<FinalizeRestructuring>) -> Result<()> {
+        let now = now(ctx.remaining_accounts)?;
+        let restructuring = &mut ctx.accounts.restructuring;
+        if restructuring.status != RestructuringStatus::Voting {
+            return Err(error!(TokenManagerError::RestructuringVotingClosed));
+        }
+        if !now.has_passed(
+            restructuring.voting_ends_at,
+            ctx.accounts.token_manager.clock_tolerance_seconds,
+        ) {
+            return Err(error!(TokenManagerError::RestructuringVotingOpen));
+        }
+
+        let approved = restructuring.is_approved();
+        if approved {
+            restructuring.status = RestructuringStatus::Approved;
+            ctx.accounts
+                .debt_instrument
+                .transition(CreditState::Restructured);
+        } else {
+            restructuring.status = RestructuringStatus::Rejected;
+        }
+        emit!(RestructuringDecided {
+            mint: restructuring.mint,
+            round: restructuring.round,
+            approved,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct ExchangeRestructured<'info> {
+        /// The holder exchanging its tokens
+        pub holder: Signer<'info>,
+
+        /// Token manager of both instruments
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the restructured instrument
+        #[account(
+            mut,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The approved restructuring
+        #[account(
+            seeds = [b"restructuring", token_share.mint.as_ref()],
+            bump,
+            constraint = restructuring.status == RestructuringStatus::Approved
+                @ TokenManagerError::RestructuringNotApproved,
+        )]
+        pub restructuring: Account<'info, Restructuring>,
+
+        /// Configuration of the instrument received in exchange
+        #[account(
+            mut,
+            seeds = [b"token-share", token_manager.key().as_ref(), restructuring.new_isin.as_bytes()],
+            bump,
+        )]
+        pub new_token_share: Account<'info, TokenShare>,
+
+        /// The mint of the restructured instrument, from which tokens are burnt
+        #[account(mut, address = token_share.mint @ TokenManagerError::InvalidTokenAccount)]
+        pub mint: InterfaceAccount<'info, Mint>,
+
+        /// The mint of the new instrument
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &new_token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub new_mint: InterfaceAccount<'info, Mint>,
+
+        /// Account of the holder in the restructured instrument, emptied
+        #[account(mut, token::mint = mint, token::authority = holder)]
+        pub holder_shares: InterfaceAccount<'info, TokenAccount>,
+
+        /// Account of the holder in the new instrument
+        #[account(mut, token::mint = new_mint, token::authority = holder)]
+        pub holder_new_shares: InterfaceAccount<'info, TokenAccount>,
+
+        /// Whitelist entry of the holder for the new instrument
+        /// CHECK: May not exist; deserialized in the ExchangeRestructured implementation
+        #[account(
+            seeds = [b"whitelist", new_mint.key().as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub new_entry: UncheckedAccount<'info>,
+
+        /// Acknowledgment by the holder of the notice the exchange requires, if any
+        pub notice_acknowledgment: Option<Account<'info, NoticeAcknowledgment>>,
+
+        pub token_program: Program<'info, Token2022>,
+    }
+
+    /// Exchanges every token of `holder_shares` in a restructured instrument
+    /// identified by its ISIN for new tokens, less the approved haircut. When the
+    /// proposal requires a notice, the holder must have acknowledged it first.
+    ///
+    /// As an issuance of the new instrument, the exchange requires the holder to
+    /// be whitelisted for it with a current authorization, and stays within its
+    /// supply cap, holder count and per-holder balance cap.
+    pub fn exchange_restructured(ctx: Context<ExchangeRestructured>, _isin: Isin) -> Result<()> {
+        ctx.accounts.token_manager.require_unlocked()?;
+        check_notice_acknowledged(
+            ctx.accounts.restructuring.required_notice,
+            ctx.accounts.notice_acknowledgment.as_deref(),
+            &ctx.accounts.holder.key(),
+        )?;
+        let now = now(ctx.remaining_accounts)?;
+        let tolerance = ctx.accounts.token_manager.clock_tolerance_seconds;
+        if !is_whitelisted(&ctx.accounts.new_entry, &now, tolerance)? {
+            return Err(error!(TokenManagerError::ExchangeRecipientNotEligible));
+        }
+        let burnt = ctx.accounts.holder_shares.amount;
+        let minted = ctx.accounts.restructuring.exchanged_amount(burnt);
+        let new_balance = ctx.accounts.holder_new_shares.amount;
+        let new_balance_after = ctx.accounts.new_token_share.check_issuance(
+            ctx.accounts.new_mint.supply,
+            new_balance,
+            minted,
+        )?;
+
+        burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.mint.to_account_info(),
+                    from: ctx.accounts.holder_shares.to_account_info(),
+                    authority: ctx.accounts.holder.to_account_info(),
+                },
+            ),
+            burnt,
+        )?;
+        mint_share_tokens(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.new_mint.to_account_info(),
+            ctx.accounts.holder_new_shares.to_account_info(),
+            &ctx.accounts.token_manager.key(),
+            ctx.accounts.new_token_share.index,
+            ctx.bumps.new_mint,
+            minted,
+        )?;
+        ctx.accounts.token_share.record_holding(burnt, 0)?;
+        ctx.accounts
+            .new_token_share
+            .record_holding(new_balance, new_balance_after)?;
+
+        emit!(RestructuringExchanged {
+            mint: ctx.accounts.mint.key(),
+            holder: ctx.accounts.holder.key(),
+            burnt,
+            minted,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct AcceptAgreement<'info> {
+        /// Pays for the acceptance record: the investor or an allowlisted relayer
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// The investor accepting the agreement
+        pub investor: Signer<'info>,
+
+        /// Account storing token information
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The mint of the token the agreement belongs to
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, which must use `token_mint`
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Relayers allowed to pay for investor actions, if any were configured
+        #[account(
+            seeds = [b"relayers", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub relayer_registry: Option<Account<'info, RelayerRegistry>>,
+
+        /// Record of the accepted agreement, replaced on every acceptance
+        #[account(
+            init_if_needed,
+            payer = payer,
+            space = 8 + AgreementAcceptance::INIT_SPACE,
+            seeds = [b"agreement-acceptance", token_mint.key().as_ref(), investor.key().as_ref()],
+            bump,
+        )]
+        pub agreement_acceptance: Account<'info, AgreementAcceptance>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Records that the investor accepted the current subscription agreement of a
+    /// token identified by its ISIN. `agreement_hash` must match the agreement
+    /// currently set by the issuer.
+    pub fn accept_agreement(
+        ctx: Context<AcceptAgreement>,
+        _isin: Isin,
+        agreement_hash: [u8; 32],
+    ) -> Result<()> {
+        check_sponsor(
+            &ctx.accounts.payer.key(),
+            &ctx.accounts.investor.key(),
+            ctx.accounts.relayer_registry.as_deref(),
+        )?;
+        let token = &ctx.accounts.token_share;
+        if token.agreement_hash != agreement_hash {
+            return Err(error!(TokenManagerError::AgreementMismatch));
+        }
+
+        let acceptance = &mut ctx.accounts.agreement_acceptance;
+        acceptance.mint = ctx.accounts.token_mint.key();
+        acceptance.investor = ctx.accounts.investor.key();
+        acceptance.agreement_hash = agreement_hash;
+        acceptance.agreement_version = token.agreement_version;
+        acceptance.accepted_at = now(ctx.remaining_accounts)?.unix_timestamp;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct OpenHolderStatement<'info> {
+        /// Pays for the statement: the holder or an allowlisted relayer
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// The holder the statement summarizes
+        pub holder: Signer<'info>,
+
+        /// Account storing token information
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The mint of the token the statement covers
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, which must use `token_mint`
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Relayers allowed to pay for investor actions, if any were configured
+        #[account(
+            seeds = [b"relayers", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub relayer_registry: Option<Account<'info, RelayerRegistry>>,
+
+        /// The statement being opened
+        #[account(
+            init,
+            payer = payer,
+            space = 8 + HolderStatement::INIT_SPACE,
+            seeds = [b"holder-statement", token_mint.key().as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub holder_statement: Account<'info, HolderStatement>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Opens the running statement of a holder for a token identified by its ISIN.
+    /// From then on the hook and the offer settlements keep it up to date, so that
+    /// an investor statement can be produced from this single account.
+    pub fn open_holder_statement(ctx: Context<OpenHolderStatement>, _isin: Isin) -> Result<()> {
+        check_sponsor(
+            &ctx.accounts.payer.key(),
+            &ctx.accounts.holder.key(),
+            ctx.accounts.relayer_registry.as_deref(),
+        )?;
+
+        ctx.accounts.holder_statement.set_inner(HolderStatement {
+            mint: ctx.accounts.token_mint.key(),
+            holder: ctx.accounts.holder.key(),
+            total_received: 0,
+            total_sent: 0,
+            distributions_claimed: 0,
+            last_activity: now(ctx.remaining_accounts)?.unix_timestamp,
+            last_memo_hash: [0; 32],
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct AcceptIncoming<'info> {
+        /// Pays for the consent: the investor or an allowlisted relayer
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// The investor consenting to receive the token
+        pub investor: Signer<'info>,
+
+        /// Account storing token information
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The mint of the token the investor consents to receive
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, which must use `token_mint`
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Relayers allowed to pay for investor actions, if any were configured
+        #[account(
+            seeds = [b"relayers", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub relayer_registry: Option<Account<'info, RelayerRegistry>>,
+
+        /// The consent being posted
+        #[account(
+            init,
+            payer = payer,
+            space = 8 + IncomingConsent::INIT_SPACE,
+            seeds = [b"incoming-consent", token_mint.key().as_ref(), investor.key().as_ref()],
+            bump,
+        )]
+        pub incoming_consent: Account<'info, IncomingConsent>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Posts the consent of an investor to receive a token identified by its ISIN,
+    /// required by the hook when the issuer enabled positive consent.
+    pub fn accept_incoming(ctx: Context<AcceptIncoming>, _isin: Isin) -> Result<()> {
+        check_sponsor(
+            &ctx.accounts.payer.key(),
+            &ctx.accounts.investor.key(),
+            ctx.accounts.relayer_registry.as_deref(),
+        )?;
+
+        ctx.accounts.incoming_consent.set_inner(IncomingConsent {
+            mint: ctx.accounts.token_mint.key(),
+            investor: ctx.accounts.investor.key(),
+            accepted_at: now(ctx.remaining_accounts)?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct RevokeIncoming<'info> {
+        /// The investor withdrawing its consent, receiving the reclaimed rent
+        #[account(mut)]
+        pub investor: Signer<'info>,
+
+        /// The consent being withdrawn
+        #[account(
+            mut,
+            close = investor,
+            has_one = investor @ TokenManagerError::Unauthorized,
+        )]
+        pub incoming_consent: Account<'info, IncomingConsent>,
+    }
+
+    /// Withdraws the consent of an investor to receive a token.
+    pub fn revoke_incoming(_ctx: Context<RevokeIncoming>) -> Result<()> {
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin, category: NoticeCategory, uri: String, hash: [u8; 32])]
+    pub struct PostNotice<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token whose holders are notified
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The notice, identified by the hash of its document
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + Notice::INIT_SPACE,
+            seeds = [b"notice", token_share.mint.as_ref(), hash.as_ref()],
+            bump,
+        )]
+        pub notice: Account<'info, Notice>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Posts a notice to the holders of a token identified by its ISIN, such as a
+    /// meeting notice or a consent solicitation. The document lives at `uri` and
+    /// is identified by its `hash`; holders may acknowledge it until `expiry`.
+    pub fn post_notice(
+        ctx: Context<PostNotice>,
+        _isin: Isin,
+        category: NoticeCategory,
+        uri: String,
+        hash: [u8; 32],
+        expiry: i64,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        let now = now(ctx.remaining_accounts)?;
+        if uri.len() > 200 || expiry <= now.unix_timestamp {
+            return Err(error!(TokenManagerError::InvalidNotice));
+        }
+
+        let mint = ctx.accounts.token_share.mint;
+        ctx.accounts.notice.set_inner(Notice {
+            mint,
+            category,
+            uri: uri.clone(),
+            hash,
+            posted_at: now.unix_timestamp,
+            expiry,
+        });
+        emit!(NoticePosted {
+            mint,
+            notice: ctx.accounts.notice.key(),
+            category,
+            uri,
+            hash,
+            expiry,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct AcknowledgeNotice<'info> {
+        /// Pays for the receipt: the holder or an allowlisted relayer
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// The holder acknowledging the notice
+        pub holder: Signer<'info>,
+
+        /// Token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Relayers allowed to pay for investor actions, if any were configured
+        #[account(
+            seeds = [b"relayers", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub relayer_registry: Option<Account<'info, RelayerRegistry>>,
+
+        /// The notice being acknowledged
+        #[account(
+            seeds = [b"notice", notice.mint.as_ref(), notice.hash.as_ref()],
+            bump,
+        )]
+        pub notice: Account<'info, Notice>,
+
+        /// Receipt of the acknowledgment
+        #[account(
+            init,
+            payer = payer,
+            space = 8 + NoticeAcknowledgment::INIT_SPACE,
+            seeds = [b"notice-ack", notice.key().as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub acknowledgment: Account<'info, NoticeAcknowledgment>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Records that the holder acknowledged a notice before its expiry, for
+    /// notices whose acknowledgment is legally required.
+    pub fn acknowledge_notice(ctx: Context<AcknowledgeNotice>) -> Result<()> {
+        check_sponsor(
+            &ctx.accounts.payer.key(),
+            &ctx.accounts.holder.key(),
+            ctx.accounts.relayer_registry.as_deref(),
+        )?;
+        let now = now(ctx.remaining_accounts)?;
+        let notice = &ctx.accounts.notice;
+        if now.has_passed(
+            notice.expiry,
+            ctx.accounts.token_manager.clock_tolerance_seconds,
+        ) {
+            return Err(error!(TokenManagerError::NoticeExpired));
+        }
+
+        ctx.accounts.acknowledgment.set_inner(NoticeAcknowledgment {
+            notice: notice.key(),
+            holder: ctx.accounts.holder.key(),
+            acknowledged_at: now.unix_timestamp,
+        });
+        emit!(NoticeAcknowledged {
+            mint: notice.mint,
+            notice: notice.key(),
+            holder: ctx.accounts.holder.key(),
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct ConfigureTransferReceipts<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// Account storing token information
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The mint the receipts are recorded for
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, which must use `token_mint`
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Compliance receipts of the mint, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + TransferReceiptLog::INIT_SPACE,
+            seeds = [b"transfer-receipts", token_mint.key().as_ref()],
+            bump,
+        )]
+        pub transfer_receipts: Account<'info, TransferReceiptLog>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Enables per-transfer compliance receipts for a token identified by its ISIN.
+    /// The hook then records the rules evaluated for every accepted transfer in a
+    /// log of the latest receipts, and emits each one as `TransferReceiptRecorded`
+    /// for archives keeping them for `retention_days`. The log may only be closed
+    /// once every receipt it holds is past its retention.
+    pub fn configure_transfer_receipts(
+        ctx: Context<ConfigureTransferReceipts>,
+        _isin: Isin,
+        retention_days: u16,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        let receipts = &mut ctx.accounts.transfer_receipts;
+        receipts.mint = ctx.accounts.token_mint.key();
+        receipts.retention_days = retention_days;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct CloseTransferReceipts<'info> {
+        /// The wallet signing the transaction and receiving the rent
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// Account storing token information
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The mint the receipts are recorded for
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, which must use `token_mint`
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::InvalidTokenAccount,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Compliance receipts of the mint
+        #[account(
+            mut,
+            close = signer,
+            seeds = [b"transfer-receipts", token_mint.key().as_ref()],
+            bump,
+        )]
+        pub transfer_receipts: Account<'info, TransferReceiptLog>,
+    }
+
+    /// Disables compliance receipts for a token identified by its ISIN once every
+    /// recorded receipt is past its retention period.
+    pub fn close_transfer_receipts(ctx: Context<CloseTransferReceipts>, _isin: Isin) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        let now = now(ctx.remaining_accounts)?;
+        let tolerance_seconds = ctx.accounts.token_manager.clock_tolerance_seconds;
+        let receipts = &ctx.accounts.transfer_receipts;
+        if receipts
+            .receipts
+            .iter()
+            .any(|receipt| !receipts.is_expired(receipt, &now, tolerance_seconds))
+        {
+            return Err(error!(TokenManagerError::ReceiptsUnderRetention));
+        }
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct ConfigureManager<'info> {
+        /// The wallet signing the transaction
+        pub signer: Signer<'info>,
+
+        /// Account storing the manager-wide settings
+        /// Only the creator should modify it
+        #[account(
+            mut,
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+    }
+
+    /// Sets the drift of the cluster timestamp every time-based rule of the manager
+    /// tolerates, at most `constants::MAX_CLOCK_TOLERANCE_SECONDS`.
+    pub fn set_clock_tolerance(
+        ctx: Context<ConfigureManager>,
+        tolerance_seconds: u32,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        if tolerance_seconds > constants::MAX_CLOCK_TOLERANCE_SECONDS {
+            return Err(error!(TokenManagerError::InvalidClockTolerance));
+        }
+        ctx.accounts.token_manager.clock_tolerance_seconds = tolerance_seconds;
+        Ok(())
+    }
+
+    /// Sets the withholding applied to distributions of holders who have no
+    /// approved, unexpired tax certification.
+    pub fn set_penalty_withholding(
+        ctx: Context<ConfigureManager>,
+        withholding_bps: u16,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        if withholding_bps > 10_000 {
+            return Err(error!(TokenManagerError::InvalidTaxCertification));
+        }
+        ctx.accounts.token_manager.penalty_withholding_bps = withholding_bps;
+        Ok(())
+    }
+
+    /// Proposes `new_creator` as the next creator of the token manager, or cancels
+    /// the pending proposal when `None`. The current creator keeps its rights
+    /// until the new one accepts with `accept_creator`, so a mistyped key cannot
+    /// lock the issuer out.
+    pub fn propose_new_creator(
+        ctx: Context<ConfigureManager>,
+        new_creator: Option<Pubkey>,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let token_manager = &mut ctx.accounts.token_manager;
+        token_manager.pending_creator = new_creator;
+        emit!(CreatorProposed {
+            token_manager: token_manager.key(),
+            creator: token_manager.creator,
+            pending_creator: new_creator,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct AcceptCreator<'info> {
+        /// The proposed creator
+        pub signer: Signer<'info>,
+
+        /// The token manager changing hands
+        #[account(
+            mut,
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+    }
+
+    /// Makes the signer the creator of the token manager it was proposed for. The
+    /// address of the manager and of every account derived from it stays the same.
+    pub fn accept_creator(ctx: Context<AcceptCreator>) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let token_manager = &mut ctx.accounts.token_manager;
+        if token_manager.pending_creator != Some(signer) {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+        token_manager.require_unlocked()?;
+
+        let previous_creator = token_manager.creator;
+        token_manager.creator = signer;
+        token_manager.pending_creator = None;
+        emit!(CreatorChanged {
+            token_manager: token_manager.key(),
+            previous_creator,
+            creator: signer,
+        });
+        Ok(())
+    }
+
+    /// Sets the wallet allowed to lock the manager down, or removes it when `None`.
+    pub fn set_lockdown_guardian(
+        ctx: Context<ConfigureManager>,
+        guardian: Option<Pubkey>,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        ctx.accounts.token_manager.lockdown_guardian = guardian;
+        Ok(())
+    }
+
+    /// Locks the whole manager down in response to a suspected key compromise;
+    /// only the lockdown guardian may trigger it. Transfers of every token fail,
+    /// as do in-kind claims, exchanges, splits and corridor imports, and every
+    /// admin action, by the creator or any role, is refused until the creator
+    /// lifts the lockdown with `end_lockdown`, at the earliest
+    /// `constants::LOCKDOWN_UNLOCK_DELAY_SECONDS` later. Locking down a manager
+    /// already locked down restarts that delay.
+    pub fn lockdown(ctx: Context<ConfigureManager>) -> Result<()> {
+        let guardian = ctx.accounts.signer.key();
+        let token_manager = &mut ctx.accounts.token_manager;
+        if token_manager.lockdown_guardian != Some(guardian) {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+
+        let locked_down_at = now(ctx.remaining_accounts)?.unix_timestamp;
+        token_manager.locked_down_at = Some(locked_down_at);
+        emit!(ManagerLockedDown {
+            token_manager: token_manager.key(),
+            guardian,
+            locked_down_at,
+            unlocks_at: locked_down_at
+                .saturating_add(i64::from(constants::LOCKDOWN_UNLOCK_DELAY_SECONDS)),
+        });
+        log_structured(
+            constants::LOG_MANAGER_LOCKDOWN,
+            &[
+                ("manager", token_manager.key().to_string()),
+                ("guardian", guardian.to_string()),
+            ],
+        );
+        Ok(())
+    }
+
+    /// Lifts the lockdown of the manager; only the creator may, once
+    /// `constants::LOCKDOWN_UNLOCK_DELAY_SECONDS` have passed since it started.
+    pub fn end_lockdown(ctx: Context<ConfigureManager>) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let token_manager = &mut ctx.accounts.token_manager;
+        if signer != token_manager.creator {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+        let Some(locked_down_at) = token_manager.locked_down_at else {
+            return Ok(());
+        };
+        if !now(ctx.remaining_accounts)?.has_passed(
+            locked_down_at.saturating_add(i64::from(constants::LOCKDOWN_UNLOCK_DELAY_SECONDS)),
+            token_manager.clock_tolerance_seconds,
+        ) {
+            return Err(error!(TokenManagerError::LockdownTimelocked));
+        }
+
+        token_manager.locked_down_at = None;
+        emit!(ManagerLockdownLifted {
+            token_manager: token_manager.key(),
+            creator: signer,
+        });
+        Ok(())
+    }
+
+    /// Suspends a compliance module, identified by its `constants::RULE_*` id, for
+    /// every token of the manager until `until_slot`, e.g. during a registrar
+    /// migration. The rule applies again automatically from that slot on; passing a
+    /// past slot lifts the suspension immediately. The rules of
+    /// `constants::NON_SUSPENDABLE_RULES` cannot be suspended.
+    pub fn suspend_module(
+        ctx: Context<ConfigureManager>,
+        module_id: u16,
+        until_slot: u64,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        if constants::NON_SUSPENDABLE_RULES.contains(&module_id) {
+            return Err(error!(TokenManagerError::ModuleNotSuspendable));
+        }
+
+        let slot = now(ctx.remaining_accounts)?.slot;
+        let token_manager = &mut ctx.accounts.token_manager;
+        token_manager
+            .suspended_modules
+            .retain(|suspension| suspension.rule_id != module_id && slot < suspension.until_slot);
+        if slot < until_slot {
+            if token_manager.suspended_modules.len() >= 8 {
+                return Err(error!(TokenManagerError::ModuleSuspensionsFull));
+            }
+            token_manager.suspended_modules.push(ModuleSuspension {
+                rule_id: module_id,
+                until_slot,
+            });
+        }
+
+        emit!(ModuleSuspended {
+            token_manager: token_manager.key(),
+            rule_id: module_id,
+            until_slot,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct InitializeRulebook<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager the rulebook belongs to
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Issuer-specific descriptions of the rule ids emitted on rejection
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + Rulebook::INIT_SPACE,
+            seeds = [b"rulebook", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub rulebook: Account<'info, Rulebook>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Creates the empty rulebook of a token manager.
+    pub fn initialize_rulebook(ctx: Context<InitializeRulebook>) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        ctx.accounts.rulebook.token_manager = ctx.accounts.token_manager.key();
+        ctx.accounts.rulebook.rules = Vec::new();
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct UpdateRulebook<'info> {
+        /// The wallet signing the transaction
+        pub signer: Signer<'info>,
+
+        /// The token manager the rulebook belongs to
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The rulebook being modified
+        #[account(
+            mut,
+            seeds = [b"rulebook", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub rulebook: Account<'info, Rulebook>,
+    }
+
+    /// Sets the description shown to holders for a rule id.
+    /// An empty description removes the entry so front-ends fall back to their defaults.
+    ///
+    /// # Arguments
+    ///
+    /// * `rule_id` - One of the `RULE_*` ids from the `constants` module.
+    /// * `description` - Short issuer-specific explanation, at most 64 bytes.
+    pub fn set_rule_description(
+        ctx: Context<UpdateRulebook>,
+        rule_id: u16,
+        description: String,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        if description.len() > 64 {
+            return Err(error!(TokenManagerError::DescriptionTooLong));
+        }
+
+        let rules = &mut ctx.accounts.rulebook.rules;
+        let position = rules.iter().position(|rule| rule.rule_id == rule_id);
+
+        match (position, description.is_empty()) {
+            (Some(index), true) => {
+                rules.remove(index);
+            }
+            (Some(index), false) => rules[index].description = description,
+            (None, true) => {}
+            (None, false) => {
+                if rules.len() >= 16 {
+                    return Err(error!(TokenManagerError::RulebookFull));
+                }
+                rules.push(RuleDescription {
+                    rule_id,
+                    description,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct UpdateIssuerProfile<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager the profile describes
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Public information about the issuer, created on first update
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + IssuerProfile::INIT_SPACE,
+            seeds = [b"issuer-profile", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub issuer_profile: Account<'info, IssuerProfile>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Creates or replaces the public profile of the issuer behind a token manager,
+    /// so wallets and portals can show who stands behind its ISINs.
+    ///
+    /// # Arguments
+    ///
+    /// * `display_name` - Issuer name, at most 64 bytes.
+    /// * `website` - Issuer website, at most 128 bytes.
+    /// * `logo_uri` - URI of the issuer logo, at most 200 bytes.
+    pub fn update_issuer_profile(
+        ctx: Context<UpdateIssuerProfile>,
+        display_name: String,
+        website: String,
+        logo_uri: String,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        if display_name.len() > 64 || website.len() > 128 || logo_uri.len() > 200 {
+            return Err(error!(TokenManagerError::FieldTooLong));
+        }
+
+        let issuer_profile = &mut ctx.accounts.issuer_profile;
+        issuer_profile.token_manager = ctx.accounts.token_manager.key();
+        issuer_profile.display_name = display_name;
+        issuer_profile.website = website;
+        issuer_profile.logo_uri = logo_uri;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct SetRegistryIdentifiers<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Identifiers of the token in external systems, created on first update
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + RegistryIdentifiers::INIT_SPACE,
+            seeds = [b"registry-ids", token_share.mint.as_ref()],
+            bump,
+        )]
+        pub registry_identifiers: Account<'info, RegistryIdentifiers>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Creates or replaces the identifiers of a token identified by its ISIN in the
+    /// off-chain systems servicing it, so middle-office systems can reconcile
+    /// against the mint without keeping their own mapping tables. Empty strings
+    /// leave an identifier unset.
+    ///
+    /// # Arguments
+    ///
+    /// * `transfer_agent_account` - Account number at the transfer agent, at most 34 bytes.
+    /// * `csd_identifier` - Identifier at the central securities depository, at most 32 bytes.
+    /// * `paying_agent_bic` - SWIFT BIC of the paying agent, 8 or 11 characters.
+    pub fn set_registry_identifiers(
+        ctx: Context<SetRegistryIdentifiers>,
+        _isin: Isin,
+        transfer_agent_account: String,
+        csd_identifier: String,
+        paying_agent_bic: String,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        if transfer_agent_account.len() > 34 || csd_identifier.len() > 32 {
+            return Err(error!(TokenManagerError::FieldTooLong));
+        }
+        if !paying_agent_bic.is_empty() && !is_valid_bic(&paying_agent_bic) {
+            return Err(error!(TokenManagerError::InvalidBic));
+        }
+
+        let mint = ctx.accounts.token_share.mint;
+        ctx.accounts
+            .registry_identifiers
+            .set_inner(RegistryIdentifiers {
+                mint,
+                transfer_agent_account: transfer_agent_account.clone(),
+                csd_identifier: csd_identifier.clone(),
+                paying_agent_bic: paying_agent_bic.clone(),
+            });
+        emit!(RegistryIdentifiersUpdated {
+            mint,
+            transfer_agent_account,
+            csd_identifier,
+            paying_agent_bic,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct SetRelayer<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager the relayers act for
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Relayers allowed to pay fees for investor actions, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + RelayerRegistry::INIT_SPACE,
+            seeds = [b"relayers", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub relayer_registry: Account<'info, RelayerRegistry>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Adds or removes a relayer allowed to pay transaction fees and rent on behalf
+    /// of investors. Investor-facing instructions take a separate `payer` which must
+    /// be either the investor or one of these relayers, enabling gasless flows.
+    pub fn set_relayer(ctx: Context<SetRelayer>, relayer: Pubkey, allowed: bool) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let registry = &mut ctx.accounts.relayer_registry;
+        registry.token_manager = ctx.accounts.token_manager.key();
+        let position = registry.relayers.iter().position(|key| *key == relayer);
+
+        match (position, allowed) {
+            (Some(index), false) => {
+                registry.relayers.remove(index);
+            }
+            (None, true) => {
+                if registry.relayers.len() >= 8 {
+                    return Err(error!(TokenManagerError::RelayerRegistryFull));
+                }
+                registry.relayers.push(relayer);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct SetPaymentMint<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager accepting the payment mint
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The payment mint being approved or revoked
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Payment mints approved by the issuer, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + PaymentMintRegistry::INIT_SPACE,
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Approves or revokes a stablecoin as payment mint of the token manager. Every
+    /// instruction moving cash (settlement batches, netting cycles, settlement
+    /// fails) only accepts approved mints. `oracle` references the price feed
+    /// off-chain services should use to value the mint.
+    pub fn set_payment_mint(
+        ctx: Context<SetPaymentMint>,
+        oracle: Option<Pubkey>,
+        allowed: bool,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let registry = &mut ctx.accounts.payment_mints;
+        registry.token_manager = ctx.accounts.token_manager.key();
+        let mint = ctx.accounts.payment_mint.key();
+        registry
+            .mints
+            .retain(|payment_mint| payment_mint.mint != mint);
+        if allowed {
+            if registry.mints.len() >= 8 {
+                return Err(error!(TokenManagerError::PaymentMintRegistryFull));
+            }
+            registry.mints.push(PaymentMint {
+                mint,
+                decimals: ctx.accounts.payment_mint.decimals,
+                oracle,
+            });
+        }
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct ConfigureSanctionsList<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager screening its transfers
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Sanctions list checked by the hook, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + SanctionsList::INIT_SPACE,
+            seeds = [b"sanctions-list", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub sanctions_list: Account<'info, SanctionsList>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Sets the screening provider maintaining the sanctions list of the token
+    /// manager. Once the list exists, the hook denies every transfer whose source
+    /// or destination wallet it contains.
+    pub fn set_sanctions_provider(
+        ctx: Context<ConfigureSanctionsList>,
+        provider: Pubkey,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let sanctions_list = &mut ctx.accounts.sanctions_list;
+        sanctions_list.token_manager = ctx.accounts.token_manager.key();
+        sanctions_list.provider = provider;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct UpdateSanctionsList<'info> {
+        /// The screening provider of the list
+        pub provider: Signer<'info>,
+
+        /// Sanctions list being updated
+        #[account(
+            mut,
+            has_one = provider @ TokenManagerError::Unauthorized,
+        )]
+        pub sanctions_list: Account<'info, SanctionsList>,
+    }
+
+    /// Overwrites the bloom filter of a sanctions list from byte `offset` with
+    /// `bits`, so providers can publish a filter built off-chain in chunks.
+    pub fn update_sanctions_filter(
+        ctx: Context<UpdateSanctionsList>,
+        offset: u16,
+        bits: Vec<u8>,
+    ) -> Result<()> {
+        let sanctions_list = &mut ctx.accounts.sanctions_list;
+        let start = offset as usize;
+        let end = start
+            .checked_add(bits.len())
+            .filter(|end| *end <= sanctions_list.filter.len())
+            .ok_or(error!(TokenManagerError::InvalidSanctionsFilter))?;
+        sanctions_list.filter[start..end].copy_from_slice(&bits);
+        sanctions_list.updated_slot = now(ctx.remaining_accounts)?.slot;
+        Ok(())
+    }
+
+    /// Adds `wallet` to the bloom filter of a sanctions list.
+    pub fn flag_sanctioned_wallet(ctx: Context<UpdateSanctionsList>, wallet: Pubkey) -> Result<()> {
+        let sanctions_list = &mut ctx.accounts.sanctions_list;
+        sanctions_list.insert(&wallet);
+        sanctions_list.updated_slot = now(ctx.remaining_accounts)?.slot;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct ConfigureFeeSchedule<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager charging the fees
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Fee schedule of the manager, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + FeeSchedule::INIT_SPACE,
+            seeds = [b"fee-schedule", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub fee_schedule: Account<'info, FeeSchedule>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Publishes a new version of the fee schedule of the manager, which users can
+    /// read before signing. Offers and netting cycles already open keep the fees
+    /// in force when they opened.
+    pub fn set_fee_schedule(ctx: Context<ConfigureFeeSchedule>, terms: FeeTerms) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        terms.validate()?;
+
+        let fee_schedule = &mut ctx.accounts.fee_schedule;
+        fee_schedule.token_manager = ctx.accounts.token_manager.key();
+        fee_schedule.version = fee_schedule
+            .version
+            .checked_add(1)
+            .ok_or(error!(TokenManagerError::IndexOverflow))?;
+        fee_schedule.terms = terms.clone();
+        emit!(FeeScheduleUpdated {
+            token_manager: fee_schedule.token_manager,
+            version: fee_schedule.version,
+            terms,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct ConfigureKeeperRewards<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager whose cranks are rewarded
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Bounty of the manager, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + KeeperRewards::INIT_SPACE,
+            seeds = [b"keeper-rewards", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub keeper_rewards: Account<'info, KeeperRewards>,
+
+        /// Mint token bounties are paid in; lamport bounties are paid when omitted
+        pub reward_mint: Option<InterfaceAccount<'info, Mint>>,
+
+        /// Vault holding the token bounties, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            seeds = [b"keeper-vault", token_manager.key().as_ref()],
+            bump,
+            token::mint = reward_mint,
+            token::authority = keeper_rewards,
+            token::token_program = reward_token_program,
+        )]
+        pub reward_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+        /// Token program of the reward mint
+        pub reward_token_program: Option<Interface<'info, TokenInterface>>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Sets the bounty paid to keepers for every item a permissionless crank of the
+    /// manager processes, in units of `reward_mint` when given and in lamports
+    /// otherwise. The issuer funds it by transferring lamports to the keeper
+    /// rewards account or tokens to the keeper vault; cranks pay what is left when
+    /// the funds run short rather than failing.
+    pub fn set_keeper_bounty(
+        ctx: Context<ConfigureKeeperRewards>,
+        bounty_per_item: u64,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        let reward_mint = ctx.accounts.reward_mint.as_ref().map(|mint| mint.key());
+        if reward_mint.is_some() && ctx.accounts.reward_vault.is_none() {
+            return Err(error!(TokenManagerError::KeeperRewardAccountsRequired));
+        }
+
+        let keeper_rewards = &mut ctx.accounts.keeper_rewards;
+        keeper_rewards.token_manager = ctx.accounts.token_manager.key();
+        keeper_rewards.bounty_per_item = bounty_per_item;
+        keeper_rewards.reward_mint = reward_mint;
+        keeper_rewards.bump = ctx.bumps.keeper_rewards;
+        emit!(KeeperBountySet {
+            token_manager: keeper_rewards.token_manager,
+            bounty_per_item,
+            reward_mint,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct SetReportingCurrency<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager reporting in the currency
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Payment mints approved by the issuer, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + PaymentMintRegistry::INIT_SPACE,
+            seeds = [b"payment-mints", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub payment_mints: Account<'info, PaymentMintRegistry>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Sets the ISO 4217 currency the token manager reports in. Payouts in a
+    /// payment mint with an oracle record the rate of the mint in this currency,
+    /// published at most `fx_rate_max_age_seconds` earlier; zero disables the check.
+    pub fn set_reporting_currency(
+        ctx: Context<SetReportingCurrency>,
+        currency: [u8; 3],
+        fx_rate_max_age_seconds: u32,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let registry = &mut ctx.accounts.payment_mints;
+        registry.token_manager = ctx.accounts.token_manager.key();
+        registry.reporting_currency = currency;
+        registry.fx_rate_max_age_seconds = fx_rate_max_age_seconds;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct PublishFxRate<'info> {
+        /// The oracle publishing the rate, paying for the feed
+        #[account(mut)]
+        pub authority: Signer<'info>,
+
+        /// The payment mint the rate values
+        pub payment_mint: InterfaceAccount<'info, Mint>,
+
+        /// Rate feed of the oracle for the mint, created on first use
+        #[account(
+            init_if_needed,
+            payer = authority,
+            space = 8 + FxRateFeed::INIT_SPACE,
+            seeds = [b"fx-rate", payment_mint.key().as_ref(), authority.key().as_ref()],
+            bump,
+        )]
+        pub fx_rate: Account<'info, FxRateFeed>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Publishes the rate of a payment mint in `currency`: one whole token of the
+    /// mint is worth `rate / 10^decimals` units of the currency. Issuers reference
+    /// the feed as oracle of the mint with `set_payment_mint`.
+    pub fn publish_fx_rate(
+        ctx: Context<PublishFxRate>,
+        currency: [u8; 3],
+        rate: u64,
+        decimals: u8,
+    ) -> Result<()> {
+        ctx.accounts.fx_rate.set_inner(FxRateFeed {
+            authority: ctx.accounts.authority.key(),
+            payment_mint: ctx.accounts.payment_mint.key(),
+            currency,
+            rate,
+            decimals,
+            published_at: now(ctx.remaining_accounts)?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(holder: Pubkey)]
+    pub struct ConfigureRoles<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager the roles apply to
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Roles of the holder, created on first grant
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + RoleAssignment::INIT_SPACE,
+            seeds = [b"role", token_manager.key().as_ref(), holder.as_ref()],
+            bump,
+        )]
+        pub role_assignment: Account<'info, RoleAssignment>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Grants `role` on every token of the manager to `holder`, so day-to-day
+    /// operations do not all depend on the creator key.
+    pub fn grant_role(ctx: Context<ConfigureRoles>, holder: Pubkey, role: Role) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let token_manager = ctx.accounts.token_manager.key();
+        let assignment = &mut ctx.accounts.role_assignment;
+        assignment.token_manager = token_manager;
+        assignment.holder = holder;
+        assignment.roles |= role.bit();
+        emit!(RoleGranted {
+            token_manager,
+            holder,
+            role,
+        });
+        Ok(())
+    }
+
+    /// Revokes `role` from `holder`, closing its role assignment once it holds no
+    /// role anymore.
+    pub fn revoke_role(ctx: Context<ConfigureRoles>, holder: Pubkey, role: Role) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let token_manager = ctx.accounts.token_manager.key();
+        let assignment = &mut ctx.accounts.role_assignment;
+        assignment.roles &= !role.bit();
+        if assignment.roles == 0 {
+            assignment.close(ctx.accounts.signer.to_account_info())?;
+        }
+        emit!(RoleRevoked {
+            token_manager,
+            holder,
+            role,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct SetComplianceOfficer<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager the officers work for
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Officers and their pending tasks, created on first use
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + ComplianceQueue::INIT_SPACE,
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: Account<'info, ComplianceQueue>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Adds or removes a compliance officer allowed to work the task queue.
+    pub fn set_compliance_officer(
+        ctx: Context<SetComplianceOfficer>,
+        officer: Pubkey,
+        allowed: bool,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let queue = &mut ctx.accounts.compliance_queue;
+        queue.token_manager = ctx.accounts.token_manager.key();
+        let position = queue.officers.iter().position(|key| *key == officer);
+
+        match (position, allowed) {
+            (Some(index), false) => {
+                queue.officers.remove(index);
+            }
+            (None, true) => {
+                if queue.officers.len() >= 8 {
+                    return Err(error!(TokenManagerError::ComplianceOfficersFull));
+                }
+                queue.officers.push(officer);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Sets the SLA of the tasks queued at every priority, in seconds indexed by
+    /// `TaskPriority` with 0 for none, and what happens to transfer approvals left
+    /// unanswered past it. Tasks already queued keep their deadline.
+    pub fn set_task_sla(
+        ctx: Context<SetComplianceOfficer>,
+        sla_seconds: [u32; 4],
+        escalation: SlaEscalation,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        let queue = &mut ctx.accounts.compliance_queue;
+        queue.token_manager = ctx.accounts.token_manager.key();
+        queue.sla_seconds = sla_seconds;
+        queue.escalation = escalation;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct ComplianceAction<'info> {
+        /// The creator of the token manager or one of its compliance officers
+        pub signer: Signer<'info>,
+
+        /// The token manager the queue belongs to
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Roles of the signer; only needed when it is not an officer of the queue
+        /// CHECK: May not exist; deserialized in the ComplianceAction implementations
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+
+        /// Officers and their pending tasks
+        #[account(
+            mut,
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: Account<'info, ComplianceQueue>,
+    }
+
+    /// Adds an item needing officer attention to the queue, ahead of the tasks of
+    /// lower priority and due within the SLA of its priority.
+    pub fn enqueue_task(
+        ctx: Context<ComplianceAction>,
+        kind: TaskKind,
+        subject: Pubkey,
+        priority: TaskPriority,
+    ) -> Result<()> {
+        ctx.accounts.require_officer()?;
+
+        let created_at = now(ctx.remaining_accounts)?.unix_timestamp;
+        ctx.accounts
+            .compliance_queue
+            .push_task(kind, subject, priority, created_at)?;
+        Ok(())
+    }
+
+    /// Claims a pending task so other officers know it is being worked on.
+    pub fn claim_task(ctx: Context<ComplianceAction>, task_id: u64) -> Result<()> {
+        let officer = ctx.accounts.signer.key();
+        ctx.accounts.require_officer()?;
+        let queue = &mut ctx.accounts.compliance_queue;
+
+        let task = queue
+            .tasks
+            .iter_mut()
+            .find(|task| task.id == task_id)
+            .ok_or(error!(TokenManagerError::TaskNotFound))?;
+        if matches!(task.claimed_by, Some(claimant) if claimant != officer) {
+            return Err(error!(TokenManagerError::TaskAlreadyClaimed));
+        }
+        task.claimed_by = Some(officer);
+        Ok(())
+    }
+
+    /// Removes a task claimed by the signer from the queue once it has been handled,
+    /// provided the signer is still an officer.
+    pub fn resolve_task(ctx: Context<ComplianceAction>, task_id: u64) -> Result<()> {
+        let officer = ctx.accounts.signer.key();
+        ctx.accounts.require_officer()?;
+        let queue = &mut ctx.accounts.compliance_queue;
+        let index = queue
+            .tasks
+            .iter()
+            .position(|task| task.id == task_id)
+            .ok_or(error!(TokenManagerError::TaskNotFound))?;
+        if queue.tasks[index].claimed_by != Some(officer) {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+        queue.tasks.remove(index);
+
+        emit!(ComplianceTaskResolved {
+            token_manager: queue.token_manager,
+            id: task_id,
+            officer,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct EscalateTask<'info> {
+        /// The token manager the queue belongs to
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Officers and their pending tasks
+        #[account(
+            mut,
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: Account<'info, ComplianceQueue>,
+
+        /// The transfer request the task was queued for
+        #[account(
+            mut,
+            seeds = [
+                b"transfer-request",
+                transfer_request.mint.as_ref(),
+                transfer_request.from.as_ref(),
+                transfer_request.to.as_ref(),
+            ],
+            bump = transfer_request.bump,
+        )]
+        pub transfer_request: Account<'info, TransferRequest>,
+
+        /// The holder of the request, refunded when it is rejected
+        /// CHECK: Only receives the rent of a rejected request
+        #[account(mut, address = transfer_request.from)]
+        pub holder: UncheckedAccount<'info>,
+    }
+
+    /// Resolves a transfer approval left unanswered past its SLA deadline as the
+    /// escalation of the queue says, approving the request for the queue or
+    /// closing it; callable by anyone so counterparties are not left waiting on
+    /// an officer. A request an officer already answered only leaves the queue.
+    pub fn escalate_task(ctx: Context<EscalateTask>, task_id: u64) -> Result<()> {
+        let now = now(ctx.remaining_accounts)?;
+        let tolerance = ctx.accounts.token_manager.clock_tolerance_seconds;
+        let queue = &mut ctx.accounts.compliance_queue;
+        let index = queue
+            .tasks
+            .iter()
+            .position(|task| task.id == task_id)
+            .ok_or(error!(TokenManagerError::TaskNotFound))?;
+        let task = &queue.tasks[index];
+        let approved = match queue.escalation {
+            SlaEscalation::AutoApprove => true,
+            SlaEscalation::AutoReject => false,
+            SlaEscalation::None => return Err(error!(TokenManagerError::EscalationDisabled)),
+        };
+        let Some(sla_deadline) = task.sla_deadline else {
+            return Err(error!(TokenManagerError::EscalationDisabled));
+        };
+        if task.kind != TaskKind::TransferApproval {
+            return Err(error!(TokenManagerError::EscalationDisabled));
+        }
+        if !now.has_passed(sla_deadline, tolerance) {
+            return Err(error!(TokenManagerError::TaskWithinSla));
+        }
+        let request = &mut ctx.accounts.transfer_request;
+        if task.subject != request.key() || request.task_id != Some(task_id) {
+            return Err(error!(TokenManagerError::TransferRequestMismatch));
+        }
+
+        let task = queue.tasks.remove(index);
+        if request.approved_by.is_none() && !request.consumed {
+            if approved {
+                request.approved_by = Some(queue.key());
+            } else {
+                request.close(ctx.accounts.holder.to_account_info())?;
+            }
+        }
+        emit!(ComplianceTaskEscalated {
+            token_manager: queue.token_manager,
+            id: task_id,
+            subject: task.subject,
+            approved,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct SubmitTaxCertification<'info> {
+        /// Pays for the certification: the holder or an allowlisted relayer
+        #[account(mut)]
+        pub payer: Signer<'info>,
+
+        /// The holder certifying its tax residency
+        pub holder: Signer<'info>,
+
+        /// Token manager the certification is submitted to
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Relayers allowed to pay for investor actions, if any were configured
+        #[account(
+            seeds = [b"relayers", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub relayer_registry: Option<Account<'info, RelayerRegistry>>,
+
+        /// Certification of the holder, replaced by every submission
+        #[account(
+            init_if_needed,
+            payer = payer,
+            space = 8 + TaxCertification::INIT_SPACE,
+            seeds = [b"tax-certification", token_manager.key().as_ref(), holder.key().as_ref()],
+            bump,
+        )]
+        pub tax_certification: Account<'info, TaxCertification>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Submits a tax-residency self-certification of the holder, identified by the
+    /// `hash` of the signed form and valid until `expiry`. Every submission waits
+    /// for an officer to review it before distributions stop being withheld.
+    pub fn submit_tax_certification(
+        ctx: Context<SubmitTaxCertification>,
+        form_type: TaxFormType,
+        hash: [u8; 32],
+        expiry: i64,
+    ) -> Result<()> {
+        check_sponsor(
+            &ctx.accounts.payer.key(),
+            &ctx.accounts.holder.key(),
+            ctx.accounts.relayer_registry.as_deref(),
+        )?;
+        let now = now(ctx.remaining_accounts)?;
+        if expiry <= now.unix_timestamp {
+            return Err(error!(TokenManagerError::InvalidTaxCertification));
+        }
+
+        let holder = ctx.accounts.holder.key();
+        ctx.accounts.tax_certification.set_inner(TaxCertification {
+            token_manager: ctx.accounts.token_manager.key(),
+            holder,
+            form_type,
+            hash,
+            expiry,
+            submitted_at: now.unix_timestamp,
+            status: CertificationStatus::Pending,
+            reviewed_by: None,
+        });
+        emit!(TaxCertificationSubmitted {
+            token_manager: ctx.accounts.token_manager.key(),
+            holder,
+            form_type,
+            expiry,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(holder: Pubkey)]
+    pub struct ReviewTaxCertification<'info> {
+        /// The creator of the token manager or one of its compliance officers
+        pub signer: Signer<'info>,
+
+        /// The token manager the certification was submitted to
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Compliance officers of the manager, if any were appointed
+        #[account(
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: Option<Account<'info, ComplianceQueue>>,
+
+        /// The certification being reviewed
+        #[account(
+            mut,
+            seeds = [b"tax-certification", token_manager.key().as_ref(), holder.as_ref()],
+            bump,
+        )]
+        pub tax_certification: Account<'info, TaxCertification>,
+    }
+
+    /// Approves or rejects the pending tax certification of `holder`.
+    pub fn review_tax_certification(
+        ctx: Context<ReviewTaxCertification>,
+        holder: Pubkey,
+        approve: bool,
+    ) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let creator = ctx.accounts.token_manager.creator;
+        ctx.accounts.token_manager.require_unlocked()?;
+        let is_officer = ctx
+            .accounts
+            .compliance_queue
+            .as_ref()
+            .is_some_and(|queue| queue.is_officer(&signer, &creator));
+        if signer != creator && !is_officer {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+
+        let certification = &mut ctx.accounts.tax_certification;
+        if certification.status != CertificationStatus::Pending {
+            return Err(error!(TokenManagerError::InvalidTaxCertification));
+        }
+        certification.status = if approve {
+            CertificationStatus::Approved
+        } else {
+            CertificationStatus::Rejected
+        };
+        certification.reviewed_by = Some(signer);
+        emit!(TaxCertificationReviewed {
+            token_manager: certification.token_manager,
+            holder,
+            approved: approve,
+            officer: signer,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(holder: Pubkey)]
+    pub struct GetWithholdingRate<'info> {
+        /// The token manager distributing
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Certification of the holder, if it submitted one
+        /// CHECK: May not exist; deserialized in the GetWithholdingRate implementation
+        #[account(
+            seeds = [b"tax-certification", token_manager.key().as_ref(), holder.as_ref()],
+            bump,
+        )]
+        pub tax_certification: UncheckedAccount<'info>,
+    }
+
+    /// Returns the withholding, in basis points, applied to distributions paid to
+    /// `holder`: none with an approved, unexpired certification, and the penalty
+    /// withholding of the manager otherwise.
+    pub fn get_withholding_rate(ctx: Context<GetWithholdingRate>, _holder: Pubkey) -> Result<u16> {
+        let certification =
+            load_optional_account::<TaxCertification>(&ctx.accounts.tax_certification)?;
+        Ok(withholding_bps(
+            &ctx.accounts.token_manager,
+            certification.as_ref(),
+            now(ctx.remaining_accounts)?,
+        ))
+    }
+
+    #[derive(Accounts)]
+    pub struct InitializeMockClock<'info> {
+        /// The wallet signing the transaction, paying for and controlling the clock
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// Program-wide mock clock, only honoured by `test-clock` builds
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + MockClock::INIT_SPACE,
+            seeds = [b"mock-clock"],
+            bump,
+        )]
+        pub mock_clock: Account<'info, MockClock>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Creates the mock clock used instead of the `Clock` sysvar in `test-clock` builds.
+    /// Fails on any build without the `test-clock` feature.
+    pub fn initialize_mock_clock(
+        ctx: Context<InitializeMockClock>,
+        unix_timestamp: i64,
+        slot: u64,
+    ) -> Result<()> {
+        if !cfg!(feature = "test-clock") {
+            return Err(error!(TokenManagerError::MockClockDisabled));
+        }
+
+        ctx.accounts.mock_clock.authority = ctx.accounts.signer.key();
+        ctx.accounts.mock_clock.unix_timestamp = unix_timestamp;
+        ctx.accounts.mock_clock.slot = slot;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct SetMockClock<'info> {
+        /// The wallet that created the mock clock
+        pub signer: Signer<'info>,
+
+        /// Program-wide mock clock
+        #[account(
+            mut,
+            seeds = [b"mock-clock"],
+            bump,
+            constraint = mock_clock.authority == signer.key() @ TokenManagerError::Unauthorized,
+        )]
+        pub mock_clock: Account<'info, MockClock>,
+    }
+
+    /// Moves the mock clock to an arbitrary point in time, forwards or backwards.
+    /// Fails on any build without the `test-clock` feature.
+    pub fn set_mock_clock(
+        ctx: Context<SetMockClock>,
+        unix_timestamp: i64,
+        slot: u64,
+    ) -> Result<()> {
+        if !cfg!(feature = "test-clock") {
+            return Err(error!(TokenManagerError::MockClockDisabled));
+        }
+
+        ctx.accounts.mock_clock.unix_timestamp = unix_timestamp;
+        ctx.accounts.mock_clock.slot = slot;
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(operator: Pubkey, isin: Isin)]
+    pub struct CreateMintSession<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token the operator may mint
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Minting allowance of the operator, replaced when it already exists
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + MintSession::INIT_SPACE,
+            seeds = [b"mint-session", token_share.mint.as_ref(), operator.as_ref()],
+            bump,
+        )]
+        pub mint_session: Account<'info, MintSession>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Lets an operations key mint up to `max_amount` tokens of a token identified
+    /// by its ISIN until `expiry`, so day-to-day issuance does not need the creator
+    /// key online. Creating a session for an operator that already has one
+    /// replaces it, resetting the amount already minted.
+    pub fn create_mint_session(
+        ctx: Context<CreateMintSession>,
+        operator: Pubkey,
+        _isin: Isin,
+        max_amount: u64,
+        expiry: i64,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        if expiry <= now(ctx.remaining_accounts)?.unix_timestamp {
+            return Err(error!(TokenManagerError::MintSessionExpired));
+        }
+
+        let mint = ctx.accounts.token_share.mint;
+        ctx.accounts.mint_session.set_inner(MintSession {
+            mint,
+            operator,
+            max_amount,
+            minted: 0,
+            expiry,
+        });
+        emit!(MintSessionCreated {
+            mint,
+            operator,
+            max_amount,
+            expiry,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(operator: Pubkey, isin: Isin)]
+    pub struct RevokeMintSession<'info> {
+        /// The wallet signing the transaction, refunded the rent of the session
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Minting allowance of the operator
+        #[account(
+            mut,
+            close = signer,
+            seeds = [b"mint-session", token_share.mint.as_ref(), operator.as_ref()],
+            bump,
+        )]
+        pub mint_session: Account<'info, MintSession>,
+    }
+
+    /// Ends the mint session of an operator before its expiry.
+    pub fn revoke_mint_session(
+        ctx: Context<RevokeMintSession>,
+        operator: Pubkey,
+        _isin: Isin,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        emit!(MintSessionRevoked {
+            mint: ctx.accounts.token_share.mint,
+            operator,
+            minted: ctx.accounts.mint_session.minted,
+        });
+        Ok(())
+    }
+
+    /// Structure for the mint_tokens instruction
+    #[derive(Accounts)]
+    #[instruction(token_index: u64)]
+    pub struct MintToken<'info> {
+        /// The creator of the token manager, a minter, or an operator with a mint session
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// Account storing token metadata and whitelist information
+        #[account(
+            mut,
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// The token mint - with seeds derived from token-manager + index
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_index.to_le_bytes()],
+            bump,
+        )]
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// Configuration of the token, holding its supply cap
+        #[account(
+            mut,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::TokenNotFound,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The account receiving the tokens
+        #[account(mut)]
+        pub destination: InterfaceAccount<'info, TokenAccount>,
+
+        /// Collateral attestation of the token, if it is asset-backed
+        /// CHECK: May not exist; deserialized in the MintToken implementation
+        #[account(
+            seeds = [b"collateral", token_mint.key().as_ref()],
+            bump,
+        )]
+        pub collateral: UncheckedAccount<'info>,
+
+        /// Mint session of the signer, when it is not the creator
+        /// CHECK: May not exist; deserialized in the MintToken implementation
+        #[account(
+            mut,
+            seeds = [b"mint-session", token_mint.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub mint_session: UncheckedAccount<'info>,
+
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the MintToken implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+
+        /// Operation lock of the token; a split in progress keeps the supply
+        /// CHECK: May not exist; deserialized in the MintToken implementation
+        #[account(
+            seeds = [b"operation-lock", token_mint.key().as_ref()],
+            bump,
+        )]
+        pub operation_lock: UncheckedAccount<'info>,
+
+        /// The Token 2022 program
+        pub token_program: Program<'info, Token2022>,
+    }
+
+    /// Mints tokens to `destination`. Besides the creator, minters may mint
+    /// without limit, while other operators mint within their mint session, which
+    /// is debited by `amount`. Nothing may be minted while a split holds the
+    /// operation lock.
+    pub fn mint_tokens(ctx: Context<MintToken>, token_index: u64, amount: u64) -> Result<()> {
+        check_operation_not_held(&ctx.accounts.operation_lock, TokenOperation::Split)?;
+        let current = now(ctx.remaining_accounts)?;
+        if !has_role(
+            &ctx.accounts.token_manager,
+            &ctx.accounts.signer.key(),
+            &ctx.accounts.role_assignment,
+            Role::Minter,
+        )? {
+            debit_mint_session(&ctx.accounts.mint_session, amount, current.unix_timestamp)?;
+        }
+        if let Some(max_supply) = ctx.accounts.token_share.max_supply {
+            ctx.accounts
+                .token_mint
+                .supply
+                .checked_add(amount)
+                .filter(|supply| *supply <= max_supply)
+                .ok_or(error!(TokenManagerError::SupplyCapExceeded))?;
+        }
+        check_collateral_fresh(
+            &ctx.accounts.collateral,
+            &current,
+            ctx.accounts.token_manager.clock_tolerance_seconds,
+        )?;
+
+        mint_share_tokens(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.token_mint.to_account_info(),
+            ctx.accounts.destination.to_account_info(),
+            &ctx.accounts.token_manager.key(),
+            token_index,
+            ctx.bumps.token_mint,
+            amount,
+        )?;
+        let balance = ctx.accounts.destination.amount;
+        ctx.accounts
+            .token_share
+            .record_holding(balance, balance + amount)?;
+
+        emit!(TokensMinted {
+            mint: ctx.accounts.token_mint.key(),
+            holder: ctx.accounts.destination.owner,
+            amount,
+            minted_by: ctx.accounts.signer.key(),
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(operator: Pubkey, isin: Isin)]
+    pub struct CreateBurnSession<'info> {
+        /// The wallet signing and paying for the transaction
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token the operator may burn
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Burning allowance of the operator, replaced when it already exists
+        #[account(
+            init_if_needed,
+            payer = signer,
+            space = 8 + BurnSession::INIT_SPACE,
+            seeds = [b"burn-session", token_share.mint.as_ref(), operator.as_ref()],
+            bump,
+        )]
+        pub burn_session: Account<'info, BurnSession>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Lets an operations key, typically the redemptions desk, burn up to
+    /// `daily_cap` tokens per UTC day of a token identified by its ISIN until
+    /// `expiry`, from accounts it owns or was delegated. Creating a session for an
+    /// operator that already has one replaces it.
+    pub fn create_burn_session(
+        ctx: Context<CreateBurnSession>,
+        operator: Pubkey,
+        _isin: Isin,
+        daily_cap: u64,
+        expiry: i64,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+        if expiry <= now(ctx.remaining_accounts)?.unix_timestamp {
+            return Err(error!(TokenManagerError::BurnSessionExpired));
+        }
+
+        let mint = ctx.accounts.token_share.mint;
+        ctx.accounts.burn_session.set_inner(BurnSession {
+            mint,
+            operator,
+            daily_cap,
+            day: 0,
+            burned_today: 0,
+            expiry,
+        });
+        emit!(BurnSessionCreated {
+            mint,
+            operator,
+            daily_cap,
+            expiry,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(operator: Pubkey, isin: Isin)]
+    pub struct RevokeBurnSession<'info> {
+        /// The wallet signing the transaction, refunded the rent of the session
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// Burning allowance of the operator
+        #[account(
+            mut,
+            close = signer,
+            seeds = [b"burn-session", token_share.mint.as_ref(), operator.as_ref()],
+            bump,
+        )]
+        pub burn_session: Account<'info, BurnSession>,
+    }
+
+    /// Ends the burn session of an operator before its expiry.
+    pub fn revoke_burn_session(
+        ctx: Context<RevokeBurnSession>,
+        operator: Pubkey,
+        _isin: Isin,
+    ) -> Result<()> {
+        require_creator(&ctx.accounts.token_manager, &ctx.accounts.signer.key())?;
+
+        emit!(BurnSessionRevoked {
+            mint: ctx.accounts.token_share.mint,
+            operator,
+        });
+        Ok(())
+    }
+
+    /// Structure for the burn_tokens instruction
+    #[derive(Accounts)]
+    #[instruction(token_index: u64)]
+    pub struct BurnToken<'info> {
+        /// The creator of the token manager, or the holder redeeming its own tokens
+        pub signer: Signer<'info>,
+
+        /// Account storing manager-wide settings
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token, telling whether holders may redeem
+        #[account(
+            mut,
+            constraint = token_share.mint == token_mint.key() @ TokenManagerError::TokenNotFound,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The token mint - with seeds derived from token-manager + index
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_index.to_le_bytes()],
+            bump,
+        )]
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// The account the tokens are burned from
+        #[account(mut, token::mint = token_mint)]
+        pub source: InterfaceAccount<'info, TokenAccount>,
+
+        /// Burn session of the signer, when it is neither the creator nor the holder
+        /// CHECK: May not exist; deserialized in the BurnToken implementation
+        #[account(
+            mut,
+            seeds = [b"burn-session", token_mint.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub burn_session: UncheckedAccount<'info>,
+
+        /// Operation lock of the token; a split in progress keeps the supply
+        /// CHECK: May not exist; deserialized in the BurnToken implementation
+        #[account(
+            seeds = [b"operation-lock", token_mint.key().as_ref()],
+            bump,
+        )]
+        pub operation_lock: UncheckedAccount<'info>,
+
+        /// The Token 2022 program
+        pub token_program: Program<'info, Token2022>,
+    }
+
+    /// Burns tokens of a holder, to redeem shares at maturity or after a buyback.
+    /// The creator burns from accounts it owns or was delegated; holders may burn
+    /// from their own accounts when the token allows self-redemption. Other
+    /// operators burn within their burn session, which is debited by `amount`.
+    /// Nothing may be burnt while a split holds the operation lock.
+    pub fn burn_tokens(ctx: Context<BurnToken>, _token_index: u64, amount: u64) -> Result<()> {
+        check_operation_not_held(&ctx.accounts.operation_lock, TokenOperation::Split)?;
+        let signer = ctx.accounts.signer.key();
+        let self_redemption =
+            ctx.accounts.token_share.allow_self_redemption && signer == ctx.accounts.source.owner;
+        if !self_redemption {
+            ctx.accounts.token_manager.require_unlocked()?;
+        }
+        if signer != ctx.accounts.token_manager.creator && !self_redemption {
+            let unix_timestamp = now(ctx.remaining_accounts)?.unix_timestamp;
+            debit_burn_session(&ctx.accounts.burn_session, amount, unix_timestamp)?;
+        }
+
+        burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    from: ctx.accounts.source.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+        let balance = ctx.accounts.source.amount;
+        ctx.accounts
+            .token_share
+            .record_holding(balance, balance - amount)?;
+
+        emit!(TokensBurned {
+            mint: ctx.accounts.token_mint.key(),
+            holder: ctx.accounts.source.owner,
+            amount,
+            burned_by: signer,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    #[instruction(isin: Isin)]
+    pub struct ForceTransfer<'info> {
+        /// The creator of the token manager or one of its compliance officers
+        pub signer: Signer<'info>,
+
+        /// The token manager of the token
+        #[account(
+            seeds = [b"token-manager", token_manager.original_creator.as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Configuration of the token
+        #[account(
+            mut,
+            seeds = [b"token-share", token_manager.key().as_ref(), isin.as_bytes()],
+            bump,
+        )]
+        pub token_share: Account<'info, TokenShare>,
+
+        /// The mint of the token, which is the permanent delegate of its accounts
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &token_share.index.to_le_bytes()],
+            bump,
+        )]
+        pub token_mint: InterfaceAccount<'info, Mint>,
+
+        /// The account the tokens are clawed back from
+        #[account(mut, token::mint = token_mint)]
+        pub source: InterfaceAccount<'info, TokenAccount>,
+
+        /// The account receiving the tokens
+        #[account(mut, token::mint = token_mint)]
+        pub destination: InterfaceAccount<'info, TokenAccount>,
+
+        /// Compliance officers of the token manager; only needed when an officer signs
+        #[account(
+            seeds = [b"compliance-queue", token_manager.key().as_ref()],
+            bump,
+        )]
+        pub compliance_queue: Option<Account<'info, ComplianceQueue>>,
+
+        /// Roles of the signer; only needed when it is not the creator
+        /// CHECK: May not exist; deserialized in the ForceTransfer implementation
+        #[account(
+            seeds = [b"role", token_manager.key().as_ref(), signer.key().as_ref()],
+            bump,
+        )]
+        pub role_assignment: UncheckedAccount<'info>,
+
+        /// Circuit breaker of the token, counting forced transfers
+        /// CHECK: May not exist; deserialized in the ForceTransfer implementation
+        #[account(
+            mut,
+            seeds = [b"circuit-breaker", token_mint.key().as_ref()],
+            bump,
+        )]
+        pub circuit_breaker: UncheckedAccount<'info>,
+
+        /// The Token 2022 program
+        pub token_program: Program<'info, Token2022>,
+    }
+
+    /// Claws tokens of a token identified by its ISIN back from any holder, as
+    /// security-token regulations may require, using the permanent delegate of
+    /// the mint. Since this program is the transfer hook of its own mints, the
+    /// tokens are burned from `source` and minted to `destination` rather than
+    /// transferred, so the transfer rules do not apply. Only tokens created with
+    /// the permanent delegate extension can be clawed back.
+    pub fn force_transfer(ctx: Context<ForceTransfer>, _isin: Isin, amount: u64) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let creator = ctx.accounts.token_manager.creator;
+        ctx.accounts.token_manager.require_unlocked()?;
+        let is_officer = ctx
+            .accounts
+            .compliance_queue
+            .as_ref()
+            .is_some_and(|queue| queue.is_officer(&signer, &creator));
+        if !is_officer
+            && !has_role(
+                &ctx.accounts.token_manager,
+                &signer,
+                &ctx.accounts.role_assignment,
+                Role::ComplianceOfficer,
+            )?
+        {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+
+        let token_index = ctx.accounts.token_share.index;
+        let token_index_bytes = token_index.to_le_bytes();
+        let token_mint_seeds = &[
+            b"token-mint".as_ref(),
+            ctx.accounts.token_manager.to_account_info().key.as_ref(),
+            &token_index_bytes,
+            &[ctx.bumps.token_mint],
+        ];
+        burn(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    from: ctx.accounts.source.to_account_info(),
+                    authority: ctx.accounts.token_mint.to_account_info(),
+                },
+                &[&token_mint_seeds[..]],
+            ),
+            amount,
+        )?;
+        mint_share_tokens(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.token_mint.to_account_info(),
+            ctx.accounts.destination.to_account_info(),
+            &ctx.accounts.token_manager.key(),
+            token_index,
+            ctx.bumps.token_mint,
+            amount,
+        )?;
+        if ctx.accounts.source.key() != ctx.accounts.destination.key() {
+            let (source, destination) =
+                (ctx.accounts.source.amount, ctx.accounts.destination.amount);
+            let token_share = &mut ctx.accounts.token_share;
+            token_share.record_holding(source, source - amount)?;
+            token_share.record_holding(destination, destination + amount)?;
+        }
+
+        let slot = now(ctx.remaining_accounts)?.slot;
+        count_anomaly(
+            &ctx.accounts.circuit_breaker,
+            AnomalyKind::ForceTransfer,
+            1,
+            slot,
+        )?;
+        emit!(TokensForceTransferred {
+            mint: ctx.accounts.token_mint.key(),
+            source: ctx.accounts.source.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+            authority: signer,
+        });
+        Ok(())
+    }
+
+    #[derive(Accounts)]
+    pub struct DemoBootstrap<'info> {
+        /// The wallet signing and paying for the whole demo setup
+        #[account(mut)]
+        pub signer: Signer<'info>,
+
+        /// A fresh token manager owned by the signer
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + TokenManager::INIT_SPACE,
+            seeds = [b"token-manager", signer.key().as_ref()],
+            bump,
+        )]
+        pub token_manager: Account<'info, TokenManager>,
+
+        /// Mint of the first demo share (token index 0)
+        /// CHECK: This is initialized within the instruction
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &0u64.to_le_bytes()],
+            bump,
+        )]
+        pub first_mint: AccountInfo<'info>,
+
+        /// Transfer hook meta list of the first demo share
+        /// CHECK: This is initialized within the instruction
+        #[account(
+            mut,
+            seeds = [b"extra-account-metas", first_mint.key().as_ref()],
+            bump,
+        )]
+        pub first_extra_account_meta_list: AccountInfo<'info>,
+
+        /// Configuration of the first demo share
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + TokenShare::INIT_SPACE,
+            seeds = [b"token-share", token_manager.key().as_ref(), DEMO_SHARES[0].0.as_bytes()],
+            bump,
+        )]
+        pub first_token_share: Account<'info, TokenShare>,
+
+        /// Whitelist of the first demo share
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + TokenWhitelist::INIT_SPACE,
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), DEMO_SHARES[0].0.as_bytes()],
+            bump,
+        )]
+        pub first_token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// Mint of the second demo share (token index 1)
+        /// CHECK: This is initialized within the instruction
+        #[account(
+            mut,
+            seeds = [b"token-mint", token_manager.key().as_ref(), &1u64.to_le_bytes()],
+            bump,
+        )]
+        pub second_mint: AccountInfo<'info>,
+
+        /// Transfer hook meta list of the second demo share
+        /// CHECK: This is initialized within the instruction
+        #[account(
+            mut,
+            seeds = [b"extra-account-metas", second_mint.key().as_ref()],
+            bump,
+        )]
+        pub second_extra_account_meta_list: AccountInfo<'info>,
+
+        /// Configuration of the second demo share
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + TokenShare::INIT_SPACE,
+            seeds = [b"token-share", token_manager.key().as_ref(), DEMO_SHARES[1].0.as_bytes()],
+            bump,
+        )]
+        pub second_token_share: Account<'info, TokenShare>,
+
+        /// Whitelist of the second demo share
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + TokenWhitelist::INIT_SPACE,
+            seeds = [b"token-whitelist", token_manager.key().as_ref(), DEMO_SHARES[1].0.as_bytes()],
+            bump,
+        )]
+        pub second_token_whitelist: Account<'info, TokenWhitelist>,
+
+        /// Demo investor whitelisted for both shares and funded with both
+        /// CHECK: Any wallet can act as the demo investor
+        pub investor: UncheckedAccount<'info>,
+
+        /// Second demo investor, whitelisted but unfunded, to receive transfers
+        /// CHECK: Any wallet can act as the demo investor
+        pub second_investor: UncheckedAccount<'info>,
+
+        /// Whitelist entry of the investor for the first share
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + WhitelistEntry::INIT_SPACE,
+            seeds = [b"whitelist", first_mint.key().as_ref(), investor.key().as_ref()],
+            bump,
+        )]
+        pub investor_first_entry: Account<'info, WhitelistEntry>,
+
+        /// Whitelist entry of the second investor for the first share
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + WhitelistEntry::INIT_SPACE,
+            seeds = [b"whitelist", first_mint.key().as_ref(), second_investor.key().as_ref()],
+            bump,
+        )]
+        pub second_investor_first_entry: Account<'info, WhitelistEntry>,
+
+        /// Whitelist entry of the investor for the second share
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + WhitelistEntry::INIT_SPACE,
+            seeds = [b"whitelist", second_mint.key().as_ref(), investor.key().as_ref()],
+            bump,
+        )]
+        pub investor_second_entry: Account<'info, WhitelistEntry>,
+
+        /// Whitelist entry of the second investor for the second share
+        #[account(
+            init,
+            payer = signer,
+            space = 8 + WhitelistEntry::INIT_SPACE,
+            seeds = [b"whitelist", second_mint.key().as_ref(), second_investor.key().as_ref()],
+            bump,
+        )]
+        pub second_investor_second_entry: Account<'info, WhitelistEntry>,
+
+        /// Associated token account of `investor` for the first share
+        /// CHECK: Created and validated by the associated token program
+        #[account(mut)]
+        pub investor_first_token_account: UncheckedAccount<'info>,
+
+        /// Associated token account of `investor` for the second share
+        /// CHECK: Created and validated by the associated token program
+        #[account(mut)]
+        pub investor_second_token_account: UncheckedAccount<'info>,
+
+        /// Token program interface for SPL Token 2022
+        pub token_program: Program<'info, Token2022>,
+
+        /// Used to create the investor token accounts
+        pub associated_token_program: Program<'info, AssociatedToken>,
+
+        /// Required for creating new accounts
+        pub system_program: Program<'info, System>,
+    }
+
+    /// Spins up a complete demo issuance in a single transaction: a token manager,
+    /// two shares, whitelist entries for two investors and an initial balance for
+    /// the first one. Only available in builds with the `devnet-tools` feature.
+    /// The transaction needs a raised compute budget (around 600k units).
+    pub fn demo_bootstrap(ctx: Context<DemoBootstrap>) -> Result<()> {
+        if !cfg!(feature = "devnet-tools") {
+            return Err(error!(TokenManagerError::DevnetToolsDisabled));
+        }
+
+        let accounts = ctx.accounts;
+        accounts.token_manager.suspended_modules = Vec::new();
+        accounts.token_manager.clock_tolerance_seconds = 0;
+        accounts.token_manager.penalty_withholding_bps = 0;
+        accounts.token_manager.current_token_index = 0;
+        accounts.token_manager.creator = accounts.signer.key();
+        accounts.token_manager.original_creator = accounts.signer.key();
+        accounts.token_manager.pending_creator = None;
+        accounts.token_manager.lockdown_guardian = None;
+        accounts.token_manager.locked_down_at = None;
+        accounts.token_manager.platform = None;
+
+        let shares = [
+            (
+                &accounts.first_mint,
+                ctx.bumps.first_mint,
+                &accounts.first_extra_account_meta_list,
+                ctx.bumps.first_extra_account_meta_list,
+                &mut accounts.first_token_share,
+                &mut accounts.first_token_whitelist,
+                [
+                    &mut accounts.investor_first_entry,
+                    &mut accounts.second_investor_first_entry,
+                ],
+                &accounts.investor_first_token_account,
+                DEMO_SHARES[0],
+            ),
+            (
+                &accounts.second_mint,
+                ctx.bumps.second_mint,
+                &accounts.second_extra_account_meta_list,
+                ctx.bumps.second_extra_account_meta_list,
+                &mut accounts.second_token_share,
+                &mut accounts.second_token_whitelist,
+                [
+                    &mut accounts.investor_second_entry,
+                    &mut accounts.second_investor_second_entry,
+                ],
+                &accounts.investor_second_token_account,
+                DEMO_SHARES[1],
+            ),
+        ];
+
+        for (
+            token_index,
+            (
+                mint,
+                mint_bump,
+                meta_list,
+                meta_list_bump,
+                token_share,
+                token_whitelist,
+                entries,
+                token_account,
+                share,
+            ),
+        ) in shares.into_iter().enumerate()
+        {
+            let (isin, decimals, amount) = share;
+
+            create_share_mint(
+                ShareMintAccounts {
+                    signer: accounts.signer.to_account_info(),
+                    token_mint: mint.to_account_info(),
+                    token_mint_bump: mint_bump,
+                    extra_account_meta_list: meta_list.to_account_info(),
+                    extra_account_meta_list_bump: meta_list_bump,
+                    token_program: accounts.token_program.to_account_info(),
+                    system_program: accounts.system_program.to_account_info(),
+                },
+                &mut accounts.token_manager,
+                token_share,
+                token_whitelist,
+                decimals,
+                isin,
+                &[],
+            )?;
+
+            let wallets = [accounts.investor.key(), accounts.second_investor.key()];
+            for (entry, wallet) in entries.into_iter().zip(wallets) {
+                token_whitelist.count += 1;
+                entry.set_inner(WhitelistEntry {
+                    mint: mint.key(),
+                    wallet,
+                    corridor: None,
+                    jurisdiction: [0; 2],
+                    investor_class: 0,
+                    expires_at: 0,
+                    _reserved: [0; 24],
+                });
+                emit!(WhitelistEntryAdded {
+                    mint: mint.key(),
+                    wallet,
+                });
+            }
+
+            associated_token::create(CpiContext::new(
+                accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: accounts.signer.to_account_info(),
+                    associated_token: token_account.to_account_info(),
+                    authority: accounts.investor.to_account_info(),
+                    mint: mint.to_account_info(),
+                    system_program: accounts.system_program.to_account_info(),
+                    token_program: accounts.token_program.to_account_info(),
+                },
+            ))?;
+
+            mint_share_tokens(
+                accounts.token_program.to_account_info(),
+                mint.to_account_info(),
+                token_account.to_account_info(),
+                &accounts.token_manager.key(),
+                token_index as u64,
+                mint_bump,
+                amount,
+            )?;
+            token_share.record_holding(0, amount)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Accounts needed to deploy the mint of a new share.
+struct ShareMintAccounts<'info> {
+    signer: AccountInfo<'info>,
+    token_mint: AccountInfo<'info>,
+    token_mint_bump: u8,
+    extra_account_meta_list: AccountInfo<'info>,
+    extra_account_meta_list_bump: u8,
+    token_program: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+}
+
+/// Deploys the Token-2022 mint of a new share with its transfer hook, metadata
+/// and extra account meta list, then stores its configuration and empty whitelist
+/// in the given accounts. The mint must be the `token-mint` PDA for the manager's
+/// current token index.
+fn create_share_mint<'info>(
+    accounts: ShareMintAccounts<'info>,
+    token_manager: &mut Account<'info, TokenManager>,
+    token_share: &mut Account<'info, TokenShare>,
+    token_whitelist: &mut Account<'info, TokenWhitelist>,
+    decimals: u8,
+    isin: Isin,
+    additional_metadata: &[MetadataPair],
+) -> Result<()> {
+    // The share accounts are only created if needed so that an existing ISIN is
+    // reported as such rather than as an account already in use
+    if token_share.mint != Pubkey::default() {
+        return Err(error!(TokenManagerError::DuplicateIsin));
+    }
+
+    // 1. Calculate required space for mint with all extensions and metadata
+    let (name, symbol, uri) = share_metadata(&isin);
+
+    // Calculate space with embedded metadata
+    let token_space = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(
+        &SHARE_MINT_EXTENSIONS,
+    )
+    .expect("Failed to calculate space");
+    let metadata_space = calculate_metadata_space(&name, &symbol, &uri, additional_metadata);
+    let total_space = token_space + metadata_space;
+
+    // 2. Calculate rent exemption
+    let rent = Rent::get()?;
+    let lamports = rent.minimum_balance(total_space);
+
+    // 3. Get PDA seeds
+    let token_mint_bump = accounts.token_mint_bump;
+    let token_manager_key = token_manager.key();
+    let token_index_bytes = token_manager.current_token_index.to_le_bytes();
+    let token_mint_seeds = &[
+        b"token-mint".as_ref(),
+        token_manager_key.as_ref(),
+        &token_index_bytes,
+        &[token_mint_bump],
+    ];
+    let token_mint_signer = &[&token_mint_seeds[..]];
+
+    // 4. Create the mint account
+    let token_mint_key = &accounts.token_mint.key();
+
+    invoke_signed(
+        &system_instruction::create_account(
+            &accounts.signer.key(),
+            token_mint_key,
+            lamports,
+            token_space as u64,
+            &accounts.token_program.key(),
+        ),
+        &[
+            accounts.signer.clone(),
+            accounts.token_mint.clone(),
+            accounts.system_program.clone(),
+        ],
+        token_mint_signer,
+    )?;
+
+    // 5. Initialize extensions first
+
+    // Initialize TransferHook extension
+    let transfer_hook_ix = spl_token_2022::extension::transfer_hook::instruction::initialize(
+        &accounts.token_program.key(),
+        token_mint_key,
+        Some(token_manager.key()),
+        Some(crate::ID),
+    )?;
+
+    invoke(
+        &transfer_hook_ix,
+        &[
+            accounts.token_mint.clone(),
+            accounts.extra_account_meta_list.clone(),
+            accounts.token_program.clone(),
+        ],
+    )?;
+
+    // Initialize MetadataPointer extension
+    let metadata_pointer_ix = spl_token_2022::extension::metadata_pointer::instruction::initialize(
+        &accounts.token_program.key(),
+        token_mint_key,
+        Some(*token_mint_key),
+        Some(*token_mint_key),
+    )?;
+
+    invoke(
+        &metadata_pointer_ix,
+        std::slice::from_ref(&accounts.token_mint),
+    )?;
+
+    // Initialize PermanentDelegate extension, letting the mint claw back tokens
+    let permanent_delegate_ix = spl_token_2022::instruction::initialize_permanent_delegate(
+        &accounts.token_program.key(),
+        token_mint_key,
+        token_mint_key,
+    )?;
+
+    invoke(
+        &permanent_delegate_ix,
+        std::slice::from_ref(&accounts.token_mint),
+    )?;
+
+    // 6. Now initialize the basic mint
+    let init_mint_ix = spl_token_2022::instruction::initialize_mint2(
+        &accounts.token_program.key(),
+        token_mint_key,
+        token_mint_key,
+        Some(token_mint_key),
+        decimals,
+    )?;
+
+    invoke(&init_mint_ix, std::slice::from_ref(&accounts.token_mint))?;
+
+    // Initialize TokenMetadata extension
+    let token_metadata_ix = spl_token_metadata_interface::instruction::initialize(
+        &accounts.token_program.key(),
+        token_mint_key,
+        token_mint_key,
+        token_mint_key,
+        token_mint_key,
+        name.clone(),
+        symbol.clone(),
+        uri.clone(),
+    );
+
+    invoke_signed(
+        &token_metadata_ix,
+        std::slice::from_ref(&accounts.token_mint),
+        token_mint_signer,
+    )?;
+
+    // Write the custom fields, topping up the rent of the grown metadata if the
+    // estimate fell short
+    for pair in additional_metadata {
+        update_metadata_field(
+            &accounts.token_program,
+            &accounts.token_mint,
+            token_mint_signer,
+            MetadataField::Key(pair.key.clone()),
+            pair.value.clone(),
+        )?;
+    }
+    if !additional_metadata.is_empty() {
+        top_up_rent(
+            &accounts.token_mint,
+            &accounts.signer,
+            &accounts.system_program,
+        )?;
+    }
+
+    // 7. Create and initialize the extra account meta list for transfer hooks
+    let account_metas =
+        transfer_hook_extra_account_metas(&token_manager.key(), &token_share.key())?;
+
+    // Calculate account size for meta list
+    let account_size = ExtraAccountMetaList::size_of(account_metas.len())?;
+    let meta_list_lamports = rent.minimum_balance(account_size);
+
+    // Create the account for the meta list
+    let meta_list_seeds = &[
+        b"extra-account-metas",
+        token_mint_key.as_ref(),
+        &[accounts.extra_account_meta_list_bump],
+    ];
+    let meta_list_signer = &[&meta_list_seeds[..]];
+    invoke_signed(
+        &system_instruction::create_account(
+            &accounts.signer.key(),
+            &accounts.extra_account_meta_list.key(),
+            meta_list_lamports,
+            account_size as u64,
+            &crate::ID,
+        ),
+        &[
+            accounts.signer.clone(),
+            accounts.extra_account_meta_list.clone(),
+            accounts.system_program.clone(),
+        ],
+        meta_list_signer,
+    )?;
+
+    // Initialize the meta list data
+    let mut data = accounts.extra_account_meta_list.try_borrow_mut_data()?;
+    ExtraAccountMetaList::init::<ExecuteInstruction>(&mut data, &account_metas)?;
+
+    // 9. Store the token configuration and its whitelist
+    let current_index = token_manager.current_token_index;
+    token_share.set_inner(TokenShare {
+        token_manager: token_manager_key,
+        mint: *token_mint_key,
+        isin,
+        index: current_index,
+        zero_amount_policy: EdgeCasePolicy::Evaluate,
+        account_emptying_policy: EdgeCasePolicy::Evaluate,
+        allow_self_transfers: false,
+        require_agreement: token_manager.compliance_defaults.require_agreement,
+        require_reacceptance: token_manager.compliance_defaults.require_reacceptance,
+        require_incoming_consent: token_manager.compliance_defaults.require_incoming_consent,
+        require_sender_whitelisted: token_manager.compliance_defaults.require_sender_whitelisted,
+        allow_self_redemption: false,
+        max_supply: None,
+        agreement_version: 0,
+        agreement_hash: [0; 32],
+        fail_penalty_bps_per_day: 0,
+        buy_in_after_days: 0,
+        blackouts: Vec::new(),
+        holder_count: 0,
+        max_holders: None,
+        whitelist_approval_window: None,
+        max_balance_per_holder: None,
+        snapshot_count: 0,
+        require_transfer_approval: false,
+        _reserved: [0; 32],
+    });
+    token_whitelist.set_inner(TokenWhitelist {
+        token_manager: token_manager_key,
+        mint: *token_mint_key,
+        count: 0,
+    });
+    token_manager.current_token_index = current_index
+        .checked_add(1)
+        .ok_or(error!(TokenManagerError::IndexOverflow))?;
+
+    emit!(ShareCreated {
+        token_manager: token_manager_key,
+        mint: *token_mint_key,
+        isin,
+        index: current_index,
+        decimals,
+    });
+    Ok(())
+}
+
+/// Mints `amount` tokens of the share at `token_index`, signed by its mint PDA.
+fn mint_share_tokens<'info>(
+    token_program: AccountInfo<'info>,
+    token_mint: AccountInfo<'info>,
+    destination: AccountInfo<'info>,
+    token_manager: &Pubkey,
+    token_index: u64,
+    token_mint_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let token_index_bytes = token_index.to_le_bytes();
+    let token_mint_seeds = &[
+        b"token-mint".as_ref(),
+        token_manager.as_ref(),
+        &token_index_bytes,
+        &[token_mint_bump],
+    ];
+    let token_mint_signer = &[&token_mint_seeds[..]];
+
+    let cpi_accounts = MintTo {
+        mint: token_mint.clone(),
+        to: destination,
+        authority: token_mint,
+    };
+
+    mint_to(
+        CpiContext::new_with_signer(token_program, cpi_accounts, token_mint_signer),
+        amount,
+    )
+}
+
+/// Burns `amount` share tokens from `source` as the permanent delegate of the mint.
+fn burn_share_tokens<'info>(
+    token_program: AccountInfo<'info>,
+    token_mint: AccountInfo<'info>,
+    source: AccountInfo<'info>,
+    token_manager: &Pubkey,
+    token_index: u64,
+    token_mint_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let token_index_bytes = token_index.to_le_bytes();
+    let token_mint_seeds = &[
+        b"token-mint".as_ref(),
+        token_manager.as_ref(),
+        &token_index_bytes,
+        &[token_mint_bump],
+    ];
+    let token_mint_signer = &[&token_mint_seeds[..]];
+
+    let cpi_accounts = Burn {
+        mint: token_mint.clone(),
+        from: source,
+        authority: token_mint,
+    };
+
+    burn(
+        CpiContext::new_with_signer(token_program, cpi_accounts, token_mint_signer),
+        amount,
+    )
+}
+
+/// Calls an allowlisted yield program from `recall_from_yield` and
+/// `emergency_recall`, crediting the cash returned to the vault against the
+/// deposits of the program.
+fn recall<'info>(
+    ctx: Context<'_, '_, 'info, 'info, YieldSweep<'info>>,
+    data: Vec<u8>,
+) -> Result<()> {
+    let program_id = ctx.accounts.yield_program.key();
+    if ctx.accounts.treasury_yield.program(&program_id).is_none() {
+        return Err(error!(TokenManagerError::YieldProgramNotAllowed));
+    }
+
+    let before = ctx.accounts.offer_vault.amount;
+    invoke_yield_program(
+        &ctx.accounts.offer,
+        &mut ctx.accounts.offer_vault,
+        &ctx.accounts.yield_program,
+        ctx.remaining_accounts,
+        data,
+    )?;
+    let recalled = ctx.accounts.offer_vault.amount.saturating_sub(before);
+
+    let program = ctx
+        .accounts
+        .treasury_yield
+        .program_mut(&program_id)
+        .ok_or(error!(TokenManagerError::YieldProgramNotAllowed))?;
+    program.deposited = program.deposited.saturating_sub(recalled);
+    emit!(TreasuryRecalled {
+        offer: ctx.accounts.offer.key(),
+        yield_program: program_id,
+        amount: recalled,
+    });
+    Ok(())
+}
+
+/// Programs that may never be called as yield programs: signed by the offer,
+/// they could delegate, reassign or close its vault.
+const RESERVED_YIELD_PROGRAM_IDS: [Pubkey; 5] = [
+    anchor_spl::token::ID,
+    spl_token_2022::ID,
+    anchor_lang::system_program::ID,
+    associated_token::ID,
+    crate::ID,
+];
+
+/// Calls `yield_program` with `accounts`, the offer signing for its vault, then
+/// reloads the vault. The call may move cash but not change who controls the
+/// vault.
+fn invoke_yield_program<'info>(
+    offer: &Account<'info, Offer>,
+    offer_vault: &mut InterfaceAccount<'info, TokenAccount>,
+    yield_program: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    data: Vec<u8>,
+) -> Result<()> {
+    if RESERVED_YIELD_PROGRAM_IDS.contains(yield_program.key) {
+        return Err(error!(TokenManagerError::YieldProgramNotAllowed));
+    }
+    let authorities = (
+        offer_vault.owner,
+        offer_vault.delegate,
+        offer_vault.close_authority,
+    );
+
+    let offer_key = offer.key();
+    let instruction = Instruction {
+        program_id: yield_program.key(),
+        accounts: accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer || account.key() == offer_key,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data,
+    };
+    let offer_seeds = &[
+        b"offer".as_ref(),
+        offer.mint.as_ref(),
+        &offer.id.to_le_bytes(),
+        &[offer.bump],
+    ];
+
+    let mut account_infos = accounts.to_vec();
+    account_infos.push(offer.to_account_info());
+    account_infos.push(yield_program.clone());
+    invoke_signed(&instruction, &account_infos, &[&offer_seeds[..]])?;
+
+    offer_vault.reload()?;
+    if (
+        offer_vault.owner,
+        offer_vault.delegate,
+        offer_vault.close_authority,
+    ) != authorities
+    {
+        return Err(error!(TokenManagerError::YieldVaultAuthorityChanged));
+    }
+    Ok(())
+}
+
+/// Fails when the collateral attestation stored in `collateral`, if any, is stale
+/// and its policy pauses issuance.
+fn check_collateral_fresh(
+    collateral: &AccountInfo,
+    now: &Now,
+    tolerance_seconds: u32,
+) -> Result<()> {
+    let Some(collateral) = load_optional_account::<CollateralAttestation>(collateral)? else {
+        return Ok(());
+    };
+    if collateral.pause_issuance_when_stale && collateral.is_stale(now, tolerance_seconds) {
+        return Err(error!(TokenManagerError::StaleCollateral));
+    }
+    Ok(())
+}
+
+/// Records in `offer` the rate of its payment mint in the reporting currency,
+/// as read by `fx_snapshot`.
+fn take_fx_snapshot(
+    offer: &mut Offer,
+    payment_mints: &PaymentMintRegistry,
+    fx_rate: Option<&Account<FxRateFeed>>,
+    now: &Now,
+    tolerance_seconds: u32,
+) -> Result<()> {
+    let Some(snapshot) = fx_snapshot(
+        &offer.payment_mint,
+        payment_mints,
+        fx_rate,
+        now,
+        tolerance_seconds,
+    )?
+    else {
+        return Ok(());
+    };
+    emit!(FxSnapshotRecorded {
+        mint: offer.mint,
+        offer_id: offer.id,
+        payment_mint: offer.payment_mint,
+        currency: snapshot.currency,
+        rate: snapshot.rate,
+        decimals: snapshot.decimals,
+        published_at: snapshot.published_at,
+    });
+    offer.fx_snapshot = Some(snapshot);
+    Ok(())
+}
+
+/// The rate of `payment_mint` in the reporting currency, read from the oracle the
+/// issuer set for the mint, or `None` for mints without an oracle. The rate may
+/// not be older than the maximum age set with the reporting currency.
+fn fx_snapshot(
+    payment_mint: &Pubkey,
+    payment_mints: &PaymentMintRegistry,
+    fx_rate: Option<&Account<FxRateFeed>>,
+    now: &Now,
+    tolerance_seconds: u32,
+) -> Result<Option<FxSnapshot>> {
+    let Some(oracle) = payment_mints
+        .mints
+        .iter()
+        .find(|approved| approved.mint == *payment_mint)
+        .and_then(|approved| approved.oracle)
+    else {
+        return Ok(None);
+    };
+    let fx_rate = fx_rate.ok_or(error!(TokenManagerError::FxRateRequired))?;
+    if fx_rate.key() != oracle
+        || fx_rate.payment_mint != *payment_mint
+        || fx_rate.currency != payment_mints.reporting_currency
+    {
+        return Err(error!(TokenManagerError::FxRateMismatch));
+    }
+    if payment_mints.fx_rate_max_age_seconds > 0
+        && now.may_have_passed(
+            fx_rate
+                .published_at
+                .saturating_add(i64::from(payment_mints.fx_rate_max_age_seconds)),
+            tolerance_seconds,
+        )
+    {
+        return Err(error!(TokenManagerError::StaleFxRate));
+    }
+
+    Ok(Some(FxSnapshot {
+        currency: fx_rate.currency,
+        rate: fx_rate.rate,
+        decimals: fx_rate.decimals,
+        published_at: fx_rate.published_at,
+    }))
+}
+
+/// Calendar date `(year, month, day)` of a unix timestamp, in UTC.
+fn civil_date(unix_timestamp: i64) -> (i64, i64, i64) {
+    // Days since 0000-03-01, so that leap days fall at the end of the year
+    let days = unix_timestamp.div_euclid(SECONDS_PER_DAY) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Days since 1970-01-01 of a calendar date, in UTC.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Adds `months` calendar months to a unix timestamp, moving to the last day of
+/// the month when the day does not exist in it.
+fn add_months(unix_timestamp: i64, months: i64) -> i64 {
+    let (year, month, day) = civil_date(unix_timestamp);
+    let total_months = year * 12 + (month - 1) + months;
+    let (year, month) = (total_months.div_euclid(12), total_months.rem_euclid(12) + 1);
+    let next_month_start = if month == 12 {
+        days_from_civil(year + 1, 1, 1)
+    } else {
+        days_from_civil(year, month + 1, 1)
+    };
+    let days_in_month = next_month_start - days_from_civil(year, month, 1);
+    days_from_civil(year, month, day.min(days_in_month)) * SECONDS_PER_DAY
+        + unix_timestamp.rem_euclid(SECONDS_PER_DAY)
+}
+
+/// ISIN, decimals and initial investor balance of the `demo_bootstrap` shares.
+/// The ISINs use the `XS` prefix and carry valid check digits.
+const DEMO_SHARES: [(Isin, u8, u64); 2] = [
+    (Isin(*b"XS0000000017"), 6, 1_000_000_000),
+    (Isin(*b"XS0000000025"), 0, 500),
+];
+
+/// Builds the list of extra accounts Token-2022 passes to `transfer_hook`.
+fn transfer_hook_extra_account_metas(
+    token_manager: &Pubkey,
+    token_share: &Pubkey,
+) -> Result<Vec<ExtraAccountMeta>> {
+    #[allow(unused_mut)]
+    let mut account_metas = vec![
+        ExtraAccountMeta::new_with_pubkey(
+            token_manager,
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Writable to keep its holder count
+        ExtraAccountMeta::new_with_pubkey(
+            token_share,
+            false, // is_signer
+            true,  // is_writable
+        )?,
+        // Whitelist entry of the destination owner
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"whitelist".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 2, // destination token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Market maker registration of the destination owner
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"market-maker".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 2, // destination token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Agreement acceptance of the destination owner
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"agreement-acceptance".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 2, // destination token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Agreement acceptance of the source owner
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"agreement-acceptance".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 0, // source token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Trusted vault registration of the source owner
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"trusted-vault".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 0, // source token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Trusted vault registration of the destination owner
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"trusted-vault".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 2, // destination token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Compliance receipts of the mint, written by the hook
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"transfer-receipts".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+            ],
+            false, // is_signer
+            true,  // is_writable
+        )?,
+        // Due bill ledger of the mint
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"due-bills".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Statement of the source owner, written by the hook
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"holder-statement".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 0, // source token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            true,  // is_writable
+        )?,
+        // Statement of the destination owner, written by the hook
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"holder-statement".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 2, // destination token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            true,  // is_writable
+        )?,
+        // Sanctions list of the token manager
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"sanctions-list".to_vec(),
+                },
+                Seed::AccountKey { index: 5 }, // token manager
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Incoming consent of the destination owner
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"incoming-consent".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 2, // destination token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Debt terms of the mint
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"debt-instrument".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Circuit breaker of the mint
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"circuit-breaker".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Whitelist entry of the source owner
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"whitelist".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 0, // source token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Lockup of the source owner
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"lockup".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 0, // source token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Whitelist bucket of the source owner
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"whitelist-bucket".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 0, // source token account
+                    data_index: 32,   // first byte of the owner
+                    length: 1,
+                },
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Whitelist bucket of the destination owner
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"whitelist-bucket".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 2, // destination token account
+                    data_index: 32,   // first byte of the owner
+                    length: 1,
+                },
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Instructions of the transaction, to read the memo of the transfer
+        ExtraAccountMeta::new_with_pubkey(
+            &sysvar::instructions::ID,
+            false, // is_signer
+            false, // is_writable
+        )?,
+        // Transfer request of the source owner to the destination owner, writable
+        // to use up its approval
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"transfer-request".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 0, // source token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+                Seed::AccountData {
+                    account_index: 2, // destination token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            true,  // is_writable
+        )?,
+        // Due bills of the source and destination owners, written by the hook
+        // while a corporate action tracks them
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"due-bill".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 0, // source token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            true,  // is_writable
+        )?,
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"due-bill".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+                Seed::AccountData {
+                    account_index: 2, // destination token account
+                    data_index: 32,   // owner
+                    length: 32,
+                },
+            ],
+            false, // is_signer
+            true,  // is_writable
+        )?,
+    ];
+
+    // Test builds read time from the mock clock, so transfers must carry it too
+    #[cfg(feature = "test-clock")]
+    account_metas.push(ExtraAccountMeta::new_with_pubkey(
+        &mock_clock_address(),
+        false, // is_signer
+        false, // is_writable
+    )?);
+
+    Ok(account_metas)
+}
+
+/// Programs whose instructions are memos: SPL Memo and its first version.
+const MEMO_PROGRAM_IDS: [Pubkey; 2] = [
+    pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr"),
+    pubkey!("Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo"),
+];
+
+/// SHA-256 of the memo of the transfer being executed: the memo instruction
+/// right before it, where Token-2022 expects it for accounts requiring memos and
+/// where Solana Pay wallets put the payment reference. `None` without a memo, or
+/// when the instructions sysvar is not among `accounts` because the meta list of
+/// the mint predates it.
+fn transfer_memo_hash(accounts: &[AccountInfo]) -> Result<Option<[u8; 32]>> {
+    let Some(instructions) = accounts
+        .iter()
+        .find(|account| account.key() == sysvar::instructions::ID)
+    else {
+        return Ok(None);
+    };
+    let Some(previous) = load_current_index_checked(instructions)?.checked_sub(1) else {
+        return Ok(None);
+    };
+    let instruction = load_instruction_at_checked(previous.into(), instructions)?;
+    Ok(MEMO_PROGRAM_IDS
+        .contains(&instruction.program_id)
+        .then(|| hashv(&[&instruction.data]).to_bytes()))
+}
+
+/// Extra account of the registration of the chained hook of the mint.
+fn chained_hook_registration_meta() -> Result<ExtraAccountMeta> {
+    Ok(ExtraAccountMeta::new_with_seeds(
+        &[
+            Seed::Literal {
+                bytes: b"chained-hook".to_vec(),
+            },
+            Seed::AccountKey { index: 1 }, // mint
+        ],
+        false, // is_signer
+        false, // is_writable
+    )?)
+}
+
+/// Builds the extra accounts of the external hook chained after `transfer_hook`,
+/// appended to the `base_len` accounts of `transfer_hook_extra_account_metas`.
+fn chained_hook_extra_account_metas(
+    program: &Pubkey,
+    base_len: usize,
+) -> Result<Vec<ExtraAccountMeta>> {
+    // Execute accounts: source, mint, destination, owner, meta list, then extras
+    let program_index = u8::try_from(5 + base_len + 1)
+        .map_err(|_| error!(TokenManagerError::InvalidChainedHook))?;
+    Ok(vec![
+        chained_hook_registration_meta()?,
+        ExtraAccountMeta::new_with_pubkey(
+            program, false, // is_signer
+            false, // is_writable
+        )?,
+        // Extra account meta list of the external hook for the mint
+        ExtraAccountMeta::new_external_pda_with_seeds(
+            program_index,
+            &[
+                Seed::Literal {
+                    bytes: b"extra-account-metas".to_vec(),
+                },
+                Seed::AccountKey { index: 1 }, // mint
+            ],
+            false, // is_signer
+            false, // is_writable
+        )?,
+    ])
+}
+
+/// Rewrites the extra account meta list of a mint with `account_metas`, resizing
+/// the account and topping up its rent from `payer`.
+fn write_extra_account_meta_list<'info>(
+    meta_list: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    account_metas: &[ExtraAccountMeta],
+) -> Result<()> {
+    let account_size = ExtraAccountMetaList::size_of(account_metas.len())?;
+    if account_size > meta_list.data_len() {
+        let missing = Rent::get()?
+            .minimum_balance(account_size)
+            .saturating_sub(meta_list.lamports());
+        if missing > 0 {
+            invoke(
+                &system_instruction::transfer(payer.key, meta_list.key, missing),
+                &[payer.clone(), meta_list.clone(), system_program.clone()],
+            )?;
+        }
+        meta_list.realloc(account_size, false)?;
+    }
+    ExtraAccountMetaList::update::<ExecuteInstruction>(
+        &mut meta_list.try_borrow_mut_data()?,
+        account_metas,
+    )?;
+    meta_list.realloc(account_size, false)?;
+    Ok(())
+}
+
+/// Calls the external hook the issuer chained to the mint, if any, with the
+/// accounts of the transfer as read-only and within its compute budget.
+/// `transfer` holds the source, mint, destination and owner accounts, and
+/// `meta_list` the extra account meta list of the mint: a registration listed
+/// there must be passed, so the chained hook cannot be skipped.
+fn invoke_chained_hook<'info>(
+    transfer: [&AccountInfo<'info>; 4],
+    meta_list: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    amount: u64,
+) -> Result<()> {
+    let [source, mint, destination, owner] = transfer;
+    let registration_address =
+        Pubkey::find_program_address(&[b"chained-hook", mint.key.as_ref()], &crate::ID).0;
+    let Some(registration) = accounts
+        .iter()
+        .find(|account| account.key() == registration_address)
+    else {
+        let data = meta_list.try_borrow_data()?;
+        let tlv_state = TlvStateBorrowed::unpack(&data)?;
+        let account_metas =
+            ExtraAccountMetaList::unpack_with_tlv_state::<ExecuteInstruction>(&tlv_state)?;
+        if account_metas
+            .data()
+            .contains(&chained_hook_registration_meta()?)
+        {
+            return Err(error!(TokenManagerError::ChainedHookAccountsMissing));
+        }
+        return Ok(());
+    };
+    let Some(chained_hook) = load_optional_account::<ChainedHook>(registration)? else {
+        return Ok(());
+    };
+
+    let meta_list_address = Pubkey::find_program_address(
+        &[b"extra-account-metas", mint.key.as_ref()],
+        &chained_hook.program,
+    )
+    .0;
+    let find = |address: Pubkey| accounts.iter().find(|account| account.key() == address);
+    let (Some(program), Some(meta_list)) = (find(chained_hook.program), find(meta_list_address))
+    else {
+        return Err(error!(TokenManagerError::ChainedHookAccountsMissing));
+    };
+
+    let budget = u64::from(chained_hook.max_compute_units);
+    let remaining = sol_remaining_compute_units();
+    if remaining < budget {
+        return Err(error!(TokenManagerError::ChainedHookOverBudget));
+    }
+    invoke(
+        &spl_transfer_hook_interface::instruction::execute(
+            &chained_hook.program,
+            source.key,
+            mint.key,
+            destination.key,
+            owner.key,
+            meta_list.key,
+            amount,
+        ),
+        &[
+            source.clone(),
+            mint.clone(),
+            destination.clone(),
+            owner.clone(),
+            meta_list.clone(),
+            program.clone(),
+        ],
+    )?;
+    if remaining.saturating_sub(sol_remaining_compute_units()) > budget {
+        return Err(error!(TokenManagerError::ChainedHookOverBudget));
+    }
+    Ok(())
+}
+
+/// Everything the transfer rules need to know about a transfer.
+struct TransferCheck<'a> {
+    token_manager: &'a TokenManager,
+    token: &'a TokenShare,
+    /// Authorization of the source wallet, if it is whitelisted
+    source_entry: Option<WhitelistEntry>,
+    /// Authorization of the destination wallet, if it is whitelisted
+    destination_entry: Option<WhitelistEntry>,
+    mint: Pubkey,
+    source_owner: Pubkey,
+    destination_owner: Pubkey,
+    amount: u64,
+    /// Whether the source account holds no tokens once the transfer completes
+    empties_source: bool,
+    /// Balance of the source account once the transfer completes, if known
+    source_balance: Option<u64>,
+    /// Lockup of the source wallet, if the issuer set one
+    source_lockup: Option<Lockup>,
+    /// Balance of the destination account once the transfer completes, if known
+    destination_balance: Option<u64>,
+    destination_market_maker: Option<MarketMaker>,
+    destination_agreement: Option<AgreementAcceptance>,
+    source_agreement: Option<AgreementAcceptance>,
+    source_trusted_vault: bool,
+    destination_trusted_vault: bool,
+    /// Whether the destination wallet posted an incoming consent
+    destination_consent: bool,
+    /// Whether a compliance officer approved the transfer with `approve_transfer`
+    transfer_approved: bool,
+    sanctions_list: Option<&'a SanctionsList>,
+    /// Credit state of the token, if it is a debt instrument
+    credit_state: Option<CreditState>,
+    circuit_breaker: Option<&'a CircuitBreaker>,
+    now: Now,
+}
+
+impl TransferCheck<'_> {
+    /// Whether the destination account held no tokens before the transfer.
+    /// Unknown balances are treated as a first receipt.
+    fn is_first_receipt(&self) -> bool {
+        !matches!(self.destination_balance, Some(balance) if balance != self.amount)
+    }
+}
+
+/// Result of a single transfer rule, identified by its `constants::RULE_*` id.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RuleEvaluation {
+    pub rule_id: u16,
+    pub passed: bool,
+}
+
+/// Movement of one asset between two token accounts of a settlement batch,
+/// referenced by their index among the remaining accounts.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SettlementLeg {
+    pub asset: LegAsset,
+    pub from: u8,
+    pub to: u8,
+    pub amount: u64,
+}
+
+/// Trade whose settlement failed, passed to `open_settlement_fail`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FailedTrade {
+    /// Party that did not deliver the tokens or the cash
+    pub failing_party: Pubkey,
+    pub counterparty: Pubkey,
+    pub quantity: u64,
+    pub cash_amount: u64,
+    pub intended_settlement: i64,
+}
+
+/// Gross trade registered in a netting cycle.
+#[account]
+#[derive(InitSpace)]
+pub struct TradeObligation {
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    /// Tokens owed by the seller
+    pub quantity: u64,
+    /// Payment mint units owed by the buyer
+    pub cash_amount: u64,
+}
+
+/// Asset moved by a settlement leg.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum LegAsset {
+    /// The security being settled
+    Security,
+    /// The payment mint of the batch
+    Cash,
+}
+
+/// Direction of an offer.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum OfferKind {
+    /// The issuer sells newly issued tokens
+    Subscription,
+    /// The issuer buys back tokens from holders
+    Buyback,
+}
+
+/// Roles held by a wallet across a token manager, returned by `get_permissions`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Permissions {
+    pub creator: bool,
+    pub compliance_officer: bool,
+    pub relayer: bool,
+    pub tokens: Vec<TokenPermissions>,
+}
+
+/// Per-token roles of a wallet.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TokenPermissions {
+    pub mint: Pubkey,
+    pub whitelisted: bool,
+    pub market_maker: bool,
+}
+
+/// Evaluates every transfer rule in order. The transfer is allowed only if all
+/// of them pass; the hook reports the first failing one. Rules of suspended
+/// modules are reported as passed.
+fn evaluate_transfer(check: &TransferCheck) -> Vec<RuleEvaluation> {
+    let mut evaluations = evaluate_rules(check);
+    for evaluation in &mut evaluations {
+        if check
+            .token_manager
+            .is_module_suspended(evaluation.rule_id, check.now.slot)
+        {
+            evaluation.passed = true;
+        }
+    }
+    evaluations
+}
+
+fn evaluate_rules(check: &TransferCheck) -> Vec<RuleEvaluation> {
+    let mut evaluations = Vec::new();
+    let token = check.token;
+
+    // Protective stops apply to every transfer, including those exempted from the
+    // regular rules below: sanctioned wallets may neither send nor receive
+    if let Some(sanctions_list) = check.sanctions_list {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_SANCTIONS_SCREENING,
+            passed: !sanctions_list.contains(&check.source_owner)
+                && !sanctions_list.contains(&check.destination_owner),
+        });
+    }
+
+    if check.token_manager.locked_down_at.is_some() {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_MANAGER_LOCKDOWN,
+            passed: false,
+        });
+    }
+
+    if let Some(circuit_breaker) = check.circuit_breaker {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_CIRCUIT_BREAKER,
+            passed: circuit_breaker.tripped_at.is_none(),
+        });
+    }
+
+    if !token.blackouts.is_empty() {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_RECORD_DATE_BLACKOUT,
+            passed: !token.in_blackout(check.now.slot),
+        });
+    }
+
+    if let Some(state) = check.credit_state {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_CREDIT_STATE,
+            passed: state.allows_transfers(),
+        });
+    }
+
+    // Locked tokens must stay in the source account until released
+    if let Some(lockup) = &check.source_lockup {
+        let locked = lockup.locked(&check.now, check.token_manager.clock_tolerance_seconds);
+        if locked > 0 {
+            evaluations.push(RuleEvaluation {
+                rule_id: constants::RULE_LOCKUP,
+                passed: !matches!(check.source_balance, Some(balance) if balance < locked),
+            });
+        }
+    }
+
+    if token.require_transfer_approval {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_TRANSFER_APPROVED,
+            passed: check.transfer_approved,
+        });
+    }
+
+    // Moving tokens between two accounts of the same holder is internal
+    if token.allow_self_transfers && check.source_owner == check.destination_owner {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_SELF_TRANSFER,
+            passed: true,
+        });
+        return evaluations;
+    }
+
+    // Movements between internal vaults of the issuer are plumbing, not trades
+    if check.source_trusted_vault && check.destination_trusted_vault {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_TRUSTED_VAULT,
+            passed: true,
+        });
+        return evaluations;
+    }
+
+    let edge_cases = [
+        (
+            check.amount == 0,
+            token.zero_amount_policy,
+            constants::RULE_ZERO_AMOUNT,
+        ),
+        (
+            check.empties_source,
+            token.account_emptying_policy,
+            constants::RULE_ACCOUNT_EMPTYING,
+        ),
+    ];
+    // Only zero-amount transfers move nothing and may skip the regular rules;
+    // allowing a transfer that empties its source only lifts the edge-case denial
+    let mut waived = false;
+    for (applies, policy, rule_id) in edge_cases {
+        if !applies {
+            continue;
+        }
+        match policy {
+            EdgeCasePolicy::Evaluate => {}
+            EdgeCasePolicy::Allow => {
+                evaluations.push(RuleEvaluation {
+                    rule_id,
+                    passed: true,
+                });
+                waived |= rule_id == constants::RULE_ZERO_AMOUNT;
+            }
+            EdgeCasePolicy::Deny => {
+                evaluations.push(RuleEvaluation {
+                    rule_id,
+                    passed: false,
+                });
+            }
+        }
+    }
+    if waived {
+        return evaluations;
+    }
+
+    evaluations.push(RuleEvaluation {
+        rule_id: constants::RULE_DESTINATION_WHITELISTED,
+        passed: check.destination_entry.is_some(),
+    });
+
+    if token.require_sender_whitelisted {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_SOURCE_WHITELISTED,
+            passed: check.source_entry.is_some(),
+        });
+    }
+
+    // Holders whose KYC lapsed may neither send nor receive until renewed
+    let expiries: Vec<i64> = [&check.source_entry, &check.destination_entry]
+        .into_iter()
+        .flatten()
+        .map(|entry| entry.expires_at)
+        .filter(|expires_at| *expires_at != 0)
+        .collect();
+    if !expiries.is_empty() {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_AUTHORIZATION_CURRENT,
+            passed: !expiries.iter().any(|expires_at| {
+                check
+                    .now
+                    .may_have_passed(*expires_at, check.token_manager.clock_tolerance_seconds)
+            }),
+        });
+    }
+
+    if let Some(market_maker) = &check.destination_market_maker {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_MARKET_MAKER_INVENTORY,
+            passed: !matches!(
+                check.destination_balance,
+                Some(balance) if balance > market_maker.max_inventory
+            ),
+        });
+    }
+
+    // A transfer adds a holder when it reaches an empty account without emptying
+    // its source
+    if let Some(max_holders) = token.max_holders {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_MAX_HOLDERS,
+            passed: !check.is_first_receipt()
+                || check.empties_source
+                || token.holder_count < max_holders,
+        });
+    }
+
+    if let Some(max_balance) = token.max_balance_per_holder {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_HOLDER_CAP,
+            passed: !matches!(
+                check.destination_balance,
+                Some(balance) if balance > max_balance
+            ),
+        });
+    }
+
+    if token.require_agreement && check.is_first_receipt() {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_AGREEMENT_ACCEPTED,
+            passed: check
+                .destination_agreement
+                .as_ref()
+                .is_some_and(|acceptance| acceptance.agreement_hash == token.agreement_hash),
+        });
+    }
+
+    if token.require_incoming_consent {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_INCOMING_CONSENT,
+            passed: check.destination_consent,
+        });
+    }
+
+    if token.require_reacceptance {
+        evaluations.push(RuleEvaluation {
+            rule_id: constants::RULE_AGREEMENT_REACCEPTED,
+            passed: check
+                .source_agreement
+                .as_ref()
+                .is_some_and(|acceptance| acceptance.agreement_hash == token.agreement_hash),
+        });
+    }
+
+    evaluations
+}
+
+/// Accounts shared by every leg of a settlement batch.
+struct Settlement<'a, 'info> {
+    venue: AccountInfo<'info>,
+    token_manager: &'a Account<'info, TokenManager>,
+    token_share: &'a mut TokenShare,
+    security_mint: &'a InterfaceAccount<'info, Mint>,
+    security_mint_bump: u8,
+    /// Mint and token program of the cash legs, `None` when only securities move
+    payment_mint: Option<&'a InterfaceAccount<'info, Mint>>,
+    token_program: AccountInfo<'info>,
+    payment_token_program: Option<AccountInfo<'info>>,
+    sanctions_list: Option<SanctionsList>,
+    credit_state: Option<CreditState>,
+    circuit_breaker: Option<CircuitBreaker>,
+    /// Whether the issuer chained an external hook to the security, which only
+    /// runs on Token-2022 transfers
+    chained_hook: bool,
+    /// Fee charged on cash legs, paid to `fee_collector`
+    transfer_fee_bps: u16,
+    fee_collector: Option<AccountInfo<'info>>,
+    /// Whether the transfers were approved with `approve_transfer`
+    transfer_approved: bool,
+    now: Now,
+}
+
+impl ComplianceAction<'_> {
+    /// Fails unless the signer is an officer of the queue or holds the compliance
+    /// officer role, or the token manager is locked down.
+    fn require_officer(&self) -> Result<()> {
+        let signer = self.signer.key();
+        if !self
+            .compliance_queue
+            .is_officer(&signer, &self.token_manager.creator)
+            && !has_role(
+                &self.token_manager,
+                &signer,
+                &self.role_assignment,
+                Role::ComplianceOfficer,
+            )?
+        {
+            return Err(error!(TokenManagerError::Unauthorized));
+        }
+        self.token_manager.require_unlocked()
+    }
+}
+
+impl<'info> Settlement<'_, 'info> {
+    /// Moves the tokens of `leg` between token accounts taken from `accounts`.
+    fn settle(&mut self, leg: &SettlementLeg, accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        let source_info = accounts
+            .get(leg.from as usize)
+            .ok_or(error!(TokenManagerError::InvalidSettlementLeg))?;
+        let destination_info = accounts
+            .get(leg.to as usize)
+            .ok_or(error!(TokenManagerError::InvalidSettlementLeg))?;
+        let source = InterfaceAccount::<TokenAccount>::try_from(source_info)?;
+        let destination = InterfaceAccount::<TokenAccount>::try_from(destination_info)?;
+
+        let mint = match leg.asset {
+            LegAsset::Security => self.security_mint.key(),
+            LegAsset::Cash => self
+                .payment_mint
+                .ok_or(error!(TokenManagerError::InvalidSettlementLeg))?
+                .key(),
+        };
+        if source.mint != mint || destination.mint != mint {
+            return Err(error!(TokenManagerError::InvalidSettlementLeg));
+        }
+
+        match leg.asset {
+            LegAsset::Security => {
+                if self.chained_hook {
+                    return Err(error!(TokenManagerError::ChainedHookUnsupported));
+                }
+                let check = TransferCheck {
+                    token_manager: self.token_manager,
+                    token: self.token_share,
+                    source_entry: find_whitelist_entry(accounts, &mint, &source.owner)?,
+                    destination_entry: find_whitelist_entry(accounts, &mint, &destination.owner)?,
+                    mint,
+                    source_owner: source.owner,
+                    destination_owner: destination.owner,
+                    amount: leg.amount,
+                    empties_source: source.amount == leg.amount,
+                    source_balance: Some(source.amount.saturating_sub(leg.amount)),
+                    source_lockup: find_lockup(accounts, &mint, &source.owner)?,
+                    destination_balance: Some(destination.amount.saturating_add(leg.amount)),
+                    destination_market_maker: find_wallet_record(
+                        accounts,
+                        b"market-maker",
+                        &mint,
+                        &destination.owner,
+                    )?,
+                    destination_agreement: find_wallet_record(
+                        accounts,
+                        b"agreement-acceptance",
+                        &mint,
+                        &destination.owner,
+                    )?,
+                    source_agreement: find_wallet_record(
+                        accounts,
+                        b"agreement-acceptance",
+                        &mint,
+                        &source.owner,
+                    )?,
+                    source_trusted_vault: find_wallet_record::<TrustedVault>(
+                        accounts,
+                        b"trusted-vault",
+                        &mint,
+                        &source.owner,
+                    )?
+                    .is_some(),
+                    destination_trusted_vault: find_wallet_record::<TrustedVault>(
+                        accounts,
+                        b"trusted-vault",
+                        &mint,
+                        &destination.owner,
+                    )?
+                    .is_some(),
+                    destination_consent: find_wallet_record::<IncomingConsent>(
+                        accounts,
+                        b"incoming-consent",
+                        &mint,
+                        &destination.owner,
+                    )?
+                    .is_some(),
+                    transfer_approved: self.transfer_approved,
+                    sanctions_list: self.sanctions_list.as_ref(),
+                    credit_state: self.credit_state,
+                    circuit_breaker: self.circuit_breaker.as_ref(),
+                    now: self.now,
+                };
+                let evaluations = evaluate_transfer(&check);
+                if let Some(failed) = evaluations.iter().find(|evaluation| !evaluation.passed) {
+                    return Err(reject_transfer(&check, failed.rule_id));
+                }
+                // Receipts are written when enabled, as the hook writes them
+                let receipts_address = Pubkey::find_program_address(
+                    &[b"transfer-receipts", mint.as_ref()],
+                    &crate::ID,
+                )
+                .0;
+                if let Some(receipts_info) = accounts
+                    .iter()
+                    .find(|account| account.key() == receipts_address)
+                {
+                    record_transfer_receipt(receipts_info, &check, &evaluations)?;
+                }
+
+                burn(
+                    CpiContext::new(
+                        self.token_program.clone(),
+                        Burn {
+                            mint: self.security_mint.to_account_info(),
+                            from: source_info.clone(),
+                            authority: self.venue.clone(),
+                        },
+                    ),
+                    leg.amount,
+                )?;
+                mint_share_tokens(
+                    self.token_program.clone(),
+                    self.security_mint.to_account_info(),
+                    destination_info.clone(),
+                    &self.token_manager.key(),
+                    self.token_share.index,
+                    self.security_mint_bump,
+                    leg.amount,
+                )?;
+                if source_info.key() != destination_info.key() {
+                    self.token_share
+                        .record_holding(source.amount, source.amount - leg.amount)?;
+                    self.token_share.record_holding(
+                        destination.amount,
+                        destination.amount.saturating_add(leg.amount),
+                    )?;
+                }
+                // As in the hook, both parties must have opened their due bill
+                // while an action tracks them
+                if let Some(ledger) = find_due_bill_ledger(accounts, &mint)? {
+                    record_due_bills(
+                        &ledger,
+                        accounts,
+                        source.owner,
+                        destination.owner,
+                        leg.amount,
+                        self.now.slot,
+                    )?;
+                }
+                // Statements are written when opened, as the hook writes them
+                if source.owner != destination.owner {
+                    let memo_hash = transfer_memo_hash(accounts)?;
+                    for (owner, received, sent) in [
+                        (source.owner, 0, leg.amount),
+                        (destination.owner, leg.amount, 0),
+                    ] {
+                        let address = Pubkey::find_program_address(
+                            &[b"holder-statement", mint.as_ref(), owner.as_ref()],
+                            &crate::ID,
+                        )
+                        .0;
+                        if let Some(statement_info) =
+                            accounts.iter().find(|account| account.key() == address)
+                        {
+                            record_statement_activity(
+                                statement_info,
+                                received,
+                                sent,
+                                &self.now,
+                                memo_hash,
+                            )?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            LegAsset::Cash => {
+                let (Some(payment_mint), Some(payment_token_program)) =
+                    (self.payment_mint, self.payment_token_program.as_ref())
+                else {
+                    return Err(error!(TokenManagerError::InvalidSettlementLeg));
+                };
+                let fee = fee_on(leg.amount, self.transfer_fee_bps);
+                let pay = |to: &AccountInfo<'info>, amount: u64| {
+                    transfer_checked(
+                        CpiContext::new(
+                            payment_token_program.clone(),
+                            TransferChecked {
+                                from: source_info.clone(),
+                                mint: payment_mint.to_account_info(),
+                                to: to.clone(),
+                                authority: self.venue.clone(),
+                            },
+                        ),
+                        amount,
+                        payment_mint.decimals,
+                    )
+                };
+                if fee > 0 {
+                    let collector = self
+                        .fee_collector
+                        .as_ref()
+                        .ok_or(error!(TokenManagerError::FeeCollectorRequired))?;
+                    pay(collector, fee)?;
+                    emit!(FeesCharged {
+                        mint: self.security_mint.key(),
+                        payer: source.owner,
+                        amount: fee,
+                    });
+                }
+                pay(destination_info, leg.amount - fee)
+            }
+        }
+    }
+}
+
+/// Records the terms of a delivery-versus-payment trade being opened.
+fn open_trade(trade: &mut Account<DvpTrade>, terms: DvpTrade) -> Result<()> {
+    if terms.seller == terms.buyer || terms.amount == 0 || terms.payment == 0 {
+        return Err(error!(TokenManagerError::InvalidDvpTrade));
+    }
+    emit!(DvpOpened {
+        mint: terms.mint,
+        buyer: terms.buyer,
+        seller: terms.seller,
+        trade_id: terms.id,
+        amount: terms.amount,
+        payment_mint: terms.payment_mint,
+        payment: terms.payment,
+        expires_at: terms.expires_at,
+    });
+    trade.set_inner(terms);
+    Ok(())
+}
+
+/// Pays the escrow of `trade` to `recipient`: its wallet for trades paid in SOL,
+/// its payment account otherwise. The escrow vault is emptied and closed,
+/// refunding its rent to `buyer`.
+fn release_dvp_payment<'info>(
+    trade: &Account<'info, DvpTrade>,
+    recipient: &AccountInfo<'info>,
+    buyer: &AccountInfo<'info>,
+    payment_mint: Option<&InterfaceAccount<'info, Mint>>,
+    dvp_vault: Option<&InterfaceAccount<'info, TokenAccount>>,
+    payment_token_program: Option<&Interface<'info, TokenInterface>>,
+) -> Result<()> {
+    let Some(mint) = trade.payment_mint else {
+        **trade.to_account_info().try_borrow_mut_lamports()? -= trade.payment;
+        **recipient.try_borrow_mut_lamports()? += trade.payment;
+        return Ok(());
+    };
+    let (Some(payment_mint), Some(dvp_vault), Some(payment_token_program)) =
+        (payment_mint, dvp_vault, payment_token_program)
+    else {
+        return Err(error!(TokenManagerError::DvpPaymentMismatch));
+    };
+    if payment_mint.key() != mint {
+        return Err(error!(TokenManagerError::DvpPaymentMismatch));
+    }
+
+    let trade_id = trade.id.to_le_bytes();
+    let seeds: &[&[u8]] = &[
+        b"dvp",
+        trade.mint.as_ref(),
+        trade.buyer.as_ref(),
+        &trade_id,
+        &[trade.bump],
+    ];
+    // The whole vault is paid out, so that tokens sent to it by anyone else
+    // cannot keep it from closing
+    transfer_checked(
+        CpiContext::new_with_signer(
+            payment_token_program.to_account_info(),
+            TransferChecked {
+                from: dvp_vault.to_account_info(),
+                mint: payment_mint.to_account_info(),
+                to: recipient.clone(),
+                authority: trade.to_account_info(),
+            },
+            &[seeds],
+        ),
+        dvp_vault.amount,
+        payment_mint.decimals,
+    )?;
+    close_account(CpiContext::new_with_signer(
+        payment_token_program.to_account_info(),
+        CloseAccount {
+            account: dvp_vault.to_account_info(),
+            destination: buyer.clone(),
+            authority: trade.to_account_info(),
+        },
+        &[seeds],
+    ))
+}
+
+/// Fixed-length extensions of the receipt mint of an offer.
+const RECEIPT_MINT_EXTENSIONS: [ExtensionType; 2] = [
+    ExtensionType::NonTransferable,
+    ExtensionType::PermanentDelegate,
+];
+
+/// Receipt mint of an offer, minting the units of every order to its investor
+/// until the order is settled. Offers without receipts have no mint, which makes
+/// every operation a no-op.
+struct OrderReceipt<'info> {
+    offer: Pubkey,
+    mint: AccountInfo<'info>,
+    mint_bump: u8,
+    token_program: AccountInfo<'info>,
+}
+
+impl<'info> OrderReceipt<'info> {
+    fn is_enabled(&self) -> bool {
+        !self.mint.data_is_empty()
+    }
+
+    fn account_address(&self, investor: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[b"offer-receipt", self.offer.as_ref(), investor.as_ref()],
+            &crate::ID,
+        )
+        .0
+    }
+
+    /// Mints `amount` receipts to the receipt account of `investor`, creating the
+    /// account on its first order.
+    fn issue(
+        &self,
+        account: &AccountInfo<'info>,
+        account_bump: u8,
+        investor: &AccountInfo<'info>,
+        payer: &AccountInfo<'info>,
+        system_program: &AccountInfo<'info>,
+        amount: u64,
+    ) -> Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        if account.data_is_empty() {
+            let space = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(
+                &ExtensionType::get_required_init_account_extensions(&RECEIPT_MINT_EXTENSIONS),
+            )?;
+            invoke_signed(
+                &system_instruction::create_account(
+                    payer.key,
+                    account.key,
+                    Rent::get()?.minimum_balance(space),
+                    space as u64,
+                    self.token_program.key,
+                ),
+                &[payer.clone(), account.clone(), system_program.clone()],
+                &[&[
+                    b"offer-receipt",
+                    self.offer.as_ref(),
+                    investor.key.as_ref(),
+                    &[account_bump],
+                ]],
+            )?;
+            invoke(
+                &spl_token_2022::instruction::initialize_account3(
+                    self.token_program.key,
+                    account.key,
+                    self.mint.key,
+                    investor.key,
+                )?,
+                &[account.clone(), self.mint.clone()],
+            )?;
+        }
+        invoke_signed(
+            &spl_token_2022::instruction::mint_to(
+                self.token_program.key,
+                self.mint.key,
+                account.key,
+                self.mint.key,
+                &[],
+                amount,
+            )?,
+            &[
+                self.mint.clone(),
+                account.clone(),
+                self.token_program.clone(),
+            ],
+            &[&self.mint_seeds()],
+        )?;
+        Ok(())
+    }
+
+    /// Burns every receipt held by `account`, if it was created.
+    fn release(&self, account: &AccountInfo<'info>) -> Result<()> {
+        if !self.is_enabled() || account.data_is_empty() {
+            return Ok(());
+        }
+        let amount = token_balance(account)?;
+        if amount == 0 {
+            return Ok(());
+        }
+        invoke_signed(
+            &spl_token_2022::instruction::burn(
+                self.token_program.key,
+                account.key,
+                self.mint.key,
+                self.mint.key,
+                &[],
+                amount,
+            )?,
+            &[
+                account.clone(),
+                self.mint.clone(),
+                self.token_program.clone(),
+            ],
+            &[&self.mint_seeds()],
+        )?;
+        Ok(())
+    }
+
+    fn mint_seeds(&self) -> [&[u8]; 3] {
+        [
+            b"offer-receipt",
+            self.offer.as_ref(),
+            std::slice::from_ref(&self.mint_bump),
+        ]
+    }
+}
+
+/// Accounts shared by every order settled on an offer.
+struct OrderSettlement<'a, 'info> {
+    token_manager: &'a Account<'info, TokenManager>,
+    token_share: &'a TokenShare,
+    offer: &'a Account<'info, Offer>,
+    security_mint: AccountInfo<'info>,
+    security_mint_bump: u8,
+    payment_mint: &'a InterfaceAccount<'info, Mint>,
+    offer_vault: AccountInfo<'info>,
+    issuer_payment: AccountInfo<'info>,
+    collateral: &'a AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    payment_token_program: AccountInfo<'info>,
+    now: Now,
+}
+
+impl<'info> OrderSettlement<'_, 'info> {
+    /// Pays and mints what `order` is owed once the offer closed, recording the
+    /// filled units on the investor's statement if opened.
+    fn settle(
+        &self,
+        order: &OfferOrder,
+        investor_security: AccountInfo<'info>,
+        investor_payment: AccountInfo<'info>,
+        statement: Option<&mut HolderStatement>,
+    ) -> Result<()> {
+        let offer = self.offer;
+        let requested = order.requested;
+        let filled = offer.filled(requested);
+        let unfilled = requested - filled;
+        let offer_seeds = &[
+            b"offer".as_ref(),
+            offer.mint.as_ref(),
+            &offer.id.to_le_bytes(),
+            &[offer.bump],
+        ];
+        let offer_signer = &[&offer_seeds[..]];
+        let pay_from_vault = |to: AccountInfo<'info>, amount: u64| -> Result<()> {
+            if amount == 0 {
+                return Ok(());
+            }
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    self.payment_token_program.clone(),
+                    TransferChecked {
+                        from: self.offer_vault.clone(),
+                        mint: self.payment_mint.to_account_info(),
+                        to,
+                        authority: offer.to_account_info(),
+                    },
+                    offer_signer,
+                ),
+                amount,
+                self.payment_mint.decimals,
+            )
+        };
+
+        let (tokens, cash, refund, fees) = match offer.kind {
+            OfferKind::Subscription => {
+                // Fees of the unfilled portion are refunded, the admin fee is not
+                let fee_refund = if requested == 0 {
+                    0
+                } else {
+                    (u128::from(order.fees_paid) * u128::from(unfilled) / u128::from(requested))
+                        as u64
+                };
+                let fees = order.fees_paid - fee_refund + order.admin_fee;
+                let proceeds = offer
+                    .cash_for(filled)?
+                    .checked_add(fees)
+                    .ok_or(error!(TokenManagerError::AmountOverflow))?;
+                pay_from_vault(self.issuer_payment.clone(), proceeds)?;
+                let refund = offer
+                    .cash_for(unfilled)?
+                    .checked_add(fee_refund)
+                    .ok_or(error!(TokenManagerError::AmountOverflow))?;
+                pay_from_vault(investor_payment, refund)?;
+                (filled, 0, refund, fees)
+            }
+            OfferKind::Buyback => {
+                // Fees stay in the buyback budget of the issuer
+                let gross = offer.cash_for(filled)?;
+                let fees = fee_on(gross, offer.fees.fee_bps)
+                    .saturating_add(offer.fees.admin_fee)
+                    .min(gross);
+                let price = gross - fees;
+                pay_from_vault(investor_payment, price)?;
+                (unfilled, price, 0, fees)
+            }
+        };
+        if fees > 0 {
+            emit!(FeesCharged {
+                mint: offer.mint,
+                payer: order.investor,
+                amount: fees,
+            });
+        }
+        if tokens > 0 {
+            if offer.kind == OfferKind::Subscription {
+                check_collateral_fresh(
+                    self.collateral,
+                    &self.now,
+                    self.token_manager.clock_tolerance_seconds,
+                )?;
+                self.token_share.check_issuance(
+                    mint_supply(&self.security_mint)?,
+                    token_balance(&investor_security)?,
+                    tokens,
+                )?;
+            }
+            mint_share_tokens(
+                self.token_program.clone(),
+                self.security_mint.clone(),
+                investor_security,
+                &self.token_manager.key(),
+                self.token_share.index,
+                self.security_mint_bump,
+                tokens,
+            )?;
+        }
+
+        if let Some(statement) = statement {
+            let (received, sent) = match offer.kind {
+                OfferKind::Subscription => (filled, 0),
+                OfferKind::Buyback => (0, filled),
+            };
+            statement.record_transfer(received, sent, self.now.unix_timestamp);
+        }
+
+        emit!(OrderSettled {
+            mint: offer.mint,
+            id: offer.id,
+            investor: order.investor,
+            requested,
+            filled,
+            cash_paid: cash,
+            refund,
+        });
+        Ok(())
+    }
+}
+
+/// Net security and cash positions of the counterparties of a netting cycle.
+#[derive(Default)]
+struct NetPositions(Vec<(Pubkey, i128, i128)>);
+
+impl NetPositions {
+    fn add(&mut self, wallet: Pubkey, security: i128, cash: i128) {
+        match self.0.iter_mut().find(|position| position.0 == wallet) {
+            Some(position) => {
+                position.1 += security;
+                position.2 += cash;
+            }
+            None => self.0.push((wallet, security, cash)),
+        }
+    }
+
+    /// Pairs the counterparties owing the asset selected by `net` with those owed
+    /// it, yielding at most one transfer less than there are counterparties.
+    fn transfers(&self, net: impl Fn(&(Pubkey, i128, i128)) -> i128) -> Vec<(Pubkey, Pubkey, u64)> {
+        let mut payers: Vec<(Pubkey, i128)> = Vec::new();
+        let mut payees: Vec<(Pubkey, i128)> = Vec::new();
+        for position in &self.0 {
+            match net(position) {
+                amount if amount < 0 => payers.push((position.0, -amount)),
+                amount if amount > 0 => payees.push((position.0, amount)),
+                _ => {}
+            }
+        }
+
+        let mut transfers = Vec::new();
+        let (mut payer, mut payee) = (0, 0);
+        while payer < payers.len() && payee < payees.len() {
+            let amount = payers[payer].1.min(payees[payee].1);
+            transfers.push((payers[payer].0, payees[payee].0, amount as u64));
+            payers[payer].1 -= amount;
+            payees[payee].1 -= amount;
+            if payers[payer].1 == 0 {
+                payer += 1;
+            }
+            if payees[payee].1 == 0 {
+                payee += 1;
+            }
+        }
+        transfers
+    }
+}
+
+/// Index among `accounts` of the token account of `owner` for `mint`.
+fn find_token_account<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> Result<u8> {
+    accounts
+        .iter()
+        .position(|account| {
+            InterfaceAccount::<TokenAccount>::try_from(account).is_ok_and(|token_account| {
+                token_account.owner == *owner && token_account.mint == *mint
+            })
+        })
+        .map(|index| index as u8)
+        .ok_or(error!(TokenManagerError::InvalidSettlementLeg))
+}
+
+/// Reports a transfer rejected by `rule_id` and returns the error failing it.
+fn reject_transfer(check: &TransferCheck, rule_id: u16) -> Error {
+    emit!(TransferRejected {
+        mint: check.mint,
+        from: check.source_owner,
+        to: check.destination_owner,
+        amount: check.amount,
+        rule_id,
+    });
+    log_structured(
+        constants::LOG_TRANSFER_REJECTED,
+        &[
+            ("mint", check.mint.to_string()),
+            ("from", check.source_owner.to_string()),
+            ("to", check.destination_owner.to_string()),
+            ("amount", check.amount.to_string()),
+            ("rule", rule_id.to_string()),
+        ],
+    );
+    match rule_id {
+        constants::RULE_SOURCE_WHITELISTED => error!(TokenManagerError::SenderNotWhitelisted),
+        constants::RULE_HOLDER_CAP => error!(TokenManagerError::HolderCapExceeded),
+        constants::RULE_LOCKUP => error!(TokenManagerError::TokensLocked),
+        constants::RULE_MANAGER_LOCKDOWN => error!(TokenManagerError::ManagerLockedDown),
+        constants::RULE_TRANSFER_APPROVED => error!(TokenManagerError::TransferNotApproved),
+        _ => error!(TokenManagerError::TransferNotAllowed),
+    }
+}
+
+/// Reports a transfer denied because a wallet is on the sanctions list.
+fn alert_sanctions_hit(check: &TransferCheck) {
+    let Some(sanctions_list) = check.sanctions_list else {
+        return;
+    };
+    for wallet in [check.source_owner, check.destination_owner] {
+        if sanctions_list.contains(&wallet) {
+            emit!(SanctionsHit {
+                mint: check.mint,
+                wallet,
+                from: check.source_owner,
+                to: check.destination_owner,
+                amount: check.amount,
+            });
+            log_structured(
+                constants::LOG_SANCTIONS_HIT,
+                &[
+                    ("mint", check.mint.to_string()),
+                    ("wallet", wallet.to_string()),
+                ],
+            );
+        }
+    }
+}
+
+/// Writes a structured log line, see `constants::LOG_PREFIX`.
+fn log_structured(kind: &str, fields: &[(&str, String)]) {
+    let fields = fields
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join(" ");
+    msg!("{} {} {}", constants::LOG_PREFIX, kind, fields);
+}
+
+/// Counts anomalies on the circuit breaker stored in `account`, if the token has one.
+fn count_anomaly(account: &AccountInfo, kind: AnomalyKind, count: u32, slot: u64) -> Result<()> {
+    if let Some(mut circuit_breaker) = load_optional_account::<CircuitBreaker>(account)? {
+        circuit_breaker.record(kind, count, slot);
+        circuit_breaker.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+    }
+    Ok(())
+}
+
+/// Fails when the operation lock stored in `account` is held by an operation other
+/// than `operation`.
+fn check_operation_lock(account: &AccountInfo, operation: TokenOperation) -> Result<()> {
+    match load_optional_account::<OperationLock>(account)? {
+        Some(lock) if lock.operation != operation => {
+            Err(error!(TokenManagerError::OperationInProgress))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Fails when the operation lock stored in `account` is held by `operation`.
+fn check_operation_not_held(account: &AccountInfo, operation: TokenOperation) -> Result<()> {
+    match load_optional_account::<OperationLock>(account)? {
+        Some(lock) if lock.operation == operation => {
+            Err(error!(TokenManagerError::OperationInProgress))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Fails unless the operation lock stored in `account` is held by `operation`
+/// and was acquired no later than `slot`.
+fn require_operation_lock(
+    account: &AccountInfo,
+    operation: TokenOperation,
+    slot: u64,
+) -> Result<()> {
+    match load_optional_account::<OperationLock>(account)? {
+        Some(lock) if lock.operation == operation && lock.acquired_slot <= slot => Ok(()),
+        _ => Err(error!(TokenManagerError::OperationLockRequired)),
+    }
+}
+
+/// Whether `signer` is the creator of `token_manager` or holds `role` in the role
+/// assignment stored in `assignment`.
+fn has_role(
+    token_manager: &Account<TokenManager>,
+    signer: &Pubkey,
+    assignment: &AccountInfo,
+    role: Role,
+) -> Result<bool> {
+    token_manager.require_unlocked()?;
+    if *signer == token_manager.creator {
+        return Ok(true);
+    }
+    Ok(load_optional_account::<RoleAssignment>(assignment)?
+        .is_some_and(|assignment| assignment.has(role)))
+}
+
+/// Fails unless `signer` is the creator of `token_manager` and the manager is not
+/// locked down.
+fn require_creator(token_manager: &TokenManager, signer: &Pubkey) -> Result<()> {
+    if *signer != token_manager.creator {
+        return Err(error!(TokenManagerError::Unauthorized));
+    }
+    token_manager.require_unlocked()
+}
+
+/// Fails unless `signer` is the creator of `token_manager` or holds `role`.
+fn require_role(
+    token_manager: &Account<TokenManager>,
+    signer: &Pubkey,
+    assignment: &AccountInfo,
+    role: Role,
+) -> Result<()> {
+    if !has_role(token_manager, signer, assignment, role)? {
+        return Err(error!(TokenManagerError::Unauthorized));
+    }
+    Ok(())
+}
+
+/// Debits `amount` from the mint session stored in `account`, failing when there is
+/// none, it expired, or its allowance is exhausted.
+fn debit_mint_session(account: &AccountInfo, amount: u64, now: i64) -> Result<()> {
+    let Some(mut session) = load_optional_account::<MintSession>(account)? else {
+        return Err(error!(TokenManagerError::Unauthorized));
+    };
+    if now >= session.expiry {
+        return Err(error!(TokenManagerError::MintSessionExpired));
+    }
+    session.minted = session
+        .minted
+        .checked_add(amount)
+        .filter(|minted| *minted <= session.max_amount)
+        .ok_or(error!(TokenManagerError::MintAllowanceExceeded))?;
+    session.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+/// Debits `amount` from the daily allowance of the burn session stored in
+/// `account`, failing when there is none, it expired, or the cap of the day is
+/// reached.
+fn debit_burn_session(account: &AccountInfo, amount: u64, now: i64) -> Result<()> {
+    let Some(mut session) = load_optional_account::<BurnSession>(account)? else {
+        return Err(error!(TokenManagerError::Unauthorized));
+    };
+    if now >= session.expiry {
+        return Err(error!(TokenManagerError::BurnSessionExpired));
+    }
+    let day = now.div_euclid(86_400);
+    if session.day != day {
+        session.day = day;
+        session.burned_today = 0;
+    }
+    session.burned_today = session
+        .burned_today
+        .checked_add(amount)
+        .filter(|burned| *burned <= session.daily_cap)
+        .ok_or(error!(TokenManagerError::BurnAllowanceExceeded))?;
+    session.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+fn log_whitelist_removed(mint: &Pubkey, wallet: &Pubkey) {
+    log_structured(
+        constants::LOG_WHITELIST_REMOVED,
+        &[("mint", mint.to_string()), ("wallet", wallet.to_string())],
+    );
+}
+
+/// Returns whether `account` holds data of the account type `T`.
+fn has_discriminator<T: anchor_lang::Discriminator>(account: &AccountInfo) -> bool {
+    account
+        .try_borrow_data()
+        .is_ok_and(|data| data.starts_with(&T::DISCRIMINATOR))
+}
+
+/// Sum of the balances `holder` had in `snapshot`, from the snapshot entries
+/// among `accounts`, each counted once.
+fn snapshot_balance(accounts: &[AccountInfo], snapshot: &Pubkey, holder: &Pubkey) -> Result<u64> {
+    let mut token_accounts = Vec::new();
+    let mut balance = 0u64;
+    for account in accounts.iter().filter(|account| {
+        account.owner == &crate::ID && has_discriminator::<SnapshotEntry>(account)
+    }) {
+        let entry = SnapshotEntry::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+        if entry.snapshot != *snapshot
+            || entry.owner != *holder
+            || token_accounts.contains(&entry.token_account)
+        {
+            return Err(error!(TokenManagerError::SnapshotEntryMismatch));
+        }
+        token_accounts.push(entry.token_account);
+        balance = balance
+            .checked_add(entry.balance)
+            .ok_or(error!(TokenManagerError::AmountOverflow))?;
+    }
+    Ok(balance)
+}
+
+/// Whether `bic` has the shape of a SWIFT BIC: a four-letter institution code, a
+/// country code, a two-character location and an optional three-character branch.
+fn is_valid_bic(bic: &str) -> bool {
+    let bytes = bic.as_bytes();
+    (bytes.len() == 8 || bytes.len() == 11)
+        && bytes[..6].iter().all(u8::is_ascii_uppercase)
+        && bytes[6..]
+            .iter()
+            .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit())
+}
+
+/// Balance of a token account only known as an `AccountInfo`.
+fn token_balance(account: &AccountInfo) -> Result<u64> {
+    Ok(
+        StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.try_borrow_data()?)?
+            .base
+            .amount,
+    )
+}
+
+fn mint_supply(mint: &AccountInfo) -> Result<u64> {
+    Ok(
+        StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint.try_borrow_data()?)?
+            .base
+            .supply,
+    )
+}
+
+/// Whether Token-2022 is transferring from or to a token account of a hooked
+/// mint, which it only flags while invoking the hook of the transfer.
+fn is_transferring(account: &AccountInfo) -> Result<bool> {
+    let data = account.try_borrow_data()?;
+    let token_account = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+    Ok(token_account
+        .get_extension::<TransferHookAccount>()
+        .is_ok_and(|extension| bool::from(extension.transferring)))
+}
+
+/// Deserializes a program-owned account that may not have been created yet.
+fn load_optional_account<T: AccountDeserialize>(account: &AccountInfo) -> Result<Option<T>> {
+    if account.owner != &crate::ID || account.data_is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(T::try_deserialize(
+        &mut &account.try_borrow_data()?[..],
+    )?))
+}
+
+/// Loads the whitelist entry of `wallet` for `mint` from `accounts`, if it was
+/// passed and exists.
+fn find_whitelist_entry(
+    accounts: &[AccountInfo],
+    mint: &Pubkey,
+    wallet: &Pubkey,
+) -> Result<Option<WhitelistEntry>> {
+    let address =
+        Pubkey::find_program_address(&[b"whitelist", mint.as_ref(), wallet.as_ref()], &crate::ID).0;
+    if let Some(account) = accounts.iter().find(|account| account.key() == address) {
+        if let Some(entry) = load_optional_account(account)? {
+            return Ok(Some(entry));
+        }
+    }
+    let bucket = Pubkey::find_program_address(
+        &[b"whitelist-bucket", mint.as_ref(), &wallet.as_ref()[..1]],
+        &crate::ID,
+    )
+    .0;
+    match accounts.iter().find(|account| account.key() == bucket) {
+        Some(account) => Ok(load_optional_account::<WhitelistBucket>(account)?
+            .filter(|bucket| bucket.contains(wallet))
+            .map(|bucket| bucket.entry(wallet))),
+        None => Ok(None),
+    }
+}
+
+/// Authorization of `wallet` from its whitelist `entry`, or from its whitelist
+/// `bucket`, if passed, as an entry without restrictions when it has none.
+fn load_whitelist_entry(
+    entry: &AccountInfo,
+    bucket: Option<&AccountInfo>,
+    wallet: &Pubkey,
+) -> Result<Option<WhitelistEntry>> {
+    if let Some(entry) = load_optional_account::<WhitelistEntry>(entry)? {
+        return Ok(Some(entry));
+    }
+    let Some(bucket) = bucket else {
+        return Ok(None);
+    };
+    Ok(load_optional_account::<WhitelistBucket>(bucket)?
+        .filter(|bucket| bucket.contains(wallet))
+        .map(|bucket| bucket.entry(wallet)))
+}
+
+/// Loads the lockup of `wallet` for `mint` from `accounts`, if it was passed and
+/// exists.
+fn find_lockup(accounts: &[AccountInfo], mint: &Pubkey, wallet: &Pubkey) -> Result<Option<Lockup>> {
+    find_wallet_record(accounts, b"lockup", mint, wallet)
+}
+
+/// Loads the record of `wallet` for `mint` derived from `seed`, such as its
+/// market maker registration or agreement acceptance, from `accounts`, if it
+/// was passed and exists.
+fn find_wallet_record<T: AccountDeserialize>(
+    accounts: &[AccountInfo],
+    seed: &[u8],
+    mint: &Pubkey,
+    wallet: &Pubkey,
+) -> Result<Option<T>> {
+    let address =
+        Pubkey::find_program_address(&[seed, mint.as_ref(), wallet.as_ref()], &crate::ID).0;
+    match accounts.iter().find(|account| account.key() == address) {
+        Some(account) => load_optional_account(account),
+        None => Ok(None),
+    }
+}
+
+/// Loads the transfer request of `from` to `to` for `mint` from `accounts`, with
+/// the account holding it, if it was passed and exists. Meta lists written
+/// before transfer approvals pass no request until migrated.
+fn find_transfer_request<'a, 'info>(
+    accounts: &'a [AccountInfo<'info>],
+    mint: &Pubkey,
+    from: &Pubkey,
+    to: &Pubkey,
+) -> Result<Option<(&'a AccountInfo<'info>, TransferRequest)>> {
+    let address = Pubkey::find_program_address(
+        &[
+            b"transfer-request",
+            mint.as_ref(),
+            from.as_ref(),
+            to.as_ref(),
+        ],
+        &crate::ID,
+    )
+    .0;
+    let Some(account) = accounts.iter().find(|account| account.key() == address) else {
+        return Ok(None);
+    };
+    Ok(load_optional_account(account)?.map(|request| (account, request)))
+}
+
+/// Loads the due bill of `holder` for `mint` from `accounts`, with the account
+/// holding it, if it was passed and opened. Meta lists written before per-holder
+/// due bills pass none until migrated.
+fn find_due_bill<'a, 'info>(
+    accounts: &'a [AccountInfo<'info>],
+    mint: &Pubkey,
+    holder: &Pubkey,
+) -> Result<Option<(&'a AccountInfo<'info>, DueBill)>> {
+    let address =
+        Pubkey::find_program_address(&[b"due-bill", mint.as_ref(), holder.as_ref()], &crate::ID).0;
+    let Some(account) = accounts.iter().find(|account| account.key() == address) else {
+        return Ok(None);
+    };
+    Ok(load_optional_account(account)?.map(|due_bill| (account, due_bill)))
+}
+
+/// Appends the receipt of the transfer `check` accepted after `evaluations` to
+/// the receipt log stored in `receipts_info`, if the issuer enabled receipts.
+fn record_transfer_receipt(
+    receipts_info: &AccountInfo,
+    check: &TransferCheck,
+    evaluations: &[RuleEvaluation],
+) -> Result<()> {
+    let Some(mut receipts) = load_optional_account::<TransferReceiptLog>(receipts_info)? else {
+        return Ok(());
+    };
+    let receipt = TransferReceipt {
+        sequence: receipts.next_sequence,
+        slot: check.now.slot,
+        unix_timestamp: check.now.unix_timestamp,
+        from: check.source_owner,
+        to: check.destination_owner,
+        amount: check.amount,
+        rule_ids: evaluations
+            .iter()
+            .take(8)
+            .map(|evaluation| evaluation.rule_id)
+            .collect(),
+    };
+    emit!(TransferReceiptRecorded {
+        mint: check.mint,
+        receipt: receipt.clone(),
+    });
+    receipts.record(receipt);
+    receipts.try_serialize(&mut &mut receipts_info.try_borrow_mut_data()?[..])
+}
+
+/// Records a transfer on the holder statement stored in `statement_info`, with
+/// the hash of its memo if any, when the holder opened its statement.
+fn record_statement_activity(
+    statement_info: &AccountInfo,
+    received: u64,
+    sent: u64,
+    now: &Now,
+    memo_hash: Option<[u8; 32]>,
+) -> Result<()> {
+    let Some(mut statement) = load_optional_account::<HolderStatement>(statement_info)? else {
+        return Ok(());
+    };
+    statement.record_transfer(received, sent, now.unix_timestamp);
+    if let Some(memo_hash) = memo_hash {
+        statement.last_memo_hash = memo_hash;
+    }
+    statement.try_serialize(&mut &mut statement_info.try_borrow_mut_data()?[..])
+}
+
+/// Loads the due bill ledger of `mint` from `accounts`, if it was passed and exists.
+fn find_due_bill_ledger(accounts: &[AccountInfo], mint: &Pubkey) -> Result<Option<DueBillLedger>> {
+    let address = Pubkey::find_program_address(&[b"due-bills", mint.as_ref()], &crate::ID).0;
+    match accounts.iter().find(|account| account.key() == address) {
+        Some(account) => load_optional_account(account),
+        None => Ok(None),
+    }
+}
+
+/// Records on the due bills of `seller` and `buyer`, taken from `accounts`, the
+/// due bill a transfer of `amount` at `slot` creates under `ledger`, if any.
+/// Both due bills must have been opened.
+fn record_due_bills(
+    ledger: &DueBillLedger,
+    accounts: &[AccountInfo],
+    seller: Pubkey,
+    buyer: Pubkey,
+    amount: u64,
+    slot: u64,
+) -> Result<()> {
+    let Some((creditor, debtor, units)) = ledger.due_bill(seller, buyer, amount, slot)? else {
+        return Ok(());
+    };
+    for (holder, units) in [(creditor, units), (debtor, -units)] {
+        let (due_bill_info, mut due_bill) = find_due_bill(accounts, &ledger.mint, &holder)?
+            .ok_or(error!(TokenManagerError::DueBillNotOpened))?;
+        due_bill.adjust(ledger.action_id, units)?;
+        due_bill.try_serialize(&mut &mut due_bill_info.try_borrow_mut_data()?[..])?;
+    }
+    Ok(())
+}
+
+/// Sets `field` of the token metadata stored in `mint` to `value`, signed by the
+/// mint as its own update authority.
+fn update_metadata_field<'info>(
+    token_program: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    mint_signer: &[&[&[u8]]],
+    field: MetadataField,
+    value: String,
+) -> Result<()> {
+    let update_field_ix = spl_token_metadata_interface::instruction::update_field(
+        token_program.key,
+        mint.key,
+        mint.key,
+        field.into(),
+        value,
+    );
+    invoke_signed(
+        &update_field_ix,
+        &[mint.clone(), token_program.clone()],
+        mint_signer,
+    )?;
+    Ok(())
+}
+
+/// Moves the lamports `account` lacks to be rent exempt at its current size from
+/// `payer`, e.g. after a CPI reallocated it.
+fn top_up_rent<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    let required = Rent::get()?.minimum_balance(account.data_len());
+    let missing = required.saturating_sub(account.lamports());
+    if missing > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, missing),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    Ok(())
+}
+
+/// Creates the account at the address derived from `signer_seeds`, owned by this
+/// program with `space` bytes paid by `payer`.
+fn create_program_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    signer_seeds: &[&[u8]],
+    space: usize,
+) -> Result<()> {
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            account.key,
+            Rent::get()?.minimum_balance(space),
+            space as u64,
+            &crate::ID,
+        ),
+        &[payer.clone(), account.clone(), system_program.clone()],
+        &[signer_seeds],
+    )?;
+    Ok(())
+}
+
+/// Closes an account owned by this program, refunding its rent to `destination`.
+fn close_program_account<'info>(
+    account: &AccountInfo<'info>,
+    destination: &AccountInfo<'info>,
+) -> Result<()> {
+    **destination.try_borrow_mut_lamports()? += account.lamports();
+    **account.try_borrow_mut_lamports()? = 0;
+    account.assign(&System::id());
+    account.realloc(0, false)?;
+    Ok(())
+}
+
+/// Address of the corridor between two token managers, in either order.
+fn corridor_address(manager: &Pubkey, other_manager: &Pubkey) -> Pubkey {
+    let (first, second) = if manager < other_manager {
+        (manager, other_manager)
+    } else {
+        (other_manager, manager)
+    };
+    Pubkey::find_program_address(&[b"corridor", first.as_ref(), second.as_ref()], &crate::ID).0
+}
+
+/// Checks that `payer` may pay for an action of `investor`: either the investor
+/// pays itself or `payer` is allowlisted in the manager's relayer registry.
+fn check_sponsor(
+    payer: &Pubkey,
+    investor: &Pubkey,
+    relayer_registry: Option<&RelayerRegistry>,
+) -> Result<()> {
+    if payer == investor
+        || relayer_registry.is_some_and(|registry| registry.relayers.contains(payer))
+    {
+        return Ok(());
+    }
+    Err(error!(TokenManagerError::RelayerNotAllowed))
+}
+
+/// Whether the whitelist entry stored in `entry` exists with an authorization
+/// that has not expired at `now`.
+fn is_whitelisted(entry: &AccountInfo, now: &Now, tolerance: u32) -> Result<bool> {
+    Ok(matches!(
+        load_optional_account::<WhitelistEntry>(entry)?,
+        Some(entry) if entry.expires_at == 0 || !now.may_have_passed(entry.expires_at, tolerance)
+    ))
+}
+
+/// Checks that `claimant` acknowledged the `required` notice of a claim, if any.
+fn check_notice_acknowledged(
+    required: Option<Pubkey>,
+    acknowledgment: Option<&NoticeAcknowledgment>,
+    claimant: &Pubkey,
+) -> Result<()> {
+    let Some(notice) = required else {
+        return Ok(());
+    };
+    if acknowledgment.is_some_and(|acknowledgment| {
+        acknowledgment.notice == notice && acknowledgment.holder == *claimant
+    }) {
+        return Ok(());
+    }
+    Err(error!(TokenManagerError::NoticeNotAcknowledged))
+}
+
+/// Withholding, in basis points, on distributions to the holder of
+/// `certification`: the manager's penalty rate unless the certification was
+/// approved and has not expired.
+fn withholding_bps(
+    token_manager: &TokenManager,
+    certification: Option<&TaxCertification>,
+    now: Now,
+) -> u16 {
+    let certified = certification.is_some_and(|certification| {
+        certification.status == CertificationStatus::Approved
+            && !now.may_have_passed(certification.expiry, token_manager.clock_tolerance_seconds)
+    });
+    if certified {
+        0
+    } else {
+        token_manager.penalty_withholding_bps
+    }
+}
+
+/// Pays `keeper` the bounty for `items` processed by a crank, capped by the funds
+/// left: lamports above the rent reserve of `keeper_rewards`, or the balance of
+/// the reward vault for token bounties.
+fn pay_keeper_bounty<'info>(
+    keeper_rewards: &mut Account<'info, KeeperRewards>,
+    keeper: &AccountInfo<'info>,
+    reward_vault: Option<&InterfaceAccount<'info, TokenAccount>>,
+    keeper_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    reward_mint: Option<&InterfaceAccount<'info, Mint>>,
+    token_program: Option<&Interface<'info, TokenInterface>>,
+    items: u64,
+) -> Result<()> {
+    let owed = keeper_rewards.bounty_per_item.saturating_mul(items);
+    if owed == 0 {
+        return Ok(());
+    }
+
+    let amount = match keeper_rewards.reward_mint {
+        None => {
+            let rewards_info = keeper_rewards.to_account_info();
+            let reserve = Rent::get()?.minimum_balance(rewards_info.data_len());
+            let amount = owed.min(rewards_info.lamports().saturating_sub(reserve));
+            **rewards_info.try_borrow_mut_lamports()? -= amount;
+            **keeper.try_borrow_mut_lamports()? += amount;
+            amount
+        }
+        Some(mint) => {
+            let (Some(reward_vault), Some(keeper_account), Some(reward_mint), Some(token_program)) =
+                (reward_vault, keeper_account, reward_mint, token_program)
+            else {
+                return Err(error!(TokenManagerError::KeeperRewardAccountsRequired));
+            };
+            if reward_mint.key() != mint || keeper_account.mint != mint {
+                return Err(error!(TokenManagerError::InvalidTokenAccount));
+            }
+            let amount = owed.min(reward_vault.amount);
+            let rewards_seeds = &[
+                b"keeper-rewards".as_ref(),
+                keeper_rewards.token_manager.as_ref(),
+                &[keeper_rewards.bump],
+            ];
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    token_program.to_account_info(),
+                    TransferChecked {
+                        from: reward_vault.to_account_info(),
+                        mint: reward_mint.to_account_info(),
+                        to: keeper_account.to_account_info(),
+                        authority: keeper_rewards.to_account_info(),
+                    },
+                    &[&rewards_seeds[..]],
+                ),
+                amount,
+                reward_mint.decimals,
+            )?;
+            amount
+        }
+    };
+
+    keeper_rewards.paid = keeper_rewards.paid.saturating_add(amount);
+    emit!(KeeperRewarded {
+        token_manager: keeper_rewards.token_manager,
+        keeper: keeper.key(),
+        items,
+        amount,
+    });
+    Ok(())
+}
+
+/// Accounts the state of a token is read from; the unchecked ones may not exist.
+struct TokenStateAccounts<'a, 'info> {
+    token_manager: &'a TokenManager,
+    token_share: &'a TokenShare,
+    token_whitelist: &'a TokenWhitelist,
+    token_mint: &'a InterfaceAccount<'info, Mint>,
+    whitelist_checkpoint: &'a AccountInfo<'info>,
+    sanctions_list: &'a AccountInfo<'info>,
+    fee_schedule: &'a AccountInfo<'info>,
+    circuit_breaker: &'a AccountInfo<'info>,
+    debt_instrument: &'a AccountInfo<'info>,
+    compliance_queue: &'a AccountInfo<'info>,
+}
+
+impl TokenStateAccounts<'_, '_> {
+    fn read(&self) -> Result<TokenState> {
+        let compliance_queue = load_optional_account::<ComplianceQueue>(self.compliance_queue)?;
+        Ok(TokenState {
+            token_manager: self.token_manager.clone(),
+            token: self.token_share.clone(),
+            supply: self.token_mint.supply,
+            decimals: self.token_mint.decimals,
+            whitelist_count: self.token_whitelist.count,
+            whitelist_checkpoint: load_optional_account(self.whitelist_checkpoint)?,
+            sanctions_list: load_optional_account(self.sanctions_list)?,
+            fee_schedule: load_optional_account(self.fee_schedule)?,
+            circuit_breaker: load_optional_account(self.circuit_breaker)?,
+            debt_instrument: load_optional_account(self.debt_instrument)?,
+            officers: compliance_queue
+                .as_ref()
+                .map_or(Vec::new(), |queue| queue.officers.clone()),
+            open_tasks: compliance_queue.map_or(0, |queue| queue.tasks.len() as u16),
+        })
+    }
+}
+
+/// Checks that the fees applied are those of the schedule version the client saw.
+fn check_fee_version(version: u32, expected_version: u32) -> Result<()> {
+    if version != expected_version {
+        return Err(error!(TokenManagerError::FeeScheduleChanged));
+    }
+    Ok(())
+}
+
+/// Fee of `bps` basis points on `amount`, rounded down.
+fn fee_on(amount: u64, bps: u16) -> u64 {
+    (u128::from(amount) * u128::from(bps) / 10_000) as u64
+}
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Point in time used by every time-based rule.
+///
+/// Cluster timestamps can drift from wall-clock time, so rules comparing against
+/// a unix timestamp take the manager's clock tolerance and resolve any moment
+/// within the tolerance window in favour of the restriction.
+#[derive(Clone, Copy)]
+pub struct Now {
+    pub unix_timestamp: i64,
+    pub slot: u64,
+}
+
+impl Now {
+    /// Whether `unix_timestamp` has certainly passed; used when a restriction ends.
+    pub fn has_passed(&self, unix_timestamp: i64, tolerance_seconds: u32) -> bool {
+        self.unix_timestamp >= unix_timestamp.saturating_add(i64::from(tolerance_seconds))
+    }
+
+    /// Whether `unix_timestamp` may already have passed; used when a permission ends.
+    pub fn may_have_passed(&self, unix_timestamp: i64, tolerance_seconds: u32) -> bool {
+        self.unix_timestamp
+            .saturating_add(i64::from(tolerance_seconds))
+            >= unix_timestamp
+    }
+}
+
+/// Address of the program-wide mock clock used by `test-clock` builds.
+pub fn mock_clock_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"mock-clock"], &crate::ID).0
+}
+
+/// Returns the current time.
+///
+/// Regular builds always read the `Clock` sysvar. Builds with the `test-clock`
+/// feature look for the mock clock among `accounts` (usually
+/// `ctx.remaining_accounts`) and use it when present, so integration tests can
+/// move time freely on localnet.
+#[allow(unused_variables)]
+pub fn now(accounts: &[AccountInfo]) -> Result<Now> {
+    #[cfg(feature = "test-clock")]
+    {
+        let mock_clock_key = mock_clock_address();
+        if let Some(account) = accounts
+            .iter()
+            .find(|account| account.key() == mock_clock_key && account.owner == &crate::ID)
+        {
+            let mock_clock = MockClock::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+            return Ok(Now {
+                unix_timestamp: mock_clock.unix_timestamp,
+                slot: mock_clock.slot,
+            });
+        }
+    }
+
+    let clock = Clock::get()?;
+    Ok(Now {
+        unix_timestamp: clock.unix_timestamp,
+        slot: clock.slot,
+    })
+}
+
+/// Fixed-length extensions of the mint of every share.
+pub const SHARE_MINT_EXTENSIONS: [ExtensionType; 3] = [
+    ExtensionType::TransferHook,
+    ExtensionType::MetadataPointer,
+    ExtensionType::PermanentDelegate,
+];
+
+/// Name, symbol and URI of the metadata of the share registered under `isin`.
+fn share_metadata(isin: &Isin) -> (String, String, String) {
+    (
+        format!("Security Token {}", isin),
+        isin.to_string(),
+        String::new(),
+    )
+}
+
+// Calculate metadata space based on actual content
+fn calculate_metadata_space(
+    name: &str,
+    symbol: &str,
+    uri: &str,
+    additional_metadata: &[MetadataPair],
+) -> usize {
+    // Base metadata header size (approximate)
+    let header_size = 32;
+
+    // Space for each field includes:
+    // - Field type identifier (1 byte)
+    // - Length prefix (typically 4 bytes)
+    // - The string content itself
+    // - Potential padding for alignment (up to 8 bytes worst case)
+
+    let name_size = 1 + 4 + name.len() + 8;
+    let symbol_size = 1 + 4 + symbol.len() + 8;
+    let uri_size = 1 + 4 + uri.len() + 8;
+
+    // Custom fields are a length-prefixed key followed by a length-prefixed value
+    let additional_metadata_size: usize = additional_metadata
+        .iter()
+        .map(|pair| 4 + pair.key.len() + 4 + pair.value.len())
+        .sum();
+
+    // Add some buffer for additional metadata fields that might be added
+    // (e.g., standard fields like "decimals" or custom fields)
+    let additional_fields_buffer = 256;
+
+    header_size
+        + name_size
+        + symbol_size
+        + uri_size
+        + additional_metadata_size
+        + additional_fields_buffer
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct TokenShare {
+    pub token_manager: Pubkey,
+    pub index: u64,
+    pub isin: Isin,
+    pub mint: Pubkey,
+    pub zero_amount_policy: EdgeCasePolicy,
+    pub account_emptying_policy: EdgeCasePolicy,
+    pub allow_self_transfers: bool,
+    pub require_agreement: bool,
+    /// Holders must accept every new agreement version before sending tokens
+    pub require_reacceptance: bool,
+    /// Wallets must post an incoming consent before receiving tokens
+    pub require_incoming_consent: bool,
+    /// Wallets must be whitelisted to send tokens, not only to receive them
+    pub require_sender_whitelisted: bool,
+    /// Holders may burn their own tokens with `burn_tokens`
+    pub allow_self_redemption: bool,
+    /// Supply issuances may never exceed, for fixed share counts
+    pub max_supply: Option<u64>,
+    /// Number of agreement versions published so far
+    pub agreement_version: u32,
+    /// Hash of the subscription agreement investors must currently accept
+    pub agreement_hash: [u8; 32],
+    /// Daily penalty charged on the cash amount of a failed settlement
+    pub fail_penalty_bps_per_day: u16,
+    /// Days after the intended settlement date from which a buy-in may be declared
+    pub buy_in_after_days: u16,
+    /// Transfer blackouts scheduled around corporate action record dates and
+    /// snapshot captures
+    #[max_len(4)]
+    pub blackouts: Vec<TransferBlackout>,
+    /// Token accounts holding a non-zero balance
+    pub holder_count: u32,
+    /// Number of holders transfers and mints may never exceed
+    pub max_holders: Option<u32>,
+    /// Seconds a second officer has to confirm a whitelist addition proposed by
+    /// another, if additions require four-eyes approval
+    pub whitelist_approval_window: Option<u32>,
+    /// Balance no token account may exceed after a transfer, for regulatory
+    /// ownership thresholds
+    pub max_balance_per_holder: Option<u64>,
+    /// Number of balance snapshots taken, the id of the latest one
+    pub snapshot_count: u64,
+    /// Transfers need a request approved by a compliance officer, used up by the
+    /// transfer it approves
+    pub require_transfer_approval: bool,
+    /// Zeroed space taken over by future fields instead of reallocating the
+    /// account
+    pub _reserved: [u8; 32],
+}
+
+impl TokenShare {
+    /// Schedules `blackout`, dropping those that ended before `slot`.
+    pub fn add_blackout(&mut self, blackout: TransferBlackout, slot: u64) -> Result<()> {
+        self.blackouts.retain(|blackout| blackout.end_slot >= slot);
+        if self.blackouts.len() >= 4 {
+            return Err(error!(TokenManagerError::TransferBlackoutsFull));
+        }
+        self.blackouts.push(blackout);
+        Ok(())
+    }
+
+    /// The blackout freezing balances during the capture of `snapshot`, if it
+    /// was not pruned yet.
+    pub fn snapshot_blackout(&mut self, snapshot: &Snapshot) -> Option<&mut TransferBlackout> {
+        self.blackouts.iter_mut().find(|blackout| {
+            blackout.action_id == snapshot.id && blackout.start_slot == snapshot.slot
+        })
+    }
+
+    /// Whether a split is still being applied, its blackout lasting until then.
+    pub fn split_in_progress(&self) -> bool {
+        self.blackouts
+            .iter()
+            .any(|blackout| blackout.end_slot == u64::MAX)
+    }
+
+    /// Whether a corporate action blackout covers `slot`.
+    pub fn in_blackout(&self, slot: u64) -> bool {
+        self.blackouts
+            .iter()
+            .any(|blackout| blackout.start_slot <= slot && slot <= blackout.end_slot)
+    }
+
+    /// Counts a token account whose balance went from `before` to `after`, failing
+    /// if it becomes a holder beyond `max_holders`.
+    pub fn record_holding(&mut self, before: u64, after: u64) -> Result<()> {
+        if before == 0 && after > 0 {
+            if self
+                .max_holders
+                .is_some_and(|max_holders| self.holder_count >= max_holders)
+            {
+                return Err(error!(TokenManagerError::MaxHoldersReached));
+            }
+            self.holder_count += 1;
+        } else if before > 0 && after == 0 {
+            self.holder_count = self.holder_count.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    /// Checks that issuing `amount` tokens to an account holding `balance` keeps
+    /// the token within its supply cap, from its current `supply`, and its
+    /// per-holder balance cap, and returns the balance after the issuance.
+    pub fn check_issuance(&self, supply: u64, balance: u64, amount: u64) -> Result<u64> {
+        let supply = supply
+            .checked_add(amount)
+            .ok_or(error!(TokenManagerError::AmountOverflow))?;
+        if matches!(self.max_supply, Some(max_supply) if supply > max_supply) {
+            return Err(error!(TokenManagerError::SupplyCapExceeded));
+        }
+        let balance_after = balance
+            .checked_add(amount)
+            .ok_or(error!(TokenManagerError::AmountOverflow))?;
+        if matches!(self.max_balance_per_holder, Some(cap) if balance_after > cap) {
+            return Err(error!(TokenManagerError::HolderCapExceeded));
+        }
+        Ok(balance_after)
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct TransferBlackout {
+    /// Corporate action, or snapshot, the blackout freezes balances for
+    pub action_id: u64,
+    pub start_slot: u64,
+    /// Last slot of the blackout, the payment slot of the action
+    pub end_slot: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct CorporateAction {
+    pub mint: Pubkey,
+    pub id: u64,
+    pub ex_slot: u64,
+    pub record_slot: u64,
+    pub payment_slot: u64,
+    /// Slots before the record slot from which transfers are blocked
+    pub blackout_slots: u64,
+    /// Convention of the due bills tracked by the hook, if any
+    pub due_bills: Option<DueBillConvention>,
+}
+
+/// Dates and options of a corporate action.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CorporateActionTerms {
+    pub ex_slot: u64,
+    pub record_slot: u64,
+    pub payment_slot: u64,
+    /// Slots before the record slot from which transfers are blocked, 0 for none
+    pub blackout_slots: u64,
+    /// Convention of the due bills tracked by the hook, `None` for none
+    pub due_bills: Option<DueBillConvention>,
+}
+
+/// Who is entitled to the distribution on tokens traded between the ex slot and
+/// the payment slot.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum DueBillConvention {
+    /// The seller keeps the distribution on tokens sold before the record slot
+    SellerKeeps,
+    /// The buyer receives the distribution on tokens bought after the record slot
+    BuyerReceives,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct DueBillLedger {
+    pub mint: Pubkey,
+    /// Corporate action the due bills belong to
+    pub action_id: u64,
+    /// Convention of the tracked action, `None` when no action is tracked
+    pub convention: Option<DueBillConvention>,
+    pub ex_slot: u64,
+    pub record_slot: u64,
+    pub payment_slot: u64,
+}
+
+impl DueBillLedger {
+    /// Creditor, debtor and token units of the due bill created by a transfer of
+    /// `amount` from `seller` to `buyer` at `slot`, if the convention asks for
+    /// one. Transfers before the record slot move the entitlement back to the
+    /// seller when the seller keeps it; transfers after it move it to the buyer
+    /// when the buyer receives it.
+    pub fn due_bill(
+        &self,
+        seller: Pubkey,
+        buyer: Pubkey,
+        amount: u64,
+        slot: u64,
+    ) -> Result<Option<(Pubkey, Pubkey, i64)>> {
+        let Some(convention) = self.convention else {
+            return Ok(None);
+        };
+        if seller == buyer || slot < self.ex_slot || slot > self.payment_slot {
+            return Ok(None);
+        }
+        let (creditor, debtor) = match convention {
+            DueBillConvention::SellerKeeps if slot < self.record_slot => (seller, buyer),
+            DueBillConvention::BuyerReceives if slot >= self.record_slot => (buyer, seller),
+            _ => return Ok(None),
+        };
+        let amount =
+            i64::try_from(amount).map_err(|_| error!(TokenManagerError::AmountOverflow))?;
+        Ok(Some((creditor, debtor, amount)))
+    }
+}
+
+/// Due bill of a holder, kept across corporate actions and reset when the
+/// hook records the first due bill of a new one.
+#[account]
+#[derive(InitSpace)]
+pub struct DueBill {
+    pub mint: Pubkey,
+    pub holder: Pubkey,
+    /// Corporate action the balance belongs to
+    pub action_id: u64,
+    /// Token units whose distribution the holder is owed, negative when it owes it
+    pub balance: i64,
+    pub bump: u8,
+}
+
+impl DueBill {
+    /// Due bill balance for the corporate action `action_id`, in token units.
+    pub fn balance(&self, action_id: u64) -> i64 {
+        if self.action_id == action_id {
+            self.balance
+        } else {
+            0
+        }
+    }
+
+    /// Removes and returns the balance for `action_id`, so that the claim of the
+    /// distribution it adjusts consumes it once.
+    pub fn consume(&mut self, action_id: u64) -> i64 {
+        let balance = self.balance(action_id);
+        self.balance = 0;
+        self.action_id = action_id;
+        balance
+    }
+
+    fn adjust(&mut self, action_id: u64, amount: i64) -> Result<()> {
+        self.balance = self
+            .balance(action_id)
+            .checked_add(amount)
+            .ok_or(error!(TokenManagerError::AmountOverflow))?;
+        self.action_id = action_id;
+        Ok(())
+    }
+}
+
+/// Distribution of a corporate action, paid from an escrow the issuer funds.
+#[account]
+#[derive(InitSpace)]
+pub struct Distribution {
+    pub mint: Pubkey,
+    /// Corporate action paying the distribution
+    pub action_id: u64,
+    /// Snapshot of the record date balances the distribution is paid on
+    pub snapshot: Pubkey,
+    pub payout_mint: Pubkey,
+    /// Index of the share of the same manager the distribution is paid in, minted
+    /// at claim time, `None` for a distribution paid from an escrow
+    pub payout_token_index: Option<u64>,
+    /// Payout mint units paid per whole token
+    pub rate: u64,
+    /// Decimals of the token, defining a whole token
+    pub token_decimals: u8,
+    pub record_slot: u64,
+    pub payment_slot: u64,
+    /// Payout mint units deposited into the escrow
+    pub funded: u64,
+    /// Payout mint units claimed by holders
+    pub claimed: u64,
+    /// Payout mint units withheld from holders without a valid tax certification
+    pub withheld: u64,
+    /// Notice holders must acknowledge before claiming, if any
+    pub required_notice: Option<Pubkey>,
+    /// Rate of the payout mint in the reporting currency at creation, if the
+    /// mint has an oracle
+    pub fx_snapshot: Option<FxSnapshot>,
+    pub bump: u8,
+}
+
+impl Distribution {
+    /// Token units a claim is paid on: the record date `balance` of the holder,
+    /// adjusted by its due bill for the action, which is consumed.
+    pub fn claimable_units(
+        &self,
+        now: &Now,
+        balance: u64,
+        due_bill: Option<&mut DueBill>,
+    ) -> Result<u64> {
+        if now.slot < self.record_slot || now.slot > self.payment_slot {
+            return Err(error!(TokenManagerError::DistributionNotClaimable));
+        }
+        let due_bill = due_bill.map_or(0, |due_bill| due_bill.consume(self.action_id));
+        let units = i128::from(balance) + i128::from(due_bill);
+        u64::try_from(units.max(0)).map_err(|_| error!(TokenManagerError::AmountOverflow))
+    }
+
+    /// Payout mint units owed on `units` token units, rounded down.
+    pub fn payout(&self, units: u64) -> Result<u64> {
+        let payout =
+            u128::from(units) * u128::from(self.rate) / 10u128.pow(u32::from(self.token_decimals));
+        u64::try_from(payout).map_err(|_| error!(TokenManagerError::AmountOverflow))
+    }
+
+    /// Payout mint units paid and withheld on `units` token units at a
+    /// withholding of `withholding_bps`, the withholding rounded down.
+    pub fn net_payout(&self, units: u64, withholding_bps: u16) -> Result<(u64, u64)> {
+        let payout = self.payout(units)?;
+        let withheld = (u128::from(payout) * u128::from(withholding_bps) / 10_000) as u64;
+        Ok((payout - withheld, withheld))
+    }
+}
+
+/// Balances of a token at a point in time, recorded account by account.
+#[account]
+#[derive(InitSpace)]
+pub struct Snapshot {
+    pub mint: Pubkey,
+    pub id: u64,
+    pub taken_at: i64,
+    pub slot: u64,
+    /// Last slot at which balances may be recorded, before transfers resume
+    pub capture_end_slot: u64,
+    /// Supply of the token when the snapshot was taken
+    pub supply: u64,
+    /// Sum of the balances recorded so far
+    pub recorded_supply: u64,
+    /// Number of token accounts recorded
+    pub entries: u32,
+}
+
+impl Snapshot {
+    /// Whether every token unit of the supply has been recorded.
+    pub fn is_complete(&self) -> bool {
+        self.recorded_supply == self.supply
+    }
+
+    /// Fails unless the snapshot is complete and balances were frozen at
+    /// `record_slot`, within its capture window.
+    pub fn require_record_balances(&self, record_slot: u64) -> Result<()> {
+        if !self.is_complete() {
+            return Err(error!(TokenManagerError::SnapshotIncomplete));
+        }
+        if record_slot < self.slot || record_slot > self.capture_end_slot {
+            return Err(error!(TokenManagerError::RecordBalancesNotFixed));
+        }
+        Ok(())
+    }
+}
+
+/// Balance of a token account in a snapshot.
+#[account]
+#[derive(InitSpace)]
+pub struct SnapshotEntry {
+    pub snapshot: Pubkey,
+    /// Position of the entry in the order balances were recorded
+    pub index: u32,
+    pub token_account: Pubkey,
+    pub owner: Pubkey,
+    pub balance: u64,
+}
+
+/// Split of the shares of a token, applied to the balances of a snapshot.
+#[account]
+#[derive(InitSpace)]
+pub struct Split {
+    pub mint: Pubkey,
+    pub snapshot_id: u64,
+    /// New shares for every `denominator` shares held
+    pub numerator: u64,
+    pub denominator: u64,
+    /// Index of the next snapshot entry to apply the split to
+    pub next_entry: u32,
+    /// Supply of the token with the split applied to the entries so far
+    pub supply: u64,
+}
+
+impl Split {
+    /// Balance `balance` shares become after the split, rounded down.
+    pub fn apply(&self, balance: u64) -> Result<u64> {
+        let split = u128::from(balance) * u128::from(self.numerator) / u128::from(self.denominator);
+        u64::try_from(split).map_err(|_| error!(TokenManagerError::AmountOverflow))
+    }
+}
+
+/// Claim of a holder on a distribution.
+#[account]
+#[derive(InitSpace)]
+pub struct DistributionClaim {
+    pub distribution: Pubkey,
+    pub holder: Pubkey,
+    /// Token units the holder was paid on
+    pub units: u64,
+    /// Payout mint units paid
+    pub amount: u64,
+    /// Payout mint units withheld
+    pub withheld: u64,
+}
+
+/// How the transfer hook treats a transfer edge case.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum EdgeCasePolicy {
+    /// Run the regular transfer rules
+    Evaluate,
+    /// Accept without evaluating the other rules, except for transfers emptying
+    /// their source, which still run the regular rules
+    Allow,
+    /// Always reject
+    Deny,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct AgreementAcceptance {
+    pub mint: Pubkey,
+    pub investor: Pubkey,
+    pub agreement_hash: [u8; 32],
+    pub agreement_version: u32,
+    pub accepted_at: i64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct IncomingConsent {
+    pub mint: Pubkey,
+    pub investor: Pubkey,
+    pub accepted_at: i64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct MarketMaker {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+    pub max_inventory: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct NettingCycle {
+    pub mint: Pubkey,
+    pub venue: Pubkey,
+    pub payment_mint: Pubkey,
+    #[max_len(16)]
+    pub obligations: Vec<TradeObligation>,
+    /// Version of the fee schedule pinned when the cycle opened, 0 for none
+    pub fee_version: u32,
+    /// Transfer fee of the cash legs, pinned when the cycle opened
+    pub transfer_fee_bps: u16,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct SettlementFail {
+    pub mint: Pubkey,
+    pub venue: Pubkey,
+    pub id: u64,
+    pub failing_party: Pubkey,
+    pub counterparty: Pubkey,
+    pub payment_mint: Pubkey,
+    pub quantity: u64,
+    pub cash_amount: u64,
+    pub intended_settlement: i64,
+    /// Moment penalties stopped accruing because of a buy-in
+    pub buy_in_declared_at: Option<i64>,
+}
+
+impl SettlementFail {
+    /// Penalty accrued by full days of delay until `until`, in payment mint units.
+    pub fn accrued_penalty(&self, until: i64, penalty_bps_per_day: u16) -> u64 {
+        let days_late = until.saturating_sub(self.intended_settlement).max(0) / SECONDS_PER_DAY;
+        let penalty =
+            u128::from(self.cash_amount) * u128::from(penalty_bps_per_day) * days_late as u128
+                / 10_000;
+        u64::try_from(penalty).unwrap_or(u64::MAX)
+    }
+}
+
+/// Delivery-versus-payment trade, escrowing the payment of the buyer until the
+/// seller delivers the tokens.
+#[account]
+#[derive(InitSpace)]
+pub struct DvpTrade {
+    pub mint: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub id: u64,
+    /// Token units delivered by the seller
+    pub amount: u64,
+    /// Mint the trade is paid in, `None` for SOL
+    pub payment_mint: Option<Pubkey>,
+    /// Payment escrowed by the buyer, in payment mint units or lamports
+    pub payment: u64,
+    pub expires_at: i64,
+    pub bump: u8,
+}
+
+/// Transfer a holder asked to make of a token requiring approved transfers,
+/// allowed once a compliance officer approved it.
+#[account]
+#[derive(InitSpace)]
+pub struct TransferRequest {
+    pub mint: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    /// Token units the holder asked to transfer
+    pub amount: u64,
+    pub requested_at: i64,
+    /// Compliance officer who approved the transfer, `None` while pending; the
+    /// compliance queue when approved by SLA escalation
+    pub approved_by: Option<Pubkey>,
+    /// Whether a transfer used the approval, which holds for a single transfer
+    pub consumed: bool,
+    /// Task of the request in the compliance queue, if the manager has one
+    pub task_id: Option<u64>,
+    pub bump: u8,
+}
+
+impl TransferRequest {
+    /// Whether the request approves a transfer of `amount` token units.
+    pub fn approves(&self, amount: u64) -> bool {
+        self.approved_by.is_some() && !self.consumed && self.amount == amount
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Offer {
+    pub mint: Pubkey,
+    pub id: u64,
+    pub kind: OfferKind,
+    pub payment_mint: Pubkey,
+    /// Payment mint units per token unit
+    pub price: u64,
+    /// Token units issued or bought back at most
+    pub cap: u64,
+    pub total_requested: u64,
+    /// Orders placed, numbering them in placement order
+    pub order_count: u64,
+    /// Orders settled, through `settle_order` or `settle_orders`
+    pub settled_orders: u64,
+    pub closes_at: i64,
+    /// Rate of the payment mint in the reporting currency, taken at the first settlement
+    pub fx_snapshot: Option<FxSnapshot>,
+    /// Fees pinned from the fee schedule when the offer was created
+    pub fees: OfferFees,
+    pub bump: u8,
+}
+
+/// Fees of an offer, pinned from the fee schedule of its manager.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct OfferFees {
+    /// Version of the fee schedule the fees were taken from, 0 for none
+    pub schedule_version: u32,
+    /// Subscription or redemption fee, depending on the kind of the offer
+    pub fee_bps: u16,
+    /// Flat fee charged once per order, in payment mint units
+    pub admin_fee: u64,
+}
+
+impl Offer {
+    /// Cash paid for `amount` token units.
+    pub fn cash_for(&self, amount: u64) -> Result<u64> {
+        amount
+            .checked_mul(self.price)
+            .ok_or(error!(TokenManagerError::AmountOverflow))
+    }
+
+    /// Token units of a `requested` order that are filled. Orders are filled in
+    /// full until the cap is reached, then pro-rata to the cap rounded down, so
+    /// that the fills never add up to more than the cap. The units lost to
+    /// rounding stay unissued for subscriptions and with the holders for buybacks.
+    pub fn filled(&self, requested: u64) -> u64 {
+        if self.total_requested <= self.cap {
+            return requested;
+        }
+        (u128::from(requested) * u128::from(self.cap) / u128::from(self.total_requested)) as u64
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct OfferOrder {
+    pub offer: Pubkey,
+    pub investor: Pubkey,
+    /// Position of the order among those of the offer, used as crank cursor
+    pub index: u64,
+    /// Token units subscribed or tendered
+    pub requested: u64,
+    /// Subscription fees deposited with the order, in payment mint units
+    pub fees_paid: u64,
+    /// Admin fee deposited with the first order, in payment mint units
+    pub admin_fee: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct TreasuryYield {
+    pub token_manager: Pubkey,
+    /// Wallet allowed to trigger an emergency recall
+    pub guardian: Pubkey,
+    /// Set by an emergency recall, blocking sweeps until lifted by the creator
+    pub emergency_recall: bool,
+    #[max_len(4)]
+    pub programs: Vec<YieldProgram>,
+}
+
+impl TreasuryYield {
+    pub fn program(&self, program_id: &Pubkey) -> Option<&YieldProgram> {
+        self.programs
+            .iter()
+            .find(|program| program.program_id == *program_id)
+    }
+
+    pub fn program_mut(&mut self, program_id: &Pubkey) -> Option<&mut YieldProgram> {
+        self.programs
+            .iter_mut()
+            .find(|program| program.program_id == *program_id)
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct YieldProgram {
+    pub program_id: Pubkey,
+    /// Payment mint units that may be deposited at most
+    pub cap: u64,
+    /// Payment mint units currently deposited, across all vaults
+    pub deposited: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct HolderStatement {
+    pub mint: Pubkey,
+    pub holder: Pubkey,
+    /// Token units received, through transfers or issuance
+    pub total_received: u64,
+    /// Token units sent, through transfers or buybacks
+    pub total_sent: u64,
+    /// Payment mint units received from distributions
+    pub distributions_claimed: u64,
+    pub last_activity: i64,
+    /// SHA-256 of the memo of the last transfer or settlement that carried one,
+    /// zero until then
+    pub last_memo_hash: [u8; 32],
+}
+
+impl HolderStatement {
+    /// Adds token units received and sent at `unix_timestamp`.
+    pub fn record_transfer(&mut self, received: u64, sent: u64, unix_timestamp: i64) {
+        self.total_received = self.total_received.saturating_add(received);
+        self.total_sent = self.total_sent.saturating_add(sent);
+        self.last_activity = unix_timestamp;
+    }
+
+    /// Adds a distribution of `amount` payment mint units claimed at `unix_timestamp`.
+    pub fn record_distribution(&mut self, amount: u64, unix_timestamp: i64) {
+        self.distributions_claimed = self.distributions_claimed.saturating_add(amount);
+        self.last_activity = unix_timestamp;
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct SanctionsList {
+    pub token_manager: Pubkey,
+    /// Screening provider maintaining the list
+    pub provider: Pubkey,
+    pub updated_slot: u64,
+    /// Bloom filter of the sanctioned wallets, see `SanctionsList::bit_indexes`
+    pub filter: [u8; 512],
+}
+
+impl SanctionsList {
+    /// Number of bits set per wallet in the filter.
+    const HASHES: usize = 3;
+
+    /// Bits of the filter a wallet sets: the first three little-endian `u16`s of
+    /// `sha256("sanctions" || wallet)`, modulo the filter size in bits.
+    fn bit_indexes(wallet: &Pubkey) -> [usize; Self::HASHES] {
+        let hash = anchor_lang::solana_program::hash::hashv(&[b"sanctions", wallet.as_ref()]);
+        let bytes = hash.to_bytes();
+        let mut indexes = [0; Self::HASHES];
+        for (i, index) in indexes.iter_mut().enumerate() {
+            *index = u16::from_le_bytes([bytes[2 * i], bytes[2 * i + 1]]) as usize % (512 * 8);
+        }
+        indexes
+    }
+
+    /// Whether `wallet` may be on the list. Bloom filters have no false negatives;
+    /// false positives are resolved by the provider off-chain.
+    pub fn contains(&self, wallet: &Pubkey) -> bool {
+        Self::bit_indexes(wallet)
+            .iter()
+            .all(|index| self.filter[index / 8] & (1 << (index % 8)) != 0)
+    }
+
+    pub fn insert(&mut self, wallet: &Pubkey) {
+        for index in Self::bit_indexes(wallet) {
+            self.filter[index / 8] |= 1 << (index % 8);
+        }
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct UnderlyingAsset {
+    /// Mint of the share issued against the asset
+    pub mint: Pubkey,
+    pub nft_mint: Pubkey,
+    /// Holder who burnt the whole supply to take the NFT back
+    pub redeemed_by: Option<Pubkey>,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct CollateralAttestation {
+    pub mint: Pubkey,
+    pub custodian: Pubkey,
+    #[max_len(200)]
+    pub attestation_uri: String,
+    pub hash: [u8; 32],
+    pub value: u64,
+    pub as_of: i64,
+    /// Seconds an attestation stays fresh after `as_of`, 0 for no limit
+    pub staleness_seconds: u32,
+    pub pause_issuance_when_stale: bool,
+}
+
+impl CollateralAttestation {
+    /// Whether the attestation may have outlived its staleness window.
+    pub fn is_stale(&self, now: &Now, tolerance_seconds: u32) -> bool {
+        self.staleness_seconds > 0
+            && now.may_have_passed(
+                self.as_of.saturating_add(i64::from(self.staleness_seconds)),
+                tolerance_seconds,
+            )
+    }
+}
+
+/// How days between two dates are counted when accruing interest.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum DayCountConvention {
+    /// Actual days over a 360-day year
+    Act360,
+    /// Actual days over a 365-day year
+    Act365,
+    /// 30-day months over a 360-day year, bond basis
+    Thirty360,
+}
+
+impl DayCountConvention {
+    /// Days counted between `from` and `to`.
+    pub fn days(self, from: i64, to: i64) -> i64 {
+        match self {
+            Self::Act360 | Self::Act365 => {
+                (to.div_euclid(SECONDS_PER_DAY) - from.div_euclid(SECONDS_PER_DAY)).max(0)
+            }
+            Self::Thirty360 => {
+                let (from_year, from_month, from_day) = civil_date(from);
+                let (to_year, to_month, to_day) = civil_date(to);
+                let from_day = from_day.min(30);
+                let to_day = if from_day == 30 {
+                    to_day.min(30)
+                } else {
+                    to_day
+                };
+                (360 * (to_year - from_year) + 30 * (to_month - from_month) + to_day - from_day)
+                    .max(0)
+            }
+        }
+    }
+
+    /// Days in a year.
+    pub fn year_days(self) -> i64 {
+        match self {
+            Self::Act365 => 365,
+            Self::Act360 | Self::Thirty360 => 360,
+        }
+    }
+}
+
+/// Terms of an interest-bearing instrument.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct DebtTerms {
+    /// Yearly interest rate
+    pub coupon_rate_bps: u16,
+    pub day_count: DayCountConvention,
+    pub issued_at: i64,
+    pub maturity: i64,
+    /// Months between two coupon dates, from 1 to 12
+    pub coupon_interval_months: u8,
+    /// Days after a missed payment before the instrument defaults
+    pub grace_period_days: u16,
+}
+
+/// Credit state of a debt instrument.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum CreditState {
+    /// Every payment due so far was funded
+    Performing,
+    /// A payment due was not funded and its grace period is running
+    GracePeriod,
+    /// A payment was still unfunded at the end of its grace period
+    Default,
+    /// The defaulted terms were replaced by restructured ones
+    Restructured,
+}
+
+impl CreditState {
+    /// Whether tokens of an instrument in this state may change hands. Defaulted
+    /// instruments are frozen until restructured, and restructured ones may only
+    /// be exchanged for the new instrument.
+    pub fn allows_transfers(self) -> bool {
+        matches!(self, Self::Performing | Self::GracePeriod)
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct DebtInstrument {
+    pub mint: Pubkey,
+    /// Yearly interest rate
+    pub coupon_rate_bps: u16,
+    pub day_count: DayCountConvention,
+    pub issued_at: i64,
+    pub maturity: i64,
+    /// Months between two coupon dates
+    pub coupon_interval_months: u8,
+    /// Days after a missed payment before the instrument defaults
+    pub grace_period_days: u16,
+    /// The mint coupons and redemptions are paid in
+    pub payment_mint: Pubkey,
+    pub state: CreditState,
+    /// Number of the next coupon to service
+    pub next_coupon: u32,
+    /// Payment mint units paid into the vault so far
+    pub funded: u64,
+    /// Payment mint units of coupons and redemptions serviced so far
+    pub serviced: u64,
+    pub bump: u8,
+}
+
+impl DebtInstrument {
+    /// Date of the coupon `number`, the first being 1 and the last falling at
+    /// maturity; 0 is the issue date.
+    pub fn coupon_date(&self, number: u32) -> i64 {
+        let months = i64::from(number) * i64::from(self.coupon_interval_months);
+        add_months(self.issued_at, months).min(self.maturity)
+    }
+
+    /// Interest on `principal` between `from` and `to`, in token units.
+    pub fn interest(&self, principal: u64, from: i64, to: i64) -> Result<u64> {
+        let days = self.day_count.days(from, to);
+        let interest = u128::from(principal) * u128::from(self.coupon_rate_bps) * days as u128
+            / (10_000 * self.day_count.year_days() as u128);
+        u64::try_from(interest).map_err(|_| error!(TokenManagerError::AmountOverflow))
+    }
+
+    /// Amount of the coupon `number` on `principal`, in token units.
+    pub fn coupon_amount(&self, principal: u64, number: u32) -> Result<u64> {
+        if number == 0 {
+            return Ok(0);
+        }
+        self.interest(
+            principal,
+            self.coupon_date(number - 1),
+            self.coupon_date(number),
+        )
+    }
+
+    /// Whether the coupon `number` falls at maturity, with the redemption.
+    pub fn is_final_coupon(&self, number: u32) -> bool {
+        self.coupon_date(number) >= self.maturity
+    }
+
+    /// Whether every coupon and the redemption were serviced.
+    pub fn is_repaid(&self) -> bool {
+        self.next_coupon > 1 && self.is_final_coupon(self.next_coupon - 1)
+    }
+
+    /// Amount due on `principal` at the coupon `number`, including the principal
+    /// itself at maturity, in payment mint units.
+    pub fn amount_due(&self, principal: u64, number: u32) -> Result<u64> {
+        let coupon = self.coupon_amount(principal, number)?;
+        if !self.is_final_coupon(number) {
+            return Ok(coupon);
+        }
+        coupon
+            .checked_add(principal)
+            .ok_or(error!(TokenManagerError::AmountOverflow))
+    }
+
+    /// Moves the instrument to `state`, announcing the credit event.
+    pub fn transition(&mut self, state: CreditState) {
+        if self.state == state {
+            return;
+        }
+        emit!(CreditStateChanged {
+            mint: self.mint,
+            previous: self.state,
+            state,
+            coupon: self.next_coupon,
+        });
+        self.state = state;
+    }
+
+    /// Interest accrued on `principal` from the last coupon date until `now`.
+    pub fn accrued_interest(&self, principal: u64, now: i64) -> Result<u64> {
+        if now <= self.issued_at || now >= self.maturity {
+            return Ok(0);
+        }
+        let mut number = 0;
+        while self.coupon_date(number + 1) <= now {
+            number += 1;
+        }
+        self.interest(principal, self.coupon_date(number), now)
+    }
+}
+
+/// Terms of a restructuring proposal.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RestructuringTerms {
+    /// Share of the principal written off in the exchange
+    pub haircut_bps: u16,
+    /// Maturity of the new instrument
+    pub new_maturity: i64,
+    /// Share of the votes cast every class must reach to approve
+    pub threshold_bps: u16,
+    pub voting_ends_at: i64,
+    /// Notice holders must acknowledge before exchanging, if any
+    pub required_notice: Option<Pubkey>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum RestructuringStatus {
+    Voting,
+    Approved,
+    Rejected,
+}
+
+/// Votes cast by the holders of one investor class, in token units.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct ClassTally {
+    pub investor_class: u8,
+    pub votes_for: u64,
+    pub votes_against: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Restructuring {
+    pub mint: Pubkey,
+    /// Number of the proposal, incremented every time a rejected one is replaced
+    pub round: u32,
+    #[max_len(12)]
+    pub new_isin: Isin,
+    pub new_mint: Pubkey,
+    pub haircut_bps: u16,
+    pub new_maturity: i64,
+    pub threshold_bps: u16,
+    pub voting_ends_at: i64,
+    pub status: RestructuringStatus,
+    /// Snapshot of the balances holders vote with
+    pub snapshot: Pubkey,
+    #[max_len(8)]
+    pub tallies: Vec<ClassTally>,
+    /// Notice holders must acknowledge before exchanging, if any
+    pub required_notice: Option<Pubkey>,
+}
+
+impl Restructuring {
+    /// Counts a vote of `weight` token units in `investor_class`.
+    pub fn record_vote(&mut self, investor_class: u8, approve: bool, weight: u64) -> Result<()> {
+        let index = match self
+            .tallies
+            .iter()
+            .position(|tally| tally.investor_class == investor_class)
+        {
+            Some(index) => index,
+            None => {
+                if self.tallies.len() >= 8 {
+                    return Err(error!(TokenManagerError::RestructuringClassesFull));
+                }
+                self.tallies.push(ClassTally {
+                    investor_class,
+                    votes_for: 0,
+                    votes_against: 0,
+                });
+                self.tallies.len() - 1
+            }
+        };
+        let tally = &mut self.tallies[index];
+        let votes = if approve {
+            &mut tally.votes_for
+        } else {
+            &mut tally.votes_against
+        };
+        *votes = votes
+            .checked_add(weight)
+            .ok_or(error!(TokenManagerError::AmountOverflow))?;
+        Ok(())
+    }
+
+    /// Whether every class that voted reached the threshold.
+    pub fn is_approved(&self) -> bool {
+        !self.tallies.is_empty()
+            && self.tallies.iter().all(|tally| {
+                let cast = u128::from(tally.votes_for) + u128::from(tally.votes_against);
+                cast > 0
+                    && u128::from(tally.votes_for) * 10_000 >= u128::from(self.threshold_bps) * cast
+            })
+    }
+
+    /// Token units of the new instrument received for `amount` exchanged.
+    pub fn exchanged_amount(&self, amount: u64) -> u64 {
+        let kept = u128::from(amount) * u128::from(10_000 - self.haircut_bps) / 10_000;
+        kept as u64
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct RestructuringVote {
+    pub restructuring: Pubkey,
+    pub round: u32,
+    pub holder: Pubkey,
+    pub investor_class: u8,
+    pub approve: bool,
+    pub weight: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum NoticeCategory {
+    Meeting,
+    ConsentSolicitation,
+    CorporateAction,
+    TaxForm,
+    Other,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Notice {
+    pub mint: Pubkey,
+    pub category: NoticeCategory,
+    #[max_len(200)]
+    pub uri: String,
+    /// Hash of the document at `uri`
+    pub hash: [u8; 32],
+    pub posted_at: i64,
+    /// Moment after which the notice may no longer be acknowledged
+    pub expiry: i64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct NoticeAcknowledgment {
+    pub notice: Pubkey,
+    pub holder: Pubkey,
+    pub acknowledged_at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum TaxFormType {
+    W9,
+    W8Ben,
+    W8BenE,
+    /// CRS self-certification
+    CrsSelfCertification,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum CertificationStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct TaxCertification {
+    pub token_manager: Pubkey,
+    pub holder: Pubkey,
+    pub form_type: TaxFormType,
+    /// Hash of the signed form, kept off-chain
+    pub hash: [u8; 32],
+    pub expiry: i64,
+    pub submitted_at: i64,
+    pub status: CertificationStatus,
+    /// Officer who approved or rejected the certification
+    pub reviewed_by: Option<Pubkey>,
+}
+
+/// Fees charged by a token manager, rates in basis points.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct FeeTerms {
+    pub subscription_fee_bps: u16,
+    pub redemption_fee_bps: u16,
+    /// Charged on the cash legs of settlements
+    pub transfer_fee_bps: u16,
+    /// Flat fee charged once per offer order, in payment mint units
+    pub admin_fee: u64,
+}
+
+impl FeeTerms {
+    pub fn validate(&self) -> Result<()> {
+        let rates = [
+            self.subscription_fee_bps,
+            self.redemption_fee_bps,
+            self.transfer_fee_bps,
+        ];
+        if rates.iter().any(|bps| *bps > 10_000) {
+            return Err(error!(TokenManagerError::InvalidFeeSchedule));
+        }
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct FeeSchedule {
+    pub token_manager: Pubkey,
+    /// Incremented by every update, pinned by the offers using it
+    pub version: u32,
+    pub terms: FeeTerms,
+}
+
+/// Batch process a crank state tracks.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum CrankKind {
+    /// Settlement of the orders of an offer
+    OfferSettlement,
+}
+
+/// Progress of a permissionless batch process over many accounts, so that it can
+/// be carried out across many transactions.
+#[account]
+#[derive(InitSpace)]
+pub struct CrankState {
+    /// Account the process runs on, such as an offer
+    pub subject: Pubkey,
+    pub kind: CrankKind,
+    /// Index of the first item left to process
+    pub cursor: u64,
+    /// Items processed so far
+    pub processed: u64,
+    /// Set once every item was processed
+    pub completed: bool,
+}
+
+/// Anomaly counted by a circuit breaker.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum AnomalyKind {
+    RejectedTransfer,
+    ForceTransfer,
+    WhitelistRemoval,
+}
+
+/// ISIN of a token, kept as its twelve ASCII characters so that it costs no
+/// length prefix and accounts can be filtered on it with a single memcmp.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Isin(pub [u8; 12]);
+
+impl Space for Isin {
+    const INIT_SPACE: usize = 12;
+}
+
+impl Isin {
+    /// Bytes of the ISIN, used as seed of the addresses of its token
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Checks the ISO 6166 format of the ISIN: a two-letter country code, nine
+    /// letters or digits and the Luhn check digit of the digits of the first
+    /// eleven characters, letters counting as 10 to 35.
+    pub fn validate(&self) -> Result<()> {
+        let (country, rest) = self.0.split_at(2);
+        if !country.iter().all(u8::is_ascii_uppercase)
+            || !rest[..9]
+                .iter()
+                .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit())
+            || !rest[9].is_ascii_digit()
+        {
+            return Err(error!(TokenManagerError::InvalidIsinFormat));
+        }
+
+        let mut digits = Vec::with_capacity(22);
+        for &byte in &self.0[..11] {
+            if byte.is_ascii_digit() {
+                digits.push(byte - b'0');
+            } else {
+                let value = byte - b'A' + 10;
+                digits.extend([value / 10, value % 10]);
+            }
+        }
+        // Every other digit is doubled, starting from the one next to the check digit
+        let sum: u32 = digits
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(position, &digit)| {
+                let digit = u32::from(digit) << (1 - position % 2);
+                digit / 10 + digit % 10
+            })
+            .sum();
+        if (10 - sum % 10) % 10 != u32::from(self.0[11] - b'0') {
+            return Err(error!(TokenManagerError::InvalidIsinChecksum));
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&str> for Isin {
+    type Error = anchor_lang::error::Error;
+
+    fn try_from(isin: &str) -> Result<Self> {
+        let isin = Isin(
+            isin.as_bytes()
+                .try_into()
+                .map_err(|_| error!(TokenManagerError::InvalidIsinLength))?,
+        );
+        isin.validate()?;
+        Ok(isin)
+    }
+}
+
+impl std::fmt::Display for Isin {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&String::from_utf8_lossy(&self.0))
+    }
+}
+
+/// Arguments of `create_share`. The variant index is the version byte the program
+/// dispatches on; a new version repeats the fields of the previous one and adds
+/// its own, which older versions default.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub enum CreateShareArgs {
+    V1 {
+        decimals: u8,
+        isin: Isin,
+    },
+    V2 {
+        decimals: u8,
+        isin: Isin,
+        /// Supply `mint_tokens` may never exceed, `None` for no cap
+        max_supply: Option<u64>,
+    },
+    V3 {
+        decimals: u8,
+        isin: Isin,
+        max_supply: Option<u64>,
+        /// Custom fields written to the token metadata, e.g. the issuer name
+        additional_metadata: Vec<MetadataPair>,
+    },
+}
+
+impl CreateShareArgs {
+    pub fn isin(&self) -> &Isin {
+        match self {
+            Self::V1 { isin, .. } | Self::V2 { isin, .. } | Self::V3 { isin, .. } => isin,
+        }
+    }
+
+    /// Returns the decimals, ISIN, maximum supply and custom metadata fields of the
+    /// share, defaulting the fields older versions lack.
+    pub fn into_latest(self) -> (u8, Isin, Option<u64>, Vec<MetadataPair>) {
+        match self {
+            Self::V1 { decimals, isin } => (decimals, isin, None, Vec::new()),
+            Self::V2 {
+                decimals,
+                isin,
+                max_supply,
+            } => (decimals, isin, max_supply, Vec::new()),
+            Self::V3 {
+                decimals,
+                isin,
+                max_supply,
+                additional_metadata,
+            } => (decimals, isin, max_supply, additional_metadata),
+        }
+    }
+}
+
+/// Long-running operation that may hold the operation lock of a token.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum TokenOperation {
+    CorporateAction,
+    Distribution,
+    Restructuring,
+    Split,
+    Migration,
+}
+
+/// Long-running operation in progress on a token, stored at
+/// `[b"operation-lock", mint]`.
+#[account]
+#[derive(InitSpace)]
+pub struct OperationLock {
+    pub mint: Pubkey,
+    pub operation: TokenOperation,
+    pub acquired_at: i64,
+    pub acquired_slot: u64,
+}
+
+/// Permission the creator of a token manager may delegate to other wallets.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// Mints tokens without a mint session
+    Minter,
+    /// Manages whitelists and claws tokens back
+    ComplianceOfficer,
+    /// Freezes and thaws holder accounts
+    Pauser,
+    /// Pushes reference data from the numbering agency into token metadata
+    DataProvider,
+}
+
+impl Role {
+    pub fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Roles a wallet holds on a token manager, stored at
+/// `[b"role", token_manager, holder]`.
+#[account]
+#[derive(InitSpace)]
+pub struct RoleAssignment {
+    pub token_manager: Pubkey,
+    pub holder: Pubkey,
+    /// One bit per `Role`
+    pub roles: u8,
+}
+
+impl RoleAssignment {
+    pub fn has(&self, role: Role) -> bool {
+        self.roles & role.bit() != 0
+    }
+}
+
+/// Time-limited minting allowance of an operator, stored at
+/// `[b"mint-session", mint, operator]`.
+#[account]
+#[derive(InitSpace)]
+pub struct MintSession {
+    pub mint: Pubkey,
+    pub operator: Pubkey,
+    pub max_amount: u64,
+    /// Amount minted so far within the session
+    pub minted: u64,
+    /// Unix timestamp from which the session can no longer mint
+    pub expiry: i64,
+}
+
+/// Daily burning allowance of an operator, stored at
+/// `[b"burn-session", mint, operator]`.
+#[account]
+#[derive(InitSpace)]
+pub struct BurnSession {
+    pub mint: Pubkey,
+    pub operator: Pubkey,
+    pub daily_cap: u64,
+    /// UTC day, in days since the Unix epoch, `burned_today` refers to
+    pub day: i64,
+    pub burned_today: u64,
+    /// Unix timestamp from which the session can no longer burn
+    pub expiry: i64,
+}
+
+/// External transfer hook program a token calls once its own rules pass, stored
+/// at `[b"chained-hook", mint]`.
+#[account]
+#[derive(InitSpace)]
+pub struct ChainedHook {
+    pub mint: Pubkey,
+    pub program: Pubkey,
+    /// Most compute units the external program may consume per transfer
+    pub max_compute_units: u32,
+}
+
+/// Pauses the transfers of a token when anomalies pile up.
+#[account]
+#[derive(InitSpace)]
+pub struct CircuitBreaker {
+    pub mint: Pubkey,
+    /// Wallet reviewing the alert before transfers resume
+    pub guardian: Pubkey,
+    /// Length of the counting window, in slots
+    pub window_slots: u64,
+    /// Anomalies tripping the breaker within a window, indexed by `AnomalyKind`, 0 for no limit
+    pub thresholds: [u32; 3],
+    /// Slot the current counting window started at
+    pub window_start: u64,
+    /// Anomalies counted in the current window, indexed by `AnomalyKind`
+    pub counts: [u32; 3],
+    /// Slot the breaker tripped at, pausing transfers until the guardian resumes them
+    pub tripped_at: Option<u64>,
+}
+
+impl CircuitBreaker {
+    /// Counts `count` anomalies of `kind` at `slot`, in a new window once the
+    /// current one elapsed, and trips the breaker when the threshold is reached.
+    pub fn record(&mut self, kind: AnomalyKind, count: u32, slot: u64) {
+        if slot >= self.window_start.saturating_add(self.window_slots) {
+            self.window_start = slot;
+            self.counts = [0; 3];
+        }
+        let index = kind as usize;
+        self.counts[index] = self.counts[index].saturating_add(count);
+
+        let threshold = self.thresholds[index];
+        if self.tripped_at.is_none() && threshold > 0 && self.counts[index] >= threshold {
+            self.tripped_at = Some(slot);
+            emit!(CircuitBreakerTripped {
+                mint: self.mint,
+                kind,
+                count: self.counts[index],
+            });
+            log_structured(
+                constants::LOG_CIRCUIT_BREAKER_TRIPPED,
+                &[
+                    ("mint", self.mint.to_string()),
+                    ("anomaly", index.to_string()),
+                    ("count", self.counts[index].to_string()),
+                ],
+            );
+        }
+    }
+}
+
+/// Bounty a token manager pays keepers for every item its cranks process.
+#[account]
+#[derive(InitSpace)]
+pub struct KeeperRewards {
+    pub token_manager: Pubkey,
+    /// Bounty per item, in lamports or in units of `reward_mint`
+    pub bounty_per_item: u64,
+    /// Mint token bounties are paid in from the keeper vault, lamports when unset
+    pub reward_mint: Option<Pubkey>,
+    /// Bounties paid so far, in the same unit
+    pub paid: u64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct TrustedVault {
+    pub mint: Pubkey,
+    /// Program-derived address owning the vault token account
+    pub vault: Pubkey,
+}
+
+/// Authorization of a wallet to receive a token, stored at
+/// `[b"whitelist", mint, wallet]` so the hook derives it from the destination owner.
+/// Data linked to the person behind the wallet lives in its `PersonalData` instead.
+/// Tokens of a wallet that may not leave its accounts before their release,
+/// stored at `[b"lockup", mint, wallet]`.
+#[account]
+#[derive(InitSpace)]
+pub struct Lockup {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+    /// Tokens locked when the lockup was set
+    pub locked_amount: u64,
+    /// Tranches by increasing release time, summing to `locked_amount`
+    #[max_len(16)]
+    pub release_schedule: Vec<ReleaseTranche>,
+}
+
+impl Lockup {
+    /// Tokens still locked at `now`; tranches are only released once their time
+    /// has certainly passed.
+    pub fn locked(&self, now: &Now, tolerance_seconds: u32) -> u64 {
+        let released: u64 = self
+            .release_schedule
+            .iter()
+            .filter(|tranche| now.has_passed(tranche.release_at, tolerance_seconds))
+            .map(|tranche| tranche.amount)
+            .sum();
+        self.locked_amount.saturating_sub(released)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct ReleaseTranche {
+    pub release_at: i64,
+    pub amount: u64,
+}
+
+/// Whitelist addition proposed by one officer, awaiting the confirmation of
+/// another.
+#[account]
+#[derive(InitSpace)]
+pub struct WhitelistProposal {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+    pub proposer: Pubkey,
+    /// Expiry of the authorization once confirmed, 0 for none
+    pub expires_at: i64,
+    pub proposed_at: i64,
+}
+
+/// Wallets whitelisted for a token whose key starts with `prefix`, kept sorted.
+#[account]
+#[derive(InitSpace)]
+pub struct WhitelistBucket {
+    pub mint: Pubkey,
+    pub prefix: u8,
+    #[max_len(32)]
+    pub wallets: Vec<Pubkey>,
+}
+
+impl WhitelistBucket {
+    pub fn contains(&self, wallet: &Pubkey) -> bool {
+        self.wallets.binary_search(wallet).is_ok()
+    }
+
+    /// Authorization the hook grants a wallet of the bucket.
+    pub fn entry(&self, wallet: &Pubkey) -> WhitelistEntry {
+        WhitelistEntry {
+            mint: self.mint,
+            wallet: *wallet,
+            corridor: None,
+            jurisdiction: [0; 2],
+            investor_class: 0,
+            expires_at: 0,
+            _reserved: [0; 24],
+        }
+    }
+
+    /// Adds `wallet`, returning whether it was not in the bucket yet.
+    pub fn insert(&mut self, wallet: Pubkey) -> Result<bool> {
+        let Err(index) = self.wallets.binary_search(&wallet) else {
+            return Ok(false);
+        };
+        if self.wallets.len() >= 32 {
+            return Err(error!(TokenManagerError::WhitelistBucketFull));
+        }
+        self.wallets.insert(index, wallet);
+        Ok(true)
+    }
+
+    /// Removes `wallet`, returning whether it was in the bucket.
+    pub fn remove(&mut self, wallet: &Pubkey) -> bool {
+        match self.wallets.binary_search(wallet) {
+            Ok(index) => {
+                self.wallets.remove(index);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct WhitelistEntry {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+    /// Partner token manager this authorization was imported from through a corridor
+    pub corridor: Option<Pubkey>,
+    /// ISO 3166-1 alpha-2 country code of the investor, zeroed when unknown
+    pub jurisdiction: [u8; 2],
+    /// Issuer-defined investor class
+    pub investor_class: u8,
+    /// Unix timestamp from which the KYC of the investor has lapsed, 0 for none
+    pub expires_at: i64,
+    /// Zeroed space for future fields
+    pub _reserved: [u8; 24],
+}
+
+/// Data linked to the person behind a whitelisted wallet, stored at
+/// `[b"personal-data", mint, wallet]` apart from the records the register needs so
+/// that it can be purged on request without touching balances or memberships
+#[account]
+#[derive(InitSpace)]
+pub struct PersonalData {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+    /// Jurisdiction of the investor when the record was created, so that the
+    /// records of one country can be listed and purged together
+    pub jurisdiction: [u8; 2],
+    /// Encrypted reference to an off-chain case, never plaintext personal data
+    pub note: Option<[u8; 64]>,
+}
+
+/// Criterion holders must satisfy to be frozen by `freeze_by_filter`; every set
+/// attribute must match.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct HolderFilter {
+    pub jurisdiction: Option<[u8; 2]>,
+    pub investor_class: Option<u8>,
+}
+
+impl HolderFilter {
+    pub fn matches(&self, entry: &WhitelistEntry) -> bool {
+        (self.jurisdiction.is_none() || self.jurisdiction == Some(entry.jurisdiction))
+            && (self.investor_class.is_none() || self.investor_class == Some(entry.investor_class))
+    }
+}
+
+/// Custom field of the token metadata, such as the issuer name, jurisdiction or
+/// prospectus URI a security token must carry on-chain.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MetadataPair {
+    pub key: String,
+    pub value: String,
+}
+
+/// Field of the token metadata set by `update_token_metadata`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum MetadataField {
+    Name,
+    Symbol,
+    Uri,
+    /// Custom field stored in the additional metadata under this key
+    Key(String),
+}
+
+impl From<MetadataField> for spl_token_metadata_interface::state::Field {
+    fn from(field: MetadataField) -> Self {
+        match field {
+            MetadataField::Name => Self::Name,
+            MetadataField::Symbol => Self::Symbol,
+            MetadataField::Uri => Self::Uri,
+            MetadataField::Key(key) => Self::Key(key),
+        }
+    }
+}
+
+/// Standardized reference data of an ISIN pushed by `push_reference_data`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ReferenceData {
+    /// Issue date, as `YYYY-MM-DD`
+    pub issue_date: Option<String>,
+    /// ISO 4217 code of the currency of denomination
+    pub currency: Option<String>,
+    /// Coupon rate in basis points, for debt instruments
+    pub coupon_rate_bps: Option<u32>,
+}
+
+impl ReferenceData {
+    /// Metadata keys and values of the fields that are set, failing if one is
+    /// malformed.
+    pub fn fields(&self) -> Result<Vec<(&'static str, String)>> {
+        let mut fields = Vec::new();
+        if let Some(issue_date) = &self.issue_date {
+            let bytes = issue_date.as_bytes();
+            let is_date = bytes.len() == 10
+                && bytes.iter().enumerate().all(|(i, byte)| match i {
+                    4 | 7 => *byte == b'-',
+                    _ => byte.is_ascii_digit(),
+                });
+            if !is_date {
+                return Err(error!(TokenManagerError::InvalidReferenceData));
+            }
+            fields.push(("issue_date", issue_date.clone()));
+        }
+        if let Some(currency) = &self.currency {
+            if currency.len() != 3 || !currency.bytes().all(|byte| byte.is_ascii_uppercase()) {
+                return Err(error!(TokenManagerError::InvalidReferenceData));
+            }
+            fields.push(("currency", currency.clone()));
+        }
+        if let Some(coupon_rate_bps) = self.coupon_rate_bps {
+            fields.push(("coupon_rate_bps", coupon_rate_bps.to_string()));
+        }
+        Ok(fields)
+    }
+}
+
+/// Whitelist of a token; the authorized wallets are `WhitelistEntry` accounts,
+/// so the whitelist itself only counts them
+#[account]
+#[derive(InitSpace)]
+pub struct TokenWhitelist {
+    pub token_manager: Pubkey,
+    pub mint: Pubkey,
+    /// Number of `WhitelistEntry` accounts of the token
+    pub count: u32,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct WhitelistHistory {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+    #[max_len(16)]
+    pub periods: Vec<MembershipPeriod>,
+}
+
+impl WhitelistHistory {
+    /// Opens a membership period starting at `slot`. Once 16 periods are kept, the
+    /// oldest one is dropped, so membership can no longer be proven before the
+    /// start of the oldest period left.
+    pub fn record_added(&mut self, mint: Pubkey, wallet: Pubkey, slot: u64) {
+        if self.periods.len() >= 16 {
+            self.periods.remove(0);
+        }
+
+        self.mint = mint;
+        self.wallet = wallet;
+        self.periods.push(MembershipPeriod {
+            added_slot: slot,
+            removed_slot: None,
+        });
+    }
+
+    /// Closes the open membership period, leaving it as a tombstone.
+    pub fn record_removed(&mut self, slot: u64) {
+        if let Some(period) = self.periods.last_mut() {
+            if period.removed_slot.is_none() {
+                period.removed_slot = Some(slot);
+            }
+        }
+    }
+
+    pub fn was_member_at(&self, slot: u64) -> bool {
+        self.periods.iter().any(|period| {
+            period.added_slot <= slot
+                && !matches!(period.removed_slot, Some(removed) if removed <= slot)
+        })
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct MembershipPeriod {
+    pub added_slot: u64,
+    /// Slot of the removal; `None` while the wallet is still whitelisted
+    pub removed_slot: Option<u64>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Corridor {
+    /// Token manager with the lower key
+    pub first_manager: Pubkey,
+    /// Token manager with the higher key
+    pub second_manager: Pubkey,
+    pub opened_at: i64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct TokenManager {
+    pub creator: Pubkey,
+    pub current_token_index: u64,
+    /// Rules temporarily skipped by the transfer hook
+    #[max_len(8)]
+    pub suspended_modules: Vec<ModuleSuspension>,
+    /// Allowed drift of the cluster timestamp, see `Now`
+    pub clock_tolerance_seconds: u32,
+    /// Platform program whose PDA is the creator, for platform-operated managers
+    pub platform: Option<Pubkey>,
+    /// Withholding applied to distributions of holders without a valid tax
+    /// certification
+    pub penalty_withholding_bps: u16,
+    /// Creator the address of the manager is derived from, kept when the creator
+    /// changes
+    pub original_creator: Pubkey,
+    /// Creator proposed by `propose_new_creator`, until it accepts
+    pub pending_creator: Option<Pubkey>,
+    /// Compliance requirements of the shares the manager creates
+    pub compliance_defaults: ComplianceDefaults,
+    /// Wallet allowed to lock the whole manager down with `lockdown`
+    pub lockdown_guardian: Option<Pubkey>,
+    /// Time the manager was locked down at, while it is
+    pub locked_down_at: Option<i64>,
+    /// Zeroed space taken over by future fields instead of reallocating the
+    /// account
+    pub _reserved: [u8; 18],
+}
+
+/// Compliance requirements a new share starts with, which can then be changed
+/// per share.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct ComplianceDefaults {
+    pub require_agreement: bool,
+    pub require_reacceptance: bool,
+    pub require_incoming_consent: bool,
+    pub require_sender_whitelisted: bool,
+}
+
+/// Configuration of a token manager set by `initialize_token_manager`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct ManagerConfig {
+    pub compliance_defaults: ComplianceDefaults,
+    /// Display name of the issuer profile, at most 64 bytes
+    pub display_name: Option<String>,
+    /// First version of the fee schedule
+    pub fee_terms: Option<FeeTerms>,
+    /// Wallet allowed to trigger an emergency recall of the treasury
+    pub guardian: Option<Pubkey>,
+}
+
+impl TokenManager {
+    /// Fails while the manager is locked down.
+    pub fn require_unlocked(&self) -> Result<()> {
+        if self.locked_down_at.is_some() {
+            return Err(error!(TokenManagerError::ManagerLockedDown));
+        }
+        Ok(())
+    }
+
+    pub fn is_module_suspended(&self, rule_id: u16, slot: u64) -> bool {
+        !constants::NON_SUSPENDABLE_RULES.contains(&rule_id)
+            && self
+                .suspended_modules
+                .iter()
+                .any(|suspension| suspension.rule_id == rule_id && slot < suspension.until_slot)
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Platform {
+    pub program: Pubkey,
+    pub registered_by: Pubkey,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct ModuleSuspension {
+    /// `constants::RULE_*` id of the suspended rule
+    pub rule_id: u16,
+    /// First slot at which the rule applies again
+    pub until_slot: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct RuleDescription {
+    pub rule_id: u16,
+    #[max_len(64)]
+    pub description: String,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Rulebook {
+    pub token_manager: Pubkey,
+    #[max_len(16)]
+    pub rules: Vec<RuleDescription>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct IssuerProfile {
+    pub token_manager: Pubkey,
+    #[max_len(64)]
+    pub display_name: String,
+    #[max_len(128)]
+    pub website: String,
+    #[max_len(200)]
+    pub logo_uri: String,
+}
+
+/// Identifiers of a token in the off-chain systems servicing it.
+#[account]
+#[derive(InitSpace)]
+pub struct RegistryIdentifiers {
+    pub mint: Pubkey,
+    /// Account number of the issue at its transfer agent
+    #[max_len(34)]
+    pub transfer_agent_account: String,
+    /// Identifier of the issue at its central securities depository
+    #[max_len(32)]
+    pub csd_identifier: String,
+    /// SWIFT BIC of the paying agent of the issue
+    #[max_len(11)]
+    pub paying_agent_bic: String,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct RelayerRegistry {
+    pub token_manager: Pubkey,
+    #[max_len(8)]
+    pub relayers: Vec<Pubkey>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct PaymentMintRegistry {
+    pub token_manager: Pubkey,
+    /// ISO 4217 code of the currency the issuer reports in
+    pub reporting_currency: [u8; 3],
+    /// Age beyond which a rate feed may not be snapshotted, zero for no limit
+    pub fx_rate_max_age_seconds: u32,
+    #[max_len(8)]
+    pub mints: Vec<PaymentMint>,
+}
+
+impl PaymentMintRegistry {
+    /// Whether `mint` is approved, with the decimals it was approved with.
+    pub fn is_approved(&self, mint: &InterfaceAccount<Mint>) -> bool {
+        self.mints.iter().any(|payment_mint| {
+            payment_mint.mint == mint.key() && payment_mint.decimals == mint.decimals
+        })
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct FxRateFeed {
+    /// Oracle publishing the rate
+    pub authority: Pubkey,
+    pub payment_mint: Pubkey,
+    /// ISO 4217 code of the currency the rate is quoted in
+    pub currency: [u8; 3],
+    /// Value of one whole payment token, scaled by `10^decimals`
+    pub rate: u64,
+    pub decimals: u8,
+    pub published_at: i64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct FxSnapshot {
+    pub currency: [u8; 3],
+    pub rate: u64,
+    pub decimals: u8,
+    pub published_at: i64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct PaymentMint {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    /// Price feed valuing the mint
+    pub oracle: Option<Pubkey>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct TransferReceiptLog {
+    pub mint: Pubkey,
+    pub retention_days: u16,
+    pub next_sequence: u64,
+    /// Most recent receipts, the receipt of sequence `n` stored at index
+    /// `n % TransferReceiptLog::CAPACITY`
+    #[max_len(32)]
+    pub receipts: Vec<TransferReceipt>,
+}
+
+impl TransferReceiptLog {
+    pub fn is_expired(&self, receipt: &TransferReceipt, now: &Now, tolerance_seconds: u32) -> bool {
+        let retention = i64::from(self.retention_days) * SECONDS_PER_DAY;
+        now.has_passed(
+            receipt.unix_timestamp.saturating_add(retention),
+            tolerance_seconds,
+        )
+    }
+
+    /// Number of receipts the log holds before overwriting the oldest.
+    pub const CAPACITY: usize = 32;
+
+    /// Records a receipt over the oldest one once the log is full, so a busy
+    /// token never has its transfers rejected for lack of space.
+    pub fn record(&mut self, receipt: TransferReceipt) {
+        let index = (self.next_sequence % Self::CAPACITY as u64) as usize;
+        match self.receipts.get_mut(index) {
+            Some(oldest) => *oldest = receipt,
+            None => self.receipts.push(receipt),
+        }
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+    }
+}
+
+/// Compliance evidence of a single accepted transfer.
+#[account]
+#[derive(InitSpace)]
+pub struct TransferReceipt {
+    pub sequence: u64,
+    pub slot: u64,
+    pub unix_timestamp: i64,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub amount: u64,
+    /// `constants::RULE_*` ids of the rules that passed, in evaluation order
+    #[max_len(8)]
+    pub rule_ids: Vec<u16>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct ComplianceQueue {
+    pub token_manager: Pubkey,
+    #[max_len(8)]
+    pub officers: Vec<Pubkey>,
+    pub next_task_id: u64,
+    /// Tasks by decreasing priority, then by SLA deadline
+    #[max_len(32)]
+    pub tasks: Vec<ComplianceTask>,
+    /// SLA of the tasks of every priority in seconds, indexed by `TaskPriority`, 0 for none
+    pub sla_seconds: [u32; 4],
+    /// What happens to transfer approvals left unanswered past their SLA
+    pub escalation: SlaEscalation,
+    /// Zeroed space for future fields
+    pub _reserved: [u8; 32],
+}
+
+impl ComplianceQueue {
+    /// The creator of the token manager can always act as an officer.
+    pub fn is_officer(&self, wallet: &Pubkey, creator: &Pubkey) -> bool {
+        wallet == creator || self.officers.contains(wallet)
+    }
+
+    /// Queues a new unclaimed task after those of higher priority or due earlier
+    /// and returns its id.
+    pub fn push_task(
+        &mut self,
+        kind: TaskKind,
+        subject: Pubkey,
+        priority: TaskPriority,
+        created_at: i64,
+    ) -> Result<u64> {
+        if self.tasks.len() >= 32 {
+            return Err(error!(TokenManagerError::ComplianceQueueFull));
+        }
+
+        let id = self.next_task_id;
+        self.next_task_id = id
+            .checked_add(1)
+            .ok_or(error!(TokenManagerError::IndexOverflow))?;
+        let sla_seconds = self.sla_seconds[priority as usize];
+        let task = ComplianceTask {
+            id,
+            kind,
+            subject,
+            created_at,
+            claimed_by: None,
+            priority,
+            sla_deadline: (sla_seconds > 0)
+                .then(|| created_at.saturating_add(i64::from(sla_seconds))),
+        };
+        let position = self
+            .tasks
+            .iter()
+            .position(|queued| queued.queue_order() > task.queue_order())
+            .unwrap_or(self.tasks.len());
+        self.tasks.insert(position, task);
+
+        emit!(ComplianceTaskQueued {
+            token_manager: self.token_manager,
+            id,
+            kind,
+            subject,
+        });
+        Ok(id)
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct ComplianceTask {
+    pub id: u64,
+    pub kind: TaskKind,
+    /// The wallet, mint or request account the task is about
+    pub subject: Pubkey,
+    pub created_at: i64,
+    pub claimed_by: Option<Pubkey>,
+    pub priority: TaskPriority,
+    /// Moment the task is overdue, if its priority has an SLA
+    pub sla_deadline: Option<i64>,
+}
+
+impl ComplianceTask {
+    /// Key the queue is sorted by: higher priorities first, then earlier deadlines.
+    fn queue_order(&self) -> (std::cmp::Reverse<TaskPriority>, i64) {
+        (
+            std::cmp::Reverse(self.priority),
+            self.sla_deadline.unwrap_or(i64::MAX),
+        )
+    }
+}
+
+/// Urgency of a compliance task.
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, InitSpace,
+)]
+pub enum TaskPriority {
+    Low,
+    Normal,
+    High,
+    Urgent,
+}
+
+/// Outcome of a transfer approval left unanswered past its SLA.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum SlaEscalation {
+    /// The approval waits for an officer however late
+    None,
+    AutoApprove,
+    AutoReject,
+}
+
+/// What a compliance task asks the officer to look at.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum TaskKind {
+    WhitelistRequest,
+    TransferApproval,
+    FlaggedWallet,
+    ExpiringKyc,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct MockClock {
+    pub authority: Pubkey,
+    pub unix_timestamp: i64,
+    pub slot: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct WhitelistCheckpoint {
+    pub mint: Pubkey,
+    pub sequence: u64,
+    pub merkle_root: [u8; 32],
+    pub count: u32,
+    pub slot: u64,
+    pub unix_timestamp: i64,
+}
+
+/// Latest commitment of the whitelist a token has on another cluster, stored at
+/// `[b"whitelist-mirror", mint]`.
+#[account]
+#[derive(InitSpace)]
+pub struct WhitelistMirror {
+    pub mint: Pubkey,
+    /// Key allowed to post commitments
+    pub attestor: Pubkey,
+    /// Genesis hash of the cluster holding the source whitelist
+    pub source_cluster: [u8; 32],
+    pub sequence: u64,
+    pub merkle_root: [u8; 32],
+    pub count: u32,
+    /// Slot at which the commitment was accepted
+    pub slot: u64,
+}
+
+impl WhitelistMirror {
+    /// Whether `proof` links `leaf` to the committed Merkle root.
+    pub fn contains(&self, leaf: [u8; 32], proof: &[[u8; 32]]) -> bool {
+        if self.count == 0 {
+            return false;
+        }
+        let mut node = leaf;
+        for sibling in proof {
+            node = if node <= *sibling {
+                hashv(&[&node, sibling])
+            } else {
+                hashv(&[sibling, &node])
+            }
+            .to_bytes();
+        }
+        node == self.merkle_root
+    }
+}
+
+/// Authorization of a wallet on the source cluster of a whitelist mirror.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct MirroredAuthorization {
+    pub jurisdiction: [u8; 2],
+    pub investor_class: u8,
+    /// Moment the authorization lapses, 0 for never
+    pub expires_at: i64,
+}
+
+impl MirroredAuthorization {
+    /// Leaf of `wallet` in a mirrored commitment:
+    /// `sha256(wallet || jurisdiction || investor_class || expires_at)`, the
+    /// expiry in little-endian bytes.
+    pub fn leaf(&self, wallet: &Pubkey) -> [u8; 32] {
+        hashv(&[
+            wallet.as_ref(),
+            &self.jurisdiction,
+            &[self.investor_class],
+            &self.expires_at.to_le_bytes(),
+        ])
+        .to_bytes()
+    }
+}
+
+/// Compliance configuration and summary stats of a token, as exported by
+/// `export_state`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct TokenState {
+    /// Manager-wide settings, such as module suspensions and clock tolerance
+    pub token_manager: TokenManager,
+    pub token: TokenShare,
+    pub supply: u64,
+    pub decimals: u8,
+    pub whitelist_count: u32,
+    /// Latest whitelist commitment, the root replicas check their whitelist against
+    pub whitelist_checkpoint: Option<WhitelistCheckpoint>,
+    pub sanctions_list: Option<SanctionsList>,
+    pub fee_schedule: Option<FeeSchedule>,
+    pub circuit_breaker: Option<CircuitBreaker>,
+    pub debt_instrument: Option<DebtInstrument>,
+    /// Compliance officers of the token manager
+    #[max_len(8)]
+    pub officers: Vec<Pubkey>,
+    /// Compliance tasks awaiting an officer
+    pub open_tasks: u16,
+}
+
+impl TokenState {
+    /// `sha256` of the layout version followed by the Borsh serialization of the state.
+    pub fn hash(&self) -> Result<[u8; 32]> {
+        let data = self.try_to_vec()?;
+        Ok(anchor_lang::solana_program::hash::hashv(&[
+            &constants::STATE_EXPORT_VERSION.to_le_bytes(),
+            &data,
+        ])
+        .to_bytes())
+    }
+}
+
+/// Read-only replica of the state of a token, stored at `[b"state-export", mint]`.
+#[account]
+#[derive(InitSpace)]
+pub struct StateExport {
+    pub mint: Pubkey,
+    /// Layout version of `state`, see `constants::STATE_EXPORT_VERSION`
+    pub format_version: u16,
+    /// Number of exports written so far
+    pub sequence: u64,
+    /// Slot the export was written at
+    pub slot: u64,
+    pub state: TokenState,
+}
+
+/// Hash of the state of a token, stored at `[b"state-hash", mint]`.
+#[account]
+#[derive(InitSpace)]
+pub struct StateHash {
+    pub mint: Pubkey,
+    /// Layout version of the hashed `TokenState`, see `constants::STATE_EXPORT_VERSION`
+    pub format_version: u16,
+    pub hash: [u8; 32],
+    /// Slot the hash was computed at
+    pub slot: u64,
+}
+
+#[event]
+pub struct ModuleSuspended {
+    pub token_manager: Pubkey,
+    pub rule_id: u16,
+    pub until_slot: u64,
+}
+
+#[event]
+pub struct ComplianceTaskQueued {
+    pub token_manager: Pubkey,
+    pub id: u64,
+    pub kind: TaskKind,
+    pub subject: Pubkey,
+}
+
+#[event]
+pub struct ComplianceTaskEscalated {
+    pub token_manager: Pubkey,
+    pub id: u64,
+    pub subject: Pubkey,
+    pub approved: bool,
+}
+
+#[event]
+pub struct ComplianceTaskResolved {
+    pub token_manager: Pubkey,
+    pub id: u64,
+    pub officer: Pubkey,
+}
+
+#[event]
+pub struct AgreementVersionPublished {
+    pub mint: Pubkey,
+    pub version: u32,
+    pub agreement_hash: [u8; 32],
+}
+
+#[event]
+pub struct SettlementFailOpened {
+    pub mint: Pubkey,
+    pub id: u64,
+    pub failing_party: Pubkey,
+    pub counterparty: Pubkey,
+}
+
+#[event]
+pub struct BuyInDeclared {
+    pub mint: Pubkey,
+    pub id: u64,
+    pub penalty: u64,
+}
+
+#[event]
+pub struct SettlementFailClosed {
+    pub mint: Pubkey,
+    pub id: u64,
+    pub penalty: u64,
+}
+
+#[event]
+pub struct OfferCreated {
+    pub mint: Pubkey,
+    pub id: u64,
+    pub kind: OfferKind,
+    pub price: u64,
+    pub cap: u64,
+    pub closes_at: i64,
+}
+
+#[event]
+pub struct OfferFunded {
+    pub mint: Pubkey,
+    pub id: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct OfferCashReturned {
+    pub mint: Pubkey,
+    pub id: u64,
+    pub returned: u64,
+}
+
+#[event]
+pub struct OrderPlaced {
+    pub mint: Pubkey,
+    pub id: u64,
+    pub investor: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct OrderSettled {
+    pub mint: Pubkey,
+    pub id: u64,
+    pub investor: Pubkey,
+    pub requested: u64,
+    pub filled: u64,
+    /// Price paid to a tendering holder
+    pub cash_paid: u64,
+    /// Unfilled subscription amount refunded
+    pub refund: u64,
+}
+
+#[event]
+pub struct TreasurySwept {
+    pub offer: Pubkey,
+    pub yield_program: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct TreasuryRecalled {
+    pub offer: Pubkey,
+    pub yield_program: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct EmergencyRecallTriggered {
+    pub token_manager: Pubkey,
+    pub guardian: Pubkey,
+}
+
+#[event]
+pub struct EmergencyRecallLifted {
+    pub token_manager: Pubkey,
+    pub guardian: Pubkey,
+}
+
+#[event]
+pub struct CorporateActionDeclared {
+    pub mint: Pubkey,
+    pub id: u64,
+    pub record_slot: u64,
+    pub payment_slot: u64,
+    pub blackout_slots: u64,
+}
+
+#[event]
+pub struct DistributionCreated {
+    pub mint: Pubkey,
+    pub action_id: u64,
+    pub payout_mint: Pubkey,
+    pub rate: u64,
+    pub funded: u64,
+    pub fx_snapshot: Option<FxSnapshot>,
+}
+
+#[event]
+pub struct DistributionClaimed {
+    pub mint: Pubkey,
+    pub action_id: u64,
+    pub holder: Pubkey,
+    pub units: u64,
+    pub amount: u64,
+    pub withheld: u64,
+}
+
+#[event]
+pub struct DistributionClosed {
+    pub mint: Pubkey,
+    pub action_id: u64,
+    pub returned: u64,
+}
+
+#[event]
+pub struct SnapshotCreated {
+    pub mint: Pubkey,
+    pub id: u64,
+    pub taken_at: i64,
+    pub capture_end_slot: u64,
+    pub supply: u64,
+}
+
+#[event]
+pub struct HolderBalanceRecorded {
+    pub mint: Pubkey,
+    pub snapshot_id: u64,
+    pub owner: Pubkey,
+    pub balance: u64,
+}
+
+#[event]
+pub struct SharesSplit {
+    pub mint: Pubkey,
+    pub snapshot_id: u64,
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+#[event]
+pub struct SplitApplied {
+    pub mint: Pubkey,
+    pub snapshot_id: u64,
+    /// Holders the split was applied to by this call
+    pub applied: u32,
+    pub next_entry: u32,
+    /// Whether every holder of the snapshot has been split
+    pub completed: bool,
+}
+
+#[event]
+pub struct SplitCancelled {
+    pub mint: Pubkey,
+    pub snapshot_id: u64,
+    /// Holders the split was applied to before its cancellation
+    pub next_entry: u32,
+}
+
+#[event]
+pub struct DvpOpened {
+    pub mint: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub trade_id: u64,
+    pub amount: u64,
+    pub payment_mint: Option<Pubkey>,
+    pub payment: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct DvpSettled {
+    pub mint: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub trade_id: u64,
+    pub amount: u64,
+    pub payment: u64,
+}
+
+#[event]
+pub struct DvpCancelled {
+    pub mint: Pubkey,
+    pub buyer: Pubkey,
+    pub trade_id: u64,
+    /// Whether the seller declined the trade, rather than the buyer reclaiming
+    /// its payment after expiry
+    pub declined: bool,
+}
+
+#[event]
+pub struct TransferRequested {
+    pub mint: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct TransferApproved {
+    pub mint: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub amount: u64,
+    pub approved_by: Pubkey,
+}
+
+#[event]
+pub struct ApprovedTransferExecuted {
+    pub mint: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct HolderAccountFrozen {
+    pub mint: Pubkey,
+    pub account: Pubkey,
+    pub holder: Pubkey,
+}
+
+#[event]
+pub struct HolderAccountThawed {
+    pub mint: Pubkey,
+    pub account: Pubkey,
+    pub holder: Pubkey,
+}
+
+#[event]
+pub struct HoldersFrozen {
+    pub mint: Pubkey,
+    pub jurisdiction: Option<[u8; 2]>,
+    pub investor_class: Option<u8>,
+    /// Accounts frozen, excluding those already frozen
+    pub accounts: u16,
+}
+
+#[event]
+pub struct SanctionsHit {
+    pub mint: Pubkey,
+    /// The wallet found on the sanctions list
+    pub wallet: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct UnderlyingLinked {
+    pub mint: Pubkey,
+    pub nft_mint: Pubkey,
+}
+
+#[event]
+pub struct UnderlyingRedeemed {
+    pub mint: Pubkey,
+    pub nft_mint: Pubkey,
+    pub holder: Pubkey,
+    pub burnt: u64,
+}
+
+#[event]
+pub struct CollateralAttested {
+    pub mint: Pubkey,
+    pub custodian: Pubkey,
+    pub hash: [u8; 32],
+    pub value: u64,
+    pub as_of: i64,
+}
+
+#[event]
+pub struct FxSnapshotRecorded {
+    pub mint: Pubkey,
+    pub offer_id: u64,
+    pub payment_mint: Pubkey,
+    pub currency: [u8; 3],
+    pub rate: u64,
+    pub decimals: u8,
+    pub published_at: i64,
+}
+
+#[event]
+pub struct DebtInstrumentCreated {
+    pub mint: Pubkey,
+    pub coupon_rate_bps: u16,
+    pub day_count: DayCountConvention,
+    pub issued_at: i64,
+    pub maturity: i64,
+}
+
+#[event]
+pub struct DebtServiceFunded {
+    pub mint: Pubkey,
+    pub amount: u64,
+    /// Payment mint units paid in so far
+    pub funded: u64,
+}
+
+#[event]
+pub struct CouponServiced {
+    pub mint: Pubkey,
+    pub coupon: u32,
+    /// Coupon, and principal at maturity, in payment mint units
+    pub amount: u64,
+}
+
+#[event]
+pub struct CreditStateChanged {
+    pub mint: Pubkey,
+    pub previous: CreditState,
+    pub state: CreditState,
+    /// Number of the coupon the event relates to
+    pub coupon: u32,
+}
+
+#[event]
+pub struct RestructuringProposed {
+    pub mint: Pubkey,
+    pub new_mint: Pubkey,
+    pub round: u32,
+    pub haircut_bps: u16,
+    pub new_maturity: i64,
+    pub voting_ends_at: i64,
+}
+
+#[event]
+pub struct RestructuringVoteCast {
+    pub mint: Pubkey,
+    pub holder: Pubkey,
+    pub investor_class: u8,
+    pub approve: bool,
+    pub weight: u64,
+}
+
+#[event]
+pub struct RestructuringDecided {
+    pub mint: Pubkey,
+    pub round: u32,
+    pub approved: bool,
+}
+
+#[event]
+pub struct RestructuringExchanged {
+    pub mint: Pubkey,
+    pub holder: Pubkey,
+    pub burnt: u64,
+    pub minted: u64,
+}
+
+#[event]
+pub struct NoticePosted {
+    pub mint: Pubkey,
+    pub notice: Pubkey,
+    pub category: NoticeCategory,
+    pub uri: String,
+    pub hash: [u8; 32],
+    pub expiry: i64,
+}
+
+#[event]
+pub struct NoticeAcknowledged {
+    pub mint: Pubkey,
+    pub notice: Pubkey,
+    pub holder: Pubkey,
+}
+
+#[event]
+pub struct TaxCertificationSubmitted {
+    pub token_manager: Pubkey,
+    pub holder: Pubkey,
+    pub form_type: TaxFormType,
+    pub expiry: i64,
+}
+
+#[event]
+pub struct TaxCertificationReviewed {
+    pub token_manager: Pubkey,
+    pub holder: Pubkey,
+    pub approved: bool,
+    pub officer: Pubkey,
+}
+
+#[event]
+pub struct FeeScheduleUpdated {
+    pub token_manager: Pubkey,
+    pub version: u32,
+    pub terms: FeeTerms,
+}
+
+#[event]
+pub struct FeesCharged {
+    pub mint: Pubkey,
+    pub payer: Pubkey,
+    /// In payment mint units
+    pub amount: u64,
+}
+
+#[event]
+pub struct CrankAdvanced {
+    pub subject: Pubkey,
+    pub kind: CrankKind,
+    /// Cursor the next batch starts from
+    pub cursor: u64,
+    /// Items processed by this batch
+    pub processed: u64,
+    pub completed: bool,
+}
+
+#[event]
+pub struct KeeperBountySet {
+    pub token_manager: Pubkey,
+    pub bounty_per_item: u64,
+    pub reward_mint: Option<Pubkey>,
+}
+
+#[event]
+pub struct KeeperRewarded {
+    pub token_manager: Pubkey,
+    pub keeper: Pubkey,
+    pub items: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct CircuitBreakerTripped {
+    pub mint: Pubkey,
+    pub kind: AnomalyKind,
+    /// Anomalies of the kind counted in the window
+    pub count: u32,
+}
+
+#[event]
+pub struct CircuitBreakerReset {
+    pub mint: Pubkey,
+    pub guardian: Pubkey,
+}
+
+#[event]
+pub struct NettingCycleSettled {
+    pub mint: Pubkey,
+    pub venue: Pubkey,
+    pub trades: u8,
+    pub transfers: u8,
+}
+
+#[event]
+pub struct SettlementBatchExecuted {
+    pub mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub legs: u8,
+}
+
+#[event]
+pub struct TransferHookAuthorityChanged {
+    pub mint: Pubkey,
+    pub new_authority: Option<Pubkey>,
+}
+
+#[event]
+pub struct TokenMetadataUpdated {
+    pub mint: Pubkey,
+    pub field: MetadataField,
+    pub value: String,
+}
+
+#[event]
+pub struct RegistryIdentifiersUpdated {
+    pub mint: Pubkey,
+    pub transfer_agent_account: String,
+    pub csd_identifier: String,
+    pub paying_agent_bic: String,
+}
+
+#[event]
+pub struct ExtraAccountMetaListUpdated {
+    pub mint: Pubkey,
+    /// Number of extra accounts the hook now receives
+    pub accounts: u8,
+}
+
+#[event]
+pub struct OfferReceiptsEnabled {
+    pub mint: Pubkey,
+    pub offer: Pubkey,
+    pub receipt_mint: Pubkey,
+}
+
+#[event]
+pub struct TransferHookRemoved {
+    pub mint: Pubkey,
+}
+
+#[event]
+pub struct LockupSet {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+    pub locked_amount: u64,
+}
+
+#[event]
+pub struct WhitelistAdditionProposed {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+    pub proposer: Pubkey,
+}
+
+#[event]
+pub struct WhitelistEntryAdded {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+}
+
+#[event]
+pub struct PersonalDataPurged {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+}
+
+#[event]
+pub struct AuthorizationRenewed {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct WhitelistBucketEntryAdded {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+    pub prefix: u8,
+}
+
+#[event]
+pub struct WhitelistBucketEntryRemoved {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+    pub prefix: u8,
+}
+
+#[event]
+pub struct WhitelistEntryRemoved {
+    pub mint: Pubkey,
+    pub wallet: Pubkey,
+}
+
+#[event]
+pub struct WhitelistCheckpointCommitted {
+    pub mint: Pubkey,
+    pub sequence: u64,
+    pub merkle_root: [u8; 32],
+    pub count: u32,
+    pub slot: u64,
+}
+
+#[event]
+pub struct WhitelistAttestorSet {
+    pub mint: Pubkey,
+    pub attestor: Pubkey,
+    pub source_cluster: [u8; 32],
+}
+
+#[event]
+pub struct WhitelistCommitmentAccepted {
+    pub mint: Pubkey,
+    pub source_cluster: [u8; 32],
+    pub sequence: u64,
+    pub merkle_root: [u8; 32],
+    pub count: u32,
+    pub slot: u64,
+}
+
+#[event]
+pub struct ChainedHookSet {
+    pub mint: Pubkey,
+    pub program: Pubkey,
+    pub max_compute_units: u32,
+}
+
+#[event]
+pub struct ChainedHookRemoved {
+    pub mint: Pubkey,
+    pub program: Pubkey,
+}
+
+#[event]
+pub struct CreatorProposed {
+    pub token_manager: Pubkey,
+    pub creator: Pubkey,
+    pub pending_creator: Option<Pubkey>,
+}
+
+#[event]
+pub struct ManagerLockedDown {
+    pub token_manager: Pubkey,
+    pub guardian: Pubkey,
+    pub locked_down_at: i64,
+    /// Earliest time the creator may lift the lockdown
+    pub unlocks_at: i64,
+}
+
+#[event]
+pub struct ManagerLockdownLifted {
+    pub token_manager: Pubkey,
+    pub creator: Pubkey,
+}
+
+#[event]
+pub struct CreatorChanged {
+    pub token_manager: Pubkey,
+    pub previous_creator: Pubkey,
+    pub creator: Pubkey,
+}
+
+#[event]
+pub struct OperationLockAcquired {
+    pub mint: Pubkey,
+    pub operation: TokenOperation,
+}
+
+#[event]
+pub struct OperationLockReleased {
+    pub mint: Pubkey,
+    pub operation: TokenOperation,
+}
+
+#[event]
+pub struct RoleGranted {
+    pub token_manager: Pubkey,
+    pub holder: Pubkey,
+    pub role: Role,
+}
+
+#[event]
+pub struct RoleRevoked {
+    pub token_manager: Pubkey,
+    pub holder: Pubkey,
+    pub role: Role,
+}
+
+#[event]
+pub struct MintSessionCreated {
+    pub mint: Pubkey,
+    pub operator: Pubkey,
+    pub max_amount: u64,
+    pub expiry: i64,
+}
+
+#[event]
+pub struct MintSessionRevoked {
+    pub mint: Pubkey,
+    pub operator: Pubkey,
+    pub minted: u64,
+}
+
+#[event]
+pub struct TokensForceTransferred {
+    pub mint: Pubkey,
+    pub source: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    /// The creator or compliance officer who ordered the transfer
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct BurnSessionCreated {
+    pub mint: Pubkey,
+    pub operator: Pubkey,
+    pub daily_cap: u64,
+    pub expiry: i64,
+}
+
+#[event]
+pub struct BurnSessionRevoked {
+    pub mint: Pubkey,
+    pub operator: Pubkey,
+}
+
+#[event]
+pub struct ShareCreated {
+    pub token_manager: Pubkey,
+    pub mint: Pubkey,
+    pub isin: Isin,
+    pub index: u64,
+    pub decimals: u8,
+}
+
+#[event]
+pub struct TokensMinted {
+    pub mint: Pubkey,
+    pub holder: Pubkey,
+    pub amount: u64,
+    /// The creator, a minter or the operator of a mint session
+    pub minted_by: Pubkey,
+}
+
+#[event]
+pub struct TokensBurned {
+    pub mint: Pubkey,
+    pub holder: Pubkey,
+    pub amount: u64,
+    /// The creator, the holder when it redeemed its own tokens, or the operator
+    /// of a burn session
+    pub burned_by: Pubkey,
+}
+
+#[event]
+pub struct StateExported {
+    pub mint: Pubkey,
+    pub format_version: u16,
+    pub sequence: u64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct StateHashComputed {
+    pub mint: Pubkey,
+    pub format_version: u16,
+    pub hash: [u8; 32],
+    pub slot: u64,
+}
+
+/// Compliance receipt of an accepted transfer, for archives keeping receipts
+/// beyond the latest ones held by the `TransferReceiptLog` of the mint.
+#[event]
+pub struct TransferReceiptRecorded {
+    pub mint: Pubkey,
+    pub receipt: TransferReceipt,
+}
+
+/// Emitted by the transfer hook right before a transfer is rejected.
+/// `rule_id` identifies the failing rule and can be looked up in the
+/// issuer's `Rulebook` for a human readable explanation.
+#[event]
+pub struct TransferRejected {
+    pub mint: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub amount: u64,
+    pub rule_id: u16,
+}
+
+/// Errors returned by the program.
+///
+/// Every variant carries an explicit discriminant so that the on-chain code
+/// (`constants::ERROR_CODE_OFFSET` + discriminant) never shifts between
+/// releases. New variants must be appended with the next free number and
+/// existing numbers must never be reused.
+#[error_code]
+pub enum TokenManagerError {
+    #[msg("Token not found")]
+    TokenNotFound = 0,
+    #[msg("Wallet not found")]
+    WalletNotFound = 1,
+    #[msg("Transfer not allowed")]
+    TransferNotAllowed = 2,
+    #[msg("Failed to initialize transfer hook")]
+    TransferHookInitFailed = 3,
+    #[msg("Invalid token account")]
+    InvalidTokenAccount = 4,
+    #[msg("Invalid ISIN length")]
+    InvalidIsinLength = 5,
+    #[msg("Unauthorized operation")]
+    Unauthorized = 6,
+    #[msg("Index overflow")]
+    IndexOverflow = 7,
+    #[msg("Whitelist is full")]
+    WhitelistFull = 8,
+    #[msg("Rulebook is full")]
+    RulebookFull = 9,
+    #[msg("Description is too long")]
+    DescriptionTooLong = 10,
+    #[msg("Mock clock requires the test-clock feature")]
+    MockClockDisabled = 11,
+    #[msg("Demo instructions require the devnet-tools feature")]
+    DevnetToolsDisabled = 12,
+    #[msg("Field is too long")]
+    FieldTooLong = 13,
+    #[msg("Whitelist count does not match on-chain entries")]
+    WhitelistCountMismatch = 14,
+    #[msg("Corridor does not link these token managers")]
+    CorridorMismatch = 15,
+    #[msg("Corridor managers must be in ascending key order")]
+    CorridorManagersUnordered = 16,
+    #[msg("Payer is not an allowed relayer")]
+    RelayerNotAllowed = 17,
+    #[msg("Relayer registry is full")]
+    RelayerRegistryFull = 18,
+    #[msg("Agreement does not match the current version")]
+    AgreementMismatch = 19,
+    #[msg("Compliance officer list is full")]
+    ComplianceOfficersFull = 20,
+    #[msg("Compliance queue is full")]
+    ComplianceQueueFull = 21,
+    #[msg("Task not found")]
+    TaskNotFound = 22,
+    #[msg("Task is claimed by another officer")]
+    TaskAlreadyClaimed = 23,
+    #[msg("Whitelist membership history is full")]
+    WhitelistHistoryFull = 24,
+    #[msg("Too many suspended modules")]
+    ModuleSuspensionsFull = 25,
+    #[msg("Transfer receipts are still under retention")]
+    ReceiptsUnderRetention = 26,
+    #[msg("Clock tolerance is too large")]
+    InvalidClockTolerance = 27,
+    #[msg("Creator is not a PDA of the platform program")]
+    NotPlatformAuthority = 28,
+    #[msg("Trusted vaults must be program-derived addresses of this program")]
+    VaultNotPda = 29,
+    #[msg("Too many legs in the settlement batch")]
+    TooManySettlementLegs = 30,
+    #[msg("Settlement leg references an invalid token account")]
+    InvalidSettlementLeg = 31,
+    #[msg("Netting cycle is full")]
+    NettingCycleFull = 32,
+    #[msg("Payment mint differs from the one of the netting cycle")]
+    PaymentMintMismatch = 33,
+    #[msg("Settlement is not late enough for a buy-in")]
+    BuyInNotYetAllowed = 34,
+    #[msg("Buy-in already declared")]
+    BuyInAlreadyDeclared = 35,
+    #[msg("Payment mint is not approved by the issuer")]
+    PaymentMintNotApproved = 36,
+    #[msg("Payment mint registry is full")]
+    PaymentMintRegistryFull = 37,
+    #[msg("Offer is closed")]
+    OfferClosed = 38,
+    #[msg("Offer is still open")]
+    OfferStillOpen = 39,
+    #[msg("Amount overflow")]
+    AmountOverflow = 40,
+    #[msg("Yield program is not allowlisted")]
+    YieldProgramNotAllowed = 41,
+    #[msg("Yield program cap exceeded")]
+    YieldCapExceeded = 42,
+    #[msg("Yield program registry is full")]
+    YieldProgramRegistryFull = 43,
+    #[msg("Emergency recall in force")]
+    EmergencyRecallActive = 44,
+    #[msg("Yield program debited more than the swept amount")]
+    YieldSweepExceeded = 45,
+    #[msg("Invalid corporate action dates")]
+    InvalidCorporateAction = 46,
+    #[msg("Too many scheduled transfer blackouts")]
+    TransferBlackoutsFull = 47,
+    #[msg("Due bills of another corporate action are still tracked")]
+    DueBillLedgerBusy = 48,
+    #[msg(
+        "Both parties must have opened their due bill while a corporate action tracks due bills"
+    )]
+    DueBillNotOpened = 49,
+    #[msg("Holder does not match the filter")]
+    HolderDoesNotMatchFilter = 50,
+    #[msg("Sanctions filter update out of bounds")]
+    InvalidSanctionsFilter = 51,
+    #[msg("Underlying asset must be an NFT")]
+    InvalidUnderlying = 52,
+    #[msg("Redemption requires the whole supply")]
+    FullSupplyRequired = 53,
+    #[msg("Underlying asset already redeemed")]
+    UnderlyingRedeemed = 54,
+    #[msg("Invalid collateral attestation")]
+    InvalidCollateralAttestation = 55,
+    #[msg("Collateral attestation is stale")]
+    StaleCollateral = 56,
+    #[msg("Rate of the payment mint is required")]
+    FxRateRequired = 57,
+    #[msg("Rate feed does not match the oracle of the payment mint")]
+    FxRateMismatch = 58,
+    #[msg("Maturity must follow the issue date and coupons fall 1 to 12 months apart")]
+    InvalidDebtTerms = 59,
+    #[msg("Only defaulted instruments may be restructured")]
+    InstrumentNotInDefault = 60,
+    #[msg("Invalid restructuring terms, or a proposal is already pending or approved")]
+    InvalidRestructuring = 61,
+    #[msg("Voting on the restructuring is closed")]
+    RestructuringVotingClosed = 62,
+    #[msg("Voting on the restructuring is still open")]
+    RestructuringVotingOpen = 63,
+    #[msg("Restructuring was not approved")]
+    RestructuringNotApproved = 64,
+    #[msg("Too many investor classes voted on the restructuring")]
+    RestructuringClassesFull = 65,
+    #[msg("Notice URI is too long or its expiry has passed")]
+    InvalidNotice = 66,
+    #[msg("Notice has expired")]
+    NoticeExpired = 67,
+    #[msg("The required notice was not acknowledged")]
+    NoticeNotAcknowledged = 68,
+    #[msg("Tax certification has expired, is not pending review, or the rate is invalid")]
+    InvalidTaxCertification = 69,
+    #[msg("Fee rates may not exceed 10000 basis points")]
+    InvalidFeeSchedule = 70,
+    #[msg("A fee collector account is required to charge transfer fees")]
+    FeeCollectorRequired = 71,
+    #[msg("Price differs from the one quoted to the client")]
+    PriceChanged = 72,
+    #[msg("Fee schedule differs from the version quoted to the client")]
+    FeeScheduleChanged = 73,
+    #[msg("Crank cursor differs from the progress recorded on chain")]
+    CrankCursorMismatch = 74,
+    #[msg("Crank batches must hold between one and the maximum number of items, in order")]
+    InvalidCrankBatch = 75,
+    #[msg("The reward vault, mint and token program are required for token bounties")]
+    KeeperRewardAccountsRequired = 76,
+    #[msg("Task has not exceeded its SLA yet")]
+    TaskWithinSla = 77,
+    #[msg("Task is not a transfer approval with an SLA escalation configured")]
+    EscalationDisabled = 78,
+    #[msg("Circuit breaker windows must last at least one slot")]
+    InvalidCircuitBreaker = 79,
+    #[msg("Sender is not whitelisted")]
+    SenderNotWhitelisted = 80,
+    #[msg("Chained hook must be another program with a compute budget within the limit")]
+    InvalidChainedHook = 81,
+    #[msg("Accounts of the chained hook are missing from the transfer")]
+    ChainedHookAccountsMissing = 82,
+    #[msg("Chained hook exceeded its compute budget")]
+    ChainedHookOverBudget = 83,
+    #[msg("Whitelist commitment is older than the accepted one")]
+    InvalidWhitelistCommitment = 84,
+    #[msg("Wallet is not part of the committed whitelist")]
+    InvalidWhitelistProof = 85,
+    #[msg("Mint session has expired")]
+    MintSessionExpired = 86,
+    #[msg("Amount exceeds the remaining allowance of the mint session")]
+    MintAllowanceExceeded = 87,
+    #[msg("Burn session has expired")]
+    BurnSessionExpired = 88,
+    #[msg("Amount exceeds the daily cap of the burn session")]
+    BurnAllowanceExceeded = 89,
+    #[msg("Another operation is in progress on this token")]
+    OperationInProgress = 90,
+    #[msg("Minting would exceed the maximum supply of the token")]
+    SupplyCapExceeded = 91,
+    #[msg("Whitelist batches must hold between one and the maximum number of wallets, with their accounts in order")]
+    InvalidWhitelistBatch = 92,
+    #[msg("ISIN must be a country code, nine letters or digits and a check digit")]
+    InvalidIsinFormat = 93,
+    #[msg("ISIN check digit is invalid")]
+    InvalidIsinChecksum = 94,
+    #[msg("A share with this ISIN already exists")]
+    DuplicateIsin = 95,
+    #[msg("Token manager is already initialized")]
+    TokenManagerAlreadyInitialized = 96,
+    #[msg("Receipts are already enabled for this offer")]
+    OfferReceiptsAlreadyEnabled = 97,
+    #[msg("The token reached its maximum number of holders")]
+    MaxHoldersReached = 98,
+    #[msg(
+        "BIC must be four letters, a country code and a location, optionally followed by a branch"
+    )]
+    InvalidBic = 99,
+    #[msg("Whitelist additions of this token must be confirmed by a second officer")]
+    WhitelistApprovalRequired = 100,
+    #[msg("The proposer of a whitelist addition cannot confirm it")]
+    ApproverNotDistinct = 101,
+    #[msg("The whitelist proposal is past its approval window")]
+    WhitelistProposalExpired = 102,
+    #[msg("The transfer would take the destination above the maximum balance per holder")]
+    HolderCapExceeded = 103,
+    #[msg("Reference data must use a YYYY-MM-DD issue date and an ISO 4217 currency")]
+    InvalidReferenceData = 104,
+    #[msg("Lockup tranches must be in release order and sum to the locked amount")]
+    InvalidLockupSchedule = 105,
+    #[msg("The transfer would move tokens that are still locked")]
+    TokensLocked = 106,
+    #[msg("Distributions are paid on a snapshot capturing the record slot of the action")]
+    RecordBalancesNotFixed = 107,
+    #[msg("Distributions are claimed between the record slot and the payment slot")]
+    DistributionNotClaimable = 108,
+    #[msg("The distribution can still be claimed")]
+    DistributionStillOpen = 109,
+    #[msg("The holder may not receive the token the distribution is paid in")]
+    PayoutRecipientNotEligible = 110,
+    #[msg("A snapshot needs a capture window of at least one slot")]
+    InvalidSnapshotWindow = 111,
+    #[msg("The capture window of the snapshot has closed")]
+    SnapshotCaptureClosed = 112,
+    #[msg("The whitelist bucket of the wallet is full")]
+    WhitelistBucketFull = 113,
+    #[msg("A split needs a non-zero ratio other than one")]
+    InvalidSplitRatio = 114,
+    #[msg("The recorded balances do not add up to the supply of the snapshot")]
+    SnapshotIncomplete = 115,
+    #[msg("A trade needs a seller other than the buyer, tokens and a payment")]
+    InvalidDvpTrade = 116,
+    #[msg("The trade has expired")]
+    DvpExpired = 117,
+    #[msg("Only the seller may cancel the trade before it expires")]
+    DvpNotExpired = 118,
+    #[msg("The payment accounts do not match the payment of the trade")]
+    DvpPaymentMismatch = 119,
+    #[msg("The token manager is locked down")]
+    ManagerLockedDown = 120,
+    #[msg("The lockdown can only be lifted once its delay has passed")]
+    LockdownTimelocked = 121,
+    #[msg("The transfer needs a request approved by a compliance officer")]
+    TransferNotApproved = 122,
+    #[msg("The transfer request is not pending for the amount being approved")]
+    TransferRequestMismatch = 123,
+    #[msg("The transfer hook may only run within a transfer")]
+    NotTransferring = 124,
+    #[msg("The yield program changed the owner, delegate or close authority of the vault")]
+    YieldVaultAuthorityChanged = 125,
+    #[msg("The snapshot entry is not one of the holder in the snapshot of the distribution")]
+    SnapshotEntryMismatch = 126,
+    #[msg("The wallet was removed from the whitelist and only an officer may add it back")]
+    MirroredWalletRemoved = 127,
+    #[msg("The rule protects every transfer and cannot be suspended")]
+    ModuleNotSuspendable = 128,
+    #[msg("The operation must hold the operation lock since its snapshot was taken")]
+    OperationLockRequired = 129,
+    #[msg("Supply of the token changed since the snapshot of the split")]
+    SplitSupplyMismatch = 130,
+    #[msg("Orders must be for at least one token unit")]
+    InvalidOrderAmount = 131,
+    #[msg("The offer vault does not hold the price of the tendered tokens")]
+    BuybackUnderfunded = 132,
+    #[msg("Every order must be settled before the offer is closed")]
+    OfferOrdersPending = 133,
+    #[msg("Tokens with a chained hook can only move through Token-2022 transfers")]
+    ChainedHookUnsupported = 134,
+    #[msg("The rate feed of the payment mint is older than its maximum age")]
+    StaleFxRate = 135,
+    #[msg("The guardian of a tripped circuit breaker cannot be replaced")]
+    CircuitBreakerGuardianLocked = 136,
+    #[msg("The token account still holds tokens")]
+    HolderStillHolding = 137,
+    #[msg("The due bill carries a balance for the corporate action tracking due bills")]
+    DueBillOutstanding = 138,
+    #[msg("The holder may not receive the instrument offered in exchange")]
+    ExchangeRecipientNotEligible = 139,
+    #[msg("A split is still being applied to the token")]
+    SplitInProgress = 140,
 }
@@ -0,0 +1,105 @@
+//! Sizes and rent of the accounts the program creates, so that front-ends can
+//! show users the exact cost of an action before they sign it.
+//!
+//! Only compiled with the `client` feature. Rent is computed with the default
+//! rent parameters, which every public cluster uses.
+
+use anchor_lang::prelude::*;
+use spl_tlv_account_resolution::state::ExtraAccountMetaList;
+use spl_token_2022::extension::ExtensionType;
+
+use crate::{
+    Isin, MetadataPair, TokenShare, TokenWhitelist, WhitelistEntry, WhitelistHistory,
+    SHARE_MINT_EXTENSIONS,
+};
+
+/// Size of the type and length header of a variable-length mint extension.
+const TLV_HEADER_SIZE: usize = 4;
+
+/// Size of a program account holding an Anchor account of type `T`.
+pub fn account_size<T: Space>() -> usize {
+    8 + T::INIT_SPACE
+}
+
+/// Lamports an account of `size` bytes must hold to be rent exempt.
+pub fn rent_exempt_lamports(size: usize) -> u64 {
+    Rent::default().minimum_balance(size)
+}
+
+/// Size of a Token-2022 mint account with the fixed-length `extensions`.
+pub fn mint_size(extensions: &[ExtensionType]) -> Result<usize> {
+    Ok(ExtensionType::try_calculate_account_len::<
+        spl_token_2022::state::Mint,
+    >(extensions)?)
+}
+
+/// Size of the token metadata extension holding `name`, `symbol`, `uri` and the
+/// `additional_metadata` key and value pairs.
+pub fn token_metadata_size(
+    name: &str,
+    symbol: &str,
+    uri: &str,
+    additional_metadata: &[(&str, &str)],
+) -> usize {
+    let string_size = |value: &str| 4 + value.len();
+    TLV_HEADER_SIZE
+        // Update authority and mint
+        + 32
+        + 32
+        + string_size(name)
+        + string_size(symbol)
+        + string_size(uri)
+        + 4
+        + additional_metadata
+            .iter()
+            .map(|(key, value)| string_size(key) + string_size(value))
+            .sum::<usize>()
+}
+
+/// Size of a transfer hook meta list holding `entries` extra accounts.
+pub fn extra_account_meta_list_size(entries: usize) -> Result<usize> {
+    Ok(ExtraAccountMetaList::size_of(entries)?)
+}
+
+/// Rent of the accounts `create_new_share` or `create_share` creates.
+pub struct ShareCreationCost {
+    /// Lamports moved to the mint, covering the metadata written after it
+    pub mint: u64,
+    pub extra_account_meta_list: u64,
+    pub token_share: u64,
+    pub token_whitelist: u64,
+}
+
+impl ShareCreationCost {
+    pub fn total(&self) -> u64 {
+        self.mint + self.extra_account_meta_list + self.token_share + self.token_whitelist
+    }
+}
+
+/// Rent the creation of the share registered under `isin` with the custom
+/// `additional_metadata` fields costs its creator.
+pub fn share_creation_cost(
+    isin: &Isin,
+    additional_metadata: &[MetadataPair],
+) -> Result<ShareCreationCost> {
+    let (name, symbol, uri) = crate::share_metadata(isin);
+    let mint_space = mint_size(&SHARE_MINT_EXTENSIONS)?
+        + crate::calculate_metadata_space(&name, &symbol, &uri, additional_metadata);
+    let meta_list_entries =
+        crate::transfer_hook_extra_account_metas(&Pubkey::default(), &Pubkey::default())?.len();
+    Ok(ShareCreationCost {
+        mint: rent_exempt_lamports(mint_space),
+        extra_account_meta_list: rent_exempt_lamports(extra_account_meta_list_size(
+            meta_list_entries,
+        )?),
+        token_share: rent_exempt_lamports(account_size::<TokenShare>()),
+        token_whitelist: rent_exempt_lamports(account_size::<TokenWhitelist>()),
+    })
+}
+
+/// Rent of the accounts `add_to_whitelist` creates for a wallet whitelisted for
+/// the first time.
+pub fn whitelist_cost() -> u64 {
+    rent_exempt_lamports(account_size::<WhitelistEntry>())
+        + rent_exempt_lamports(account_size::<WhitelistHistory>())
+}
@@ -0,0 +1,341 @@
+//! Solana Pay transaction requests for the actions investors take in issuer
+//! portals, so that portals can offer them as QR codes any wallet can scan.
+//!
+//! Only compiled with the `client` feature. The portal answers the `POST` of the
+//! wallet with the JSON of [`transaction_request`], built for the `account` the
+//! wallet sent; the wallet then signs, pays for and sends the transaction.
+
+use std::future::Future;
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{hash::Hash, instruction::Instruction, message::Message},
+    InstructionData,
+};
+use base64::Engine;
+use spl_tlv_account_resolution::state::{AccountDataResult, AccountFetchError};
+
+use crate::{Distribution, Isin, Offer};
+
+/// Program owning the associated token accounts of investors.
+const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey = pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// Response of a portal to the `POST` of a Solana Pay transaction request.
+pub struct TransactionRequest {
+    /// Base64 of the unsigned transaction
+    pub transaction: String,
+    /// Description of the action shown by the wallet
+    pub message: Option<String>,
+}
+
+impl TransactionRequest {
+    /// Body of the response, as specified by Solana Pay.
+    pub fn to_json(&self) -> String {
+        match &self.message {
+            Some(message) => format!(
+                "{{\"transaction\":{},\"message\":{}}}",
+                json_string(&self.transaction),
+                json_string(message),
+            ),
+            None => format!("{{\"transaction\":{}}}", json_string(&self.transaction)),
+        }
+    }
+}
+
+/// Transaction running `instructions`, paid for and signed by `account`, the
+/// wallet answering the request.
+pub fn transaction_request(
+    account: &Pubkey,
+    instructions: &[Instruction],
+    recent_blockhash: Hash,
+    message: Option<String>,
+) -> TransactionRequest {
+    let message_data = Message::new_with_blockhash(instructions, Some(account), &recent_blockhash);
+
+    // A transaction is its signatures, left empty for the wallet to fill, followed
+    // by its message
+    let signatures = message_data.header.num_required_signatures as usize;
+    let mut transaction = short_vec_len(signatures);
+    transaction.resize(transaction.len() + signatures * 64, 0);
+    transaction.extend(message_data.serialize());
+
+    TransactionRequest {
+        transaction: base64::engine::general_purpose::STANDARD.encode(transaction),
+        message,
+    }
+}
+
+/// Records that `investor` accepted the agreement `agreement_hash` of the token
+/// registered under `isin`.
+pub fn accept_agreement(
+    token_manager: &Pubkey,
+    isin: Isin,
+    mint: &Pubkey,
+    investor: &Pubkey,
+    agreement_hash: [u8; 32],
+) -> Instruction {
+    let accounts = crate::accounts::AcceptAgreement {
+        payer: *investor,
+        investor: *investor,
+        token_manager: *token_manager,
+        token_mint: *mint,
+        token_share: token_share_address(token_manager, &isin),
+        relayer_registry: None,
+        agreement_acceptance: Pubkey::find_program_address(
+            &[b"agreement-acceptance", mint.as_ref(), investor.as_ref()],
+            &crate::ID,
+        )
+        .0,
+        system_program: anchor_lang::system_program::ID,
+    };
+    Instruction {
+        program_id: crate::ID,
+        accounts: accounts.to_account_metas(None),
+        data: crate::instruction::AcceptAgreement {
+            _isin: isin,
+            agreement_hash,
+        }
+        .data(),
+    }
+}
+
+/// Subscribes `amount` token units of the token registered under `isin` on the
+/// offer at `offer_address`, at the price and fees of `offer` as the investor was
+/// shown them. The payment is taken from the associated account of the investor
+/// for the payment mint, held by `payment_token_program`.
+pub fn subscribe(
+    token_manager: &Pubkey,
+    isin: Isin,
+    offer_address: &Pubkey,
+    offer: &Offer,
+    investor: &Pubkey,
+    amount: u64,
+    payment_token_program: &Pubkey,
+) -> Instruction {
+    let accounts = crate::accounts::PlaceOrder {
+        payer: *investor,
+        investor: *investor,
+        token_manager: *token_manager,
+        token_share: token_share_address(token_manager, &isin),
+        whitelist_entry: Pubkey::find_program_address(
+            &[b"whitelist", offer.mint.as_ref(), investor.as_ref()],
+            &crate::ID,
+        )
+        .0,
+        relayer_registry: None,
+        offer: *offer_address,
+        security_mint: offer.mint,
+        payment_mint: offer.payment_mint,
+        offer_vault: Pubkey::find_program_address(
+            &[b"offer-vault", offer_address.as_ref()],
+            &crate::ID,
+        )
+        .0,
+        investor_security: associated_token_address(investor, &offer.mint, &spl_token_2022::ID),
+        investor_payment: associated_token_address(
+            investor,
+            &offer.payment_mint,
+            payment_token_program,
+        ),
+        order: Pubkey::find_program_address(
+            &[b"offer-order", offer_address.as_ref(), investor.as_ref()],
+            &crate::ID,
+        )
+        .0,
+        receipt_mint: Pubkey::find_program_address(
+            &[b"offer-receipt", offer_address.as_ref()],
+            &crate::ID,
+        )
+        .0,
+        investor_receipt: Pubkey::find_program_address(
+            &[b"offer-receipt", offer_address.as_ref(), investor.as_ref()],
+            &crate::ID,
+        )
+        .0,
+        token_program: spl_token_2022::ID,
+        payment_token_program: *payment_token_program,
+        system_program: anchor_lang::system_program::ID,
+    };
+    Instruction {
+        program_id: crate::ID,
+        accounts: accounts.to_account_metas(None),
+        data: crate::instruction::PlaceOrder {
+            _isin: isin,
+            amount,
+            expected_price: offer.price,
+            expected_fee_version: offer.fees.schedule_version,
+        }
+        .data(),
+    }
+}
+
+/// Claims the share of `holder` in `distribution`, the distribution of the
+/// corporate action `action_id` of the token registered under `isin`. The holder
+/// is paid on the balance of its associated token account in the snapshot of the
+/// distribution, into its associated account for the payout mint, held by
+/// `payout_token_program`.
+pub fn claim_distribution(
+    token_manager: &Pubkey,
+    isin: Isin,
+    action_id: u64,
+    distribution: &Distribution,
+    holder: &Pubkey,
+    payout_token_program: &Pubkey,
+) -> Instruction {
+    let corporate_action = Pubkey::find_program_address(
+        &[
+            b"corporate-action",
+            distribution.mint.as_ref(),
+            &action_id.to_le_bytes(),
+        ],
+        &crate::ID,
+    )
+    .0;
+    let distribution_address =
+        Pubkey::find_program_address(&[b"distribution", corporate_action.as_ref()], &crate::ID).0;
+    let accounts = crate::accounts::ClaimDistribution {
+        holder: *holder,
+        token_manager: *token_manager,
+        token_share: token_share_address(token_manager, &isin),
+        corporate_action,
+        distribution: distribution_address,
+        due_bill: Pubkey::find_program_address(
+            &[b"due-bill", distribution.mint.as_ref(), holder.as_ref()],
+            &crate::ID,
+        )
+        .0,
+        notice_acknowledgment: distribution.required_notice.map(|notice| {
+            Pubkey::find_program_address(
+                &[b"notice-ack", notice.as_ref(), holder.as_ref()],
+                &crate::ID,
+            )
+            .0
+        }),
+        tax_certification: Pubkey::find_program_address(
+            &[
+                b"tax-certification",
+                token_manager.as_ref(),
+                holder.as_ref(),
+            ],
+            &crate::ID,
+        )
+        .0,
+        claim: Pubkey::find_program_address(
+            &[
+                b"distribution-claim",
+                distribution_address.as_ref(),
+                holder.as_ref(),
+            ],
+            &crate::ID,
+        )
+        .0,
+        payout_mint: distribution.payout_mint,
+        distribution_vault: Pubkey::find_program_address(
+            &[b"distribution-vault", distribution_address.as_ref()],
+            &crate::ID,
+        )
+        .0,
+        holder_payout: associated_token_address(
+            holder,
+            &distribution.payout_mint,
+            payout_token_program,
+        ),
+        payout_token_program: *payout_token_program,
+        system_program: anchor_lang::system_program::ID,
+    };
+    let holder_security = associated_token_address(holder, &distribution.mint, &spl_token_2022::ID);
+    let snapshot_entry = Pubkey::find_program_address(
+        &[
+            b"snapshot-entry",
+            distribution.snapshot.as_ref(),
+            holder_security.as_ref(),
+        ],
+        &crate::ID,
+    )
+    .0;
+    let mut metas = accounts.to_account_metas(None);
+    metas.push(AccountMeta::new_readonly(snapshot_entry, false));
+    Instruction {
+        program_id: crate::ID,
+        accounts: metas,
+        data: crate::instruction::ClaimDistribution {
+            _isin: isin,
+            action_id,
+        }
+        .data(),
+    }
+}
+
+/// Transfers `amount` token units of `mint` between the associated accounts of
+/// `owner` and `recipient`, with every extra account the transfer hook reads.
+/// `fetch_account_data_fn` returns the data of an account, typically through RPC.
+pub async fn transfer<F, Fut>(
+    mint: &Pubkey,
+    owner: &Pubkey,
+    recipient: &Pubkey,
+    amount: u64,
+    decimals: u8,
+    fetch_account_data_fn: F,
+) -> std::result::Result<Instruction, AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    spl_token_2022::offchain::create_transfer_checked_instruction_with_extra_metas(
+        &spl_token_2022::ID,
+        &associated_token_address(owner, mint, &spl_token_2022::ID),
+        mint,
+        &associated_token_address(recipient, mint, &spl_token_2022::ID),
+        owner,
+        &[],
+        amount,
+        decimals,
+        fetch_account_data_fn,
+    )
+    .await
+}
+
+fn token_share_address(token_manager: &Pubkey, isin: &Isin) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"token-share", token_manager.as_ref(), isin.as_bytes()],
+        &crate::ID,
+    )
+    .0
+}
+
+fn associated_token_address(wallet: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[wallet.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Compact encoding of a length, 7 bits per byte.
+fn short_vec_len(mut len: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
@@ -0,0 +1,282 @@
+//! Replay of the events emitted by the program, checked against the accounts
+//! they describe, so operators can confirm that the state indexers rebuild from
+//! the event stream is the state the chain holds.
+//!
+//! Only compiled with the `verifier` feature. Feed [`Replay::apply_log`] every
+//! log line of the transactions of the program since its deployment, in order,
+//! then call [`Replay::verify`]; an empty list of mismatches means the events and
+//! the accounts agree. Transactions whose logs were truncated by the runtime
+//! cannot be replayed and show up as mismatches.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+};
+
+use anchor_lang::{prelude::*, Discriminator};
+use base64::Engine;
+use spl_tlv_account_resolution::state::{AccountDataResult, AccountFetchError};
+
+use crate::{
+    Distribution, DistributionClaimed, DistributionCreated, Isin, Lockup, LockupSet,
+    RoleAssignment, RoleGranted, RoleRevoked, ShareCreated, TokenShare, WhitelistEntryAdded,
+    WhitelistEntryRemoved,
+};
+
+/// Prefix of the log lines carrying an event emitted by `emit!`.
+const EVENT_LOG_PREFIX: &str = "Program data: ";
+
+/// State rebuilt from the events of the program.
+#[derive(Default)]
+pub struct Replay {
+    /// Share registered under each token manager and ISIN, and its mint
+    shares: BTreeMap<(Pubkey, [u8; 12]), Pubkey>,
+    /// Whether each mint and wallet pair seen in the events is whitelisted
+    whitelist: BTreeMap<(Pubkey, Pubkey), bool>,
+    /// Role bits of each token manager and holder pair seen in the events
+    roles: BTreeMap<(Pubkey, Pubkey), u8>,
+    /// Locked amount of each mint and wallet pair
+    lockups: BTreeMap<(Pubkey, Pubkey), u64>,
+    /// Payout claimed on each distribution, by mint and action
+    distributions: BTreeMap<(Pubkey, u64), u64>,
+    /// Events whose payload could not be decoded
+    undecoded: BTreeSet<usize>,
+    events: usize,
+}
+
+/// Difference between the replayed state and an account on chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The share of a `ShareCreated` event is missing or holds another mint
+    Share {
+        token_manager: Pubkey,
+        isin: Isin,
+        expected_mint: Pubkey,
+        actual_mint: Option<Pubkey>,
+    },
+    WhitelistEntry {
+        mint: Pubkey,
+        wallet: Pubkey,
+        expected: bool,
+    },
+    Roles {
+        token_manager: Pubkey,
+        holder: Pubkey,
+        expected: u8,
+        actual: u8,
+    },
+    Lockup {
+        mint: Pubkey,
+        wallet: Pubkey,
+        expected: u64,
+        actual: Option<u64>,
+    },
+    DistributionClaimed {
+        mint: Pubkey,
+        action_id: u64,
+        expected: u64,
+        actual: Option<u64>,
+    },
+    /// The event at this position of the replay carried a known discriminator
+    /// but a payload that does not decode
+    UndecodedEvent { position: usize },
+}
+
+impl Replay {
+    /// Applies the event carried by a log line of the program, if any.
+    pub fn apply_log(&mut self, line: &str) {
+        let Some(encoded) = line.strip_prefix(EVENT_LOG_PREFIX) else {
+            return;
+        };
+        if let Ok(data) = base64::engine::general_purpose::STANDARD.decode(encoded) {
+            self.apply_event(&data);
+        }
+    }
+
+    /// Applies an event given as its discriminator followed by its payload.
+    /// Events that do not change the verified state are ignored.
+    pub fn apply_event(&mut self, data: &[u8]) {
+        if data.len() < 8 {
+            return;
+        }
+        let (discriminator, mut payload) = data.split_at(8);
+        let position = self.events;
+        self.events += 1;
+
+        let applied = match discriminator {
+            d if d == ShareCreated::DISCRIMINATOR => {
+                ShareCreated::deserialize(&mut payload).map(|event| {
+                    self.shares
+                        .insert((event.token_manager, event.isin.0), event.mint);
+                })
+            }
+            d if d == WhitelistEntryAdded::DISCRIMINATOR => {
+                WhitelistEntryAdded::deserialize(&mut payload).map(|event| {
+                    self.whitelist.insert((event.mint, event.wallet), true);
+                })
+            }
+            d if d == WhitelistEntryRemoved::DISCRIMINATOR => {
+                WhitelistEntryRemoved::deserialize(&mut payload).map(|event| {
+                    self.whitelist.insert((event.mint, event.wallet), false);
+                })
+            }
+            d if d == RoleGranted::DISCRIMINATOR => {
+                RoleGranted::deserialize(&mut payload).map(|event| {
+                    *self
+                        .roles
+                        .entry((event.token_manager, event.holder))
+                        .or_default() |= event.role.bit();
+                })
+            }
+            d if d == RoleRevoked::DISCRIMINATOR => {
+                RoleRevoked::deserialize(&mut payload).map(|event| {
+                    *self
+                        .roles
+                        .entry((event.token_manager, event.holder))
+                        .or_default() &= !event.role.bit();
+                })
+            }
+            d if d == LockupSet::DISCRIMINATOR => {
+                LockupSet::deserialize(&mut payload).map(|event| {
+                    self.lockups
+                        .insert((event.mint, event.wallet), event.locked_amount);
+                })
+            }
+            d if d == DistributionCreated::DISCRIMINATOR => {
+                DistributionCreated::deserialize(&mut payload).map(|event| {
+                    self.distributions.insert((event.mint, event.action_id), 0);
+                })
+            }
+            d if d == DistributionClaimed::DISCRIMINATOR => {
+                DistributionClaimed::deserialize(&mut payload).map(|event| {
+                    let claimed = self
+                        .distributions
+                        .entry((event.mint, event.action_id))
+                        .or_default();
+                    *claimed = claimed.saturating_add(event.amount);
+                })
+            }
+            _ => Ok(()),
+        };
+        if applied.is_err() {
+            self.undecoded.insert(position);
+        }
+    }
+
+    /// Compares the replayed state with the accounts on chain.
+    /// `fetch_account_data_fn` returns the data of an account, typically through RPC.
+    pub async fn verify<F, Fut>(
+        &self,
+        fetch_account_data_fn: F,
+    ) -> std::result::Result<Vec<Mismatch>, AccountFetchError>
+    where
+        F: Fn(Pubkey) -> Fut,
+        Fut: Future<Output = AccountDataResult>,
+    {
+        let mut mismatches: Vec<Mismatch> = self
+            .undecoded
+            .iter()
+            .map(|position| Mismatch::UndecodedEvent {
+                position: *position,
+            })
+            .collect();
+
+        for ((token_manager, isin), mint) in &self.shares {
+            let address = find_address(&[b"token-share", token_manager.as_ref(), isin]);
+            let actual_mint = fetch::<TokenShare, _, _>(&fetch_account_data_fn, address)
+                .await?
+                .map(|share| share.mint);
+            if actual_mint != Some(*mint) {
+                mismatches.push(Mismatch::Share {
+                    token_manager: *token_manager,
+                    isin: Isin(*isin),
+                    expected_mint: *mint,
+                    actual_mint,
+                });
+            }
+        }
+
+        for ((mint, wallet), expected) in &self.whitelist {
+            let address = find_address(&[b"whitelist", mint.as_ref(), wallet.as_ref()]);
+            let exists = fetch_account_data_fn(address)
+                .await?
+                .is_some_and(|data| !data.is_empty());
+            if exists != *expected {
+                mismatches.push(Mismatch::WhitelistEntry {
+                    mint: *mint,
+                    wallet: *wallet,
+                    expected: *expected,
+                });
+            }
+        }
+
+        for ((token_manager, holder), expected) in &self.roles {
+            let address = find_address(&[b"role", token_manager.as_ref(), holder.as_ref()]);
+            let actual = fetch::<RoleAssignment, _, _>(&fetch_account_data_fn, address)
+                .await?
+                .map_or(0, |assignment| assignment.roles);
+            if actual != *expected {
+                mismatches.push(Mismatch::Roles {
+                    token_manager: *token_manager,
+                    holder: *holder,
+                    expected: *expected,
+                    actual,
+                });
+            }
+        }
+
+        for ((mint, wallet), expected) in &self.lockups {
+            let address = find_address(&[b"lockup", mint.as_ref(), wallet.as_ref()]);
+            let actual = fetch::<Lockup, _, _>(&fetch_account_data_fn, address)
+                .await?
+                .map(|lockup| lockup.locked_amount);
+            if actual != Some(*expected) {
+                mismatches.push(Mismatch::Lockup {
+                    mint: *mint,
+                    wallet: *wallet,
+                    expected: *expected,
+                    actual,
+                });
+            }
+        }
+
+        for ((mint, action_id), expected) in &self.distributions {
+            let action =
+                find_address(&[b"corporate-action", mint.as_ref(), &action_id.to_le_bytes()]);
+            let address = find_address(&[b"distribution", action.as_ref()]);
+            let actual = fetch::<Distribution, _, _>(&fetch_account_data_fn, address)
+                .await?
+                .map(|distribution| distribution.claimed);
+            if actual != Some(*expected) {
+                mismatches.push(Mismatch::DistributionClaimed {
+                    mint: *mint,
+                    action_id: *action_id,
+                    expected: *expected,
+                    actual,
+                });
+            }
+        }
+
+        Ok(mismatches)
+    }
+}
+
+fn find_address(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &crate::ID).0
+}
+
+/// Account of type `T` at `address`, `None` when it does not exist or holds
+/// another type.
+async fn fetch<T, F, Fut>(
+    fetch_account_data_fn: &F,
+    address: Pubkey,
+) -> std::result::Result<Option<T>, AccountFetchError>
+where
+    T: AccountDeserialize,
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    Ok(fetch_account_data_fn(address)
+        .await?
+        .and_then(|data| T::try_deserialize(&mut data.as_slice()).ok()))
+}
@@ -9,9 +9,18 @@ import {
   getAssociatedTokenAddress,
   createAssociatedTokenAccountInstruction,
   createTransferCheckedWithTransferHookInstruction,
-  createAssociatedTokenAccountIdempotent
+  createAssociatedTokenAccountIdempotent,
+  getTransferHook,
+  TOKEN_PROGRAM_ID,
+  createBurnCheckedInstruction,
+  createMint,
+  mintTo,
+  approve,
+  getOrCreateAssociatedTokenAccount,
+  getTokenMetadata
 } from "@solana/spl-token";
 import { expect } from "chai";
+import { createHash } from "crypto";
 
 describe("Token Manager Program", () => {
   const provider = anchor.AnchorProvider.env();
@@ -24,9 +33,9 @@ describe("Token Manager Program", () => {
   );
 
   const tokensToCreate = [
-    { decimals: 6, isin: "US1234567890" },
-    { decimals: 8, isin: "US9876543210" },
-    { decimals: 2, isin: "EU1234567890" },
+    { decimals: 6, isin: "US1234567899" },
+    { decimals: 8, isin: "US9876543219" },
+    { decimals: 2, isin: "EU1234567895" },
   ];
 
   const wallets = {
@@ -86,9 +95,89 @@ describe("Token Manager Program", () => {
     }
   }
 
+  // Hooked transfers carry every extra account of the hook, which a legacy
+  // transaction no longer fits next to a memo or a chained hook: they are sent
+  // as versioned transactions reading the accounts from a lookup table
+  async function sendWithLookupTable(instructions: web3.TransactionInstruction[], signers: web3.Keypair[]) {
+    const payer = (provider.wallet as anchor.Wallet).payer;
+    const [createIx, lookupTable] = web3.AddressLookupTableProgram.createLookupTable({
+      authority: payer.publicKey,
+      payer: payer.publicKey,
+      recentSlot: await provider.connection.getSlot("finalized"),
+    });
+    await provider.sendAndConfirm(new web3.Transaction().add(createIx));
+    const addresses = [...new Set(
+      instructions.flatMap(ix => ix.keys.filter(key => !key.isSigner).map(key => key.pubkey.toBase58())),
+    )].map(address => new PublicKey(address));
+    for (let start = 0; start < addresses.length; start += 20) {
+      await provider.sendAndConfirm(new web3.Transaction().add(
+        web3.AddressLookupTableProgram.extendLookupTable({
+          authority: payer.publicKey,
+          payer: payer.publicKey,
+          lookupTable,
+          addresses: addresses.slice(start, start + 20),
+        }),
+      ));
+    }
+    // Addresses added to a table can only be looked up from the next slot
+    const extendedAt = await provider.connection.getSlot("confirmed");
+    while (await provider.connection.getSlot("confirmed") <= extendedAt) {
+      await new Promise(resolve => setTimeout(resolve, 200));
+    }
+
+    const table = (await provider.connection.getAddressLookupTable(lookupTable, { commitment: "confirmed" })).value;
+    const message = new web3.TransactionMessage({
+      payerKey: signers[0].publicKey,
+      recentBlockhash: (await provider.connection.getLatestBlockhash("confirmed")).blockhash,
+      instructions,
+    }).compileToV0Message([table]);
+    const transaction = new web3.VersionedTransaction(message);
+    transaction.sign(signers);
+    const signature = await provider.connection.sendTransaction(transaction);
+    await confirmTransaction(signature);
+    return signature;
+  }
+
+  // ISINs are passed to the program and stored as their twelve ASCII bytes
+  function isinArg(isin: string) {
+    if (!/^[A-Z0-9]{12}$/.test(isin)) {
+      throw new Error(`Invalid ISIN ${isin}`);
+    }
+    return { 0: Array.from(Buffer.from(isin, "ascii")) };
+  }
+
+  function isinString(isin: { 0: number[] }): string {
+    return Buffer.from(isin[0]).toString("ascii");
+  }
+
+  const defaultManagerConfig = {
+    complianceDefaults: {
+      requireAgreement: false,
+      requireReacceptance: false,
+      requireIncomingConsent: false,
+      requireSenderWhitelisted: false,
+    },
+    displayName: null,
+    feeTerms: null,
+    guardian: null,
+  };
+
+  function tokenSharePDA(isin: string, manager: PublicKey = tokenManagerPDA): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("token-share"), manager.toBuffer(), Buffer.from(isin)],
+      program.programId,
+    )[0];
+  }
+
+  function tokenWhitelistPDA(isin: string, manager: PublicKey = tokenManagerPDA): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("token-whitelist"), manager.toBuffer(), Buffer.from(isin)],
+      program.programId,
+    )[0];
+  }
+
   async function getTokenForIsin(isin: string): Promise<{ mint: PublicKey, index: anchor.BN }> {
-    const tokenManagerAccount = await program.account.tokenManager.fetch(tokenManagerPDA);
-    const token = tokenManagerAccount.tokens.find(t => t.isin === isin);
+    const token = await program.account.tokenShare.fetchNullable(tokenSharePDA(isin));
 
     if (!token) {
       throw new Error(`Token with ISIN ${isin} not found`);
@@ -97,6 +186,76 @@ describe("Token Manager Program", () => {
     return token;
   }
 
+  // The registry holds 8 payment mints; each test only uses the ones it just
+  // created, so the oldest are revoked to leave room for the others tests approve
+  const approvedPaymentMints: PublicKey[] = [];
+  const setPaymentMint = (paymentMint: PublicKey, allowed: boolean) =>
+    program.methods
+      .setPaymentMint(null, allowed)
+      .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, paymentMint })
+      .rpc();
+
+  async function createPaymentMint(): Promise<PublicKey> {
+    const payer = (provider.wallet as anchor.Wallet).payer;
+    const paymentMint = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+    while (approvedPaymentMints.length >= 5) {
+      await setPaymentMint(approvedPaymentMints.shift(), false);
+    }
+    await setPaymentMint(paymentMint, true);
+    approvedPaymentMints.push(paymentMint);
+    return paymentMint;
+  }
+
+  // Takes the first snapshot of the share registered under `isin`, held through
+  // `tokenAccounts`, and records their balances
+  async function snapshotHolders(isin: string, tokenAccounts: PublicKey[]) {
+    const { mint, index } = await getTokenForIsin(isin);
+    const [snapshot] = PublicKey.findProgramAddressSync(
+      [Buffer.from("snapshot"), mint.toBuffer(), new anchor.BN(1).toArrayLike(Buffer, "le", 8)],
+      program.programId,
+    );
+    await program.methods
+      .createSnapshot(new anchor.BN(index), new anchor.BN(100))
+      .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), snapshot })
+      .rpc();
+    const entries = [];
+    for (const tokenAccount of tokenAccounts) {
+      await program.methods
+        .recordHolderBalance()
+        .accounts({ payer: provider.wallet.publicKey, snapshot, tokenAccount })
+        .rpc();
+      entries.push(
+        PublicKey.findProgramAddressSync(
+          [Buffer.from("snapshot-entry"), snapshot.toBuffer(), tokenAccount.toBuffer()],
+          program.programId,
+        )[0],
+      );
+    }
+    return { snapshot, entries };
+  }
+
+  function whitelistEntryPDA(mint: PublicKey, wallet: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("whitelist"), mint.toBuffer(), wallet.toBuffer()],
+      program.programId,
+    )[0];
+  }
+
+  function personalDataPDA(mint: PublicKey, wallet: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("personal-data"), mint.toBuffer(), wallet.toBuffer()],
+      program.programId,
+    )[0];
+  }
+
+  async function getWhitelistForIsin(isin: string, manager: PublicKey = tokenManagerPDA) {
+    const { mint } = await program.account.tokenWhitelist.fetch(tokenWhitelistPDA(isin, manager));
+    const entries = await program.account.whitelistEntry.all([
+      { memcmp: { offset: 8, bytes: mint.toBase58() } },
+    ]);
+    return entries.map(entry => entry.account);
+  }
+
   before(async () => {
     for (const key in wallets) {
       await fundWallet(wallets[key]);
@@ -109,7 +268,7 @@ describe("Token Manager Program", () => {
         await program.account.tokenManager.fetch(tokenManagerPDA);
       } catch (error) {
         const txSig = await program.methods
-          .initializeTokenManager()
+          .initializeTokenManager(defaultManagerConfig)
           .accounts({
             signer: provider.wallet.publicKey,
           })
@@ -119,8 +278,7 @@ describe("Token Manager Program", () => {
       }
 
       const tokenManagerAccount = await program.account.tokenManager.fetch(tokenManagerPDA);
-      expect(tokenManagerAccount.tokens).to.be.an("array");
-      expect(tokenManagerAccount.whitelist).to.be.an("array");
+      expect(tokenManagerAccount.currentTokenIndex.toNumber()).to.be.at.least(0);
       expect(tokenManagerAccount.creator.toString()).to.equal(provider.wallet.publicKey.toString());
     });
   });
@@ -140,10 +298,13 @@ describe("Token Manager Program", () => {
           const txSig = await program.methods
             .createNewShare(
               tokenData.decimals,
-              tokenData.isin,
+              isinArg(tokenData.isin),
+              null,
+              null,
             )
             .accounts({
               signer: provider.wallet.publicKey,
+              tokenManager: tokenManagerPDA,
             })
             .rpc();
 
@@ -161,20 +322,16 @@ describe("Token Manager Program", () => {
           expect(mintInfo.decimals).to.equal(tokenData.decimals);
           expect(mintInfo.supply.toString()).to.equal("0");
 
-          tokenManagerAccount = await program.account.tokenManager.fetch(tokenManagerPDA);
-          const lastIndex = tokenManagerAccount.tokens.length - 1;
-          expect(tokenManagerAccount.tokens[lastIndex].mint.toString()).to.equal(tokenMintPDA.toString());
-          expect(tokenManagerAccount.tokens[lastIndex].isin).to.equal(tokenData.isin);
+          const token = await getTokenForIsin(tokenData.isin);
+          expect(token.mint.toString()).to.equal(tokenMintPDA.toString());
+          expect(token.index.toNumber()).to.equal(index);
+          expect(await getWhitelistForIsin(tokenData.isin)).to.be.empty;
         } catch (error) {
-          if (error.message?.includes("already in use")) {
+          if (error.error?.errorCode?.code === "DuplicateIsin") {
             console.log(`Token ${tokenData.isin} already exists, skipping creation`);
 
-            tokenManagerAccount = await program.account.tokenManager.fetch(tokenManagerPDA);
-            const existingToken = tokenManagerAccount.tokens.find(t => t.isin === tokenData.isin);
-
-            if (existingToken) {
-              tokenMints.push(existingToken.mint);
-            }
+            const existingToken = await getTokenForIsin(tokenData.isin);
+            tokenMints.push(existingToken.mint);
           } else {
             throw error;
           }
@@ -182,47 +339,41 @@ describe("Token Manager Program", () => {
       });
     });
 
-    it("should have all tokens correctly stored in TokenManager", async () => {
+    it("should have all tokens correctly stored in their own accounts", async () => {
       const tokenManagerAccount = await program.account.tokenManager.fetch(tokenManagerPDA);
 
-      expect(tokenManagerAccount.tokens.length).to.be.at.equal(tokensToCreate.length);
+      expect(tokenManagerAccount.currentTokenIndex.toNumber()).to.be.at.equal(tokensToCreate.length);
 
       for (const element of tokensToCreate) {
-        const token = tokenManagerAccount.tokens.find(t => t.isin === element.isin);
-        expect(token).to.not.be.undefined;
-        expect(token.isin).to.equal(element.isin);
+        const token = await program.account.tokenShare.fetch(tokenSharePDA(element.isin));
+        expect(isinString(token.isin)).to.equal(element.isin);
+        expect(token.tokenManager.toString()).to.equal(tokenManagerPDA.toString());
       }
     });
   });
 
   describe("3. Whitelist Management", () => {
     it("should fail when adding a wallet to a non-existent token", async () => {
-      const nonExistentIsin = "DOES_NOT_EXIST";
+      const nonExistentIsin = "XX0000000000";
 
       try {
         await program.methods
-          .addToWhitelist(wallets.destination.publicKey, nonExistentIsin)
+          .addToWhitelist(wallets.destination.publicKey, isinArg(nonExistentIsin), new anchor.BN(0))
           .accounts({
             signer: provider.wallet.publicKey,
+            tokenManager: tokenManagerPDA,
           })
           .rpc();
         expect.fail("Expected error when adding to a non-existent token");
       } catch (err: any) {
-        expect(err.error.errorCode.code).to.equal("TokenNotFound");
+        expect(err.error.errorCode.code).to.equal("AccountNotInitialized");
       }
     });
 
     it("should add a wallet to the whitelist for each token", async () => {
-      let tokenManagerAccount = await program.account.tokenManager.fetch(tokenManagerPDA);
-
       for (const tokenData of tokensToCreate) {
-        const existingAuth = tokenManagerAccount.whitelist.find(
-          auth => {
-            const token = tokenManagerAccount.tokens.find(t => t.isin === tokenData.isin);
-            return token &&
-              auth.mint.toString() === token.mint.toString() &&
-              auth.wallet.toString() === wallets.destination.publicKey.toString();
-          }
+        const existingAuth = (await getWhitelistForIsin(tokenData.isin)).find(
+          auth => auth.wallet.toString() === wallets.destination.publicKey.toString()
         );
 
         if (existingAuth) {
@@ -230,22 +381,20 @@ describe("Token Manager Program", () => {
         }
 
         const txSig = await program.methods
-          .addToWhitelist(wallets.destination.publicKey, tokenData.isin)
+          .addToWhitelist(wallets.destination.publicKey, isinArg(tokenData.isin), new anchor.BN(0))
           .accounts({
             signer: provider.wallet.publicKey,
+            tokenManager: tokenManagerPDA,
           })
           .rpc();
 
         await confirmTransaction(txSig);
       }
 
-      tokenManagerAccount = await program.account.tokenManager.fetch(tokenManagerPDA);
-
       for (const tokenData of tokensToCreate) {
-        const token = tokenManagerAccount.tokens.find(t => t.isin === tokenData.isin);
-        expect(token).to.not.be.undefined;
+        const token = await getTokenForIsin(tokenData.isin);
 
-        const authorization = tokenManagerAccount.whitelist.find(
+        const authorization = (await getWhitelistForIsin(tokenData.isin)).find(
           auth => auth.mint.toString() === token.mint.toString() &&
             auth.wallet.toString() === wallets.destination.publicKey.toString()
         );
@@ -260,9 +409,10 @@ describe("Token Manager Program", () => {
 
       try {
         await program.methods
-          .removeFromWhitelist(randomWallet.publicKey, validIsin)
+          .removeFromWhitelist(randomWallet.publicKey, isinArg(validIsin))
           .accounts({
             signer: provider.wallet.publicKey,
+            tokenManager: tokenManagerPDA,
           })
           .rpc();
         expect.fail("Expected error when removing a wallet not in the whitelist");
@@ -289,6 +439,8 @@ describe("Token Manager Program", () => {
         .mintTokens(testToken.index, mintAmount)
         .accounts({
           signer: provider.wallet.publicKey,
+          tokenManager: tokenManagerPDA,
+          tokenShare: tokenSharePDA(tokensToCreate[0].isin),
           destination: tokenAccount,
         })
         .rpc();
@@ -455,6 +607,4924 @@ describe("Token Manager Program", () => {
     });
   });
 
+  describe("6. Error Codes", () => {
+    it("should expose stable explicit error codes in the IDL", async () => {
+      const offset = program.idl.constants.find(c => c.name === "errorCodeOffset");
+      expect(offset).to.not.be.undefined;
+
+      const codes = Object.fromEntries(program.idl.errors.map(e => [e.name, e.code]));
+      expect(codes["tokenNotFound"]).to.equal(Number(offset.value));
+      expect(codes["transferNotAllowed"]).to.equal(Number(offset.value) + 2);
+      expect(codes["whitelistFull"]).to.equal(Number(offset.value) + 8);
+    });
+  });
+
+  describe("7. Rulebook", () => {
+    const [rulebookPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("rulebook"), tokenManagerPDA.toBuffer()],
+      program.programId,
+    );
+
+    it("should store and remove issuer descriptions for rule ids", async () => {
+      try {
+        await program.account.rulebook.fetch(rulebookPDA);
+      } catch (error) {
+        await program.methods
+          .initializeRulebook()
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      }
+
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleDestinationWhitelisted").value);
+
+      await program.methods
+        .setRuleDescription(ruleId, "Receiver has not completed onboarding")
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      let rulebook = await program.account.rulebook.fetch(rulebookPDA);
+      expect(rulebook.rules.find(r => r.ruleId === ruleId).description)
+        .to.equal("Receiver has not completed onboarding");
+
+      await program.methods
+        .setRuleDescription(ruleId, "")
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      rulebook = await program.account.rulebook.fetch(rulebookPDA);
+      expect(rulebook.rules.find(r => r.ruleId === ruleId)).to.be.undefined;
+    });
+  });
+
+  describe("8. Issuer Profile", () => {
+    it("should create and update the issuer profile", async () => {
+      const [issuerProfilePDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("issuer-profile"), tokenManagerPDA.toBuffer()],
+        program.programId,
+      );
+
+      await program.methods
+        .updateIssuerProfile("Example Capital", "https://example.com", "https://example.com/logo.png")
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      await program.methods
+        .updateIssuerProfile("Example Capital SA", "https://example.com", "")
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const profile = await program.account.issuerProfile.fetch(issuerProfilePDA);
+      expect(profile.displayName).to.equal("Example Capital SA");
+      expect(profile.logoUri).to.equal("");
+      expect(profile.tokenManager.toString()).to.equal(tokenManagerPDA.toString());
+    });
+  });
+
+  describe("9. Whitelist Checkpoints", () => {
+    it("should commit a whitelist hash matching the on-chain entry count", async () => {
+      const isin = tokensToCreate[0].isin;
+      const count = (await getWhitelistForIsin(isin)).length;
+
+      try {
+        await program.methods
+          .commitWhitelistHash(isinArg(isin), Array(32).fill(0), count + 1)
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        expect.fail("Expected error when committing a wrong count");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("WhitelistCountMismatch");
+      }
+
+      const [checkpointPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("whitelist-checkpoint"), tokenManagerPDA.toBuffer(), Buffer.from(isin)],
+        program.programId,
+      );
+      const txSig = await program.methods
+        .commitWhitelistHash(isinArg(isin), Array(32).fill(7), count)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await confirmTransaction(txSig);
+
+      const checkpoint = await program.account.whitelistCheckpoint.fetch(checkpointPDA);
+      expect(checkpoint.count).to.equal(count);
+      expect(checkpoint.merkleRoot).to.deep.equal(Array(32).fill(7));
+      expect(checkpoint.sequence.toNumber()).to.be.greaterThan(0);
+    });
+  });
+
+  describe("10. Transfer Explanation", () => {
+    it("should report each evaluated rule without failing", async () => {
+      const isin = tokensToCreate[0].isin;
+      const token = await getTokenForIsin(isin);
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleDestinationWhitelisted").value);
+
+      const allowed = await program.methods
+        .explainTransfer(wallets.authorized.publicKey, wallets.destination.publicKey, new anchor.BN(1))
+        .accounts({
+          mint: token.mint,
+          sourceToken: null,
+          destinationToken: null,
+          tokenManager: tokenManagerPDA,
+          tokenShare: tokenSharePDA(isin),
+        })
+        .view();
+      expect(allowed.find(r => r.ruleId === ruleId).passed).to.be.true;
+
+      const blocked = await program.methods
+        .explainTransfer(wallets.authorized.publicKey, wallets.unauthorized.publicKey, new anchor.BN(1))
+        .accounts({
+          mint: token.mint,
+          sourceToken: null,
+          destinationToken: null,
+          tokenManager: tokenManagerPDA,
+          tokenShare: tokenSharePDA(isin),
+        })
+        .view();
+      expect(blocked.find(r => r.ruleId === ruleId).passed).to.be.false;
+    });
+  });
+
+  describe("11. Transfer Edge Policies", () => {
+    it("should deny zero-amount transfers when configured", async () => {
+      const isin = tokensToCreate[0].isin;
+      const token = await getTokenForIsin(isin);
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleZeroAmount").value);
+
+      await program.methods
+        .setTransferEdgePolicies(isinArg(isin), { deny: {} }, { evaluate: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const evaluations = await program.methods
+        .explainTransfer(wallets.authorized.publicKey, wallets.destination.publicKey, new anchor.BN(0))
+        .accounts({
+          mint: token.mint,
+          sourceToken: null,
+          destinationToken: null,
+          tokenManager: tokenManagerPDA,
+          tokenShare: tokenSharePDA(isin),
+        })
+        .view();
+      expect(evaluations.find(r => r.ruleId === ruleId).passed).to.be.false;
+
+      await program.methods
+        .setTransferEdgePolicies(isinArg(isin), { evaluate: {} }, { evaluate: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+
+    it("should only explain transfers between the token accounts of the wallets", async () => {
+      const isin = tokensToCreate[0].isin;
+      const token = await getTokenForIsin(isin);
+      try {
+        await program.methods
+          .explainTransfer(wallets.authorized.publicKey, wallets.destination.publicKey, new anchor.BN(1))
+          .accounts({
+            mint: token.mint,
+            sourceToken: await createTokenAccount(wallets.unauthorized, token.mint),
+            destinationToken: null,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+          })
+          .view();
+        expect.fail("Expected a source account of another wallet to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidTokenAccount");
+      }
+    });
+
+    it("should close the accounts of a holder once a transfer empties it", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint, index } = await getTokenForIsin(isin);
+      const holder = web3.Keypair.generate();
+      await fundWallet(holder);
+      const holderToken = await createTokenAccount(holder, mint);
+      const [statementPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("holder-statement"), mint.toBuffer(), holder.publicKey.toBuffer()],
+        program.programId,
+      );
+      await program.methods
+        .openHolderStatement(isinArg(isin))
+        .accounts({
+          payer: holder.publicKey,
+          holder: holder.publicKey,
+          tokenManager: tokenManagerPDA,
+          tokenMint: mint,
+          relayerRegistry: null,
+        })
+        .signers([holder])
+        .rpc();
+      await program.methods
+        .mintTokens(index, new anchor.BN(1))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: holderToken })
+        .rpc();
+      const close = () =>
+        program.methods
+          .closeHolderAccounts(isinArg(isin))
+          .accounts({ holder: holder.publicKey, tokenManager: tokenManagerPDA, holderToken })
+          .signers([holder])
+          .rpc();
+
+      try {
+        await close();
+        expect.fail("Expected a holder still holding tokens to keep its accounts");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("HolderStillHolding");
+      }
+
+      const mintInfo = await getMint(provider.connection, mint, "confirmed", TOKEN_2022_PROGRAM_ID);
+      const transferIx = await createTransferCheckedWithTransferHookInstruction(
+        provider.connection,
+        holderToken,
+        mint,
+        await createTokenAccount(wallets.destination, mint),
+        holder.publicKey,
+        BigInt(1),
+        mintInfo.decimals,
+        [],
+        "confirmed",
+        TOKEN_2022_PROGRAM_ID,
+      );
+      await web3.sendAndConfirmTransaction(provider.connection, new web3.Transaction().add(transferIx), [holder], { commitment: "confirmed" });
+      await close();
+      expect(await provider.connection.getAccountInfo(statementPDA)).to.be.null;
+    });
+
+    it("should still apply the regular rules to allowed account-emptying transfers", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint, index } = await getTokenForIsin(isin);
+      const holder = web3.Keypair.generate();
+      await fundWallet(holder);
+      await program.methods
+        .addToWhitelist(holder.publicKey, isinArg(isin), new anchor.BN(0))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const holderToken = await createTokenAccount(holder, mint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(1))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: holderToken })
+        .rpc();
+      await program.methods
+        .setTransferEdgePolicies(isinArg(isin), { evaluate: {} }, { allow: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const mintInfo = await getMint(provider.connection, mint, "confirmed", TOKEN_2022_PROGRAM_ID);
+      const transferIx = await createTransferCheckedWithTransferHookInstruction(
+        provider.connection,
+        holderToken,
+        mint,
+        await createTokenAccount(wallets.unauthorized, mint),
+        holder.publicKey,
+        BigInt(1),
+        mintInfo.decimals,
+        [],
+        "confirmed",
+        TOKEN_2022_PROGRAM_ID,
+      );
+      try {
+        await web3.sendAndConfirmTransaction(provider.connection, new web3.Transaction().add(transferIx), [holder], { commitment: "confirmed" });
+        expect.fail("Expected the destination to still need whitelisting");
+      } catch (error) {
+        expect(error.logs.some(log => log.includes("Transfer not allowed"))).to.be.true;
+      } finally {
+        await program.methods
+          .setTransferEdgePolicies(isinArg(isin), { evaluate: {} }, { evaluate: {} })
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      }
+    });
+  });
+
+  describe("12. Self Transfers", () => {
+    it("should let a non-whitelisted holder move tokens between its own accounts", async () => {
+      const isin = tokensToCreate[0].isin;
+      const token = await getTokenForIsin(isin);
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleSelfTransfer").value);
+
+      await program.methods
+        .setSelfTransferExemption(isinArg(isin), true)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const evaluations = await program.methods
+        .explainTransfer(wallets.unauthorized.publicKey, wallets.unauthorized.publicKey, new anchor.BN(1))
+        .accounts({
+          mint: token.mint,
+          sourceToken: null,
+          destinationToken: null,
+          tokenManager: tokenManagerPDA,
+          tokenShare: tokenSharePDA(isin),
+        })
+        .view();
+      expect(evaluations).to.have.length(1);
+      expect(evaluations[0].ruleId).to.equal(ruleId);
+      expect(evaluations[0].passed).to.be.true;
+
+      await program.methods
+        .setSelfTransferExemption(isinArg(isin), false)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+  });
+
+  describe("13. Market Makers", () => {
+    it("should bound the inventory of a registered market maker", async () => {
+      const isin = tokensToCreate[0].isin;
+      const token = await getTokenForIsin(isin);
+      const destinationTokenAccount = await createTokenAccount(wallets.destination, token.mint);
+      const balance = (await getAccount(
+        provider.connection,
+        destinationTokenAccount,
+        "confirmed",
+        TOKEN_2022_PROGRAM_ID,
+      )).amount;
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleMarketMakerInventory").value);
+
+      await program.methods
+        .registerMarketMaker(wallets.destination.publicKey, isinArg(isin), new anchor.BN(balance.toString()))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenMint: token.mint })
+        .rpc();
+
+      const evaluations = await program.methods
+        .explainTransfer(wallets.authorized.publicKey, wallets.destination.publicKey, new anchor.BN(1))
+        .accounts({
+          mint: token.mint,
+          sourceToken: null,
+          destinationToken: destinationTokenAccount,
+          tokenManager: tokenManagerPDA,
+          tokenShare: tokenSharePDA(isin),
+        })
+        .view();
+      expect(evaluations.find(r => r.ruleId === ruleId).passed).to.be.false;
+
+      await program.methods
+        .removeMarketMaker(wallets.destination.publicKey, isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenMint: token.mint })
+        .rpc();
+    });
+  });
+
+  describe("14. Transfer Corridors", () => {
+    const partner = wallets.authorized;
+    const partnerIsin = "FR0000120271";
+    const [partnerManagerPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("token-manager"), partner.publicKey.toBuffer()],
+      program.programId,
+    );
+
+    before(async () => {
+      try {
+        await program.account.tokenManager.fetch(partnerManagerPDA);
+      } catch (error) {
+        await program.methods
+          .initializeTokenManager(defaultManagerConfig)
+          .accounts({ signer: partner.publicKey })
+          .signers([partner])
+          .rpc();
+        await program.methods
+          .createNewShare(0, isinArg(partnerIsin), null, null)
+          .accounts({ signer: partner.publicKey, tokenManager: partnerManagerPDA })
+          .signers([partner])
+          .rpc();
+      }
+    });
+
+    it("should import a partner authorization through a co-signed corridor", async () => {
+      const [first, second] = [
+        { creator: provider.wallet.publicKey, manager: tokenManagerPDA },
+        { creator: partner.publicKey, manager: partnerManagerPDA },
+      ].sort((a, b) => a.manager.toBuffer().compare(b.manager.toBuffer()));
+      const [corridorPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("corridor"), first.manager.toBuffer(), second.manager.toBuffer()],
+        program.programId,
+      );
+
+      await program.methods
+        .openCorridor()
+        .accounts({
+          firstCreator: first.creator,
+          secondCreator: second.creator,
+          firstManager: first.manager,
+          secondManager: second.manager,
+        })
+        .signers([partner])
+        .rpc();
+
+      await program.methods
+        .applyCorridorAuthorization(wallets.destination.publicKey, isinArg(tokensToCreate[0].isin), isinArg(partnerIsin))
+        .accounts({
+          payer: provider.wallet.publicKey,
+          sourceManager: tokenManagerPDA,
+          targetManager: partnerManagerPDA,
+          corridor: corridorPDA,
+        })
+        .rpc();
+
+      const imported = (await getWhitelistForIsin(partnerIsin, partnerManagerPDA)).find(
+        auth => auth.wallet.toString() === wallets.destination.publicKey.toString()
+      );
+      expect(imported.corridor.toString()).to.equal(tokenManagerPDA.toString());
+    });
+
+    it("should refuse corridor imports into a token requiring four-eyes additions", async () => {
+      const [first, second] = [tokenManagerPDA, partnerManagerPDA].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+      const [corridorPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("corridor"), first.toBuffer(), second.toBuffer()],
+        program.programId,
+      );
+      await program.methods
+        .setWhitelistApprovalWindow(isinArg(partnerIsin), 3_600)
+        .accounts({ signer: partner.publicKey, tokenManager: partnerManagerPDA })
+        .signers([partner])
+        .rpc();
+
+      try {
+        await program.methods
+          .applyCorridorAuthorization(wallets.source.publicKey, isinArg(tokensToCreate[0].isin), isinArg(partnerIsin))
+          .accounts({
+            payer: provider.wallet.publicKey,
+            sourceManager: tokenManagerPDA,
+            targetManager: partnerManagerPDA,
+            corridor: corridorPDA,
+          })
+          .rpc();
+        expect.fail("Expected the import to require a four-eyes approval");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("WhitelistApprovalRequired");
+      }
+
+      await program.methods
+        .setWhitelistApprovalWindow(isinArg(partnerIsin), null)
+        .accounts({ signer: partner.publicKey, tokenManager: partnerManagerPDA })
+        .signers([partner])
+        .rpc();
+    });
+  });
+
+  describe("15. Relayers", () => {
+    it("should add and remove an allowlisted relayer", async () => {
+      const relayer = web3.Keypair.generate().publicKey;
+      const [relayerRegistryPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("relayers"), tokenManagerPDA.toBuffer()],
+        program.programId,
+      );
+
+      await program.methods
+        .setRelayer(relayer, true)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      let registry = await program.account.relayerRegistry.fetch(relayerRegistryPDA);
+      expect(registry.relayers.map(r => r.toString())).to.include(relayer.toString());
+
+      await program.methods
+        .setRelayer(relayer, false)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      registry = await program.account.relayerRegistry.fetch(relayerRegistryPDA);
+      expect(registry.relayers.map(r => r.toString())).to.not.include(relayer.toString());
+    });
+  });
+
+  describe("16. Subscription Agreements", () => {
+    it("should record acceptance of the current agreement only", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint: tokenMint } = await getTokenForIsin(isin);
+      const agreementHash = Array.from(Buffer.alloc(32, 7));
+
+      await program.methods
+        .publishAgreementVersion(isinArg(isin), agreementHash)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await program.methods
+        .setAgreementRequirement(isinArg(isin), true, false)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      try {
+        await program.methods
+          .acceptAgreement(isinArg(isin), Array.from(Buffer.alloc(32, 8)))
+          .accounts({
+            payer: provider.wallet.publicKey,
+            investor: provider.wallet.publicKey,
+            tokenManager: tokenManagerPDA,
+            tokenMint,
+            relayerRegistry: null,
+          })
+          .rpc();
+        expect.fail("Expected a stale agreement to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("AgreementMismatch");
+      }
+
+      await program.methods
+        .acceptAgreement(isinArg(isin), agreementHash)
+        .accounts({
+          payer: provider.wallet.publicKey,
+          investor: provider.wallet.publicKey,
+          tokenManager: tokenManagerPDA,
+          tokenMint,
+          relayerRegistry: null,
+        })
+        .rpc();
+
+      const [acceptancePDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("agreement-acceptance"), tokenMint.toBuffer(), provider.wallet.publicKey.toBuffer()],
+        program.programId,
+      );
+      const acceptance = await program.account.agreementAcceptance.fetch(acceptancePDA);
+      expect(acceptance.agreementHash).to.deep.equal(agreementHash);
+
+      await program.methods
+        .setAgreementRequirement(isinArg(isin), false, false)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+
+    it("should bump the agreement version on publication", async () => {
+      const isin = tokensToCreate[0].isin;
+      const previous = (await program.account.tokenShare.fetch(tokenSharePDA(isin))).agreementVersion;
+
+      await program.methods
+        .publishAgreementVersion(isinArg(isin), Array.from(Buffer.alloc(32, 9)))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const after = await program.account.tokenShare.fetch(tokenSharePDA(isin));
+      expect(after.agreementVersion).to.equal(previous + 1);
+    });
+  });
+
+  describe("17. Compliance Queue", () => {
+    it("should let one officer claim and resolve a task", async () => {
+      const officer = web3.Keypair.generate();
+      const [complianceQueuePDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("compliance-queue"), tokenManagerPDA.toBuffer()],
+        program.programId,
+      );
+
+      await program.methods
+        .setComplianceOfficer(officer.publicKey, true)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      await program.methods
+        .enqueueTask({ flaggedWallet: {} }, wallets.unauthorized.publicKey, { normal: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      let queue = await program.account.complianceQueue.fetch(complianceQueuePDA);
+      const task = queue.tasks.find(t => t.subject.equals(wallets.unauthorized.publicKey));
+
+      await program.methods
+        .claimTask(task.id)
+        .accounts({ signer: officer.publicKey, tokenManager: tokenManagerPDA })
+        .signers([officer])
+        .rpc();
+
+      try {
+        await program.methods
+          .claimTask(task.id)
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        expect.fail("Expected a claimed task to be protected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("TaskAlreadyClaimed");
+      }
+
+      await program.methods
+        .setComplianceOfficer(officer.publicKey, false)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      try {
+        await program.methods
+          .resolveTask(task.id)
+          .accounts({ signer: officer.publicKey, tokenManager: tokenManagerPDA })
+          .signers([officer])
+          .rpc();
+        expect.fail("Expected a removed officer to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+      await program.methods
+        .setComplianceOfficer(officer.publicKey, true)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      await program.methods
+        .resolveTask(task.id)
+        .accounts({ signer: officer.publicKey, tokenManager: tokenManagerPDA })
+        .signers([officer])
+        .rpc();
+      queue = await program.account.complianceQueue.fetch(complianceQueuePDA);
+      expect(queue.tasks.map(t => t.id.toString())).to.not.include(task.id.toString());
+    });
+
+    it("should queue transfer requests and hold them within their SLA", async () => {
+      const isin = tokensToCreate[1].isin;
+      const holder = wallets.authorized;
+      const to = web3.Keypair.generate().publicKey;
+      const { mint } = await getTokenForIsin(isin);
+      const [complianceQueuePDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("compliance-queue"), tokenManagerPDA.toBuffer()],
+        program.programId,
+      );
+      const [requestPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("transfer-request"), mint.toBuffer(), holder.publicKey.toBuffer(), to.toBuffer()],
+        program.programId,
+      );
+      await program.methods
+        .setTaskSla([0, 3_600, 0, 0], { autoApprove: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const request = async (amount: number) => {
+        await program.methods
+          .requestTransfer(isinArg(isin), to, new anchor.BN(amount))
+          .accounts({ holder: holder.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin) })
+          .signers([holder])
+          .rpc();
+        return program.account.transferRequest.fetch(requestPDA);
+      };
+      const first = await request(1);
+      const second = await request(2);
+      const queue = await program.account.complianceQueue.fetch(complianceQueuePDA);
+      const tasks = queue.tasks.filter(t => t.subject.equals(requestPDA));
+      expect(tasks.map(t => t.id.toString())).to.deep.equal([second.taskId.toString()]);
+      expect(tasks[0].id.toString()).to.not.equal(first.taskId.toString());
+      expect(tasks[0].slaDeadline.toNumber()).to.equal(tasks[0].createdAt.toNumber() + 3_600);
+
+      try {
+        await program.methods
+          .escalateTask(tasks[0].id)
+          .accounts({ tokenManager: tokenManagerPDA, transferRequest: requestPDA, holder: holder.publicKey })
+          .rpc();
+        expect.fail("Expected the task to still be within its SLA");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("TaskWithinSla");
+      }
+      expect((await program.account.transferRequest.fetch(requestPDA)).approvedBy).to.be.null;
+
+      await program.methods
+        .approveTransfer(isinArg(isin), holder.publicKey, to, new anchor.BN(2))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin) })
+        .rpc();
+      const approvedQueue = await program.account.complianceQueue.fetch(complianceQueuePDA);
+      expect(approvedQueue.tasks.map(t => t.id.toString())).to.not.include(tasks[0].id.toString());
+    });
+  });
+
+  describe("18. Whitelist History", () => {
+    it("should prove membership before and after removal", async () => {
+      const isin = tokensToCreate[1].isin;
+      const wallet = web3.Keypair.generate().publicKey;
+
+      await program.methods
+        .addToWhitelist(wallet, isinArg(isin), new anchor.BN(0))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const addedSlot = await provider.connection.getSlot("confirmed");
+
+      await program.methods
+        .removeFromWhitelist(wallet, isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const removedSlot = await provider.connection.getSlot("confirmed");
+
+      const memberWhileAdded = await program.methods
+        .proveWhitelistMembership(wallet, isinArg(isin), new anchor.BN(addedSlot))
+        .accounts({ tokenManager: tokenManagerPDA })
+        .view();
+      expect(memberWhileAdded).to.be.true;
+
+      const memberAfterRemoval = await program.methods
+        .proveWhitelistMembership(wallet, isinArg(isin), new anchor.BN(removedSlot + 1))
+        .accounts({ tokenManager: tokenManagerPDA })
+        .view();
+      expect(memberAfterRemoval).to.be.false;
+    });
+
+    it("should drop the oldest period once the history is full", async () => {
+      const isin = tokensToCreate[1].isin;
+      const wallet = web3.Keypair.generate().publicKey;
+
+      let firstAddedSlot = 0;
+      for (let period = 0; period < 17; period++) {
+        await program.methods
+          .addToWhitelist(wallet, isinArg(isin), new anchor.BN(0))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        if (period === 0) {
+          firstAddedSlot = await provider.connection.getSlot("confirmed");
+        }
+        await program.methods
+          .removeFromWhitelist(wallet, isinArg(isin))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      }
+
+      const memberInDroppedPeriod = await program.methods
+        .proveWhitelistMembership(wallet, isinArg(isin), new anchor.BN(firstAddedSlot))
+        .accounts({ tokenManager: tokenManagerPDA })
+        .view();
+      expect(memberInDroppedPeriod).to.be.false;
+    });
+  });
+
+  describe("19. Whitelist Notes", () => {
+    it("should attach and clear an encrypted note", async () => {
+      const isin = tokensToCreate[0].isin;
+      const note = Array.from(Buffer.alloc(64, 3));
+      const { mint } = await getTokenForIsin(isin);
+      const personalData = personalDataPDA(mint, wallets.destination.publicKey);
+
+      await program.methods
+        .setWhitelistNote(wallets.destination.publicKey, isinArg(isin), note)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, complianceQueue: null })
+        .rpc();
+      expect((await program.account.personalData.fetch(personalData)).note).to.deep.equal(note);
+
+      await program.methods
+        .setWhitelistNote(wallets.destination.publicKey, isinArg(isin), null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, complianceQueue: null })
+        .rpc();
+      expect((await program.account.personalData.fetch(personalData)).note).to.be.null;
+    });
+
+    it("should let the investor purge its personal data while staying whitelisted", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const personalData = personalDataPDA(mint, wallets.destination.publicKey);
+
+      await program.methods
+        .setWhitelistNote(wallets.destination.publicKey, isinArg(isin), Array.from(Buffer.alloc(64, 4)))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, complianceQueue: null })
+        .rpc();
+      try {
+        await program.methods
+          .purgePersonalData(wallets.destination.publicKey, isinArg(isin))
+          .accounts({ signer: wallets.unauthorized.publicKey, tokenManager: tokenManagerPDA, complianceQueue: null })
+          .signers([wallets.unauthorized])
+          .rpc();
+        expect.fail("Expected an unrelated wallet to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+
+      await program.methods
+        .purgePersonalData(wallets.destination.publicKey, isinArg(isin))
+        .accounts({ signer: wallets.destination.publicKey, tokenManager: tokenManagerPDA, complianceQueue: null })
+        .signers([wallets.destination])
+        .rpc();
+      expect(await program.account.personalData.fetchNullable(personalData)).to.be.null;
+      expect(await program.account.whitelistEntry.fetchNullable(whitelistEntryPDA(mint, wallets.destination.publicKey))).to.not.be.null;
+    });
+  });
+
+  describe("20. Permission Matrix", () => {
+    it("should report the roles of the creator and of an investor", async () => {
+      const creatorPermissions = await program.methods
+        .getPermissions(provider.wallet.publicKey)
+        .accounts({ tokenManager: tokenManagerPDA, complianceQueue: null, relayerRegistry: null })
+        .view();
+      expect(creatorPermissions.creator).to.be.true;
+
+      const { mint } = await getTokenForIsin(tokensToCreate[0].isin);
+      const investorPermissions = await program.methods
+        .getPermissions(wallets.destination.publicKey)
+        .accounts({ tokenManager: tokenManagerPDA, complianceQueue: null, relayerRegistry: null })
+        .remainingAccounts([
+          { pubkey: tokenWhitelistPDA(tokensToCreate[0].isin), isSigner: false, isWritable: false },
+          {
+            pubkey: whitelistEntryPDA(mint, wallets.destination.publicKey),
+            isSigner: false,
+            isWritable: false,
+          },
+        ])
+        .view();
+      expect(investorPermissions.creator).to.be.false;
+      const token = investorPermissions.tokens.find(t => t.mint.equals(mint));
+      expect(token.whitelisted).to.be.true;
+    });
+  });
+
+  describe("21. Module Suspensions", () => {
+    it("should report a suspended rule as passed until the suspension ends", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const constant = (name: string) => Number(program.idl.constants.find(c => c.name === name).value);
+      const rule = constant("ruleIncomingConsent");
+      const stranger = web3.Keypair.generate().publicKey;
+      const explain = () =>
+        program.methods
+          .explainTransfer(provider.wallet.publicKey, stranger, new anchor.BN(1))
+          .accounts({
+            mint,
+            sourceToken: null,
+            destinationToken: null,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+          })
+          .view();
+      const suspend = (ruleId: number, untilSlot: number) =>
+        program.methods
+          .suspendModule(ruleId, new anchor.BN(untilSlot))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      const requireConsent = (required: boolean) =>
+        program.methods
+          .setIncomingConsentRequirement(isinArg(isin), required)
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+
+      const slot = await provider.connection.getSlot("confirmed");
+      try {
+        await suspend(constant("ruleDestinationWhitelisted"), slot + 1000);
+        expect.fail("Expected the whitelist rule to stay enforced");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("ModuleNotSuspendable");
+      }
+
+      await requireConsent(true);
+      await suspend(rule, slot + 1000);
+      let evaluations = await explain();
+      expect(evaluations.find(e => e.ruleId === rule).passed).to.be.true;
+
+      await suspend(rule, 0);
+      evaluations = await explain();
+      expect(evaluations.find(e => e.ruleId === rule).passed).to.be.false;
+      await requireConsent(false);
+    });
+  });
+
+  describe("22. Transfer Receipts", () => {
+    it("should enable and close receipts for a token", async () => {
+      const isin = tokensToCreate[2].isin;
+      const { mint: tokenMint } = await getTokenForIsin(isin);
+      const [receiptsPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("transfer-receipts"), tokenMint.toBuffer()],
+        program.programId,
+      );
+
+      await program.methods
+        .configureTransferReceipts(isinArg(isin), 0)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenMint })
+        .rpc();
+      const receipts = await program.account.transferReceiptLog.fetch(receiptsPDA);
+      expect(receipts.mint.toString()).to.equal(tokenMint.toString());
+      expect(receipts.receipts).to.have.length(0);
+
+      await program.methods
+        .closeTransferReceipts(isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenMint })
+        .rpc();
+      expect(await provider.connection.getAccountInfo(receiptsPDA)).to.be.null;
+    });
+  });
+
+  describe("23. Clock Tolerance", () => {
+    it("should store a tolerance and reject one above the maximum", async () => {
+      const max = Number(program.idl.constants.find(c => c.name === "maxClockToleranceSeconds").value);
+
+      await program.methods
+        .setClockTolerance(30)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const manager = await program.account.tokenManager.fetch(tokenManagerPDA);
+      expect(manager.clockToleranceSeconds).to.equal(30);
+
+      try {
+        await program.methods
+          .setClockTolerance(max + 1)
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        expect.fail("Expected an excessive tolerance to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidClockTolerance");
+      }
+
+      await program.methods
+        .setClockTolerance(0)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+  });
+
+  describe("24. Transfer Hook Authority", () => {
+    it("should remove the hook of a wound-down token and drop its authority", async () => {
+      const isin = "XS0000000033";
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint } = await getTokenForIsin(isin);
+
+      await program.methods
+        .removeTransferHook(isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenMint: mint })
+        .rpc();
+      let hook = getTransferHook(await getMint(provider.connection, mint, "confirmed", TOKEN_2022_PROGRAM_ID));
+      expect(hook.programId.equals(PublicKey.default)).to.be.true;
+      expect(hook.authority.equals(tokenManagerPDA)).to.be.true;
+
+      await program.methods
+        .setTransferHookAuthority(isinArg(isin), null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenMint: mint })
+        .rpc();
+      hook = getTransferHook(await getMint(provider.connection, mint, "confirmed", TOKEN_2022_PROGRAM_ID));
+      expect(hook.authority.equals(PublicKey.default)).to.be.true;
+    });
+  });
+
+  describe("25. Trusted Vaults", () => {
+    it("should fast-path transfers between trusted vaults only", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleTrustedVault").value);
+      const vaultSeeds = (seed: string) => {
+        const [vault, bump] = PublicKey.findProgramAddressSync([Buffer.from(seed)], program.programId);
+        return { vault, seeds: [Buffer.from(seed), Buffer.from([bump])] };
+      };
+      const { vault: escrow, seeds: escrowSeeds } = vaultSeeds("escrow");
+      const { vault: treasury, seeds: treasurySeeds } = vaultSeeds("treasury");
+      const register = (vault: PublicKey, seeds: Buffer[]) =>
+        program.methods
+          .registerTrustedVault(vault, isinArg(isin), seeds)
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenMint: mint })
+          .rpc();
+
+      // A wallet, or a PDA of another program, is not a vault of this program
+      const [foreignVault, foreignBump] = PublicKey.findProgramAddressSync([Buffer.from("escrow")], TOKEN_PROGRAM_ID);
+      for (const [vault, seeds] of [
+        [wallets.destination.publicKey, escrowSeeds],
+        [foreignVault, [Buffer.from("escrow"), Buffer.from([foreignBump])]],
+      ] as [PublicKey, Buffer[]][]) {
+        try {
+          await register(vault, seeds);
+          expect.fail("Expected the vault to be rejected as trusted vault");
+        } catch (err: any) {
+          expect(err.error.errorCode.code).to.equal("VaultNotPda");
+        }
+      }
+
+      await register(escrow, escrowSeeds);
+      await register(treasury, treasurySeeds);
+
+      const explain = (from: PublicKey, to: PublicKey) =>
+        program.methods
+          .explainTransfer(from, to, new anchor.BN(1))
+          .accounts({
+            mint,
+            sourceToken: null,
+            destinationToken: null,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+          })
+          .view();
+
+      const internal = await explain(escrow, treasury);
+      expect(internal).to.have.length(1);
+      expect(internal[0].ruleId).to.equal(ruleId);
+      expect(internal[0].passed).to.be.true;
+
+      const outbound = await explain(escrow, wallets.unauthorized.publicKey);
+      expect(outbound.find(r => r.ruleId === ruleId)).to.be.undefined;
+
+      for (const vault of [escrow, treasury]) {
+        await program.methods
+          .removeTrustedVault(vault, isinArg(isin))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenMint: mint })
+          .rpc();
+      }
+    });
+  });
+
+  describe("26. Settlement Batches", () => {
+    it("should settle a security and a cash leg atomically", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      const paymentMint = await createPaymentMint();
+      const sellerCash = await getOrCreateAssociatedTokenAccount(
+        provider.connection, payer, paymentMint, wallets.authorized.publicKey,
+      );
+      const buyerCash = await getOrCreateAssociatedTokenAccount(
+        provider.connection, payer, paymentMint, wallets.destination.publicKey,
+      );
+      await mintTo(provider.connection, payer, paymentMint, buyerCash.address, payer, 500);
+      // The seller acts as venue: the buyer delegates its cash account to it
+      await approve(provider.connection, payer, buyerCash.address, wallets.authorized.publicKey, wallets.destination, 500);
+
+      const sellerSecurity = await createTokenAccount(wallets.authorized, mint);
+      const buyerSecurity = await createTokenAccount(wallets.destination, mint);
+      const balance = async (account: PublicKey) =>
+        (await getAccount(provider.connection, account, "confirmed", TOKEN_2022_PROGRAM_ID)).amount;
+      const buyerBefore = await balance(buyerSecurity);
+
+      await program.methods
+        .settleBatch(isinArg(isin), [
+          { asset: { security: {} }, from: 0, to: 1, amount: new anchor.BN(5) },
+          { asset: { cash: {} }, from: 2, to: 3, amount: new anchor.BN(500) },
+        ], 0)
+        .accounts({
+          venue: wallets.authorized.publicKey,
+          tokenManager: tokenManagerPDA,
+          paymentMint,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          feeCollector: null,
+        })
+        .remainingAccounts([
+          ...[sellerSecurity, buyerSecurity, buyerCash.address, sellerCash.address].map(pubkey => ({
+            pubkey,
+            isSigner: false,
+            isWritable: true,
+          })),
+          { pubkey: whitelistEntryPDA(mint, wallets.destination.publicKey), isSigner: false, isWritable: false },
+        ])
+        .signers([wallets.authorized])
+        .rpc();
+
+      expect((await balance(buyerSecurity)) - buyerBefore).to.equal(BigInt(5));
+      const sellerCashAfter = await getAccount(provider.connection, sellerCash.address, "confirmed", TOKEN_PROGRAM_ID);
+      expect(sellerCashAfter.amount).to.equal(BigInt(500));
+    });
+
+    it("should check security legs against the agreement acceptance of the buyer", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const sellerSecurity = await createTokenAccount(wallets.authorized, mint);
+      const buyerSecurity = await createTokenAccount(wallets.destination, mint);
+      const paymentMint = await createPaymentMint();
+      const [acceptancePDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("agreement-acceptance"), mint.toBuffer(), wallets.destination.publicKey.toBuffer()],
+        program.programId,
+      );
+      const settle = (records: PublicKey[]) =>
+        program.methods
+          .settleBatch(isinArg(isin), [{ asset: { security: {} }, from: 0, to: 1, amount: new anchor.BN(1) }], 0)
+          .accounts({
+            venue: wallets.authorized.publicKey,
+            tokenManager: tokenManagerPDA,
+            paymentMint,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+            feeCollector: null,
+          })
+          .remainingAccounts([
+            ...[sellerSecurity, buyerSecurity].map(pubkey => ({ pubkey, isSigner: false, isWritable: true })),
+            ...[whitelistEntryPDA(mint, wallets.destination.publicKey), ...records].map(pubkey => ({
+              pubkey,
+              isSigner: false,
+              isWritable: false,
+            })),
+          ])
+          .signers([wallets.authorized])
+          .rpc();
+
+      await program.methods
+        .setAgreementRequirement(isinArg(isin), true, false)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      try {
+        await settle([]);
+        expect.fail("Expected the leg to need the acceptance of the buyer");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("TransferNotAllowed");
+      }
+
+      const { agreementHash } = await program.account.tokenShare.fetch(tokenSharePDA(isin));
+      await program.methods
+        .acceptAgreement(isinArg(isin), agreementHash)
+        .accounts({
+          payer: provider.wallet.publicKey,
+          investor: wallets.destination.publicKey,
+          tokenManager: tokenManagerPDA,
+          tokenMint: mint,
+          relayerRegistry: null,
+        })
+        .signers([wallets.destination])
+        .rpc();
+      await settle([acceptancePDA]);
+
+      await program.methods
+        .setAgreementRequirement(isinArg(isin), false, false)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+  });
+
+  describe("27. Netting", () => {
+    it("should settle opposite trades as a single net transfer per asset", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      const venue = wallets.authorized;
+      const counterparty = wallets.destination;
+      const paymentMint = await createPaymentMint();
+      const venueCash = await getOrCreateAssociatedTokenAccount(provider.connection, payer, paymentMint, venue.publicKey);
+      const counterpartyCash = await getOrCreateAssociatedTokenAccount(
+        provider.connection, payer, paymentMint, counterparty.publicKey,
+      );
+      await mintTo(provider.connection, payer, paymentMint, counterpartyCash.address, payer, 100);
+      await approve(provider.connection, payer, counterpartyCash.address, venue.publicKey, counterparty, 100);
+
+      for (const [seller, buyer, quantity, cash] of [
+        [venue.publicKey, counterparty.publicKey, 10, 100],
+        [counterparty.publicKey, venue.publicKey, 4, 40],
+      ] as [PublicKey, PublicKey, number, number][]) {
+        await program.methods
+          .registerTrade(isinArg(isin), seller, buyer, new anchor.BN(quantity), new anchor.BN(cash), 0)
+          .accounts({ venue: venue.publicKey, tokenManager: tokenManagerPDA, paymentMint })
+          .signers([venue])
+          .rpc();
+      }
+
+      const venueSecurity = await createTokenAccount(venue, mint);
+      const counterpartySecurity = await createTokenAccount(counterparty, mint);
+      const before = (await getAccount(provider.connection, counterpartySecurity, "confirmed", TOKEN_2022_PROGRAM_ID)).amount;
+
+      await program.methods
+        .netAndSettle(isinArg(isin))
+        .accounts({
+          venue: venue.publicKey,
+          tokenManager: tokenManagerPDA,
+          paymentMint,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          feeCollector: null,
+        })
+        .remainingAccounts([
+          ...[venueSecurity, counterpartySecurity, venueCash.address, counterpartyCash.address].map(pubkey => ({
+            pubkey,
+            isSigner: false,
+            isWritable: true,
+          })),
+          { pubkey: whitelistEntryPDA(mint, counterparty.publicKey), isSigner: false, isWritable: false },
+        ])
+        .signers([venue])
+        .rpc();
+
+      const after = (await getAccount(provider.connection, counterpartySecurity, "confirmed", TOKEN_2022_PROGRAM_ID)).amount;
+      expect(after - before).to.equal(BigInt(6));
+      const cash = await getAccount(provider.connection, venueCash.address, "confirmed", TOKEN_PROGRAM_ID);
+      expect(cash.amount).to.equal(BigInt(60));
+
+      const [cyclePDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("netting-cycle"), mint.toBuffer(), venue.publicKey.toBuffer()],
+        program.programId,
+      );
+      const cycle = await program.account.nettingCycle.fetch(cyclePDA);
+      expect(cycle.obligations).to.be.empty;
+    });
+  });
+
+  describe("28. Settlement Fails", () => {
+    it("should accrue penalties on a late settlement and gate the buy-in", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      const paymentMint = await createPaymentMint();
+      const venue = wallets.authorized;
+      const failingParty = wallets.destination;
+      const counterparty = wallets.unauthorized;
+      const failingPayment = await getOrCreateAssociatedTokenAccount(
+        provider.connection, payer, paymentMint, failingParty.publicKey,
+      );
+      const counterpartyPayment = await getOrCreateAssociatedTokenAccount(
+        provider.connection, payer, paymentMint, counterparty.publicKey,
+      );
+      await mintTo(provider.connection, payer, paymentMint, failingPayment.address, payer, 1_000_000);
+      await approve(provider.connection, payer, failingPayment.address, venue.publicKey, failingParty, 1_000_000);
+
+      await program.methods
+        .setFailPenalty(isinArg(isin), 10, 4)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      // Intended to settle three days ago: 30 bps accrued, buy-in one day away
+      const failId = new anchor.BN(Date.now());
+      const intendedSettlement = Math.floor(Date.now() / 1000) - 3 * 86_400 - 60;
+      await program.methods
+        .openSettlementFail(isinArg(isin), failId, {
+          failingParty: failingParty.publicKey,
+          counterparty: counterparty.publicKey,
+          quantity: new anchor.BN(10),
+          cashAmount: new anchor.BN(1_000_000),
+          intendedSettlement: new anchor.BN(intendedSettlement),
+        })
+        .accounts({ venue: venue.publicKey, tokenManager: tokenManagerPDA, paymentMint })
+        .signers([venue])
+        .rpc();
+      const [settlementFailPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("settlement-fail"), mint.toBuffer(), venue.publicKey.toBuffer(), failId.toArrayLike(Buffer, "le", 8)],
+        program.programId,
+      );
+
+      try {
+        await program.methods
+          .declareBuyIn(isinArg(isin))
+          .accounts({ counterparty: counterparty.publicKey, tokenManager: tokenManagerPDA, settlementFail: settlementFailPDA })
+          .signers([counterparty])
+          .rpc();
+        expect.fail("Expected the buy-in to be too early");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("BuyInNotYetAllowed");
+      }
+
+      await program.methods
+        .closeSettlementFail(isinArg(isin))
+        .accounts({
+          venue: venue.publicKey,
+          tokenManager: tokenManagerPDA,
+          paymentMint,
+          settlementFail: settlementFailPDA,
+          failingPartyPayment: failingPayment.address,
+          counterpartyPayment: counterpartyPayment.address,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([venue])
+        .rpc();
+      const credited = await getAccount(provider.connection, counterpartyPayment.address, "confirmed", TOKEN_PROGRAM_ID);
+      expect(credited.amount).to.equal(BigInt(3_000));
+
+      await program.methods
+        .setFailPenalty(isinArg(isin), 0, 0)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+  });
+
+  describe("29. Payment Mints", () => {
+    it("should only accept approved payment mints", async () => {
+      const isin = tokensToCreate[0].isin;
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      const fakeStablecoin = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+      const trade = (paymentMint: PublicKey) =>
+        program.methods
+          .registerTrade(isinArg(isin), wallets.authorized.publicKey, wallets.destination.publicKey, new anchor.BN(1), new anchor.BN(1), 0)
+          .accounts({ venue: provider.wallet.publicKey, tokenManager: tokenManagerPDA, paymentMint })
+          .rpc();
+
+      try {
+        await trade(fakeStablecoin);
+        expect.fail("Expected an unapproved payment mint to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("PaymentMintNotApproved");
+      }
+
+      await program.methods
+        .setPaymentMint(web3.Keypair.generate().publicKey, true)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, paymentMint: fakeStablecoin })
+        .rpc();
+      await trade(fakeStablecoin);
+
+      await program.methods
+        .setPaymentMint(null, false)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, paymentMint: fakeStablecoin })
+        .rpc();
+    });
+  });
+
+  describe("30. Offers", () => {
+    const isin = tokensToCreate[0].isin;
+    const payer = () => (provider.wallet as anchor.Wallet).payer;
+
+    async function openOffer(kind, price: number, cap: number, paymentMint: PublicKey) {
+      const offerId = new anchor.BN(Date.now());
+      const manager = await program.account.tokenManager.fetch(tokenManagerPDA);
+      const closesAt = Math.floor(Date.now() / 1000) + 4;
+      await program.methods
+        .createOffer(isinArg(isin), offerId, kind, new anchor.BN(price), new anchor.BN(cap), new anchor.BN(closesAt))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, paymentMint, paymentTokenProgram: TOKEN_PROGRAM_ID })
+        .rpc();
+      const { mint } = await getTokenForIsin(isin);
+      const [offer] = PublicKey.findProgramAddressSync(
+        [Buffer.from("offer"), mint.toBuffer(), offerId.toArrayLike(Buffer, "le", 8)],
+        program.programId,
+      );
+      const waitForClose = () =>
+        new Promise(resolve => setTimeout(resolve, (4 + manager.clockToleranceSeconds + 2) * 1000));
+      return { offer, waitForClose };
+    }
+
+    async function placeAndSettle(offer: PublicKey, paymentMint: PublicKey, orders: [web3.Keypair, number][]) {
+      const { mint } = await getTokenForIsin(isin);
+      const { price, fees } = await program.account.offer.fetch(offer);
+      const accounts = [];
+      for (const [investor, amount] of orders) {
+        const investorSecurity = await createTokenAccount(investor, mint);
+        const investorPayment = (await getOrCreateAssociatedTokenAccount(
+          provider.connection, payer(), paymentMint, investor.publicKey,
+        )).address;
+        await program.methods
+          .placeOrder(isinArg(isin), new anchor.BN(amount), price, fees.scheduleVersion)
+          .accounts({
+            payer: investor.publicKey,
+            investor: investor.publicKey,
+            tokenManager: tokenManagerPDA,
+            relayerRegistry: null,
+            offer,
+            paymentMint,
+            investorSecurity,
+            investorPayment,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([investor])
+          .rpc();
+        accounts.push({ investor, investorSecurity, investorPayment });
+      }
+      return accounts;
+    }
+
+    async function settle(offer: PublicKey, paymentMint: PublicKey, account) {
+      const issuerPayment = (await getOrCreateAssociatedTokenAccount(
+        provider.connection, payer(), paymentMint, provider.wallet.publicKey,
+      )).address;
+      await program.methods
+        .settleOrder(isinArg(isin))
+        .accounts({
+          tokenManager: tokenManagerPDA,
+          offer,
+          paymentMint,
+          investor: account.investor.publicKey,
+          investorSecurity: account.investorSecurity,
+          investorPayment: account.investorPayment,
+          issuerPayment,
+          investorStatement: null,
+          fxRate: null,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+    }
+
+    async function crankOrders(offer: PublicKey, paymentMint: PublicKey, cursor: number, page, keeper = payer(), keeperRewards = null) {
+      const { mint } = await getTokenForIsin(isin);
+      const issuerPayment = (await getOrCreateAssociatedTokenAccount(
+        provider.connection, payer(), paymentMint, provider.wallet.publicKey,
+      )).address;
+      await program.methods
+        .settleOrders(isinArg(isin), new anchor.BN(cursor), 8)
+        .accounts({
+          keeper: keeper.publicKey,
+          tokenManager: tokenManagerPDA,
+          offer,
+          paymentMint,
+          issuerPayment,
+          fxRate: null,
+          keeperRewards,
+          rewardVault: null,
+          keeperRewardAccount: null,
+          rewardMint: null,
+          rewardTokenProgram: null,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(page.flatMap(account => {
+          const [order] = PublicKey.findProgramAddressSync(
+            [Buffer.from("offer-order"), offer.toBuffer(), account.investor.publicKey.toBuffer()],
+            program.programId,
+          );
+          const [statement] = PublicKey.findProgramAddressSync(
+            [Buffer.from("holder-statement"), mint.toBuffer(), account.investor.publicKey.toBuffer()],
+            program.programId,
+          );
+          return [order, account.investor.publicKey, account.investorSecurity, account.investorPayment, statement]
+            .map(pubkey => ({ pubkey, isSigner: false, isWritable: true }));
+        }))
+        .signers([keeper])
+        .rpc();
+    }
+
+    const balance = async (account: PublicKey, programId = TOKEN_PROGRAM_ID) =>
+      (await getAccount(provider.connection, account, "confirmed", programId)).amount;
+
+    it("should pro-rate an oversubscribed subscription and refund the rest", async () => {
+      const paymentMint = await createPaymentMint();
+      const second = web3.Keypair.generate();
+      await fundWallet(second);
+      await program.methods
+        .addToWhitelist(second.publicKey, isinArg(isin), new anchor.BN(0))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      for (const investor of [wallets.destination, second]) {
+        const investorPayment = await getOrCreateAssociatedTokenAccount(
+          provider.connection, payer(), paymentMint, investor.publicKey,
+        );
+        await mintTo(provider.connection, payer(), paymentMint, investorPayment.address, payer(), 2_000);
+      }
+
+      // 300 units requested for a cap of 100: fills of 200 * 100 / 300 and
+      // 100 * 100 / 300 round down to 66 and 33, leaving one unit unissued
+      const { offer, waitForClose } = await openOffer({ subscription: {} }, 10, 100, paymentMint);
+      const orders = await placeAndSettle(offer, paymentMint, [[wallets.destination, 200], [second, 100]]);
+      try {
+        await placeAndSettle(offer, paymentMint, [[second, 0]]);
+        expect.fail("Expected an empty order to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidOrderAmount");
+      }
+      const securityBefore = await Promise.all(orders.map(o => balance(o.investorSecurity, TOKEN_2022_PROGRAM_ID)));
+
+      try {
+        await settle(offer, paymentMint, orders[0]);
+        expect.fail("Expected the offer to still be open");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("OfferStillOpen");
+      }
+      await waitForClose();
+      for (const order of orders) {
+        await settle(offer, paymentMint, order);
+      }
+
+      const filled = [66n, 33n];
+      for (const [i, order] of orders.entries()) {
+        expect(await balance(order.investorSecurity, TOKEN_2022_PROGRAM_ID)).to.equal(securityBefore[i] + filled[i]);
+      }
+      expect(await balance(orders[0].investorPayment)).to.equal(BigInt(2_000 - 660));
+      expect(await balance(orders[1].investorPayment)).to.equal(BigInt(2_000 - 330));
+
+      await program.methods
+        .removeFromWhitelist(second.publicKey, isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+
+    it("should fill buyback tenders pro-rata and return the unfilled tokens", async () => {
+      const paymentMint = await createPaymentMint();
+      const { mint, index } = await getTokenForIsin(isin);
+      const holder = wallets.destination;
+      const holderSecurity = await createTokenAccount(holder, mint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(30))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: holderSecurity })
+        .rpc();
+      const securityBefore = await balance(holderSecurity, TOKEN_2022_PROGRAM_ID);
+
+      const issuerPayment = (await getOrCreateAssociatedTokenAccount(
+        provider.connection, payer(), paymentMint, provider.wallet.publicKey,
+      )).address;
+      await mintTo(provider.connection, payer(), paymentMint, issuerPayment, payer(), 110);
+
+      // A single tender of 30 units for a cap of 20 is filled for 20 units, so
+      // the budget must cover 100
+      const { offer, waitForClose } = await openOffer({ buyback: {} }, 5, 20, paymentMint);
+      const [offerVault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("offer-vault"), offer.toBuffer()],
+        program.programId,
+      );
+      const offerCash = { signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, offer, paymentMint, issuerPayment, paymentTokenProgram: TOKEN_PROGRAM_ID };
+      await program.methods.fundOffer(isinArg(isin), new anchor.BN(60)).accounts(offerCash).rpc();
+      try {
+        await placeAndSettle(offer, paymentMint, [[holder, 30]]);
+        expect.fail("Expected an underfunded buyback to refuse the tender");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("BuybackUnderfunded");
+      }
+      await program.methods.fundOffer(isinArg(isin), new anchor.BN(50)).accounts(offerCash).rpc();
+      const [order] = await placeAndSettle(offer, paymentMint, [[holder, 30]]);
+      expect(await balance(holderSecurity, TOKEN_2022_PROGRAM_ID)).to.equal(securityBefore - 30n);
+
+      await waitForClose();
+      await settle(offer, paymentMint, order);
+
+      expect(await balance(holderSecurity, TOKEN_2022_PROGRAM_ID)).to.equal(securityBefore - 20n);
+      expect(await balance(order.investorPayment)).to.equal(100n);
+      expect(await balance(offerVault)).to.equal(10n);
+
+      await program.methods.closeOffer(isinArg(isin)).accounts(offerCash).rpc();
+      expect(await balance(offerVault)).to.equal(0n);
+      expect(await balance(issuerPayment)).to.equal(10n);
+    });
+
+    it("should mint non-transferable receipts for orders and burn them on settlement", async () => {
+      const paymentMint = await createPaymentMint();
+      const investor = wallets.destination;
+      const investorPayment = await getOrCreateAssociatedTokenAccount(
+        provider.connection, payer(), paymentMint, investor.publicKey,
+      );
+      await mintTo(provider.connection, payer(), paymentMint, investorPayment.address, payer(), 100);
+
+      const { offer, waitForClose } = await openOffer({ subscription: {} }, 1, 100, paymentMint);
+      await program.methods
+        .enableOfferReceipts(isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, offer })
+        .rpc();
+      const [investorReceipt] = PublicKey.findProgramAddressSync(
+        [Buffer.from("offer-receipt"), offer.toBuffer(), investor.publicKey.toBuffer()],
+        program.programId,
+      );
+
+      const [order] = await placeAndSettle(offer, paymentMint, [[investor, 40]]);
+      expect(await balance(investorReceipt, TOKEN_2022_PROGRAM_ID)).to.equal(40n);
+      expect((await getAccount(provider.connection, investorReceipt, "confirmed", TOKEN_2022_PROGRAM_ID)).owner.toBase58())
+        .to.equal(investor.publicKey.toBase58());
+
+      await waitForClose();
+      await settle(offer, paymentMint, order);
+      expect(await balance(investorReceipt, TOKEN_2022_PROGRAM_ID)).to.equal(0n);
+    });
+
+    it("should settle orders in pages from the crank cursor", async () => {
+      const paymentMint = await createPaymentMint();
+      const investors = [web3.Keypair.generate(), web3.Keypair.generate()];
+      for (const investor of investors) {
+        await fundWallet(investor);
+        await program.methods
+          .addToWhitelist(investor.publicKey, isinArg(isin), new anchor.BN(0))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        const investorPayment = await getOrCreateAssociatedTokenAccount(
+          provider.connection, payer(), paymentMint, investor.publicKey,
+        );
+        await mintTo(provider.connection, payer(), paymentMint, investorPayment.address, payer(), 100);
+      }
+
+      const { offer, waitForClose } = await openOffer({ subscription: {} }, 1, 1_000, paymentMint);
+      const orders = await placeAndSettle(offer, paymentMint, investors.map(investor => [investor, 10] as [web3.Keypair, number]));
+      await waitForClose();
+
+      const [crankState] = PublicKey.findProgramAddressSync(
+        [Buffer.from("crank"), offer.toBuffer()],
+        program.programId,
+      );
+      const crank = (cursor: number, page) => crankOrders(offer, paymentMint, cursor, page);
+
+      await crank(0, orders.slice(0, 1));
+      let state = await program.account.crankState.fetch(crankState);
+      expect(state.cursor.toNumber()).to.equal(1);
+      expect(state.completed).to.equal(false);
+
+      try {
+        await crank(0, orders.slice(1));
+        expect.fail("Expected a stale cursor to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("CrankCursorMismatch");
+      }
+      await crank(1, orders.slice(1));
+      state = await program.account.crankState.fetch(crankState);
+      expect(state.processed.toNumber()).to.equal(2);
+      expect(state.completed).to.equal(true);
+      for (const order of orders) {
+        expect(await balance(order.investorSecurity, TOKEN_2022_PROGRAM_ID)).to.equal(10n);
+      }
+
+      for (const investor of investors) {
+        await program.methods
+          .removeFromWhitelist(investor.publicKey, isinArg(isin))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      }
+    });
+
+    it("should pay the keeper bounty for every order settled", async () => {
+      const paymentMint = await createPaymentMint();
+      const keeper = web3.Keypair.generate();
+      await fundWallet(keeper);
+      const [keeperRewards] = PublicKey.findProgramAddressSync(
+        [Buffer.from("keeper-rewards"), tokenManagerPDA.toBuffer()],
+        program.programId,
+      );
+      await program.methods
+        .setKeeperBounty(new anchor.BN(1_000))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, rewardMint: null, rewardVault: null, rewardTokenProgram: null })
+        .rpc();
+      await provider.sendAndConfirm(new web3.Transaction().add(
+        web3.SystemProgram.transfer({ fromPubkey: provider.wallet.publicKey, toPubkey: keeperRewards, lamports: 1_500 }),
+      ));
+      const holderPayment = await getOrCreateAssociatedTokenAccount(
+        provider.connection, payer(), paymentMint, wallets.destination.publicKey,
+      );
+      await mintTo(provider.connection, payer(), paymentMint, holderPayment.address, payer(), 100);
+
+      const { offer, waitForClose } = await openOffer({ subscription: {} }, 1, 1_000, paymentMint);
+      const orders = await placeAndSettle(offer, paymentMint, [[wallets.destination, 10]]);
+      await waitForClose();
+      await crankOrders(offer, paymentMint, 0, orders, keeper, keeperRewards);
+
+      // A single order earns the full bounty of 1000 lamports out of the 1500 funded
+      const rewards = await program.account.keeperRewards.fetch(keeperRewards);
+      expect(rewards.paid.toNumber()).to.equal(1_000);
+    });
+  });
+
+  describe("31. Treasury Yield", () => {
+    it("should only sweep to allowlisted programs within their cap", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const paymentMint = await createPaymentMint();
+      const offerId = new anchor.BN(Date.now());
+      await program.methods
+        .createOffer(isinArg(isin), offerId, { buyback: {} }, new anchor.BN(1), new anchor.BN(1_000), new anchor.BN(Math.floor(Date.now() / 1000) + 3_600))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, paymentMint, paymentTokenProgram: TOKEN_PROGRAM_ID })
+        .rpc();
+      const [offer] = PublicKey.findProgramAddressSync(
+        [Buffer.from("offer"), mint.toBuffer(), offerId.toArrayLike(Buffer, "le", 8)],
+        program.programId,
+      );
+      const guardian = wallets.unauthorized;
+      await program.methods
+        .setTreasuryGuardian(guardian.publicKey)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      try {
+        await program.methods
+          .setYieldProgram(TOKEN_PROGRAM_ID, new anchor.BN(100), true)
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        expect.fail("Expected the token program to be refused as a yield program");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("YieldProgramNotAllowed");
+      }
+      const yieldProgram = new PublicKey("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+      await program.methods
+        .setYieldProgram(yieldProgram, new anchor.BN(100), true)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const sweep = (yieldProgram: PublicKey, amount: number) =>
+        program.methods
+          .sweepToYield(isinArg(isin), new anchor.BN(amount), Buffer.from([]))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, offer, yieldProgram })
+          .rpc();
+      try {
+        await sweep(web3.SystemProgram.programId, 1);
+        expect.fail("Expected a program off the allowlist to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("YieldProgramNotAllowed");
+      }
+      try {
+        await sweep(yieldProgram, 101);
+        expect.fail("Expected the cap to be enforced");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("YieldCapExceeded");
+      }
+
+      try {
+        await program.methods
+          .emergencyRecall(isinArg(isin), Buffer.from([]))
+          .accounts({ signer: wallets.authorized.publicKey, tokenManager: tokenManagerPDA, offer, yieldProgram })
+          .signers([wallets.authorized])
+          .rpc();
+        expect.fail("Expected only the guardian to trigger an emergency recall");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+
+      await program.methods
+        .setYieldProgram(yieldProgram, new anchor.BN(0), false)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const treasuryYield = await program.account.treasuryYield.fetch(
+        PublicKey.findProgramAddressSync([Buffer.from("treasury-yield"), tokenManagerPDA.toBuffer()], program.programId)[0],
+      );
+      expect(treasuryYield.guardian.toString()).to.equal(guardian.publicKey.toString());
+      expect(treasuryYield.programs).to.be.empty;
+    });
+  });
+
+  describe("32. Record Date Blackouts", () => {
+    it("should block transfers around the record date of a corporate action", async () => {
+      const isin = tokensToCreate[1].isin;
+      const token = await getTokenForIsin(isin);
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleRecordDateBlackout").value);
+      const explain = () =>
+        program.methods
+          .explainTransfer(wallets.authorized.publicKey, wallets.destination.publicKey, new anchor.BN(1))
+          .accounts({
+            mint: token.mint,
+            sourceToken: null,
+            destinationToken: null,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+          })
+          .view();
+
+      // Record date in 5 slots with a 10 slot blackout: transfers are blocked now
+      const slot = await provider.connection.getSlot("confirmed");
+      await program.methods
+        .declareCorporateAction(isinArg(isin), new anchor.BN(Date.now()), {
+          exSlot: new anchor.BN(slot + 4),
+          recordSlot: new anchor.BN(slot + 5),
+          paymentSlot: new anchor.BN(slot + 15),
+          blackoutSlots: new anchor.BN(10),
+          dueBills: null,
+        })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const during = await explain();
+      expect(during.find(r => r.ruleId === ruleId).passed).to.be.false;
+
+      while ((await provider.connection.getSlot("confirmed")) <= slot + 15) {
+        await new Promise(resolve => setTimeout(resolve, 400));
+      }
+      const after = await explain();
+      expect(after.find(r => r.ruleId === ruleId).passed).to.be.true;
+    });
+  });
+
+  describe("33. Due Bills", () => {
+    it("should move the entitlement of tokens bought after the record date to the buyer", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const source = await createTokenAccount(wallets.authorized, mint);
+      const destination = await createTokenAccount(wallets.destination, mint);
+
+      const slot = await provider.connection.getSlot("confirmed");
+      await program.methods
+        .declareCorporateAction(isinArg(isin), new anchor.BN(Date.now()), {
+          exSlot: new anchor.BN(slot - 1),
+          recordSlot: new anchor.BN(slot),
+          paymentSlot: new anchor.BN(slot + 50),
+          blackoutSlots: new anchor.BN(0),
+          dueBills: { buyerReceives: {} },
+        })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const [dueBillLedger] = PublicKey.findProgramAddressSync(
+        [Buffer.from("due-bills"), mint.toBuffer()],
+        program.programId,
+      );
+      const dueBillPDA = (holder: PublicKey) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from("due-bill"), mint.toBuffer(), holder.toBuffer()],
+          program.programId,
+        )[0];
+      const openDueBill = (holder: PublicKey) =>
+        program.methods
+          .openDueBill(holder)
+          .accounts({ payer: provider.wallet.publicKey, dueBillLedger, dueBill: dueBillPDA(holder) })
+          .rpc();
+
+      const mintInfo = await getMint(provider.connection, mint, "confirmed", TOKEN_2022_PROGRAM_ID);
+      const send = async () =>
+        web3.sendAndConfirmTransaction(
+          provider.connection,
+          new web3.Transaction().add(
+            await createTransferCheckedWithTransferHookInstruction(
+              provider.connection,
+              source,
+              mint,
+              destination,
+              wallets.authorized.publicKey,
+              BigInt(1_000),
+              mintInfo.decimals,
+              [],
+              "confirmed",
+              TOKEN_2022_PROGRAM_ID,
+            ),
+          ),
+          [wallets.authorized],
+          { commitment: "confirmed" },
+        );
+      await openDueBill(wallets.authorized.publicKey);
+      try {
+        await send();
+        expect.fail("Expected the buyer to need a due bill");
+      } catch (error) {
+        expect(error.logs.some(log => log.includes("opened their due bill"))).to.be.true;
+      }
+      await openDueBill(wallets.destination.publicKey);
+      await send();
+
+      const dueBill = (holder: PublicKey) =>
+        program.methods
+          .getDueBill(holder)
+          .accounts({ dueBillLedger, dueBill: dueBillPDA(holder) })
+          .view();
+      expect((await dueBill(wallets.destination.publicKey)).toNumber()).to.equal(1_000);
+      expect((await dueBill(wallets.authorized.publicKey)).toNumber()).to.equal(-1_000);
+    });
+  });
+
+  describe("34. Holder Statements", () => {
+    it("should sum up the activity of a holder from the hook", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const statementPDA = (holder: PublicKey) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from("holder-statement"), mint.toBuffer(), holder.toBuffer()],
+          program.programId,
+        )[0];
+      for (const holder of [wallets.authorized, wallets.destination]) {
+        await program.methods
+          .openHolderStatement(isinArg(isin))
+          .accounts({
+            payer: holder.publicKey,
+            holder: holder.publicKey,
+            tokenManager: tokenManagerPDA,
+            tokenMint: mint,
+            relayerRegistry: null,
+          })
+          .signers([holder])
+          .rpc();
+      }
+
+      const source = await createTokenAccount(wallets.authorized, mint);
+      const destination = await createTokenAccount(wallets.destination, mint);
+      const mintInfo = await getMint(provider.connection, mint, "confirmed", TOKEN_2022_PROGRAM_ID);
+      const transferIx = await createTransferCheckedWithTransferHookInstruction(
+        provider.connection,
+        source,
+        mint,
+        destination,
+        wallets.authorized.publicKey,
+        BigInt(500),
+        mintInfo.decimals,
+        [],
+        "confirmed",
+        TOKEN_2022_PROGRAM_ID,
+      );
+      await web3.sendAndConfirmTransaction(
+        provider.connection,
+        new web3.Transaction().add(transferIx),
+        [wallets.authorized],
+        { commitment: "confirmed" },
+      );
+
+      const sender = await program.account.holderStatement.fetch(statementPDA(wallets.authorized.publicKey));
+      const receiver = await program.account.holderStatement.fetch(statementPDA(wallets.destination.publicKey));
+      expect(sender.totalSent.toNumber()).to.equal(500);
+      expect(sender.totalReceived.toNumber()).to.equal(0);
+      expect(receiver.totalReceived.toNumber()).to.equal(500);
+      expect(receiver.lastActivity.toNumber()).to.be.greaterThan(0);
+    });
+  });
+
+  describe("35. Freeze By Filter", () => {
+    it("should only freeze holders matching the criterion", async () => {
+      const isin = tokensToCreate[1].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const sanctioned = web3.Keypair.generate();
+      await program.methods
+        .addToWhitelist(sanctioned.publicKey, isinArg(isin), new anchor.BN(0))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await program.methods
+        .setInvestorAttributes(sanctioned.publicKey, isinArg(isin), Array.from(Buffer.from("IR")), 1)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, complianceQueue: null })
+        .rpc();
+      const entry = await program.account.whitelistEntry.fetch(whitelistEntryPDA(mint, sanctioned.publicKey));
+      expect(Buffer.from(entry.jurisdiction).toString()).to.equal("IR");
+      expect(await program.account.personalData.fetchNullable(personalDataPDA(mint, sanctioned.publicKey))).to.be.null;
+      const sanctionedAccount = await createTokenAccount(sanctioned, mint);
+      const otherAccount = await createTokenAccount(wallets.destination, mint);
+
+      const freeze = (accounts: [PublicKey, PublicKey][]) =>
+        program.methods
+          .freezeByFilter(isinArg(isin), { jurisdiction: Array.from(Buffer.from("IR")), investorClass: null })
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, complianceQueue: null })
+          .remainingAccounts(accounts.flatMap(([pubkey, owner]) => [
+            { pubkey, isSigner: false, isWritable: true },
+            { pubkey: whitelistEntryPDA(mint, owner), isSigner: false, isWritable: false },
+          ]))
+          .rpc();
+      try {
+        await freeze([[sanctionedAccount, sanctioned.publicKey], [otherAccount, wallets.destination.publicKey]]);
+        expect.fail("Expected a holder outside the criterion to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("HolderDoesNotMatchFilter");
+      }
+
+      await freeze([[sanctionedAccount, sanctioned.publicKey]]);
+      const frozen = await getAccount(provider.connection, sanctionedAccount, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect(frozen.isFrozen).to.be.true;
+      const untouched = await getAccount(provider.connection, otherAccount, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect(untouched.isFrozen).to.be.false;
+
+      await program.methods
+        .removeFromWhitelist(sanctioned.publicKey, isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+  });
+
+  describe("36. Sanctions Screening", () => {
+    it("should deny transfers to wallets flagged by the screening provider", async () => {
+      const isin = tokensToCreate[0].isin;
+      const token = await getTokenForIsin(isin);
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleSanctionsScreening").value);
+      const screeningProvider = wallets.unauthorized;
+      const sanctioned = web3.Keypair.generate().publicKey;
+      const [sanctionsList] = PublicKey.findProgramAddressSync(
+        [Buffer.from("sanctions-list"), tokenManagerPDA.toBuffer()],
+        program.programId,
+      );
+      await program.methods
+        .setSanctionsProvider(screeningProvider.publicKey)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await program.methods
+        .flagSanctionedWallet(sanctioned)
+        .accounts({ provider: screeningProvider.publicKey, sanctionsList })
+        .signers([screeningProvider])
+        .rpc();
+
+      const explain = (to: PublicKey) =>
+        program.methods
+          .explainTransfer(wallets.authorized.publicKey, to, new anchor.BN(1))
+          .accounts({
+            mint: token.mint,
+            sourceToken: null,
+            destinationToken: null,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+          })
+          .view();
+      const blocked = await explain(sanctioned);
+      expect(blocked.find(r => r.ruleId === ruleId).passed).to.be.false;
+      const allowed = await explain(wallets.destination.publicKey);
+      expect(allowed.find(r => r.ruleId === ruleId).passed).to.be.true;
+
+      try {
+        await program.methods
+          .flagSanctionedWallet(wallets.destination.publicKey)
+          .accounts({ provider: wallets.authorized.publicKey, sanctionsList })
+          .signers([wallets.authorized])
+          .rpc();
+        expect.fail("Expected only the provider to update the list");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+    });
+  });
+
+  describe("37. Incoming Consent", () => {
+    it("should require receivers to pre-accept incoming securities", async () => {
+      const isin = tokensToCreate[1].isin;
+      const token = await getTokenForIsin(isin);
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleIncomingConsent").value);
+      const receiver = wallets.destination;
+      const explain = () =>
+        program.methods
+          .explainTransfer(wallets.authorized.publicKey, receiver.publicKey, new anchor.BN(1))
+          .accounts({
+            mint: token.mint,
+            sourceToken: null,
+            destinationToken: null,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+          })
+          .view();
+
+      await program.methods
+        .setIncomingConsentRequirement(isinArg(isin), true)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const unsolicited = await explain();
+      expect(unsolicited.find(r => r.ruleId === ruleId).passed).to.be.false;
+
+      await program.methods
+        .acceptIncoming(isinArg(isin))
+        .accounts({
+          payer: receiver.publicKey,
+          investor: receiver.publicKey,
+          tokenManager: tokenManagerPDA,
+          tokenMint: token.mint,
+          relayerRegistry: null,
+        })
+        .signers([receiver])
+        .rpc();
+      const consented = await explain();
+      expect(consented.find(r => r.ruleId === ruleId).passed).to.be.true;
+
+      const [incomingConsent] = PublicKey.findProgramAddressSync(
+        [Buffer.from("incoming-consent"), token.mint.toBuffer(), receiver.publicKey.toBuffer()],
+        program.programId,
+      );
+      await program.methods
+        .revokeIncoming()
+        .accounts({ investor: receiver.publicKey, incomingConsent })
+        .signers([receiver])
+        .rpc();
+      await program.methods
+        .setIncomingConsentRequirement(isinArg(isin), false)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+  });
+
+  describe("38. Fractionalized Underlying", () => {
+    it("should release the escrowed NFT only against the whole supply", async () => {
+      const isin = "XS0000000041";
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint, index } = await getTokenForIsin(isin);
+
+      const nftMint = await createMint(provider.connection, payer, payer.publicKey, null, 0);
+      const nftSource = await getOrCreateAssociatedTokenAccount(provider.connection, payer, nftMint, payer.publicKey);
+      await mintTo(provider.connection, payer, nftMint, nftSource.address, payer, 1);
+      await program.methods
+        .fractionalize(isinArg(isin))
+        .accounts({
+          signer: provider.wallet.publicKey,
+          tokenManager: tokenManagerPDA,
+          nftMint,
+          nftSource: nftSource.address,
+          nftTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      const holder = wallets.destination;
+      const holderShares = await createTokenAccount(holder, mint);
+      const holderNft = await getOrCreateAssociatedTokenAccount(provider.connection, payer, nftMint, holder.publicKey);
+      const issue = (destination: PublicKey, amount: number) =>
+        program.methods
+          .mintTokens(index, new anchor.BN(amount))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination })
+          .rpc();
+      const redeem = () =>
+        program.methods
+          .redeemUnderlying(isinArg(isin))
+          .accounts({
+            holder: holder.publicKey,
+            tokenManager: tokenManagerPDA,
+            holderShares,
+            nftMint,
+            holderNft: holderNft.address,
+            nftTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([holder])
+          .rpc();
+
+      // The holder owns 60 of the 100 shares issued
+      const otherShares = await createTokenAccount(wallets.authorized, mint);
+      await issue(holderShares, 60);
+      await issue(otherShares, 40);
+      try {
+        await redeem();
+        expect.fail("Expected a partial holder to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("FullSupplyRequired");
+      }
+      const burnIx = createBurnCheckedInstruction(otherShares, mint, wallets.authorized.publicKey, 40, 0, [], TOKEN_2022_PROGRAM_ID);
+      await web3.sendAndConfirmTransaction(provider.connection, new web3.Transaction().add(burnIx), [wallets.authorized]);
+
+      await redeem();
+      const nft = await getAccount(provider.connection, holderNft.address, "confirmed", TOKEN_PROGRAM_ID);
+      expect(nft.amount).to.equal(1n);
+      const shareMint = await getMint(provider.connection, mint, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect(shareMint.supply).to.equal(0n);
+    });
+  });
+
+  describe("39. Collateral Attestations", () => {
+    it("should pause issuance while the collateral attestation is stale", async () => {
+      const isin = "XS0000000041";
+      const { mint, index } = await getTokenForIsin(isin);
+      const custodian = wallets.unauthorized;
+      const destination = await createTokenAccount(wallets.destination, mint);
+      const attest = (signer: web3.Keypair | null, asOf: number) => {
+        const builder = program.methods
+          .linkCollateral(isinArg(isin), custodian.publicKey, "https://custodian.example/attestations/1", Array.from(Buffer.alloc(32, 1)), new anchor.BN(1_000_000), new anchor.BN(asOf))
+          .accounts({ signer: signer ? signer.publicKey : provider.wallet.publicKey, tokenManager: tokenManagerPDA });
+        return signer ? builder.signers([signer]).rpc() : builder.rpc();
+      };
+      const issue = () =>
+        program.methods
+          .mintTokens(index, new anchor.BN(1))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination })
+          .rpc();
+
+      const now = Math.floor(Date.now() / 1000);
+      await attest(null, now - 7_200);
+      await program.methods
+        .setCollateralPolicy(isinArg(isin), 3_600, true)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      try {
+        await issue();
+        expect.fail("Expected issuance to be paused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("StaleCollateral");
+      }
+
+      try {
+        await attest(custodian, now + 3_600);
+        expect.fail("Expected an attestation dated in the future to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidCollateralAttestation");
+      }
+
+      // The custodian refreshes its attestation
+      await attest(custodian, now);
+      await issue();
+
+      await program.methods
+        .setCollateralPolicy(isinArg(isin), 0, false)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+  });
+
+  describe("40. FX Snapshots", () => {
+    it("should snapshot the payment mint rate in the reporting currency at settlement", async () => {
+      const isin = tokensToCreate[0].isin;
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      const oracle = wallets.unauthorized;
+      const eur = Array.from(Buffer.from("EUR"));
+      const paymentMint = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+      const [fxRate] = PublicKey.findProgramAddressSync(
+        [Buffer.from("fx-rate"), paymentMint.toBuffer(), oracle.publicKey.toBuffer()],
+        program.programId,
+      );
+
+      await program.methods
+        .setReportingCurrency(eur, 60)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const publish = () =>
+        program.methods
+          .publishFxRate(eur, new anchor.BN(92_000_000), 8)
+          .accounts({ authority: oracle.publicKey, paymentMint })
+          .signers([oracle])
+          .rpc();
+      await publish();
+      await program.methods
+        .setPaymentMint(fxRate, true)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, paymentMint })
+        .rpc();
+
+      const offerId = new anchor.BN(Date.now());
+      const manager = await program.account.tokenManager.fetch(tokenManagerPDA);
+      await program.methods
+        .createOffer(isinArg(isin), offerId, { subscription: {} }, new anchor.BN(1), new anchor.BN(10), new anchor.BN(Math.floor(Date.now() / 1000) + 4))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, paymentMint, paymentTokenProgram: TOKEN_PROGRAM_ID })
+        .rpc();
+      const { mint } = await getTokenForIsin(isin);
+      const [offer] = PublicKey.findProgramAddressSync(
+        [Buffer.from("offer"), mint.toBuffer(), offerId.toArrayLike(Buffer, "le", 8)],
+        program.programId,
+      );
+
+      const investor = wallets.destination;
+      const investorSecurity = await createTokenAccount(investor, mint);
+      const investorPayment = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, paymentMint, investor.publicKey)).address;
+      await mintTo(provider.connection, payer, paymentMint, investorPayment, payer, 10);
+      await program.methods
+        .placeOrder(isinArg(isin), new anchor.BN(10), new anchor.BN(1), 0)
+        .accounts({
+          payer: investor.publicKey,
+          investor: investor.publicKey,
+          tokenManager: tokenManagerPDA,
+          relayerRegistry: null,
+          offer,
+          paymentMint,
+          investorSecurity,
+          investorPayment,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([investor])
+        .rpc();
+      await new Promise(resolve => setTimeout(resolve, (4 + manager.clockToleranceSeconds + 2) * 1000));
+
+      const issuerPayment = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, paymentMint, provider.wallet.publicKey)).address;
+      const settle = (rate: PublicKey | null) =>
+        program.methods
+          .settleOrder(isinArg(isin))
+          .accounts({
+            tokenManager: tokenManagerPDA,
+            offer,
+            paymentMint,
+            investor: investor.publicKey,
+            investorSecurity,
+            investorPayment,
+            issuerPayment,
+            investorStatement: null,
+            fxRate: rate,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+      try {
+        await settle(null);
+        expect.fail("Expected settlement without the rate feed to fail");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("FxRateRequired");
+      }
+      // Waiting for the offer to close aged the rate past its maximum age, given the clock tolerance
+      try {
+        await settle(fxRate);
+        expect.fail("Expected settlement on a stale rate to fail");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("StaleFxRate");
+      }
+      await publish();
+      await settle(fxRate);
+
+      const { fxSnapshot } = await program.account.offer.fetch(offer);
+      expect(Buffer.from(fxSnapshot.currency).toString()).to.equal("EUR");
+      expect(fxSnapshot.rate.toNumber()).to.equal(92_000_000);
+      expect(fxSnapshot.decimals).to.equal(8);
+    });
+  });
+
+  describe("41. Day Count Conventions", () => {
+    it("should accrue interest under the day-count convention chosen at creation", async () => {
+      const isin = "XS0000000058";
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint } = await getTokenForIsin(isin);
+      const paymentMint = await createPaymentMint();
+      const [debtInstrument] = PublicKey.findProgramAddressSync(
+        [Buffer.from("debt-instrument"), mint.toBuffer()],
+        program.programId,
+      );
+      const day = 86_400;
+      const now = Math.floor(Date.now() / 1000);
+      const terms = {
+        couponRateBps: 500,
+        dayCount: { act360: {} },
+        issuedAt: new anchor.BN(now - 90 * day),
+        maturity: new anchor.BN(now + 5 * 365 * day),
+        couponIntervalMonths: 12,
+        gracePeriodDays: 30,
+      };
+      const create = (debtTerms) =>
+        program.methods
+          .createDebtInstrument(isinArg(isin), debtTerms)
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, paymentMint, paymentTokenProgram: TOKEN_PROGRAM_ID })
+          .rpc();
+
+      try {
+        await create({ ...terms, couponIntervalMonths: 0 });
+        expect.fail("Expected a zero coupon interval to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidDebtTerms");
+      }
+      await create(terms);
+
+      // 90 actual days over a 360-day year at 5% on 1,000,000 units
+      const accrued = await program.methods
+        .getAccruedInterest(new anchor.BN(1_000_000))
+        .accounts({ debtInstrument })
+        .view();
+      expect(accrued.toNumber()).to.equal(12_500);
+    });
+  });
+
+  describe("42. Credit States", () => {
+    it("should enter the grace period on a missed coupon and cure once funded", async () => {
+      const isin = "XS0000000066";
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint, index } = await getTokenForIsin(isin);
+      const paymentMint = await createPaymentMint();
+      const [debtInstrument] = PublicKey.findProgramAddressSync(
+        [Buffer.from("debt-instrument"), mint.toBuffer()],
+        program.programId,
+      );
+      const day = 86_400;
+      const now = Math.floor(Date.now() / 1000);
+      await program.methods
+        .createDebtInstrument(isinArg(isin), {
+          couponRateBps: 500,
+          dayCount: { act365: {} },
+          issuedAt: new anchor.BN(now - 370 * day),
+          maturity: new anchor.BN(now + 4 * 365 * day),
+          couponIntervalMonths: 12,
+          gracePeriodDays: 30,
+        })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, paymentMint, paymentTokenProgram: TOKEN_PROGRAM_ID })
+        .rpc();
+      const holderShares = await createTokenAccount(wallets.destination, mint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(1_000_000))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: holderShares })
+        .rpc();
+
+      const assess = () =>
+        program.methods
+          .assessDebtService()
+          .accounts({ debtInstrument, tokenManager: tokenManagerPDA, mint })
+          .rpc();
+      await assess();
+      let debt = await program.account.debtInstrument.fetch(debtInstrument);
+      expect(debt.state).to.deep.equal({ gracePeriod: {} });
+
+      const funderPayment = await getOrCreateAssociatedTokenAccount(provider.connection, payer, paymentMint, payer.publicKey);
+      await mintTo(provider.connection, payer, paymentMint, funderPayment.address, payer, 60_000);
+      await program.methods
+        .fundDebtService(isinArg(isin), new anchor.BN(60_000))
+        .accounts({
+          funder: provider.wallet.publicKey,
+          tokenManager: tokenManagerPDA,
+          funderPayment: funderPayment.address,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      await assess();
+      debt = await program.account.debtInstrument.fetch(debtInstrument);
+      expect(debt.state).to.deep.equal({ performing: {} });
+      expect(debt.nextCoupon).to.equal(2);
+    });
+  });
+
+  describe("43. Restructuring", () => {
+    it("should exchange a defaulted instrument for the approved new one", async () => {
+      const isin = "XS0000000074";
+      const newIsin = "XS0000000082";
+      for (const shareIsin of [isin, newIsin]) {
+        await program.methods
+          .createNewShare(0, isinArg(shareIsin), null, null)
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      }
+      const { mint, index } = await getTokenForIsin(isin);
+      const { mint: newMint } = await getTokenForIsin(newIsin);
+      const paymentMint = await createPaymentMint();
+      const [debtInstrument] = PublicKey.findProgramAddressSync(
+        [Buffer.from("debt-instrument"), mint.toBuffer()],
+        program.programId,
+      );
+      const [restructuring] = PublicKey.findProgramAddressSync(
+        [Buffer.from("restructuring"), mint.toBuffer()],
+        program.programId,
+      );
+      const day = 86_400;
+      const now = Math.floor(Date.now() / 1000);
+
+      // A coupon missed without grace period defaults the instrument
+      await program.methods
+        .createDebtInstrument(isinArg(isin), {
+          couponRateBps: 500,
+          dayCount: { thirty360: {} },
+          issuedAt: new anchor.BN(now - 370 * day),
+          maturity: new anchor.BN(now + 4 * 365 * day),
+          couponIntervalMonths: 12,
+          gracePeriodDays: 0,
+        })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, paymentMint, paymentTokenProgram: TOKEN_PROGRAM_ID })
+        .rpc();
+      const holder = wallets.destination;
+      const holderShares = await createTokenAccount(holder, mint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(1_000))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: holderShares })
+        .rpc();
+      await program.methods
+        .assessDebtService()
+        .accounts({ debtInstrument, tokenManager: tokenManagerPDA, mint })
+        .rpc();
+      expect((await program.account.debtInstrument.fetch(debtInstrument)).state).to.deep.equal({ default: {} });
+
+      // Holders must acknowledge the exchange circular before exchanging
+      const hash = Buffer.alloc(32, 43);
+      const [notice] = PublicKey.findProgramAddressSync(
+        [Buffer.from("notice"), mint.toBuffer(), hash],
+        program.programId,
+      );
+      await program.methods
+        .postNotice(isinArg(isin), { consentSolicitation: {} }, "https://issuer.example/notices/exchange", Array.from(hash), new anchor.BN(now + 86_400))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const { snapshot, entries: [entry] } = await snapshotHolders(isin, [holderShares]);
+      const manager = await program.account.tokenManager.fetch(tokenManagerPDA);
+      await program.methods
+        .proposeRestructuring(isinArg(isin), isinArg(newIsin), {
+          haircutBps: 2_500,
+          newMaturity: new anchor.BN(now + 8 * 365 * day),
+          thresholdBps: 6_667,
+          votingEndsAt: new anchor.BN(now + 4),
+          requiredNotice: notice,
+        })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, snapshot })
+        .rpc();
+      await program.methods
+        .voteRestructuring(isinArg(isin), true)
+        .accounts({ payer: holder.publicKey, holder: holder.publicKey, tokenManager: tokenManagerPDA, relayerRegistry: null })
+        .remainingAccounts([{ pubkey: entry, isSigner: false, isWritable: false }])
+        .signers([holder])
+        .rpc();
+      await new Promise(resolve => setTimeout(resolve, (4 + manager.clockToleranceSeconds + 2) * 1000));
+      await program.methods
+        .finalizeRestructuring()
+        .accounts({ restructuring, tokenManager: tokenManagerPDA })
+        .rpc();
+      expect((await program.account.debtInstrument.fetch(debtInstrument)).state).to.deep.equal({ restructured: {} });
+
+      const holderNewShares = await createTokenAccount(holder, newMint);
+      const exchange = (noticeAcknowledgment: PublicKey | null) =>
+        program.methods
+          .exchangeRestructured(isinArg(isin))
+          .accounts({
+            holder: holder.publicKey,
+            tokenManager: tokenManagerPDA,
+            holderShares,
+            holderNewShares,
+            noticeAcknowledgment,
+          })
+          .signers([holder])
+          .rpc();
+      try {
+        await exchange(null);
+        expect.fail("Expected the exchange to require the acknowledged notice");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("NoticeNotAcknowledged");
+      }
+      await program.methods
+        .acknowledgeNotice()
+        .accounts({ payer: holder.publicKey, holder: holder.publicKey, tokenManager: tokenManagerPDA, relayerRegistry: null, notice })
+        .signers([holder])
+        .rpc();
+      const [acknowledgment] = PublicKey.findProgramAddressSync(
+        [Buffer.from("notice-ack"), notice.toBuffer(), holder.publicKey.toBuffer()],
+        program.programId,
+      );
+      try {
+        await exchange(acknowledgment);
+        expect.fail("Expected a holder not whitelisted for the new instrument to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("ExchangeRecipientNotEligible");
+      }
+      await program.methods
+        .addToWhitelist(holder.publicKey, isinArg(newIsin), new anchor.BN(0))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await exchange(acknowledgment);
+      const exchanged = await getAccount(provider.connection, holderNewShares, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect(exchanged.amount).to.equal(750n);
+    });
+  });
+
+  describe("44. Holder Notices", () => {
+    it("should post a notice and record the acknowledgment of a holder", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const hash = Buffer.alloc(32, 44);
+      const [notice] = PublicKey.findProgramAddressSync(
+        [Buffer.from("notice"), mint.toBuffer(), hash],
+        program.programId,
+      );
+      const holder = wallets.destination;
+
+      await program.methods
+        .postNotice(isinArg(isin), { meeting: {} }, "https://issuer.example/notices/agm", Array.from(hash), new anchor.BN(Math.floor(Date.now() / 1000) + 86_400))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await program.methods
+        .acknowledgeNotice()
+        .accounts({
+          payer: holder.publicKey,
+          holder: holder.publicKey,
+          tokenManager: tokenManagerPDA,
+          relayerRegistry: null,
+          notice,
+        })
+        .signers([holder])
+        .rpc();
+
+      const [acknowledgment] = PublicKey.findProgramAddressSync(
+        [Buffer.from("notice-ack"), notice.toBuffer(), holder.publicKey.toBuffer()],
+        program.programId,
+      );
+      const receipt = await program.account.noticeAcknowledgment.fetch(acknowledgment);
+      expect(receipt.holder.toBase58()).to.equal(holder.publicKey.toBase58());
+      expect((await program.account.notice.fetch(notice)).category).to.deep.equal({ meeting: {} });
+    });
+  });
+
+  describe("45. Tax Certifications", () => {
+    it("should withhold at the penalty rate until a certification is approved", async () => {
+      const holder = wallets.destination;
+      const withholding = () =>
+        program.methods
+          .getWithholdingRate(holder.publicKey)
+          .accounts({ tokenManager: tokenManagerPDA })
+          .view();
+
+      await program.methods
+        .setPenaltyWithholding(3_000)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      expect(await withholding()).to.equal(3_000);
+
+      await program.methods
+        .submitTaxCertification({ w8Ben: {} }, Array.from(Buffer.alloc(32, 45)), new anchor.BN(Math.floor(Date.now() / 1000) + 365 * 86_400))
+        .accounts({ payer: holder.publicKey, holder: holder.publicKey, tokenManager: tokenManagerPDA, relayerRegistry: null })
+        .signers([holder])
+        .rpc();
+      // Pending review, distributions are still withheld
+      expect(await withholding()).to.equal(3_000);
+
+      await program.methods
+        .reviewTaxCertification(holder.publicKey, true)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, complianceQueue: null })
+        .rpc();
+      expect(await withholding()).to.equal(0);
+    });
+  });
+
+  describe("46. Fee Schedules", () => {
+    it("should charge the fees pinned when the offer opened", async () => {
+      const isin = tokensToCreate[0].isin;
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      const setFees = (subscriptionFeeBps: number, adminFee: number) =>
+        program.methods
+          .setFeeSchedule({ subscriptionFeeBps, redemptionFeeBps: 0, transferFeeBps: 0, adminFee: new anchor.BN(adminFee) })
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      await setFees(100, 5);
+
+      const paymentMint = await createPaymentMint();
+      const offerId = new anchor.BN(Date.now());
+      const manager = await program.account.tokenManager.fetch(tokenManagerPDA);
+      await program.methods
+        .createOffer(isinArg(isin), offerId, { subscription: {} }, new anchor.BN(100), new anchor.BN(10), new anchor.BN(Math.floor(Date.now() / 1000) + 4))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, paymentMint, paymentTokenProgram: TOKEN_PROGRAM_ID })
+        .rpc();
+      const { mint } = await getTokenForIsin(isin);
+      const [offer] = PublicKey.findProgramAddressSync(
+        [Buffer.from("offer"), mint.toBuffer(), offerId.toArrayLike(Buffer, "le", 8)],
+        program.programId,
+      );
+
+      // The schedule changes while the offer is open
+      await setFees(500, 50);
+      expect((await program.account.offer.fetch(offer)).fees.feeBps).to.equal(100);
+
+      const investor = wallets.destination;
+      const investorSecurity = await createTokenAccount(investor, mint);
+      const investorPayment = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, paymentMint, investor.publicKey)).address;
+      await mintTo(provider.connection, payer, paymentMint, investorPayment, payer, 1_015);
+      const place = (feeVersion: number) =>
+        program.methods
+          .placeOrder(isinArg(isin), new anchor.BN(10), new anchor.BN(100), feeVersion)
+          .accounts({
+            payer: investor.publicKey,
+            investor: investor.publicKey,
+            tokenManager: tokenManagerPDA,
+            relayerRegistry: null,
+            offer,
+            paymentMint,
+            investorSecurity,
+            investorPayment,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([investor])
+          .rpc();
+      const { version } = await program.account.feeSchedule.fetch(
+        PublicKey.findProgramAddressSync([Buffer.from("fee-schedule"), tokenManagerPDA.toBuffer()], program.programId)[0],
+      );
+      try {
+        await place(version);
+        expect.fail("Expected a quote of another fee schedule version to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("FeeScheduleChanged");
+      }
+      await place(version - 1);
+      await new Promise(resolve => setTimeout(resolve, (4 + manager.clockToleranceSeconds + 2) * 1000));
+
+      const issuerPayment = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, paymentMint, provider.wallet.publicKey)).address;
+      await program.methods
+        .settleOrder(isinArg(isin))
+        .accounts({
+          tokenManager: tokenManagerPDA,
+          offer,
+          paymentMint,
+          investor: investor.publicKey,
+          investorSecurity,
+          investorPayment,
+          issuerPayment,
+          investorStatement: null,
+          fxRate: null,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      // 1,000 of price, 1% subscription fee and the admin fee of 5
+      const proceeds = await getAccount(provider.connection, issuerPayment, "confirmed", TOKEN_PROGRAM_ID);
+      expect(proceeds.amount).to.equal(1_015n);
+
+      await setFees(0, 0);
+    });
+  });
+
+  describe("47. Circuit Breaker", () => {
+    it("should pause transfers once whitelist removals pile up until the guardian resumes them", async () => {
+      const isin = tokensToCreate[2].isin;
+      const token = await getTokenForIsin(isin);
+      const guardian = web3.Keypair.generate();
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleCircuitBreaker").value);
+      const [circuitBreaker] = PublicKey.findProgramAddressSync(
+        [Buffer.from("circuit-breaker"), token.mint.toBuffer()],
+        program.programId,
+      );
+      await program.methods
+        .configureCircuitBreaker(isinArg(isin), guardian.publicKey, new anchor.BN(1_000), [0, 0, 2])
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      for (let i = 0; i < 2; i++) {
+        const wallet = web3.Keypair.generate().publicKey;
+        await program.methods.addToWhitelist(wallet, isinArg(isin), new anchor.BN(0)).accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA }).rpc();
+        await program.methods.removeFromWhitelist(wallet, isinArg(isin)).accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA }).rpc();
+      }
+      expect((await program.account.circuitBreaker.fetch(circuitBreaker)).trippedAt).to.not.be.null;
+
+      const explain = () =>
+        program.methods
+          .explainTransfer(wallets.authorized.publicKey, wallets.destination.publicKey, new anchor.BN(1))
+          .accounts({
+            mint: token.mint,
+            sourceToken: null,
+            destinationToken: null,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+          })
+          .view();
+      expect((await explain()).find(r => r.ruleId === ruleId).passed).to.be.false;
+
+      try {
+        await program.methods
+          .configureCircuitBreaker(isinArg(isin), provider.wallet.publicKey, new anchor.BN(1_000), [0, 0, 2])
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        expect.fail("Expected the guardian of the tripped breaker to stay");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("CircuitBreakerGuardianLocked");
+      }
+      try {
+        await program.methods
+          .resumeTransfers(isinArg(isin))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, complianceQueue: null })
+          .rpc();
+        expect.fail("Expected only the guardian to resume transfers");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+      await program.methods
+        .resumeTransfers(isinArg(isin))
+        .accounts({ signer: guardian.publicKey, tokenManager: tokenManagerPDA, complianceQueue: null })
+        .signers([guardian])
+        .rpc();
+      expect((await explain()).find(r => r.ruleId === ruleId).passed).to.be.true;
+
+      await program.methods
+        .configureCircuitBreaker(isinArg(isin), guardian.publicKey, new anchor.BN(1_000), [0, 0, 0])
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+  });
+
+  describe("48. State Export", () => {
+    it("should export the configuration of a token to a single account", async () => {
+      const isin = tokensToCreate[0].isin;
+      const token = await getTokenForIsin(isin);
+      const [stateExport] = PublicKey.findProgramAddressSync(
+        [Buffer.from("state-export"), token.mint.toBuffer()],
+        program.programId,
+      );
+      const version = Number(program.idl.constants.find(c => c.name === "stateExportVersion").value);
+
+      for (let sequence = 1; sequence <= 2; sequence++) {
+        await program.methods
+          .exportState(isinArg(isin))
+          .accounts({ payer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        const exported = await program.account.stateExport.fetch(stateExport);
+        expect(exported.formatVersion).to.equal(version);
+        expect(exported.sequence.toNumber()).to.equal(sequence);
+        expect(isinString(exported.state.token.isin)).to.equal(isin);
+        expect(exported.state.whitelistCount).to.equal((await getWhitelistForIsin(isin)).length);
+      }
+    });
+  });
+
+  describe("49. State Hash", () => {
+    it("should store the hash it returns and keep it stable while the state is", async () => {
+      const isin = tokensToCreate[0].isin;
+      const token = await getTokenForIsin(isin);
+      const [stateHash] = PublicKey.findProgramAddressSync(
+        [Buffer.from("state-hash"), token.mint.toBuffer()],
+        program.programId,
+      );
+      const compute = () =>
+        program.methods
+          .computeStateHash(isinArg(isin))
+          .accounts({ payer: provider.wallet.publicKey, tokenManager: tokenManagerPDA });
+
+      await compute().rpc();
+      const stored = await program.account.stateHash.fetch(stateHash);
+      const returned = await compute().view();
+      expect(Buffer.from(returned).equals(Buffer.from(stored.hash))).to.be.true;
+
+      const wallet = web3.Keypair.generate().publicKey;
+      await program.methods.addToWhitelist(wallet, isinArg(isin), new anchor.BN(0)).accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA }).rpc();
+      expect(Buffer.from(await compute().view()).equals(Buffer.from(stored.hash))).to.be.false;
+      await program.methods.removeFromWhitelist(wallet, isinArg(isin)).accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA }).rpc();
+    });
+  });
+
+  describe("50. Sender Whitelist", () => {
+    it("should require the sender to be whitelisted once the mode is on", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleSourceWhitelisted").value);
+      const formerHolder = web3.Keypair.generate().publicKey;
+      const explain = () =>
+        program.methods
+          .explainTransfer(formerHolder, wallets.destination.publicKey, new anchor.BN(1))
+          .accounts({
+            mint,
+            sourceToken: null,
+            destinationToken: null,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+          })
+          .view();
+      const setRequirement = (required: boolean) =>
+        program.methods
+          .setSenderWhitelistRequirement(isinArg(isin), required)
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+
+      expect((await explain()).find(r => r.ruleId === ruleId)).to.be.undefined;
+      await setRequirement(true);
+      expect((await explain()).find(r => r.ruleId === ruleId).passed).to.be.false;
+      await setRequirement(false);
+    });
+  });
+
+  describe("51. Burning", () => {
+    it("should let holders burn their own tokens only once self-redemption is allowed", async () => {
+      const isin = tokensToCreate[1].isin;
+      const { mint, index } = await getTokenForIsin(isin);
+      const holder = web3.Keypair.generate();
+      await fundWallet(holder);
+      const holderAccount = await createTokenAccount(holder, mint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(10))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: holderAccount })
+        .rpc();
+      const redeem = () =>
+        program.methods
+          .burnTokens(index, new anchor.BN(4))
+          .accounts({
+            signer: holder.publicKey,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+            source: holderAccount,
+          })
+          .signers([holder])
+          .rpc();
+      const setSelfRedemption = (allowed: boolean) =>
+        program.methods
+          .setSelfRedemption(isinArg(isin), allowed)
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+
+      try {
+        await redeem();
+        expect.fail("Expected self-redemption to be disabled by default");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+      await setSelfRedemption(true);
+      await redeem();
+      const remaining = await getAccount(provider.connection, holderAccount, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect(remaining.amount).to.equal(BigInt(6));
+      await setSelfRedemption(false);
+    });
+  });
+
+  // Compute units the hook may consume for a plain whitelisted transfer; every
+  // holder pays for them on each transfer, so raising it needs a good reason.
+  const HOOK_CU_BUDGET = 40_000;
+
+  describe("52. Hook Compute Budget", () => {
+    // A share of its own, so rules configured by other tests do not skew the units
+    const isin = "XS0000000306";
+
+    before(async () => {
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await program.methods
+        .addToWhitelist(wallets.destination.publicKey, isinArg(isin), new anchor.BN(0))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+
+    async function hookUnits(sender: web3.Keypair, source: PublicKey, mint: PublicKey, destination: PublicKey) {
+      const mintInfo = await getMint(provider.connection, mint, "confirmed", TOKEN_2022_PROGRAM_ID);
+      const transferIx = await createTransferCheckedWithTransferHookInstruction(
+        provider.connection,
+        source,
+        mint,
+        destination,
+        sender.publicKey,
+        BigInt(1),
+        mintInfo.decimals,
+        [],
+        "confirmed",
+        TOKEN_2022_PROGRAM_ID,
+      );
+      const simulation = await provider.connection.simulateTransaction(
+        new web3.Transaction().add(transferIx),
+        [sender],
+      );
+      expect(simulation.value.err).to.be.null;
+      const consumed = simulation.value.logs
+        .map(log => log.match(new RegExp(`^Program ${program.programId} consumed (\\d+) of`)))
+        .find(match => match);
+      return Number(consumed[1]);
+    }
+
+    it("should keep the hook within its compute budget across rule configurations", async () => {
+      const { mint, index } = await getTokenForIsin(isin);
+      const sender = web3.Keypair.generate();
+      await fundWallet(sender);
+      const source = await createTokenAccount(sender, mint);
+      const destination = await createTokenAccount(wallets.destination, mint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(10))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: source })
+        .rpc();
+
+      const units: Record<string, number> = {};
+      units["default"] = await hookUnits(sender, source, mint, destination);
+
+      await program.methods.addToWhitelist(sender.publicKey, isinArg(isin), new anchor.BN(0)).accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA }).rpc();
+      await program.methods
+        .setSenderWhitelistRequirement(isinArg(isin), true)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      units["sender whitelist"] = await hookUnits(sender, source, mint, destination);
+
+      await program.methods
+        .configureCircuitBreaker(isinArg(isin), provider.wallet.publicKey, new anchor.BN(1_000), [0, 0, 0])
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      units["sender whitelist + circuit breaker"] = await hookUnits(sender, source, mint, destination);
+
+      await program.methods
+        .setSenderWhitelistRequirement(isinArg(isin), false)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await program.methods.removeFromWhitelist(sender.publicKey, isinArg(isin)).accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA }).rpc();
+
+      console.table(units);
+      expect(units["default"]).to.be.at.most(HOOK_CU_BUDGET);
+    });
+  });
+
+  describe("53. Holder Account Freeze", () => {
+    it("should freeze and thaw a single holder account", async () => {
+      const isin = tokensToCreate[1].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const holderAccount = await createTokenAccount(wallets.unauthorized, mint);
+      const isFrozen = async () =>
+        (await getAccount(provider.connection, holderAccount, "confirmed", TOKEN_2022_PROGRAM_ID)).isFrozen;
+
+      await program.methods
+        .freezeHolderAccount(isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, holderAccount })
+        .rpc();
+      expect(await isFrozen()).to.be.true;
+      await program.methods
+        .thawHolderAccount(isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, holderAccount })
+        .rpc();
+      expect(await isFrozen()).to.be.false;
+    });
+  });
+
+  describe("54. Chained Hook", () => {
+    it("should refuse to chain this program or Token-2022", async () => {
+      const isin = tokensToCreate[0].isin;
+      for (const hookProgram of [program.programId, TOKEN_2022_PROGRAM_ID]) {
+        try {
+          await program.methods
+            .setChainedHook(isinArg(isin), 10_000)
+            .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, hookProgram })
+            .rpc();
+          expect.fail("Expected the chained hook to be refused");
+        } catch (err: any) {
+          expect(err.error.errorCode.code).to.equal("InvalidChainedHook");
+        }
+      }
+    });
+
+    it("should call the chained hook on transfers sent with a lookup table", async () => {
+      const isin = "XS0000000322";
+      const mockHook = anchor.workspace.MockHook.programId as PublicKey;
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint, index } = await getTokenForIsin(isin);
+      const sender = web3.Keypair.generate();
+      await fundWallet(sender);
+      for (const wallet of [sender.publicKey, wallets.destination.publicKey]) {
+        await program.methods
+          .addToWhitelist(wallet, isinArg(isin), new anchor.BN(0))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      }
+      const source = await createTokenAccount(sender, mint);
+      const destination = await createTokenAccount(wallets.destination, mint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(10))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: source })
+        .rpc();
+      await program.methods
+        .setChainedHook(isinArg(isin), 10_000)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, hookProgram: mockHook })
+        .rpc();
+
+      const transferIx = await createTransferCheckedWithTransferHookInstruction(
+        provider.connection,
+        source,
+        mint,
+        destination,
+        sender.publicKey,
+        BigInt(10),
+        0,
+        [],
+        "confirmed",
+        TOKEN_2022_PROGRAM_ID,
+      );
+      const legacy = new web3.Transaction({
+        feePayer: sender.publicKey,
+        ...(await provider.connection.getLatestBlockhash("confirmed")),
+      }).add(transferIx);
+      expect(() => legacy.serialize({ requireAllSignatures: false, verifySignatures: false })).to.throw(/too large/);
+
+      const signature = await sendWithLookupTable([transferIx], [sender]);
+      const transaction = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      expect(transaction.meta.logMessages.some(log => log.includes(`Chained hook: 10 units of ${mint}`))).to.be.true;
+      expect((await getAccount(provider.connection, destination, "confirmed", TOKEN_2022_PROGRAM_ID)).amount).to.equal(BigInt(10));
+    });
+  });
+
+  describe("55. Whitelist Mirroring", () => {
+    const isin = tokensToCreate[0].isin;
+    const attestor = web3.Keypair.generate();
+    const mirrored = web3.Keypair.generate().publicKey;
+    const sourceCluster = Array.from(Buffer.alloc(32, 7));
+    // Leaf of a wallet authorized in FR for investor class 1, without expiry
+    const authorization = { jurisdiction: Array.from(Buffer.from("FR")), investorClass: 1, expiresAt: new anchor.BN(0) };
+    const root = Array.from(
+      createHash("sha256")
+        .update(Buffer.concat([mirrored.toBuffer(), Buffer.from("FR"), Buffer.from([1]), Buffer.alloc(8)]))
+        .digest(),
+    );
+
+    it("should mirror a wallet proven against the attested whitelist", async () => {
+      await program.methods
+        .setWhitelistAttestor(isinArg(isin), attestor.publicKey, sourceCluster)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint } = await getTokenForIsin(isin);
+      const [whitelistMirror] = PublicKey.findProgramAddressSync(
+        [Buffer.from("whitelist-mirror"), mint.toBuffer()],
+        program.programId,
+      );
+      await program.methods
+        .acceptWhitelistCommitment(new anchor.BN(1), root, 1)
+        .accounts({ attestor: attestor.publicKey, whitelistMirror })
+        .signers([attestor])
+        .rpc();
+
+      const mirror = () =>
+        program.methods
+          .mirrorWhitelistEntry(isinArg(isin), mirrored, authorization, [])
+          .accounts({ payer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      await mirror();
+      const entry = await program.account.whitelistEntry.fetch(whitelistEntryPDA(mint, mirrored));
+      expect(entry.wallet.toString()).to.equal(mirrored.toString());
+      expect(Buffer.from(entry.jurisdiction).toString()).to.equal("FR");
+      expect(entry.investorClass).to.equal(1);
+
+      // A removal is not undone by submitting the proof again
+      await program.methods
+        .removeFromWhitelist(mirrored, isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      try {
+        await mirror();
+        expect.fail("Expected the removed wallet to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("MirroredWalletRemoved");
+      }
+
+      try {
+        await program.methods
+          .acceptWhitelistCommitment(new anchor.BN(1), root, 1)
+          .accounts({ attestor: attestor.publicKey, whitelistMirror })
+          .signers([attestor])
+          .rpc();
+        expect.fail("Expected the replayed commitment to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidWhitelistCommitment");
+      }
+    });
+
+    it("should refuse a wallet missing from the attested whitelist", async () => {
+      try {
+        await program.methods
+          .mirrorWhitelistEntry(isinArg(isin), web3.Keypair.generate().publicKey, authorization, [])
+          .accounts({ payer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        expect.fail("Expected the unproven wallet to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidWhitelistProof");
+      }
+    });
+  });
+
+  describe("56. Mint Sessions", () => {
+    it("should let an operator mint within its session allowance", async () => {
+      const isin = tokensToCreate[2].isin;
+      const { mint, index } = await getTokenForIsin(isin);
+      const operator = web3.Keypair.generate();
+      const destination = await createTokenAccount(wallets.authorized, mint);
+      const expiry = new anchor.BN(Math.floor(Date.now() / 1000) + 3_600);
+      await program.methods
+        .createMintSession(operator.publicKey, isinArg(isin), new anchor.BN(10), expiry)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const mintAs = (amount: number) =>
+        program.methods
+          .mintTokens(index, new anchor.BN(amount))
+          .accounts({ signer: operator.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination })
+          .signers([operator])
+          .rpc();
+      await mintAs(6);
+      const [mintSession] = PublicKey.findProgramAddressSync(
+        [Buffer.from("mint-session"), mint.toBuffer(), operator.publicKey.toBuffer()],
+        program.programId,
+      );
+      expect((await program.account.mintSession.fetch(mintSession)).minted.toNumber()).to.equal(6);
+
+      try {
+        await mintAs(5);
+        expect.fail("Expected the allowance to be exhausted");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("MintAllowanceExceeded");
+      }
+
+      await program.methods
+        .revokeMintSession(operator.publicKey, isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      try {
+        await mintAs(1);
+        expect.fail("Expected the revoked operator to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+    });
+  });
+
+  describe("57. Force Transfer", () => {
+    it("should claw tokens back from a holder", async () => {
+      const isin = tokensToCreate[1].isin;
+      const { mint, index } = await getTokenForIsin(isin);
+      const holder = web3.Keypair.generate();
+      const source = await createTokenAccount(holder, mint);
+      const destination = await createTokenAccount(wallets.authorized, mint);
+      const balance = async (account: PublicKey) =>
+        Number((await getAccount(provider.connection, account, "confirmed", TOKEN_2022_PROGRAM_ID)).amount);
+      await program.methods
+        .mintTokens(index, new anchor.BN(5))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: source })
+        .rpc();
+      const destinationBefore = await balance(destination);
+
+      await program.methods
+        .forceTransfer(isinArg(isin), new anchor.BN(5))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, source, destination })
+        .rpc();
+      expect(await balance(source)).to.equal(0);
+      expect(await balance(destination)).to.equal(destinationBefore + 5);
+    });
+
+    it("should refuse a forced transfer from anyone else", async () => {
+      const isin = tokensToCreate[1].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const source = await createTokenAccount(wallets.authorized, mint);
+      try {
+        await program.methods
+          .forceTransfer(isinArg(isin), new anchor.BN(1))
+          .accounts({
+            signer: wallets.unauthorized.publicKey,
+            tokenManager: tokenManagerPDA,
+            source,
+            destination: source,
+          })
+          .signers([wallets.unauthorized])
+          .rpc();
+        expect.fail("Expected the forced transfer to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+    });
+  });
+
+  describe("58. Roles", () => {
+    it("should let granted roles mint and manage the whitelist until revoked", async () => {
+      const isin = tokensToCreate[2].isin;
+      const { mint, index } = await getTokenForIsin(isin);
+      const operator = web3.Keypair.generate();
+      await fundWallet(operator);
+      const destination = await createTokenAccount(wallets.authorized, mint);
+      const wallet = web3.Keypair.generate().publicKey;
+      for (const role of [{ minter: {} }, { complianceOfficer: {} }]) {
+        await program.methods
+          .grantRole(operator.publicKey, role)
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      }
+
+      await program.methods
+        .mintTokens(index, new anchor.BN(1))
+        .accounts({ signer: operator.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination })
+        .signers([operator])
+        .rpc();
+      await program.methods
+        .addToWhitelist(wallet, isinArg(isin), new anchor.BN(0))
+        .accounts({ signer: operator.publicKey, tokenManager: tokenManagerPDA })
+        .signers([operator])
+        .rpc();
+      await program.methods
+        .removeFromWhitelist(wallet, isinArg(isin))
+        .accounts({ signer: operator.publicKey, tokenManager: tokenManagerPDA })
+        .signers([operator])
+        .rpc();
+
+      await program.methods
+        .revokeRole(operator.publicKey, { complianceOfficer: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      try {
+        await program.methods
+          .addToWhitelist(wallet, isinArg(isin), new anchor.BN(0))
+          .accounts({ signer: operator.publicKey, tokenManager: tokenManagerPDA })
+          .signers([operator])
+          .rpc();
+        expect.fail("Expected the revoked role to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+      await program.methods
+        .revokeRole(operator.publicKey, { minter: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+  });
+
+  describe("59. Burn Sessions", () => {
+    it("should let the redemptions desk burn up to its daily cap", async () => {
+      const isin = tokensToCreate[2].isin;
+      const { mint, index } = await getTokenForIsin(isin);
+      const desk = web3.Keypair.generate();
+      const source = await createTokenAccount(desk, mint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(10))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: source })
+        .rpc();
+      await program.methods
+        .createBurnSession(desk.publicKey, isinArg(isin), new anchor.BN(6), new anchor.BN(Math.floor(Date.now() / 1000) + 3_600))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const burnAs = (amount: number) =>
+        program.methods
+          .burnTokens(index, new anchor.BN(amount))
+          .accounts({ signer: desk.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), source })
+          .signers([desk])
+          .rpc();
+      await burnAs(6);
+      try {
+        await burnAs(1);
+        expect.fail("Expected the daily cap to be reached");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("BurnAllowanceExceeded");
+      }
+
+      await program.methods
+        .revokeBurnSession(desk.publicKey, isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+  });
+
+  describe("60. Operation Locks", () => {
+    it("should fail conflicting operations fast while a lock is held", async () => {
+      const isin = tokensToCreate[2].isin;
+      await program.methods
+        .acquireOperationLock(isinArg(isin), { split: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      try {
+        await program.methods
+          .acquireOperationLock(isinArg(isin), { distribution: {} })
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        expect.fail("Expected the second operation to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("OperationInProgress");
+      }
+
+      const slot = new anchor.BN(await provider.connection.getSlot("confirmed"));
+      try {
+        await program.methods
+          .declareCorporateAction(isinArg(isin), new anchor.BN(Date.now()), {
+            exSlot: slot.addn(10),
+            recordSlot: slot.addn(11),
+            paymentSlot: slot.addn(12),
+            blackoutSlots: new anchor.BN(0),
+            dueBills: null,
+          })
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        expect.fail("Expected the corporate action to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("OperationInProgress");
+      }
+      try {
+        await program.methods
+          .updateExtraAccountMetaList(isinArg(isin))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        expect.fail("Expected the meta list migration to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("OperationInProgress");
+      }
+
+      await program.methods
+        .releaseOperationLock(isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+  });
+
+  describe("61. Creator Rotation", () => {
+    it("should hand the token manager over only once the new creator accepts", async () => {
+      const isin = tokensToCreate[0].isin;
+      const successor = web3.Keypair.generate();
+      const setSelfRedemption = (signer: web3.Keypair | null) => {
+        const builder = program.methods
+          .setSelfRedemption(isinArg(isin), false)
+          .accounts({ signer: signer ? signer.publicKey : provider.wallet.publicKey, tokenManager: tokenManagerPDA });
+        return signer ? builder.signers([signer]).rpc() : builder.rpc();
+      };
+      const rotate = async (from: web3.Keypair | null, to: web3.Keypair | null) => {
+        const proposal = program.methods
+          .proposeNewCreator(to ? to.publicKey : provider.wallet.publicKey)
+          .accounts({ signer: from ? from.publicKey : provider.wallet.publicKey, tokenManager: tokenManagerPDA });
+        await (from ? proposal.signers([from]).rpc() : proposal.rpc());
+        const acceptance = program.methods
+          .acceptCreator()
+          .accounts({ signer: to ? to.publicKey : provider.wallet.publicKey, tokenManager: tokenManagerPDA });
+        await (to ? acceptance.signers([to]).rpc() : acceptance.rpc());
+      };
+
+      try {
+        await program.methods
+          .acceptCreator()
+          .accounts({ signer: successor.publicKey, tokenManager: tokenManagerPDA })
+          .signers([successor])
+          .rpc();
+        expect.fail("Expected an unproposed creator to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+
+      await rotate(null, successor);
+      expect((await program.account.tokenManager.fetch(tokenManagerPDA)).creator.toString())
+        .to.equal(successor.publicKey.toString());
+      await setSelfRedemption(successor);
+      try {
+        await setSelfRedemption(null);
+        expect.fail("Expected the previous creator to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+
+      await rotate(successor, null);
+      expect((await program.account.tokenManager.fetch(tokenManagerPDA)).pendingCreator).to.be.null;
+    });
+  });
+
+  describe("62. Supply Cap", () => {
+    it("should refuse to mint beyond the maximum supply", async () => {
+      const isin = "XS0000000090";
+      await program.methods
+        .createNewShare(0, isinArg(isin), new anchor.BN(100), null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint, index } = await getTokenForIsin(isin);
+      const destination = await createTokenAccount(wallets.authorized, mint);
+      const mintShares = (amount: number) =>
+        program.methods
+          .mintTokens(index, new anchor.BN(amount))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination })
+          .rpc();
+
+      await mintShares(100);
+      try {
+        await mintShares(1);
+        expect.fail("Expected the supply cap to be enforced");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("SupplyCapExceeded");
+      }
+    });
+  });
+
+  describe("63. Versioned Arguments", () => {
+    it("should create shares from every version of the arguments", async () => {
+      const versions = [
+        { isin: "XS0000000108", args: (isin: string) => ({ v1: { decimals: 0, isin: isinArg(isin) } }) },
+        { isin: "XS0000000116", args: (isin: string) => ({ v2: { decimals: 0, isin: isinArg(isin), maxSupply: new anchor.BN(5) } }) },
+        { isin: "XS0000000165", args: (isin: string) => ({ v3: { decimals: 0, isin: isinArg(isin), maxSupply: null, additionalMetadata: [{ key: "issuer", value: "Example AG" }] } }) },
+      ];
+      for (const { isin, args } of versions) {
+        await program.methods
+          .createShare(args(isin))
+          .accounts({
+            signer: provider.wallet.publicKey,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+            tokenWhitelist: tokenWhitelistPDA(isin),
+          })
+          .rpc();
+      }
+
+      expect((await program.account.tokenShare.fetch(tokenSharePDA(versions[0].isin))).maxSupply).to.be.null;
+      expect((await program.account.tokenShare.fetch(tokenSharePDA(versions[1].isin))).maxSupply.toNumber()).to.equal(5);
+    });
+  });
+
+  describe("64. Batch Whitelist Management", () => {
+    it("should add and remove many wallets at once, skipping those already processed", async () => {
+      const isin = "XS0000000124";
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint } = await getTokenForIsin(isin);
+      const investors = [0, 1, 2].map(() => web3.Keypair.generate().publicKey);
+      const batchAccounts = (batch: PublicKey[]) =>
+        batch.flatMap(wallet => [
+          { pubkey: whitelistEntryPDA(mint, wallet), isSigner: false, isWritable: true },
+          {
+            pubkey: PublicKey.findProgramAddressSync(
+              [Buffer.from("whitelist-history"), tokenManagerPDA.toBuffer(), Buffer.from(isin), wallet.toBuffer()],
+              program.programId,
+            )[0],
+            isSigner: false,
+            isWritable: true,
+          },
+        ]);
+
+      await program.methods
+        .addToWhitelist(investors[0], isinArg(isin), new anchor.BN(0))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      for (let attempt = 0; attempt < 2; attempt++) {
+        await program.methods
+          .addManyToWhitelist(investors, isinArg(isin), new anchor.BN(0))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .remainingAccounts(batchAccounts(investors))
+          .rpc();
+      }
+      expect((await getWhitelistForIsin(isin)).length).to.equal(3);
+      expect((await program.account.tokenWhitelist.fetch(tokenWhitelistPDA(isin))).count).to.equal(3);
+
+      await program.methods
+        .removeManyFromWhitelist(investors.slice(1), isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .remainingAccounts(batchAccounts(investors.slice(1)))
+        .rpc();
+      const remaining = await getWhitelistForIsin(isin);
+      expect(remaining.map(entry => entry.wallet.toBase58())).to.deep.equal([investors[0].toBase58()]);
+      expect((await program.account.tokenWhitelist.fetch(tokenWhitelistPDA(isin))).count).to.equal(1);
+    });
+  });
+
+  describe("65. ISIN Validation", () => {
+    const createShare = (isin: string) =>
+      program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+    it("should reject malformed ISINs and wrong check digits", async () => {
+      for (const [isin, code] of [
+        ["1S0000000033", "InvalidIsinFormat"],
+        ["XS000000003A", "InvalidIsinFormat"],
+        ["XS0000000034", "InvalidIsinChecksum"],
+      ]) {
+        try {
+          await createShare(isin);
+          expect.fail(`Expected ${isin} to be rejected`);
+        } catch (err: any) {
+          expect(err.error.errorCode.code).to.equal(code);
+        }
+      }
+    });
+
+    it("should reject an ISIN that already has a share", async () => {
+      try {
+        await createShare(tokensToCreate[0].isin);
+        expect.fail("Expected the duplicate ISIN to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("DuplicateIsin");
+      }
+    });
+  });
+
+  describe("66. Lifecycle Events", () => {
+    it("should emit events when a share is created and minted", async () => {
+      const isin = "XS0000000132";
+      const events: { name: string, data: any }[] = [];
+      const listeners = ["shareCreated", "tokensMinted"].map(name =>
+        program.addEventListener(name as any, data => events.push({ name, data })),
+      );
+
+      try {
+        await program.methods
+          .createNewShare(0, isinArg(isin), null, null)
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        const { mint, index } = await getTokenForIsin(isin);
+        const destination = await createTokenAccount(wallets.authorized, mint);
+        await program.methods
+          .mintTokens(index, new anchor.BN(10))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination })
+          .rpc({ commitment: "confirmed" });
+        await new Promise(resolve => setTimeout(resolve, 1000));
+
+        const created = events.find(event => event.name === "shareCreated");
+        expect(isinString(created.data.isin)).to.equal(isin);
+        expect(created.data.mint.toBase58()).to.equal(mint.toBase58());
+        const minted = events.find(event => event.name === "tokensMinted");
+        expect(minted.data.holder.toBase58()).to.equal(wallets.authorized.publicKey.toBase58());
+        expect(minted.data.amount.toNumber()).to.equal(10);
+      } finally {
+        for (const listener of listeners) {
+          await program.removeEventListener(listener);
+        }
+      }
+    });
+  });
+
+  describe("67. Manager Configuration", () => {
+    it("should configure a manager in its initialization and refuse to initialize it again", async () => {
+      const issuer = web3.Keypair.generate();
+      await fundWallet(issuer);
+      const [manager] = PublicKey.findProgramAddressSync(
+        [Buffer.from("token-manager"), issuer.publicKey.toBuffer()],
+        program.programId,
+      );
+      const guardian = web3.Keypair.generate().publicKey;
+      const config = {
+        ...defaultManagerConfig,
+        complianceDefaults: { ...defaultManagerConfig.complianceDefaults, requireSenderWhitelisted: true },
+        displayName: "Example Fund Manager",
+        feeTerms: { subscriptionFeeBps: 50, redemptionFeeBps: 25, transferFeeBps: 0, adminFee: new anchor.BN(0) },
+        guardian,
+      };
+      const initialize = () =>
+        program.methods
+          .initializeTokenManager(config)
+          .accounts({ signer: issuer.publicKey })
+          .signers([issuer])
+          .rpc();
+
+      await initialize();
+      const managerAccount = (name: string) =>
+        PublicKey.findProgramAddressSync([Buffer.from(name), manager.toBuffer()], program.programId)[0];
+      expect((await program.account.issuerProfile.fetch(managerAccount("issuer-profile"))).displayName).to.equal(config.displayName);
+      const feeSchedule = await program.account.feeSchedule.fetch(managerAccount("fee-schedule"));
+      expect(feeSchedule.version).to.equal(1);
+      expect(feeSchedule.terms.subscriptionFeeBps).to.equal(50);
+      expect((await program.account.treasuryYield.fetch(managerAccount("treasury-yield"))).guardian.toBase58()).to.equal(guardian.toBase58());
+
+      const isin = "XS0000000140";
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: issuer.publicKey, tokenManager: manager })
+        .signers([issuer])
+        .rpc();
+      expect((await program.account.tokenShare.fetch(tokenSharePDA(isin, manager))).requireSenderWhitelisted).to.be.true;
+
+      try {
+        await initialize();
+        expect.fail("Expected the second initialization to fail");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("TokenManagerAlreadyInitialized");
+      }
+    });
+  });
+
+  describe("68. KYC Expiry", () => {
+    it("should block lapsed authorizations until they are renewed", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleAuthorizationCurrent").value);
+      const investor = web3.Keypair.generate().publicKey;
+      const explain = async () =>
+        (await program.methods
+          .explainTransfer(wallets.destination.publicKey, investor, new anchor.BN(1))
+          .accounts({
+            mint,
+            sourceToken: null,
+            destinationToken: null,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+          })
+          .view()).find(r => r.ruleId === ruleId);
+
+      await program.methods
+        .addToWhitelist(investor, isinArg(isin), new anchor.BN(1))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      expect((await explain()).passed).to.be.false;
+
+      const renewal = Math.floor(Date.now() / 1000) + 365 * 24 * 3600;
+      await program.methods
+        .renewAuthorization(investor, isinArg(isin), new anchor.BN(renewal))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      expect((await program.account.whitelistEntry.fetch(whitelistEntryPDA(mint, investor))).expiresAt.toNumber()).to.equal(renewal);
+      expect((await explain()).passed).to.be.true;
+    });
+  });
+
+  describe("69. Metadata Updates", () => {
+    it("should update the URI and custom fields of an issued share", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const uri = "https://example.com/prospectus/" + isin + "-2.json";
+
+      await program.methods
+        .updateTokenMetadata(isinArg(isin), { uri: {} }, uri)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await program.methods
+        .updateTokenMetadata(isinArg(isin), { key: { 0: "prospectus_version" } }, "2")
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const metadata = await getTokenMetadata(provider.connection, mint, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect(metadata.uri).to.equal(uri);
+      expect(metadata.additionalMetadata).to.deep.include(["prospectus_version", "2"]);
+    });
+
+    it("should only let the creator update the metadata", async () => {
+      const isin = tokensToCreate[0].isin;
+      try {
+        await program.methods
+          .updateTokenMetadata(isinArg(isin), { name: {} }, "Renamed")
+          .accounts({ signer: wallets.unauthorized.publicKey, tokenManager: tokenManagerPDA })
+          .signers([wallets.unauthorized])
+          .rpc();
+        expect.fail("Expected an unrelated wallet to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+    });
+  });
+
+  describe("70. Custom Metadata Fields", () => {
+    it("should write the issuer, jurisdiction and prospectus to the metadata of a new share", async () => {
+      const isin = "XS0000000157";
+      const additionalMetadata = [
+        { key: "issuer", value: "Example Issuer AG" },
+        { key: "jurisdiction", value: "LU" },
+        { key: "prospectus_uri", value: "https://example.com/prospectus/" + isin + ".pdf" },
+      ];
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, additionalMetadata)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const { mint } = await getTokenForIsin(isin);
+      const metadata = await getTokenMetadata(provider.connection, mint, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect(metadata.additionalMetadata).to.deep.equal(additionalMetadata.map(({ key, value }) => [key, value]));
+    });
+  });
+
+  describe("71. Extra Account Meta List Updates", () => {
+    it("should rewrite the meta list of an existing mint with the current layout", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const [metaList] = PublicKey.findProgramAddressSync(
+        [Buffer.from("extra-account-metas"), mint.toBuffer()],
+        program.programId
+      );
+      const before = (await provider.connection.getAccountInfo(metaList)).data;
+
+      await program.methods
+        .updateExtraAccountMetaList(isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const after = (await provider.connection.getAccountInfo(metaList)).data;
+      expect(after.equals(before)).to.be.true;
+    });
+
+    it("should only let the creator rewrite the meta list", async () => {
+      try {
+        await program.methods
+          .updateExtraAccountMetaList(isinArg(tokensToCreate[0].isin))
+          .accounts({ signer: wallets.unauthorized.publicKey, tokenManager: tokenManagerPDA })
+          .signers([wallets.unauthorized])
+          .rpc();
+        expect.fail("Expected an unrelated wallet to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+    });
+  });
+
+  describe("72. Maximum Holders", () => {
+    it("should count holders and stop new ones beyond the cap", async () => {
+      const isin = "XS0000000173";
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint, index } = await getTokenForIsin(isin);
+      const first = await createTokenAccount(wallets.authorized, mint);
+      const second = await createTokenAccount(wallets.destination, mint);
+      const issue = (destination: PublicKey) =>
+        program.methods
+          .mintTokens(index, new anchor.BN(10))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination })
+          .rpc();
+
+      await issue(first);
+      await program.methods
+        .setMaxHolders(isinArg(isin), 1)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      try {
+        await issue(second);
+        expect.fail("Expected a second holder to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("MaxHoldersReached");
+      }
+      // Existing holders may still receive tokens
+      await issue(first);
+
+      await program.methods
+        .setMaxHolders(isinArg(isin), null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await issue(second);
+      const share = await program.account.tokenShare.fetch(tokenSharePDA(isin));
+      expect(share.holderCount).to.equal(2);
+    });
+
+    it("should only let the creator cap the holders", async () => {
+      try {
+        await program.methods
+          .setMaxHolders(isinArg(tokensToCreate[0].isin), 1)
+          .accounts({ signer: wallets.unauthorized.publicKey, tokenManager: tokenManagerPDA })
+          .signers([wallets.unauthorized])
+          .rpc();
+        expect.fail("Expected an unrelated wallet to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+    });
+  });
+
+  describe("73. Registry Identifiers", () => {
+    it("should map a mint to its transfer agent, CSD and paying agent identifiers", async () => {
+      const isin = tokensToCreate[0].isin;
+      const { mint } = await getTokenForIsin(isin);
+      await program.methods
+        .setRegistryIdentifiers(isinArg(isin), "TA-000123", "CSD-XS-0042", "DEUTDEFFXXX")
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const [address] = PublicKey.findProgramAddressSync(
+        [Buffer.from("registry-ids"), mint.toBuffer()],
+        program.programId
+      );
+      const identifiers = await program.account.registryIdentifiers.fetch(address);
+      expect(identifiers.mint.toBase58()).to.equal(mint.toBase58());
+      expect(identifiers.transferAgentAccount).to.equal("TA-000123");
+      expect(identifiers.csdIdentifier).to.equal("CSD-XS-0042");
+      expect(identifiers.payingAgentBic).to.equal("DEUTDEFFXXX");
+    });
+
+    it("should reject malformed BICs", async () => {
+      try {
+        await program.methods
+          .setRegistryIdentifiers(isinArg(tokensToCreate[0].isin), "", "", "deut-de")
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        expect.fail("Expected a malformed BIC to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidBic");
+      }
+    });
+  });
+
+  describe("74. Four-Eyes Whitelist Approval", () => {
+    it("should only whitelist wallets confirmed by a second officer", async () => {
+      const isin = "XS0000000181";
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint } = await getTokenForIsin(isin);
+      await program.methods
+        .setWhitelistApprovalWindow(isinArg(isin), 3_600)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const wallet = web3.Keypair.generate().publicKey;
+
+      try {
+        await program.methods
+          .addToWhitelist(wallet, isinArg(isin), new anchor.BN(0))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        expect.fail("Expected a single officer addition to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("WhitelistApprovalRequired");
+      }
+
+      await program.methods
+        .proposeWhitelistAddition(wallet, isinArg(isin), new anchor.BN(0))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      try {
+        await program.methods
+          .confirmWhitelistAddition(wallet, isinArg(isin))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, proposer: provider.wallet.publicKey })
+          .rpc();
+        expect.fail("Expected the proposer to be refused as confirmer");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("ApproverNotDistinct");
+      }
+
+      const officer = web3.Keypair.generate();
+      await fundWallet(officer);
+      await program.methods
+        .grantRole(officer.publicKey, { complianceOfficer: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await program.methods
+        .confirmWhitelistAddition(wallet, isinArg(isin))
+        .accounts({ signer: officer.publicKey, tokenManager: tokenManagerPDA, proposer: provider.wallet.publicKey })
+        .signers([officer])
+        .rpc();
+      await program.methods
+        .revokeRole(officer.publicKey, { complianceOfficer: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const [entry] = PublicKey.findProgramAddressSync(
+        [Buffer.from("whitelist"), mint.toBuffer(), wallet.toBuffer()],
+        program.programId
+      );
+      expect((await program.account.whitelistEntry.fetch(entry)).wallet.toBase58()).to.equal(wallet.toBase58());
+    });
+  });
+
+  describe("75. Maximum Balance Per Holder", () => {
+    it("should reject transfers taking a holder above the cap", async () => {
+      const isin = "XS0000000199";
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint, index } = await getTokenForIsin(isin);
+      const destinationToken = await createTokenAccount(wallets.destination, mint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(10))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: destinationToken })
+        .rpc();
+      await program.methods
+        .setMaxBalancePerHolder(isinArg(isin), new anchor.BN(15))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleHolderCap").value);
+      const explain = (amount: number) =>
+        program.methods
+          .explainTransfer(wallets.authorized.publicKey, wallets.destination.publicKey, new anchor.BN(amount))
+          .accounts({
+            mint,
+            sourceToken: null,
+            destinationToken,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+          })
+          .view();
+      expect((await explain(5)).find(r => r.ruleId === ruleId).passed).to.be.true;
+      expect((await explain(6)).find(r => r.ruleId === ruleId).passed).to.be.false;
+
+      await program.methods
+        .setMaxBalancePerHolder(isinArg(isin), null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      expect((await explain(6)).find(r => r.ruleId === ruleId)).to.be.undefined;
+    });
+  });
+
+  describe("76. Reference Data Feed", () => {
+    it("should let a data provider push reference data into the metadata", async () => {
+      const isin = tokensToCreate[1].isin;
+      const { mint } = await getTokenForIsin(isin);
+      const dataProvider = web3.Keypair.generate();
+      await fundWallet(dataProvider);
+      await program.methods
+        .grantRole(dataProvider.publicKey, { dataProvider: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      await program.methods
+        .pushReferenceData(isinArg(isin), { issueDate: "2026-01-15", currency: "EUR", couponRateBps: 425 })
+        .accounts({ signer: dataProvider.publicKey, tokenManager: tokenManagerPDA })
+        .signers([dataProvider])
+        .rpc();
+      const metadata = await getTokenMetadata(provider.connection, mint, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect(metadata.additionalMetadata).to.deep.include.members([
+        ["issue_date", "2026-01-15"],
+        ["currency", "EUR"],
+        ["coupon_rate_bps", "425"],
+      ]);
+
+      try {
+        await program.methods
+          .pushReferenceData(isinArg(isin), { issueDate: null, currency: "euro", couponRateBps: null })
+          .accounts({ signer: dataProvider.publicKey, tokenManager: tokenManagerPDA })
+          .signers([dataProvider])
+          .rpc();
+        expect.fail("Expected a malformed currency to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidReferenceData");
+      }
+      await program.methods
+        .revokeRole(dataProvider.publicKey, { dataProvider: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+
+    it("should refuse reference data from wallets without the role", async () => {
+      try {
+        await program.methods
+          .pushReferenceData(isinArg(tokensToCreate[1].isin), { issueDate: null, currency: "EUR", couponRateBps: null })
+          .accounts({ signer: wallets.unauthorized.publicKey, tokenManager: tokenManagerPDA })
+          .signers([wallets.unauthorized])
+          .rpc();
+        expect.fail("Expected an unrelated wallet to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+    });
+  });
+
+  describe("77. Lockups", () => {
+    it("should keep locked tokens in the holder account until released", async () => {
+      const isin = "XS0000000207";
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint, index } = await getTokenForIsin(isin);
+      const sourceToken = await createTokenAccount(wallets.authorized, mint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(10))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: sourceToken })
+        .rpc();
+      const now = Math.floor(Date.now() / 1000);
+      const setLockup = (releases: [number, number][]) =>
+        program.methods
+          .setLockup(
+            wallets.authorized.publicKey,
+            isinArg(isin),
+            new anchor.BN(releases.reduce((total, [, amount]) => total + amount, 0)),
+            releases.map(([releaseAt, amount]) => ({ releaseAt: new anchor.BN(releaseAt), amount: new anchor.BN(amount) })),
+          )
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleLockup").value);
+      const explain = (amount: number) =>
+        program.methods
+          .explainTransfer(wallets.authorized.publicKey, wallets.destination.publicKey, new anchor.BN(amount))
+          .accounts({
+            mint,
+            sourceToken,
+            destinationToken: null,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+          })
+          .view();
+
+      await setLockup([[now - 3_600, 4], [now + 31_536_000, 4]]);
+      expect((await explain(6)).find(r => r.ruleId === ruleId).passed).to.be.true;
+      expect((await explain(7)).find(r => r.ruleId === ruleId).passed).to.be.false;
+
+      await setLockup([[now - 3_600, 8]]);
+      expect((await explain(10)).find(r => r.ruleId === ruleId)).to.be.undefined;
+    });
+
+    it("should reject schedules that do not add up to the locked amount", async () => {
+      try {
+        await program.methods
+          .setLockup(wallets.authorized.publicKey, isinArg("XS0000000207"), new anchor.BN(5), [
+            { releaseAt: new anchor.BN(0), amount: new anchor.BN(4) },
+          ])
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+        expect.fail("Expected the schedule to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidLockupSchedule");
+      }
+    });
+  });
+
+  describe("78. Distributions", () => {
+    it("should pay holders pro rata on their record date balance once, less withholding", async () => {
+      const isin = "XS0000000215";
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint, index } = await getTokenForIsin(isin);
+      // The destination wallet has an approved tax certification, the authorized
+      // one is withheld at the penalty rate
+      const holders = [wallets.destination, wallets.authorized];
+      const holderSecurities = [];
+      for (const holder of holders) {
+        const holderSecurity = await createTokenAccount(holder, mint);
+        await program.methods
+          .mintTokens(index, new anchor.BN(1_000))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: holderSecurity })
+          .rpc();
+        holderSecurities.push(holderSecurity);
+      }
+      const mintInfo = await getMint(provider.connection, mint, "confirmed", TOKEN_2022_PROGRAM_ID);
+
+      const payoutMint = await createPaymentMint();
+      const issuerPayout = await getOrCreateAssociatedTokenAccount(provider.connection, payer, payoutMint, payer.publicKey);
+      await mintTo(provider.connection, payer, payoutMint, issuerPayout.address, payer, 10_000);
+      const { snapshot, entries } = await snapshotHolders(isin, holderSecurities);
+
+      const actionId = new anchor.BN(Date.now());
+      const slot = await provider.connection.getSlot("confirmed");
+      await program.methods
+        .declareCorporateAction(isinArg(isin), actionId, {
+          exSlot: new anchor.BN(slot + 4),
+          recordSlot: new anchor.BN(slot + 5),
+          paymentSlot: new anchor.BN(slot + 60),
+          blackoutSlots: new anchor.BN(10),
+          dueBills: null,
+        })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await program.methods
+        .createDistribution(isinArg(isin), actionId, new anchor.BN(3 * 10 ** mintInfo.decimals), null)
+        .accounts({
+          signer: provider.wallet.publicKey,
+          tokenManager: tokenManagerPDA,
+          securityMint: mint,
+          snapshot,
+          payoutMint,
+          fxRate: null,
+          issuerPayout: issuerPayout.address,
+          payoutTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      const claim = (i: number, holderPayout: PublicKey, entry = entries[i]) =>
+        program.methods
+          .claimDistribution(isinArg(isin), actionId)
+          .accounts({
+            holder: holders[i].publicKey,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+            payoutMint,
+            holderPayout,
+            noticeAcknowledgment: null,
+            payoutTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .remainingAccounts([{ pubkey: entry, isSigner: false, isWritable: false }])
+          .signers([holders[i]])
+          .rpc();
+
+      while ((await provider.connection.getSlot("confirmed")) < slot + 6) {
+        await new Promise(resolve => setTimeout(resolve, 400));
+      }
+      for (const [i, expected] of [[0, 3_000], [1, 2_100]]) {
+        const holderPayout = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, payoutMint, holders[i].publicKey)).address;
+        if (i === 1) {
+          try {
+            await claim(i, holderPayout, entries[0]);
+            expect.fail("Expected a claim on the entry of another holder to be rejected");
+          } catch (err: any) {
+            expect(err.error.errorCode.code).to.equal("SnapshotEntryMismatch");
+          }
+        }
+        await claim(i, holderPayout);
+        const paid = await getAccount(provider.connection, holderPayout, "confirmed", TOKEN_PROGRAM_ID);
+        expect(Number(paid.amount)).to.equal(expected);
+
+        try {
+          await claim(i, holderPayout);
+          expect.fail("Expected a second claim to be rejected");
+        } catch (err: any) {
+          expect(err.message).to.not.include("Expected a second claim");
+        }
+      }
+      const [corporateAction] = PublicKey.findProgramAddressSync(
+        [Buffer.from("corporate-action"), mint.toBuffer(), actionId.toArrayLike(Buffer, "le", 8)],
+        program.programId,
+      );
+      const [distribution] = PublicKey.findProgramAddressSync([Buffer.from("distribution"), corporateAction.toBuffer()], program.programId);
+      expect((await program.account.distribution.fetch(distribution)).withheld.toNumber()).to.equal(900);
+    });
+  });
+
+  describe("79. Distributions In Kind", () => {
+    it("should mint the payout share to eligible holders at claim time", async () => {
+      const isin = "XS0000000223";
+      const payoutIsin = "XS0000000231";
+      for (const share of [isin, payoutIsin]) {
+        await program.methods
+          .createNewShare(0, isinArg(share), null, null)
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      }
+      const { mint, index } = await getTokenForIsin(isin);
+      const { mint: payoutMint } = await getTokenForIsin(payoutIsin);
+      await setPaymentMint(payoutMint, true);
+      const holderSecurity = await createTokenAccount(wallets.destination, mint);
+      const holderPayout = await createTokenAccount(wallets.destination, payoutMint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(1_000))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: holderSecurity })
+        .rpc();
+      const { snapshot, entries: [entry] } = await snapshotHolders(isin, [holderSecurity]);
+
+      const actionId = new anchor.BN(Date.now());
+      const slot = await provider.connection.getSlot("confirmed");
+      await program.methods
+        .declareCorporateAction(isinArg(isin), actionId, {
+          exSlot: new anchor.BN(slot + 4),
+          recordSlot: new anchor.BN(slot + 5),
+          paymentSlot: new anchor.BN(slot + 60),
+          blackoutSlots: new anchor.BN(10),
+          dueBills: null,
+        })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await program.methods
+        .createPikDistribution(isinArg(isin), actionId, isinArg(payoutIsin), new anchor.BN(2), null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, securityMint: mint, snapshot, payoutMint })
+        .rpc();
+      await setPaymentMint(payoutMint, false);
+
+      const claim = () =>
+        program.methods
+          .claimPikDistribution(isinArg(isin), actionId)
+          .accounts({
+            holder: wallets.destination.publicKey,
+            tokenManager: tokenManagerPDA,
+            tokenShare: tokenSharePDA(isin),
+            payoutShare: tokenSharePDA(payoutIsin),
+            payoutMint,
+            holderPayout,
+            noticeAcknowledgment: null,
+          })
+          .remainingAccounts([{ pubkey: entry, isSigner: false, isWritable: false }])
+          .signers([wallets.destination])
+          .rpc();
+
+      while ((await provider.connection.getSlot("confirmed")) < slot + 6) {
+        await new Promise(resolve => setTimeout(resolve, 400));
+      }
+      try {
+        await claim();
+        expect.fail("Expected a holder not whitelisted for the payout share to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("PayoutRecipientNotEligible");
+      }
+
+      await program.methods
+        .addToWhitelist(wallets.destination.publicKey, isinArg(payoutIsin), new anchor.BN(0))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await claim();
+      const paid = await getAccount(provider.connection, holderPayout, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect(Number(paid.amount)).to.equal(2_000);
+    });
+  });
+
+  describe("80. Balance Snapshots", () => {
+    it("should record every holder balance while transfers are frozen", async () => {
+      const isin = "XS0000000249";
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint, index } = await getTokenForIsin(isin);
+      const holders = [
+        [await createTokenAccount(wallets.authorized, mint), 600],
+        [await createTokenAccount(wallets.destination, mint), 400],
+      ] as [PublicKey, number][];
+      for (const [destination, amount] of holders) {
+        await program.methods
+          .mintTokens(index, new anchor.BN(amount))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination })
+          .rpc();
+      }
+
+      const [snapshot] = PublicKey.findProgramAddressSync(
+        [Buffer.from("snapshot"), mint.toBuffer(), new anchor.BN(1).toArrayLike(Buffer, "le", 8)],
+        program.programId,
+      );
+      await program.methods
+        .createSnapshot(new anchor.BN(index), new anchor.BN(100))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), snapshot })
+        .rpc();
+      for (const [tokenAccount] of holders) {
+        await program.methods
+          .recordHolderBalance()
+          .accounts({ payer: provider.wallet.publicKey, snapshot, tokenAccount })
+          .rpc();
+      }
+
+      const recorded = await program.account.snapshot.fetch(snapshot);
+      expect(recorded.id.toNumber()).to.equal(1);
+      expect(recorded.entries).to.equal(2);
+      expect(recorded.recordedSupply.toNumber()).to.equal(recorded.supply.toNumber());
+      const share = await program.account.tokenShare.fetch(tokenSharePDA(isin));
+      expect(share.blackouts.some(b => b.actionId.toNumber() === 1)).to.be.true;
+    });
+  });
+
+  describe("81. Whitelist Buckets", () => {
+    it("should whitelist wallets through the bucket of their first key byte", async () => {
+      const isin = "XS0000000256";
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint } = await getTokenForIsin(isin);
+      const wallet = web3.Keypair.generate().publicKey;
+      const [whitelistBucket] = PublicKey.findProgramAddressSync(
+        [Buffer.from("whitelist-bucket"), mint.toBuffer(), wallet.toBuffer().subarray(0, 1)],
+        program.programId,
+      );
+
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleDestinationWhitelisted").value);
+      const destinationWhitelisted = async () =>
+        (
+          await program.methods
+            .explainTransfer(wallets.authorized.publicKey, wallet, new anchor.BN(1))
+            .accounts({
+              mint,
+              sourceToken: null,
+              destinationToken: null,
+              tokenManager: tokenManagerPDA,
+              tokenShare: tokenSharePDA(isin),
+              destinationBucket: whitelistBucket,
+            })
+            .view()
+        ).find(r => r.ruleId === ruleId).passed;
+
+      const configure = (method: "addToWhitelistBucket" | "removeFromWhitelistBucket") =>
+        program.methods[method](wallet, isinArg(isin))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, whitelistBucket })
+          .rpc();
+
+      expect(await destinationWhitelisted()).to.be.false;
+      await configure("addToWhitelistBucket");
+      const bucket = await program.account.whitelistBucket.fetch(whitelistBucket);
+      expect(bucket.prefix).to.equal(wallet.toBuffer()[0]);
+      expect(bucket.wallets.map(w => w.toBase58())).to.include(wallet.toBase58());
+      expect(await destinationWhitelisted()).to.be.true;
+
+      await configure("removeFromWhitelistBucket");
+      expect(await destinationWhitelisted()).to.be.false;
+    });
+  });
+
+  describe("82. Share Splits", () => {
+    it("should split every recorded balance of a snapshot", async () => {
+      const isin = "XS0000000264";
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint, index } = await getTokenForIsin(isin);
+      const holders = [
+        [await createTokenAccount(wallets.authorized, mint), 300],
+        [await createTokenAccount(wallets.destination, mint), 200],
+      ] as [PublicKey, number][];
+      for (const [destination, amount] of holders) {
+        await program.methods
+          .mintTokens(index, new anchor.BN(amount))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination })
+          .rpc();
+      }
+
+      const [snapshot] = PublicKey.findProgramAddressSync(
+        [Buffer.from("snapshot"), mint.toBuffer(), new anchor.BN(1).toArrayLike(Buffer, "le", 8)],
+        program.programId,
+      );
+      await program.methods
+        .acquireOperationLock(isinArg(isin), { split: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await program.methods
+        .createSnapshot(new anchor.BN(index), new anchor.BN(150))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), snapshot })
+        .rpc();
+      const entries = [];
+      for (const [tokenAccount] of holders) {
+        await program.methods
+          .recordHolderBalance()
+          .accounts({ payer: provider.wallet.publicKey, snapshot, tokenAccount })
+          .rpc();
+        const [entry] = PublicKey.findProgramAddressSync(
+          [Buffer.from("snapshot-entry"), snapshot.toBuffer(), tokenAccount.toBuffer()],
+          program.programId,
+        );
+        entries.push(entry);
+      }
+
+      try {
+        await program.methods
+          .splitShares(new anchor.BN(index), new anchor.BN(1), new anchor.BN(2), new anchor.BN(2))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin) })
+          .rpc();
+        expect.fail("Expected a one to one split to be rejected");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidSplitRatio");
+      }
+      await program.methods
+        .splitShares(new anchor.BN(index), new anchor.BN(1), new anchor.BN(3), new anchor.BN(2))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin) })
+        .rpc();
+      const [split] = PublicKey.findProgramAddressSync([Buffer.from("split"), snapshot.toBuffer()], program.programId);
+      await program.methods
+        .applySplit(new anchor.BN(index))
+        .accounts({ tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), snapshot, split })
+        .remainingAccounts(
+          holders.flatMap(([tokenAccount], i) => [
+            { pubkey: entries[i], isSigner: false, isWritable: false },
+            { pubkey: tokenAccount, isSigner: false, isWritable: true },
+          ]),
+        )
+        .rpc();
+
+      for (const [[tokenAccount], expected] of holders.map((holder, i) => [holder, [450, 300][i]] as const)) {
+        const account = await getAccount(provider.connection, tokenAccount, "confirmed", TOKEN_2022_PROGRAM_ID);
+        expect(Number(account.amount)).to.equal(expected);
+      }
+      const applied = await program.account.split.fetch(split);
+      expect(applied.nextEntry).to.equal(2);
+      expect(applied.supply.toNumber()).to.equal(750);
+      const slot = await provider.connection.getSlot("confirmed");
+      const { blackouts } = await program.account.tokenShare.fetch(tokenSharePDA(isin));
+      expect(blackouts.every(b => b.endSlot.toNumber() <= slot)).to.be.true;
+
+      await program.methods
+        .releaseOperationLock(isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+
+    it("should refuse a split without the lock held since its snapshot", async () => {
+      const isin = "XS0000000264";
+      const { mint, index } = await getTokenForIsin(isin);
+      const [snapshot] = PublicKey.findProgramAddressSync(
+        [Buffer.from("snapshot"), mint.toBuffer(), new anchor.BN(2).toArrayLike(Buffer, "le", 8)],
+        program.programId,
+      );
+      await program.methods
+        .createSnapshot(new anchor.BN(index), new anchor.BN(150))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), snapshot })
+        .rpc();
+      const split = () =>
+        program.methods
+          .splitShares(new anchor.BN(index), new anchor.BN(2), new anchor.BN(2), new anchor.BN(1))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin) })
+          .rpc();
+      try {
+        await split();
+        expect.fail("Expected the split to need the operation lock");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("OperationLockRequired");
+      }
+
+      await program.methods
+        .acquireOperationLock(isinArg(isin), { split: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      try {
+        await split();
+        expect.fail("Expected a lock acquired after the snapshot to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("OperationLockRequired");
+      }
+      await program.methods
+        .releaseOperationLock(isinArg(isin))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+    });
+
+    it("should keep the supply while a split is applied and let the creator cancel it", async () => {
+      const isin = "XS0000000264";
+      const { mint, index } = await getTokenForIsin(isin);
+      const holder = wallets.authorized;
+      const holderAccount = await createTokenAccount(holder, mint);
+      const [snapshot] = PublicKey.findProgramAddressSync(
+        [Buffer.from("snapshot"), mint.toBuffer(), new anchor.BN(3).toArrayLike(Buffer, "le", 8)],
+        program.programId,
+      );
+      await program.methods
+        .acquireOperationLock(isinArg(isin), { split: {} })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      await program.methods
+        .createSnapshot(new anchor.BN(index), new anchor.BN(150))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), snapshot })
+        .rpc();
+      for (const owner of [wallets.authorized, wallets.destination]) {
+        await program.methods
+          .recordHolderBalance()
+          .accounts({ payer: provider.wallet.publicKey, snapshot, tokenAccount: await createTokenAccount(owner, mint) })
+          .rpc();
+      }
+      await program.methods
+        .splitShares(new anchor.BN(index), new anchor.BN(3), new anchor.BN(2), new anchor.BN(1))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin) })
+        .rpc();
+
+      await program.methods
+        .setSelfRedemption(isinArg(isin), true)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      try {
+        await program.methods
+          .burnTokens(index, new anchor.BN(1))
+          .accounts({ signer: holder.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), source: holderAccount })
+          .signers([holder])
+          .rpc();
+        expect.fail("Expected a burn to wait for the split");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("OperationInProgress");
+      }
+      await program.methods
+        .setSelfRedemption(isinArg(isin), false)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      try {
+        await program.methods
+          .mintTokens(index, new anchor.BN(1))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: holderAccount })
+          .rpc();
+        expect.fail("Expected a mint to wait for the split");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("OperationInProgress");
+      }
+      const release = () =>
+        program.methods
+          .releaseOperationLock(isinArg(isin))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      try {
+        await release();
+        expect.fail("Expected the lock to stay held while the split is applied");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("SplitInProgress");
+      }
+
+      const [split] = PublicKey.findProgramAddressSync([Buffer.from("split"), snapshot.toBuffer()], program.programId);
+      await program.methods
+        .cancelSplit(new anchor.BN(index))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), snapshot, split })
+        .rpc();
+      expect(await provider.connection.getAccountInfo(split)).to.be.null;
+      const slot = await provider.connection.getSlot("confirmed");
+      const { blackouts } = await program.account.tokenShare.fetch(tokenSharePDA(isin));
+      expect(blackouts.every(b => b.endSlot.toNumber() <= slot)).to.be.true;
+      await release();
+    });
+  });
+
+  describe("83. Delivery Versus Payment", () => {
+    it("should deliver the tokens and release the escrowed payment atomically", async () => {
+      const isin = "XS0000000272";
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint, index } = await getTokenForIsin(isin);
+      const seller = wallets.authorized;
+      const buyer = wallets.destination;
+      for (const wallet of [seller.publicKey, buyer.publicKey]) {
+        await program.methods
+          .addToWhitelist(wallet, isinArg(isin), new anchor.BN(0))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      }
+      const sellerSecurity = await createTokenAccount(seller, mint);
+      const buyerSecurity = await createTokenAccount(buyer, mint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(100))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: sellerSecurity })
+        .rpc();
+
+      const tradePDA = (tradeId: number) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from("dvp"), mint.toBuffer(), buyer.publicKey.toBuffer(), new anchor.BN(tradeId).toArrayLike(Buffer, "le", 8)],
+          program.programId,
+        )[0];
+      const expiresAt = new anchor.BN(Math.floor(Date.now() / 1000) + 3_600);
+      const payment = 1_000_000;
+      await program.methods
+        .openDvpSol(isinArg(isin), new anchor.BN(1), seller.publicKey, new anchor.BN(40), new anchor.BN(payment), expiresAt)
+        .accounts({ buyer: buyer.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), trade: tradePDA(1) })
+        .signers([buyer])
+        .rpc();
+
+      const sellerLamports = await provider.connection.getBalance(seller.publicKey);
+      await program.methods
+        .settleDvp(isinArg(isin))
+        .accounts({
+          seller: seller.publicKey,
+          buyer: buyer.publicKey,
+          tokenManager: tokenManagerPDA,
+          trade: tradePDA(1),
+          paymentMint: null,
+          dvpVault: null,
+          sellerPayment: null,
+          paymentTokenProgram: null,
+        })
+        .remainingAccounts([
+          { pubkey: sellerSecurity, isSigner: false, isWritable: true },
+          { pubkey: buyerSecurity, isSigner: false, isWritable: true },
+          { pubkey: whitelistEntryPDA(mint, seller.publicKey), isSigner: false, isWritable: false },
+          { pubkey: whitelistEntryPDA(mint, buyer.publicKey), isSigner: false, isWritable: false },
+        ])
+        .signers([seller])
+        .rpc();
+
+      const delivered = await getAccount(provider.connection, buyerSecurity, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect(Number(delivered.amount)).to.equal(40);
+      expect((await provider.connection.getBalance(seller.publicKey)) - sellerLamports).to.equal(payment);
+      expect(await provider.connection.getAccountInfo(tradePDA(1))).to.be.null;
+    });
+
+    it("should refund the buyer when the seller declines a trade", async () => {
+      const isin = "XS0000000272";
+      const { mint } = await getTokenForIsin(isin);
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      const seller = wallets.authorized;
+      const buyer = wallets.destination;
+      const paymentMint = await createPaymentMint();
+      const buyerPayment = await getOrCreateAssociatedTokenAccount(provider.connection, payer, paymentMint, buyer.publicKey);
+      await mintTo(provider.connection, payer, paymentMint, buyerPayment.address, payer, 500);
+      const [trade] = PublicKey.findProgramAddressSync(
+        [Buffer.from("dvp"), mint.toBuffer(), buyer.publicKey.toBuffer(), new anchor.BN(2).toArrayLike(Buffer, "le", 8)],
+        program.programId,
+      );
+      await program.methods
+        .openDvp(
+          isinArg(isin),
+          new anchor.BN(2),
+          seller.publicKey,
+          new anchor.BN(10),
+          new anchor.BN(500),
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3_600),
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          tokenManager: tokenManagerPDA,
+          tokenShare: tokenSharePDA(isin),
+          trade,
+          paymentMint,
+          buyerPayment: buyerPayment.address,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+
+      const cancel = (signer: web3.Keypair) =>
+        program.methods
+          .cancelDvp()
+          .accounts({
+            signer: signer.publicKey,
+            buyer: buyer.publicKey,
+            tokenManager: tokenManagerPDA,
+            trade,
+            paymentMint,
+            buyerPayment: buyerPayment.address,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([signer])
+          .rpc();
+      try {
+        await cancel(buyer);
+        expect.fail("Expected the buyer to be refused a refund before expiry");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("DvpNotExpired");
+      }
+      await cancel(seller);
+
+      const refunded = await getAccount(provider.connection, buyerPayment.address, "confirmed", TOKEN_PROGRAM_ID);
+      expect(Number(refunded.amount)).to.equal(500);
+      expect(await provider.connection.getAccountInfo(trade)).to.be.null;
+    });
+
+    it("should record the due bills and statements of a trade settled after the record date", async () => {
+      const isin = "XS0000000314";
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint, index } = await getTokenForIsin(isin);
+      const seller = wallets.authorized;
+      const buyer = wallets.destination;
+      for (const wallet of [seller.publicKey, buyer.publicKey]) {
+        await program.methods
+          .addToWhitelist(wallet, isinArg(isin), new anchor.BN(0))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      }
+      const sellerSecurity = await createTokenAccount(seller, mint);
+      const buyerSecurity = await createTokenAccount(buyer, mint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(100))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: sellerSecurity })
+        .rpc();
+
+      const slot = await provider.connection.getSlot("confirmed");
+      await program.methods
+        .declareCorporateAction(isinArg(isin), new anchor.BN(Date.now()), {
+          exSlot: new anchor.BN(slot - 1),
+          recordSlot: new anchor.BN(slot),
+          paymentSlot: new anchor.BN(slot + 500),
+          blackoutSlots: new anchor.BN(0),
+          dueBills: { buyerReceives: {} },
+        })
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const [dueBillLedger] = PublicKey.findProgramAddressSync([Buffer.from("due-bills"), mint.toBuffer()], program.programId);
+      const dueBillPDA = (holder: PublicKey) =>
+        PublicKey.findProgramAddressSync([Buffer.from("due-bill"), mint.toBuffer(), holder.toBuffer()], program.programId)[0];
+      for (const holder of [seller.publicKey, buyer.publicKey]) {
+        await program.methods
+          .openDueBill(holder)
+          .accounts({ payer: provider.wallet.publicKey, dueBillLedger, dueBill: dueBillPDA(holder) })
+          .rpc();
+      }
+      const statementPDA = (holder: PublicKey) =>
+        PublicKey.findProgramAddressSync([Buffer.from("holder-statement"), mint.toBuffer(), holder.toBuffer()], program.programId)[0];
+      for (const holder of [seller, buyer]) {
+        await program.methods
+          .openHolderStatement(isinArg(isin))
+          .accounts({
+            payer: holder.publicKey,
+            holder: holder.publicKey,
+            tokenManager: tokenManagerPDA,
+            tokenMint: mint,
+            relayerRegistry: null,
+          })
+          .signers([holder])
+          .rpc();
+      }
+
+      const [trade] = PublicKey.findProgramAddressSync(
+        [Buffer.from("dvp"), mint.toBuffer(), buyer.publicKey.toBuffer(), new anchor.BN(1).toArrayLike(Buffer, "le", 8)],
+        program.programId,
+      );
+      await program.methods
+        .openDvpSol(isinArg(isin), new anchor.BN(1), seller.publicKey, new anchor.BN(40), new anchor.BN(1_000_000), new anchor.BN(Math.floor(Date.now() / 1000) + 3_600))
+        .accounts({ buyer: buyer.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), trade })
+        .signers([buyer])
+        .rpc();
+      await program.methods
+        .settleDvp(isinArg(isin))
+        .accounts({
+          seller: seller.publicKey,
+          buyer: buyer.publicKey,
+          tokenManager: tokenManagerPDA,
+          trade,
+          paymentMint: null,
+          dvpVault: null,
+          sellerPayment: null,
+          paymentTokenProgram: null,
+        })
+        .remainingAccounts([
+          { pubkey: sellerSecurity, isSigner: false, isWritable: true },
+          { pubkey: buyerSecurity, isSigner: false, isWritable: true },
+          { pubkey: whitelistEntryPDA(mint, seller.publicKey), isSigner: false, isWritable: false },
+          { pubkey: whitelistEntryPDA(mint, buyer.publicKey), isSigner: false, isWritable: false },
+          { pubkey: dueBillLedger, isSigner: false, isWritable: false },
+          { pubkey: dueBillPDA(seller.publicKey), isSigner: false, isWritable: true },
+          { pubkey: dueBillPDA(buyer.publicKey), isSigner: false, isWritable: true },
+          { pubkey: statementPDA(seller.publicKey), isSigner: false, isWritable: true },
+          { pubkey: statementPDA(buyer.publicKey), isSigner: false, isWritable: true },
+        ])
+        .signers([seller])
+        .rpc();
+
+      const dueBill = (holder: PublicKey) =>
+        program.methods
+          .getDueBill(holder)
+          .accounts({ dueBillLedger, dueBill: dueBillPDA(holder) })
+          .view();
+      expect((await dueBill(buyer.publicKey)).toNumber()).to.equal(40);
+      expect((await dueBill(seller.publicKey)).toNumber()).to.equal(-40);
+      expect((await program.account.holderStatement.fetch(statementPDA(seller.publicKey))).totalSent.toNumber()).to.equal(40);
+      expect((await program.account.holderStatement.fetch(statementPDA(buyer.publicKey))).totalReceived.toNumber()).to.equal(40);
+    });
+  });
+
+  describe("84. Transfer Memos", () => {
+    it("should record the memo of a transfer in the holder statements", async () => {
+      const isin = "XS0000000272";
+      const { mint } = await getTokenForIsin(isin);
+      const statementPDA = (holder: PublicKey) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from("holder-statement"), mint.toBuffer(), holder.toBuffer()],
+          program.programId,
+        )[0];
+      for (const holder of [wallets.authorized, wallets.destination]) {
+        await program.methods
+          .openHolderStatement(isinArg(isin))
+          .accounts({
+            payer: holder.publicKey,
+            holder: holder.publicKey,
+            tokenManager: tokenManagerPDA,
+            tokenMint: mint,
+            relayerRegistry: null,
+          })
+          .signers([holder])
+          .rpc();
+      }
+
+      const memo = "INV-2026-0042";
+      const memoIx = new web3.TransactionInstruction({
+        programId: new PublicKey("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr"),
+        keys: [],
+        data: Buffer.from(memo),
+      });
+      const transferIx = await createTransferCheckedWithTransferHookInstruction(
+        provider.connection,
+        await createTokenAccount(wallets.authorized, mint),
+        mint,
+        await createTokenAccount(wallets.destination, mint),
+        wallets.authorized.publicKey,
+        BigInt(10),
+        0,
+        [],
+        "confirmed",
+        TOKEN_2022_PROGRAM_ID,
+      );
+      await sendWithLookupTable([memoIx, transferIx], [wallets.authorized]);
+
+      const expected = Array.from(createHash("sha256").update(memo).digest());
+      for (const holder of [wallets.authorized, wallets.destination]) {
+        const statement = await program.account.holderStatement.fetch(statementPDA(holder.publicKey));
+        expect(statement.lastMemoHash).to.deep.equal(expected);
+      }
+    });
+  });
+
+  describe("85. Manager Lockdown", () => {
+    it("should freeze every token and admin action until the creator lifts it", async () => {
+      const issuer = web3.Keypair.generate();
+      const guardian = wallets.unauthorized;
+      await fundWallet(issuer);
+      const isin = "XS0000000280";
+      const [managerPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("token-manager"), issuer.publicKey.toBuffer()],
+        program.programId,
+      );
+      const [mint] = PublicKey.findProgramAddressSync(
+        [Buffer.from("token-mint"), managerPDA.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, "le", 8)],
+        program.programId,
+      );
+      const [sharePDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("token-share"), managerPDA.toBuffer(), Buffer.from(isin)],
+        program.programId,
+      );
+      await program.methods
+        .initializeTokenManager(defaultManagerConfig)
+        .accounts({ signer: issuer.publicKey })
+        .signers([issuer])
+        .rpc();
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: issuer.publicKey, tokenManager: managerPDA })
+        .signers([issuer])
+        .rpc();
+      await program.methods
+        .setLockdownGuardian(guardian.publicKey)
+        .accounts({ signer: issuer.publicKey, tokenManager: managerPDA })
+        .signers([issuer])
+        .rpc();
+
+      try {
+        await program.methods
+          .lockdown()
+          .accounts({ signer: issuer.publicKey, tokenManager: managerPDA })
+          .signers([issuer])
+          .rpc();
+        expect.fail("Expected the creator to be refused a lockdown");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+      await program.methods
+        .lockdown()
+        .accounts({ signer: guardian.publicKey, tokenManager: managerPDA })
+        .signers([guardian])
+        .rpc();
+
+      try {
+        await program.methods
+          .setClockTolerance(60)
+          .accounts({ signer: issuer.publicKey, tokenManager: managerPDA })
+          .signers([issuer])
+          .rpc();
+        expect.fail("Expected admin actions to be refused during the lockdown");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("ManagerLockedDown");
+      }
+      try {
+        await program.methods
+          .applyCorridorAuthorization(wallets.destination.publicKey, isinArg(tokensToCreate[0].isin), isinArg(isin))
+          .accounts({
+            payer: provider.wallet.publicKey,
+            sourceManager: tokenManagerPDA,
+            targetManager: managerPDA,
+            corridor: web3.Keypair.generate().publicKey,
+          })
+          .rpc();
+        expect.fail("Expected corridor imports to be refused during the lockdown");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("ManagerLockedDown");
+      }
+
+      const ruleId = Number(program.idl.constants.find(c => c.name === "ruleManagerLockdown").value);
+      const evaluations = await program.methods
+        .explainTransfer(wallets.authorized.publicKey, wallets.destination.publicKey, new anchor.BN(1))
+        .accounts({ mint, sourceToken: null, destinationToken: null, tokenManager: managerPDA, tokenShare: sharePDA })
+        .view();
+      expect(evaluations.find(r => r.ruleId === ruleId).passed).to.be.false;
+
+      try {
+        await program.methods
+          .endLockdown()
+          .accounts({ signer: issuer.publicKey, tokenManager: managerPDA })
+          .signers([issuer])
+          .rpc();
+        expect.fail("Expected the lockdown to be timelocked");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("LockdownTimelocked");
+      }
+      expect((await program.account.tokenManager.fetch(managerPDA)).lockedDownAt).to.not.be.null;
+    });
+  });
+
+  describe("86. Transfer Approvals", () => {
+    const isin = "XS0000000298";
+    const holder = wallets.authorized;
+    const recipient = wallets.destination;
+    const requestPDA = (mint: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("transfer-request"), mint.toBuffer(), holder.publicKey.toBuffer(), recipient.publicKey.toBuffer()],
+        program.programId,
+      )[0];
+    const requestAndApprove = async (amount: number) => {
+      await program.methods
+        .requestTransfer(isinArg(isin), recipient.publicKey, new anchor.BN(amount))
+        .accounts({ holder: holder.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin) })
+        .signers([holder])
+        .rpc();
+      await program.methods
+        .approveTransfer(isinArg(isin), holder.publicKey, recipient.publicKey, new anchor.BN(amount))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin) })
+        .rpc();
+    };
+
+    it("should let a transfer through only once with an approved request", async () => {
+      await program.methods
+        .createNewShare(0, isinArg(isin), null, null)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+      const { mint, index } = await getTokenForIsin(isin);
+      for (const wallet of [holder.publicKey, recipient.publicKey]) {
+        await program.methods
+          .addToWhitelist(wallet, isinArg(isin), new anchor.BN(0))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+          .rpc();
+      }
+      const source = await createTokenAccount(holder, mint);
+      const destination = await createTokenAccount(recipient, mint);
+      await program.methods
+        .mintTokens(index, new anchor.BN(100))
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin), destination: source })
+        .rpc();
+      await program.methods
+        .setTransferApprovalRequirement(isinArg(isin), true)
+        .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA })
+        .rpc();
+
+      const transfer = async () =>
+        web3.sendAndConfirmTransaction(
+          provider.connection,
+          new web3.Transaction().add(
+            await createTransferCheckedWithTransferHookInstruction(
+              provider.connection,
+              source,
+              mint,
+              destination,
+              holder.publicKey,
+              BigInt(10),
+              0,
+              [],
+              "confirmed",
+              TOKEN_2022_PROGRAM_ID,
+            ),
+          ),
+          [holder],
+          { commitment: "confirmed" },
+        );
+      const expectRefused = async () => {
+        try {
+          await transfer();
+          expect.fail("Expected the transfer to need an approval");
+        } catch (error) {
+          expect(error.logs.some(log => log.includes("approved by a compliance officer"))).to.be.true;
+        }
+      };
+
+      await expectRefused();
+      await requestAndApprove(10);
+      await transfer();
+      expect((await program.account.transferRequest.fetch(requestPDA(mint))).consumed).to.be.true;
+      await expectRefused();
+
+      const received = await getAccount(provider.connection, destination, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect(Number(received.amount)).to.equal(10);
+    });
+
+    it("should execute an approved request and close it", async () => {
+      const { mint } = await getTokenForIsin(isin);
+      const source = await createTokenAccount(holder, mint);
+      const destination = await createTokenAccount(recipient, mint);
+      await program.methods
+        .requestTransfer(isinArg(isin), recipient.publicKey, new anchor.BN(5))
+        .accounts({ holder: holder.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin) })
+        .signers([holder])
+        .rpc();
+      try {
+        await program.methods
+          .approveTransfer(isinArg(isin), holder.publicKey, recipient.publicKey, new anchor.BN(50))
+          .accounts({ signer: provider.wallet.publicKey, tokenManager: tokenManagerPDA, tokenShare: tokenSharePDA(isin) })
+          .rpc();
+        expect.fail("Expected an approval of another amount to be refused");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("TransferRequestMismatch");
+      }
+      await requestAndApprove(5);
+
+      await program.methods
+        .executeApprovedTransfer(isinArg(isin))
+        .accounts({ holder: holder.publicKey, tokenManager: tokenManagerPDA, transferRequest: requestPDA(mint) })
+        .remainingAccounts([
+          { pubkey: source, isSigner: false, isWritable: true },
+          { pubkey: destination, isSigner: false, isWritable: true },
+          { pubkey: whitelistEntryPDA(mint, holder.publicKey), isSigner: false, isWritable: false },
+          { pubkey: whitelistEntryPDA(mint, recipient.publicKey), isSigner: false, isWritable: false },
+        ])
+        .signers([holder])
+        .rpc();
+
+      const received = await getAccount(provider.connection, destination, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect(Number(received.amount)).to.equal(15);
+      expect(await provider.connection.getAccountInfo(requestPDA(mint))).to.be.null;
+    });
+  });
+
   //   describe("6. Additional Token Queries", () => {
   //     it("should correctly retrieve token mint by ISIN", async () => {
   //       const testIsin = tokensToCreate[0].isin;